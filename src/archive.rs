@@ -0,0 +1,634 @@
+use std::io::{self, Write};
+
+use flate2::{
+    write::{DeflateEncoder, GzEncoder},
+    Compression, Crc, GzBuilder,
+};
+
+use crate::{
+    format_commit, is_binary,
+    pretty::{civil_from_days, now},
+    AttrValue, Attributes, CommitInfo, Conversion, DateMode, Object, Pathspec, PrettyFormat,
+    Repository, TreeObject,
+};
+
+/// Size of tar headers, contents being padded to a multiple of it
+const TAR_BLOCK: usize = 512;
+/// Tar archives are written by records of 20 blocks, like git does
+const TAR_RECORD: usize = 20 * TAR_BLOCK;
+/// Permission bits left out of tar entries, the default `tar.umask` of git
+const TAR_UMASK: u32 = 0o002;
+/// Largest size or modification time the octal fields of a tar header hold
+const USTAR_MAX: u64 = 0o77777777777;
+/// Flag of zip entries whose path is UTF-8 encoded
+const ZIP_UTF8: u16 = 1 << 11;
+const ZIP_STORE: u16 = 0;
+const ZIP_DEFLATE: u16 = 8;
+
+/// Formats `archive` writes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[default]
+    Tar,
+    TarGz,
+    Zip,
+}
+impl ArchiveFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "tar" => Ok(Self::Tar),
+            "tgz" | "tar.gz" => Ok(Self::TarGz),
+            "zip" => Ok(Self::Zip),
+            _ => Err(format!("Unknown archive format '{}'", name)),
+        }
+    }
+    /// Format given by the extension of the file written, as `archive --output` guesses it
+    pub fn from_path(path: &str) -> Option<Self> {
+        [
+            ("tar.gz", Self::TarGz),
+            ("tgz", Self::TarGz),
+            ("tar", Self::Tar),
+            ("zip", Self::Zip),
+        ]
+        .into_iter()
+        .find(|(extension, _)| {
+            path.strip_suffix(extension)
+                .and_then(|name| name.strip_suffix('.'))
+                .is_some_and(|name| !name.is_empty())
+        })
+        .map(|(_, format)| format)
+    }
+}
+
+/// Options of [write_archive]
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveOptions {
+    pub format: ArchiveFormat,
+    /// Prepended to every path, a directory of its own when it ends with `/`
+    pub prefix: String,
+    /// Reads attributes from the worktree rather than from the tree archived
+    pub worktree_attributes: bool,
+    /// Lists the paths archived on stderr
+    pub verbose: bool,
+}
+
+/// A file, a directory or a submodule of the archive, the last two having no content
+struct ArchiveEntry<'a> {
+    path: &'a str,
+    mode: u32,
+    hash: &'a str,
+    data: &'a [u8],
+    /// Whether the content is binary, which zip archives record
+    binary: bool,
+}
+
+trait ArchiveWriter {
+    fn write_entry(&mut self, entry: &ArchiveEntry) -> io::Result<()>;
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+fn unsupported_mode(entry: &ArchiveEntry) -> io::Error {
+    io::Error::other(format!(
+        "unsupported file mode: 0{:o} (SHA1: {})",
+        entry.mode, entry.hash
+    ))
+}
+
+fn put(header: &mut [u8; TAR_BLOCK], offset: usize, bytes: &[u8]) {
+    header[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+/// Appends a `<length> <keyword>=<value>\n` pax record, the length counting its own digits
+fn pax_record(records: &mut Vec<u8>, keyword: &str, value: &[u8]) {
+    let mut length = keyword.len() + value.len() + 4;
+    let mut power = 1;
+    while length / 10 >= power {
+        length += 1;
+        power *= 10;
+    }
+    records.extend(format!("{} {}=", length, keyword).as_bytes());
+    records.extend(value);
+    records.push(b'\n');
+}
+
+/// Length of the leading directories of `path` that fit in `max` bytes, 0 if none does
+fn path_prefix_len(path: &[u8], max: usize) -> usize {
+    let mut i = path.len();
+    if i > 1 && path[i - 1] == b'/' {
+        i -= 1;
+    }
+    i = i.min(max);
+    loop {
+        i -= 1;
+        if i == 0 || path[i] == b'/' {
+            return i;
+        }
+    }
+}
+
+/// Writes ustar archives with pax extended headers where ustar falls short, the way git does
+struct TarWriter<W: Write> {
+    output: W,
+    time: u64,
+    /// Bytes written in the current record
+    offset: usize,
+}
+impl<W: Write> TarWriter<W> {
+    /// Starts the archive with a global header holding the commit archived
+    fn new(output: W, time: u64, commit: Option<&str>) -> io::Result<Self> {
+        let mut writer = Self {
+            output,
+            time,
+            offset: 0,
+        };
+        let mut records = Vec::new();
+        if let Some(commit) = commit {
+            pax_record(&mut records, "comment", commit.as_bytes());
+        }
+        if time > USTAR_MAX {
+            pax_record(&mut records, "mtime", time.to_string().as_bytes());
+            writer.time = USTAR_MAX;
+        }
+        if !records.is_empty() {
+            let mut header = [0; TAR_BLOCK];
+            put(&mut header, 0, b"pax_global_header");
+            header[156] = b'g';
+            writer.write_header(header, 0o100666, records.len() as u64)?;
+            writer.write_blocked(&records)?;
+        }
+        Ok(writer)
+    }
+    /// Writes `data`, padded with zeros to a whole number of blocks
+    fn write_blocked(&mut self, data: &[u8]) -> io::Result<()> {
+        let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+        self.output.write_all(data)?;
+        self.output.write_all(&[0; TAR_BLOCK][..padding])?;
+        self.offset = (self.offset + data.len() + padding) % TAR_RECORD;
+        Ok(())
+    }
+    /// Fills in the fields every header shares and writes it, the size only counting for
+    /// regular files
+    fn write_header(
+        &mut self,
+        mut header: [u8; TAR_BLOCK],
+        mode: u32,
+        size: u64,
+    ) -> io::Result<()> {
+        let size = if mode & 0o170000 == 0o100000 { size } else { 0 };
+        put(
+            &mut header,
+            100,
+            format!("{:07o}\0", mode & 0o7777).as_bytes(),
+        );
+        put(&mut header, 108, b"0000000\0");
+        put(&mut header, 116, b"0000000\0");
+        put(&mut header, 124, format!("{:011o}\0", size).as_bytes());
+        put(&mut header, 136, format!("{:011o}\0", self.time).as_bytes());
+        put(&mut header, 257, b"ustar\x0000");
+        put(&mut header, 265, b"root");
+        put(&mut header, 297, b"root");
+        put(&mut header, 329, b"0000000\0");
+        put(&mut header, 337, b"0000000\0");
+        // The checksum is computed with its own field filled with spaces
+        put(&mut header, 148, b"        ");
+        let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+        put(&mut header, 148, format!("{:07o}\0", checksum).as_bytes());
+        self.write_blocked(&header)
+    }
+    /// Ends the archive with zeros up to the end of the record, at least two blocks of them
+    fn write_trailer(&mut self) -> io::Result<()> {
+        let mut padding = TAR_RECORD - self.offset;
+        if padding < 2 * TAR_BLOCK {
+            padding += TAR_RECORD;
+        }
+        self.output.write_all(&vec![0; padding])
+    }
+}
+impl<W: Write> ArchiveWriter for TarWriter<W> {
+    fn write_entry(&mut self, entry: &ArchiveEntry) -> io::Result<()> {
+        let mut header = [0; TAR_BLOCK];
+        let (typeflag, mode) = match entry.mode & 0o170000 {
+            0o040000 | 0o160000 => (b'5', (entry.mode | 0o777) & !TAR_UMASK),
+            0o120000 => (b'2', entry.mode | 0o777),
+            0o100000 => match entry.mode & 0o100 {
+                0 => (b'0', (entry.mode | 0o666) & !TAR_UMASK),
+                _ => (b'0', (entry.mode | 0o777) & !TAR_UMASK),
+            },
+            _ => return Err(unsupported_mode(entry)),
+        };
+        header[156] = typeflag;
+        let mut records = Vec::new();
+        let path = entry.path.as_bytes();
+        if path.len() > 100 {
+            // Long paths are split between the prefix and name fields, if they can be
+            let prefix_len = path_prefix_len(path, 155);
+            if prefix_len > 0 && path.len() - prefix_len - 1 <= 100 {
+                put(&mut header, 345, &path[..prefix_len]);
+                put(&mut header, 0, &path[prefix_len + 1..]);
+            } else {
+                put(&mut header, 0, format!("{}.data", entry.hash).as_bytes());
+                pax_record(&mut records, "path", path);
+            }
+        } else {
+            put(&mut header, 0, path);
+        }
+        if typeflag == b'2' {
+            if entry.data.len() > 100 {
+                let linkname = format!("see {}.paxheader", entry.hash);
+                put(&mut header, 157, linkname.as_bytes());
+                pax_record(&mut records, "linkpath", entry.data);
+            } else {
+                put(&mut header, 157, entry.data);
+            }
+        }
+        let mut size = entry.data.len() as u64;
+        if typeflag == b'0' && size > USTAR_MAX {
+            pax_record(&mut records, "size", size.to_string().as_bytes());
+            size = 0;
+        }
+        if !records.is_empty() {
+            let mut extended = [0; TAR_BLOCK];
+            put(
+                &mut extended,
+                0,
+                format!("{}.paxheader", entry.hash).as_bytes(),
+            );
+            extended[156] = b'x';
+            self.write_header(extended, 0o100666, records.len() as u64)?;
+            self.write_blocked(&records)?;
+        }
+        self.write_header(header, mode, size)?;
+        if typeflag == b'0' && !entry.data.is_empty() {
+            self.write_blocked(entry.data)?;
+        }
+        Ok(())
+    }
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.write_trailer()?;
+        self.output.flush()
+    }
+}
+
+/// Writes tar archives compressed with gzip
+struct TarGzWriter<W: Write>(TarWriter<GzEncoder<W>>);
+impl<W: Write> ArchiveWriter for TarGzWriter<W> {
+    fn write_entry(&mut self, entry: &ArchiveEntry) -> io::Result<()> {
+        self.0.write_entry(entry)
+    }
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.write_trailer()?;
+        self.0.output.try_finish()?;
+        self.0.output.get_mut().flush()
+    }
+}
+
+/// Writes zip archives the way git does: entries have an extended timestamp, executables and
+/// symlinks their unix mode, and the archive the commit as comment
+struct ZipWriter<W: Write> {
+    output: W,
+    offset: u64,
+    /// Central directory, written at the end
+    directory: Vec<u8>,
+    entries: usize,
+    time: u32,
+    dos_date: u16,
+    dos_time: u16,
+    commit: Option<String>,
+}
+impl<W: Write> ZipWriter<W> {
+    /// Starts an archive whose entries are dated `time`, in UTC for their MS-DOS date and time
+    fn new(output: W, time: u64, commit: Option<&str>) -> Self {
+        let days = (time / 86400) as i64;
+        let seconds = time % 86400;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            output,
+            offset: 0,
+            directory: Vec::new(),
+            entries: 0,
+            time: time as u32,
+            dos_date: (day as i64 + month as i64 * 32 + (year - 1980) * 512) as u16,
+            dos_time: (seconds % 60 / 2 + seconds / 60 % 60 * 32 + seconds / 3600 * 2048) as u16,
+            commit: commit.map(str::to_owned),
+        }
+    }
+}
+impl<W: Write> ArchiveWriter for ZipWriter<W> {
+    fn write_entry(&mut self, entry: &ArchiveEntry) -> io::Result<()> {
+        let path = entry.path.as_bytes();
+        if path.len() > 0xffff {
+            return Err(io::Error::other(format!(
+                "path too long ({} chars, SHA1: {}): {}",
+                path.len(),
+                entry.hash,
+                entry.path
+            )));
+        }
+        let flags = if entry.path.is_ascii() { 0 } else { ZIP_UTF8 };
+        let (data, external, creator, text): (&[u8], u32, u16, bool) = match entry.mode & 0o170000 {
+            0o040000 | 0o160000 => (&[], 16, 0, false),
+            0o120000 => (
+                entry.data,
+                (entry.mode | 0o777) << 16,
+                0x0317,
+                !entry.binary,
+            ),
+            0o100000 if entry.mode & 0o111 != 0 => {
+                (entry.data, entry.mode << 16, 0x0317, !entry.binary)
+            }
+            0o100000 => (entry.data, 0, 0, !entry.binary),
+            _ => return Err(unsupported_mode(entry)),
+        };
+        if data.len() > u32::MAX as usize || self.offset > u32::MAX as u64 {
+            return Err(io::Error::other(format!(
+                "{}: too large for a zip archive",
+                entry.path
+            )));
+        }
+        let mut crc = Crc::new();
+        crc.update(data);
+        // Like git, content that does not shrink is stored as is
+        let mut method = ZIP_STORE;
+        let mut compressed = data.to_vec();
+        if entry.mode & 0o170000 == 0o100000 && !data.is_empty() {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            let deflated = encoder.finish()?;
+            if deflated.len() < data.len() {
+                method = ZIP_DEFLATE;
+                compressed = deflated;
+            }
+        }
+        let mut extra = Vec::new();
+        extra.extend(0x5455u16.to_le_bytes());
+        extra.extend(5u16.to_le_bytes());
+        extra.push(1);
+        extra.extend(self.time.to_le_bytes());
+        // Fields shared by the local header and the central directory, from the version needed
+        let mut fields = Vec::new();
+        fields.extend(10u16.to_le_bytes());
+        fields.extend(flags.to_le_bytes());
+        fields.extend(method.to_le_bytes());
+        fields.extend(self.dos_time.to_le_bytes());
+        fields.extend(self.dos_date.to_le_bytes());
+        fields.extend(crc.sum().to_le_bytes());
+        fields.extend((compressed.len() as u32).to_le_bytes());
+        fields.extend((data.len() as u32).to_le_bytes());
+        fields.extend((path.len() as u16).to_le_bytes());
+        fields.extend((extra.len() as u16).to_le_bytes());
+
+        let mut local = 0x04034b50u32.to_le_bytes().to_vec();
+        local.extend(&fields);
+        local.extend(path);
+        local.extend(&extra);
+        local.extend(&compressed);
+        self.output.write_all(&local)?;
+
+        self.directory.extend(0x02014b50u32.to_le_bytes());
+        self.directory.extend(creator.to_le_bytes());
+        self.directory.extend(&fields);
+        // Comment length and disk number
+        self.directory.extend([0; 4]);
+        self.directory.extend(u16::from(text).to_le_bytes());
+        self.directory.extend(external.to_le_bytes());
+        self.directory.extend((self.offset as u32).to_le_bytes());
+        self.directory.extend(path);
+        self.directory.extend(&extra);
+        self.entries += 1;
+        self.offset += local.len() as u64;
+        Ok(())
+    }
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        if self.entries > 0xffff || self.offset > u32::MAX as u64 {
+            return Err(io::Error::other("too many entries for a zip archive"));
+        }
+        let comment = self.commit.take().unwrap_or_default();
+        let mut trailer = 0x06054b50u32.to_le_bytes().to_vec();
+        // Disk numbers
+        trailer.extend([0; 4]);
+        trailer.extend((self.entries as u16).to_le_bytes());
+        trailer.extend((self.entries as u16).to_le_bytes());
+        trailer.extend((self.directory.len() as u32).to_le_bytes());
+        trailer.extend((self.offset as u32).to_le_bytes());
+        trailer.extend((comment.len() as u16).to_le_bytes());
+        trailer.extend(comment.as_bytes());
+        self.output.write_all(&self.directory)?;
+        self.output.write_all(&trailer)?;
+        self.output.flush()
+    }
+}
+
+/// Expands the `$Format:<format>$` placeholders of `export-subst` files with the commit
+fn substitute(commit: &CommitInfo, data: &[u8]) -> Vec<u8> {
+    let mut substituted = Vec::with_capacity(data.len());
+    let mut rest = data;
+    while let Some(start) = rest.windows(8).position(|window| window == b"$Format:") {
+        let Some(end) = rest[start + 8..].iter().position(|&byte| byte == b'$') else {
+            break;
+        };
+        let format = PrettyFormat::Format {
+            template: String::from_utf8_lossy(&rest[start + 8..start + 8 + end]).into_owned(),
+            terminated: false,
+        };
+        substituted.extend(&rest[..start]);
+        substituted.extend(format_commit(commit, &format, DateMode::Default, None).as_bytes());
+        rest = &rest[start + 8 + end + 1..];
+    }
+    substituted.extend(rest);
+    substituted
+}
+
+/// Walks trees in the order git archives them, skipping `export-ignore` paths and writing
+/// directories only once something inside them is
+struct ArchiveWalk<'a> {
+    repo: &'a Repository,
+    commit: Option<&'a CommitInfo>,
+    pathspec: &'a Pathspec,
+    options: &'a ArchiveOptions,
+    attributes: Attributes,
+    writer: Box<dyn ArchiveWriter + 'a>,
+    /// Directories entered whose entry is yet to be written, as `(path, hash)`
+    pending: Vec<(String, String)>,
+}
+impl ArchiveWalk<'_> {
+    fn write(&mut self, entry: &ArchiveEntry) -> Result<(), String> {
+        if self.options.verbose {
+            eprintln!("{}", entry.path);
+        }
+        self.writer.write_entry(entry).map_err(|e| e.to_string())
+    }
+    fn ignored(&mut self, path: &str) -> bool {
+        self.attributes.get(path, "export-ignore") == Some(AttrValue::Set)
+    }
+    /// Writes the entries of the tree at `base`, every one of them if `all` rather than those
+    /// matching the pathspec
+    fn walk(&mut self, tree: &str, base: &str, all: bool) -> Result<(), String> {
+        for node in TreeObject::read_from_sha(self.repo, tree)?.entries {
+            let path = format!("{}{}", base, node.path);
+            let mode = u32::from_str_radix(&node.mode, 8)
+                .map_err(|_| format!("Invalid mode {} for {}", node.mode, path))?;
+            if mode & 0o170000 == 0o040000 {
+                let directory = format!("{}/", path);
+                let matched = all || self.pathspec.matches(&path);
+                if (!matched && !self.pathspec.may_match_in(&path)) || self.ignored(&directory) {
+                    continue;
+                }
+                self.pending.push((directory.clone(), node.hash.clone()));
+                self.walk(&node.hash, &directory, matched)?;
+                // Nothing was written from it
+                if self
+                    .pending
+                    .last()
+                    .is_some_and(|(pending, _)| *pending == directory)
+                {
+                    self.pending.pop();
+                }
+                continue;
+            }
+            let is_submodule = mode & 0o170000 == 0o160000;
+            let checked = match is_submodule {
+                true => format!("{}/", path),
+                false => path.clone(),
+            };
+            if !(all || self.pathspec.matches(&path)) || self.ignored(&checked) {
+                continue;
+            }
+            for (directory, hash) in std::mem::take(&mut self.pending) {
+                self.write(&ArchiveEntry {
+                    path: &format!("{}{}", self.options.prefix, directory),
+                    mode: 0o040000,
+                    hash: &hash,
+                    data: &[],
+                    binary: false,
+                })?;
+            }
+            let mut data = Vec::new();
+            let mut binary = false;
+            if !is_submodule {
+                data = Object::read_data(self.repo, &node.hash)?.1;
+            }
+            if mode & 0o170000 == 0o100000 {
+                data = Conversion::new(self.repo, &mut self.attributes, &path).to_worktree(data)?;
+                if let Some(commit) = self.commit {
+                    if self.attributes.get(&path, "export-subst") == Some(AttrValue::Set) {
+                        data = substitute(commit, &data);
+                    }
+                }
+                binary = self.attributes.get(&path, "diff") == Some(AttrValue::Unset)
+                    || is_binary(&data);
+            }
+            self.write(&ArchiveEntry {
+                path: &format!("{}{}", self.options.prefix, checked),
+                mode,
+                hash: &node.hash,
+                data: &data,
+                binary,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes an archive of `tree` to `output`, straight from the object store.
+///
+/// Paths are the ones matching `pathspec`, relative to the tree, without those given the
+/// `export-ignore` attribute. Like a checkout, files go through the filters and end of line
+/// conversion their attributes ask for, and when the tree is the one of `commit`, entries are
+/// dated by it, `export-subst` files have their `$Format:<format>$` placeholders expanded and
+/// the archive records its hash.
+pub fn write_archive<'a>(
+    repo: &'a Repository,
+    tree: &str,
+    commit: Option<&'a CommitInfo>,
+    pathspec: &'a Pathspec,
+    options: &'a ArchiveOptions,
+    output: impl Write + 'a,
+) -> Result<(), String> {
+    let time = commit
+        .map_or_else(now, |commit| commit.committer.time)
+        .max(0) as u64;
+    let hash = commit.map(|commit| commit.hash.as_str());
+    let writer: Box<dyn ArchiveWriter + 'a> = match options.format {
+        ArchiveFormat::Tar => {
+            Box::new(TarWriter::new(output, time, hash).map_err(|e| e.to_string())?)
+        }
+        ArchiveFormat::TarGz => {
+            // Like `gzip -n`, the gzip header has no name nor time
+            let output = GzBuilder::new()
+                .operating_system(3)
+                .write(output, Compression::default());
+            Box::new(TarGzWriter(
+                TarWriter::new(output, time, hash).map_err(|e| e.to_string())?,
+            ))
+        }
+        ArchiveFormat::Zip => Box::new(ZipWriter::new(output, time, hash)),
+    };
+    let attributes = match options.worktree_attributes {
+        true => Attributes::new(repo),
+        false => Attributes::from_tree(repo, tree)?,
+    };
+    let mut walk = ArchiveWalk {
+        repo,
+        commit,
+        pathspec,
+        options,
+        attributes,
+        writer,
+        pending: Vec::new(),
+    };
+    if options.prefix.ends_with('/') {
+        let prefix = options.prefix.trim_end_matches('/').to_owned() + "/";
+        walk.write(&ArchiveEntry {
+            path: &prefix,
+            mode: 0o040777,
+            hash: tree,
+            data: &[],
+            binary: false,
+        })?;
+    }
+    walk.walk(tree, "", pathspec.is_empty())?;
+    walk.writer.finish().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tar_headers() {
+        let mut records = Vec::new();
+        pax_record(&mut records, "comment", &[b'0'; 40]);
+        assert_eq!(&records[..11], b"52 comment=");
+        assert_eq!(records.len(), 52);
+
+        let long = format!("{}/{}", "d".repeat(60), "f".repeat(60));
+        assert_eq!(path_prefix_len(long.as_bytes(), 155), 60);
+        assert_eq!(path_prefix_len("f".repeat(120).as_bytes(), 155), 0);
+
+        let mut tar = TarWriter::new(Vec::new(), 0, Some(&"0".repeat(40))).unwrap();
+        tar.write_entry(&ArchiveEntry {
+            path: "run.sh",
+            mode: 0o100755,
+            hash: "",
+            data: b"#!/bin/sh\n",
+            binary: false,
+        })
+        .unwrap();
+        let header = &tar.output[2 * TAR_BLOCK..3 * TAR_BLOCK];
+        assert_eq!(&header[..7], b"run.sh\0");
+        assert_eq!(&header[100..108], b"0000775\0");
+        assert_eq!(header[156], b'0');
+        Box::new(tar).finish().unwrap();
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(
+            ArchiveFormat::from_path("a.tar.gz"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::from_path("a.zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_path(".tar"), None);
+        assert!(ArchiveFormat::from_name("rar").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::{
+    config::user_file, create_path, ignore::PathPattern, Index, Object, Repository, TreeObject,
+};
+
+/// Attributes that `[attr]binary` stands for, a macro git always defines
+const BINARY_MACRO: &str = "-diff -merge -text";
+
+/// State of an attribute for a path, `None` standing for unspecified
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttrValue {
+    /// `name`
+    Set,
+    /// `-name`
+    Unset,
+    /// `name=value`
+    Value(String),
+}
+
+/// Attributes given on a line, `None` standing for `!name`
+type AttrStates = Vec<(String, Option<AttrValue>)>;
+
+fn parse_states<'a>(fields: impl Iterator<Item = &'a str>) -> AttrStates {
+    fields
+        .map(|field| match field.as_bytes()[0] {
+            b'-' => (field[1..].to_owned(), Some(AttrValue::Unset)),
+            b'!' => (field[1..].to_owned(), None),
+            _ => match field.split_once('=') {
+                Some((name, value)) => (name.to_owned(), Some(AttrValue::Value(value.to_owned()))),
+                None => (field.to_owned(), Some(AttrValue::Set)),
+            },
+        })
+        .collect()
+}
+
+/// A line of a gitattributes file: a pattern and the attributes it gives
+#[derive(Clone, Debug)]
+struct AttrLine {
+    pattern: PathPattern,
+    states: AttrStates,
+}
+
+/// Whether `name` is a valid attribute name: letters, digits, `-`, `_` and `.`, not starting
+/// with `-`
+pub fn is_valid_attr_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Attributes of paths, from `info/attributes`, the `.gitattributes` files of their directories
+/// and `core.attributesFile`, in that order of precedence.
+///
+/// Like git, the last line of a file mentioning an attribute decides its state, and macros set
+/// on a path (`binary` or those defined by `[attr]<name>` lines) give their own attributes.
+#[derive(Clone, Debug, Default)]
+pub struct Attributes {
+    worktree: PathBuf,
+    /// Content of the `.gitattributes` files of the index by directory, read instead of the
+    /// worktree files when set
+    index_files: Option<HashMap<String, Vec<u8>>>,
+    global: Vec<AttrLine>,
+    info: Vec<AttrLine>,
+    /// Lines of the `.gitattributes` files read so far, by directory
+    directories: HashMap<String, Vec<AttrLine>>,
+    macros: HashMap<String, AttrStates>,
+    /// Attribute names in the order they were first read, which [Self::all] follows
+    names: Vec<String>,
+}
+impl Attributes {
+    /// Attributes from the worktree `.gitattributes` files
+    pub fn new(repo: &Repository) -> Self {
+        Self::load(repo, None)
+    }
+    /// Attributes from the `.gitattributes` files of the index, as `check-attr --cached` reads
+    pub fn from_index(repo: &Repository, index: &Index) -> Result<Self, String> {
+        let entries = index.entries.iter().filter(|entry| entry.stage == 0);
+        Self::from_blobs(
+            repo,
+            entries.map(|entry| (entry.path.as_str(), entry.hash.as_str())),
+        )
+    }
+    /// Attributes from the `.gitattributes` files of a tree, as `archive` reads
+    pub fn from_tree(repo: &Repository, tree: &str) -> Result<Self, String> {
+        let nodes = TreeObject::read_from_sha(repo, tree)?.flatten(repo)?;
+        Self::from_blobs(
+            repo,
+            nodes
+                .iter()
+                .map(|node| (node.path.as_str(), node.hash.as_str())),
+        )
+    }
+    /// Attributes from the `.gitattributes` blobs among `(path, hash)` entries
+    fn from_blobs<'a>(
+        repo: &Repository,
+        entries: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, String> {
+        let mut files = HashMap::new();
+        for (path, hash) in entries {
+            let directory = match path.strip_suffix(".gitattributes") {
+                Some("") => "",
+                Some(directory) => match directory.strip_suffix('/') {
+                    Some(directory) => directory,
+                    None => continue,
+                },
+                None => continue,
+            };
+            files.insert(directory.to_owned(), Object::read_data(repo, hash)?.1);
+        }
+        Ok(Self::load(repo, Some(files)))
+    }
+    fn load(repo: &Repository, index_files: Option<HashMap<String, Vec<u8>>>) -> Self {
+        let mut attributes = Self {
+            worktree: repo.worktree.clone(),
+            index_files,
+            ..Default::default()
+        };
+        attributes.parse("[attr]binary ".to_owned() + BINARY_MACRO, "", "");
+        let configured = repo.config.core.attributesfile.as_deref();
+        if let Some(path) = user_file(repo, configured, "attributes") {
+            if let Ok(content) = fs::read_to_string(&path) {
+                attributes.global = attributes.parse(content, "", &path.to_string_lossy());
+            }
+        }
+        attributes.directory_lines("");
+        let path = create_path(
+            &repo.gitdir,
+            vec![String::from("info"), String::from("attributes")],
+        );
+        if let Ok(content) = fs::read_to_string(path) {
+            attributes.info = attributes.parse(content, "", "info/attributes");
+        }
+        attributes
+    }
+    /// Parses the lines of a file, defining its macros, `origin` naming it in warnings.
+    ///
+    /// Like git, macros can only be defined at the top level, not in subdirectories.
+    fn parse(&mut self, content: String, base: &str, origin: &str) -> Vec<AttrLine> {
+        let mut lines = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next().filter(|pattern| !pattern.starts_with('#')) else {
+                continue;
+            };
+            if let Some(name) = pattern.strip_prefix("[attr]") {
+                if !base.is_empty() {
+                    eprintln!("{} not allowed: {}:{}", line.trim(), origin, number + 1);
+                    continue;
+                }
+                self.register(name);
+                let states = parse_states(fields);
+                states.iter().for_each(|(name, _)| self.register(name));
+                self.macros.insert(name.to_owned(), states);
+            } else if pattern.starts_with('!') {
+                eprintln!("Negative patterns are ignored in git attributes");
+                eprintln!("Use '\\!' for literal leading exclamation.");
+            } else if let Some(pattern) = PathPattern::parse(pattern, base) {
+                let states = parse_states(fields);
+                states.iter().for_each(|(name, _)| self.register(name));
+                lines.push(AttrLine { pattern, states });
+            }
+        }
+        lines
+    }
+    fn register(&mut self, name: &str) {
+        if !self.names.iter().any(|known| known == name) {
+            self.names.push(name.to_owned());
+        }
+    }
+    fn directory_lines(&mut self, directory: &str) {
+        if self.directories.contains_key(directory) {
+            return;
+        }
+        let content = match &self.index_files {
+            Some(files) => files.get(directory).cloned(),
+            None => fs::read(create_path(
+                &self.worktree,
+                vec![directory.to_owned(), String::from(".gitattributes")],
+            ))
+            .ok(),
+        };
+        let origin = match directory {
+            "" => String::from(".gitattributes"),
+            directory => format!("{}/.gitattributes", directory),
+        };
+        let lines = content
+            .map(|content| {
+                self.parse(
+                    String::from_utf8_lossy(&content).into_owned(),
+                    directory,
+                    &origin,
+                )
+            })
+            .unwrap_or_default();
+        self.directories.insert(directory.to_owned(), lines);
+    }
+    /// Gives the attributes of `states` that are still undecided, from the last one, expanding
+    /// the macros set along the way
+    fn fill(&self, decided: &mut HashMap<String, Option<AttrValue>>, states: &AttrStates) {
+        for (name, value) in states.iter().rev() {
+            if decided.contains_key(name) {
+                continue;
+            }
+            decided.insert(name.clone(), value.clone());
+            if *value == Some(AttrValue::Set) {
+                if let Some(macro_states) = self.macros.get(name) {
+                    self.fill(decided, macro_states);
+                }
+            }
+        }
+    }
+    fn states(&mut self, path: &str) -> HashMap<String, Option<AttrValue>> {
+        // A trailing `/` marks a directory, which patterns like `dir/` match
+        let (path, is_dir) = match path.strip_suffix('/') {
+            Some(path) => (path, true),
+            None => (path, false),
+        };
+        let mut directories = Vec::new();
+        let mut directory = path;
+        while !directory.is_empty() {
+            directory = directory.rsplit_once('/').map_or("", |(parent, _)| parent);
+            self.directory_lines(directory);
+            directories.push(directory);
+        }
+        let mut decided = HashMap::new();
+        let sources = [&self.info]
+            .into_iter()
+            .chain(
+                directories
+                    .iter()
+                    .map(|directory| &self.directories[*directory]),
+            )
+            .chain([&self.global]);
+        for lines in sources {
+            for line in lines.iter().rev() {
+                if line.pattern.matches(path, is_dir) {
+                    self.fill(&mut decided, &line.states);
+                }
+            }
+        }
+        decided
+    }
+    /// State of the attribute `name` for the path, relative to the worktree root and ending with
+    /// `/` for a directory
+    pub fn get(&mut self, path: &str, name: &str) -> Option<AttrValue> {
+        self.states(path).remove(name).flatten()
+    }
+    /// Attributes that are set, unset or given a value for the path, in the order git lists them
+    pub fn all(&mut self, path: &str) -> Vec<(String, AttrValue)> {
+        let mut states = self.states(path);
+        self.names
+            .iter()
+            .filter_map(|name| Some((name.clone(), states.remove(name).flatten()?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_attribute_states() {
+        let mut attributes = Attributes::default();
+        attributes.parse(String::from("[attr]binary -diff -merge -text"), "", "");
+        attributes.parse(String::from("[attr]crlf-text text eol=crlf"), "", "");
+        let lines = attributes.parse(
+            String::from("*.txt text -diff\n*.bin binary\n*.c crlf-text\nsub/** !text\n"),
+            "",
+            "",
+        );
+        attributes.directories.insert(String::new(), lines);
+        let nested = attributes.parse(String::from("*.txt diff=plain"), "sub", "");
+        attributes.directories.insert(String::from("sub"), nested);
+
+        assert_eq!(attributes.get("a.txt", "text"), Some(AttrValue::Set));
+        assert_eq!(attributes.get("sub/a.txt", "text"), None);
+        assert_eq!(
+            attributes.get("sub/a.txt", "diff"),
+            Some(AttrValue::Value(String::from("plain")))
+        );
+        assert_eq!(
+            attributes.all("x.bin"),
+            vec![
+                (String::from("binary"), AttrValue::Set),
+                (String::from("diff"), AttrValue::Unset),
+                (String::from("merge"), AttrValue::Unset),
+                (String::from("text"), AttrValue::Unset),
+            ]
+        );
+        assert_eq!(
+            attributes.get("x.c", "eol"),
+            Some(AttrValue::Value(String::from("crlf")))
+        );
+    }
+}
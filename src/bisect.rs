@@ -0,0 +1,631 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::{
+    create_path, delete_ref, head_branch, helper::shell_unquote, is_ancestor, list_refs,
+    merge_bases, peel_to_commit, peel_to_tree, read_ref, resolve_revision_args, rev_parse,
+    shell_quote, switch_tree, update_ref_no_deref, update_symbolic_ref, CommitInfo, Repository,
+    RevWalk, RevWalkOptions,
+};
+
+const BISECT_REFS: &str = "refs/bisect/";
+const BISECT_FILES: [&str; 6] = [
+    "BISECT_START",
+    "BISECT_TERMS",
+    "BISECT_NAMES",
+    "BISECT_LOG",
+    "BISECT_EXPECTED_REV",
+    "BISECT_ANCESTORS_OK",
+];
+/// Modulo of the pseudo random numbers picking the commit to test next to a skipped one
+const PRN_MODULO: i64 = 32768;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BisectTerm {
+    Good,
+    Bad,
+    Skip,
+}
+impl BisectTerm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Good => "good",
+            Self::Bad => "bad",
+            Self::Skip => "skip",
+        }
+    }
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "good" => Ok(Self::Good),
+            "bad" => Ok(Self::Bad),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!("Invalid bisect term, {}", name)),
+        }
+    }
+    /// How `bisect run` marks a commit from the exit status of the command: 0 is good, 125 is
+    /// skip and anything else below 128 is bad. `None` means bisecting cannot go on.
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Good),
+            125 => Some(Self::Skip),
+            1..=127 => Some(Self::Bad),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BisectOutcome {
+    /// Good or bad commits are still missing, holds the status to show
+    Waiting(String),
+    /// `commit` was checked out to be tested
+    Testing {
+        commit: String,
+        /// Revisions left to test after this one
+        remaining: usize,
+        /// Roughly how many more steps are needed
+        steps: usize,
+    },
+    /// A merge base of the good and bad commits was checked out, it has to be tested first
+    MergeBase(String),
+    /// The first bad commit was found
+    Found(String),
+    /// Only skipped commits are left, the first bad commit could be any of them
+    OnlySkipped(Vec<String>),
+}
+
+fn state_path(repo: &Repository, name: &str) -> PathBuf {
+    create_path(&repo.gitdir, vec![name.to_owned()])
+}
+
+fn read_state(repo: &Repository, name: &str) -> Option<String> {
+    fs::read_to_string(state_path(repo, name))
+        .ok()
+        .map(|content| content.trim_end().to_owned())
+}
+
+fn write_state(repo: &Repository, name: &str, content: &str) -> Result<(), String> {
+    fs::write(state_path(repo, name), content)
+        .map_err(|e| format!("Error writing bisect state {}: {}", name, e))
+}
+
+fn append_log(repo: &Repository, line: &str) -> Result<(), String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(state_path(repo, "BISECT_LOG"))
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Error writing bisect log: {}", e))
+}
+
+pub fn bisect_in_progress(repo: &Repository) -> bool {
+    read_state(repo, "BISECT_START").is_some_and(|start| !start.is_empty())
+}
+
+fn commit_line(repo: &Repository, commit: &str) -> Result<String, String> {
+    Ok(format!(
+        "[{}] {}",
+        commit,
+        CommitInfo::read(repo, commit)?.subject()
+    ))
+}
+
+fn resolve_commit(repo: &Repository, revision: &str) -> Result<String, String> {
+    peel_to_commit(repo, &rev_parse(repo, revision)?)
+        .map_err(|_| format!("Bad rev input: {}", revision))
+}
+
+/// The bad commit, then the good and skipped ones
+fn read_terms(repo: &Repository) -> (Option<String>, Vec<String>, Vec<String>) {
+    let mut bad = None;
+    let (mut good, mut skip) = (Vec::new(), Vec::new());
+    for (name, hash) in list_refs(repo, BISECT_REFS) {
+        let name = &name[BISECT_REFS.len()..];
+        if name == "bad" {
+            bad = Some(hash);
+        } else if name.starts_with("good-") {
+            good.push(hash);
+        } else if name.starts_with("skip-") {
+            skip.push(hash);
+        }
+    }
+    (bad, good, skip)
+}
+
+/// Records `commit` as `term`, the command is left out of the log when `log_command` is not set
+fn write_term(
+    repo: &Repository,
+    term: BisectTerm,
+    commit: &str,
+    log_command: bool,
+) -> Result<(), String> {
+    let name = match term {
+        BisectTerm::Bad => String::from("bad"),
+        _ => format!("{}-{}", term.name(), commit),
+    };
+    update_ref_no_deref(repo, &format!("{}{}", BISECT_REFS, name), commit)?;
+    append_log(
+        repo,
+        &format!("# {}: {}", term.name(), commit_line(repo, commit)?),
+    )?;
+    if log_command {
+        append_log(repo, &format!("git bisect {} {}", term.name(), commit))?;
+    }
+    Ok(())
+}
+
+fn checkout(repo: &Repository, commit: &str) -> Result<(), String> {
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    switch_tree(
+        repo,
+        &peel_to_tree(repo, &head)?,
+        &peel_to_tree(repo, commit)?,
+    )?;
+    update_ref_no_deref(repo, "HEAD", commit)
+}
+
+/// Checks out `commit` for testing
+fn checkout_expected(repo: &Repository, commit: &str) -> Result<(), String> {
+    write_state(repo, "BISECT_EXPECTED_REV", &format!("{}\n", commit))?;
+    checkout(repo, commit)
+}
+
+/// Checks out a branch name or a commit, leaving HEAD detached in the latter case
+fn checkout_revision(repo: &Repository, revision: &str) -> Result<(), String> {
+    let branch = format!("refs/heads/{}", revision);
+    match read_ref(repo, &branch) {
+        Some(commit) => {
+            checkout(repo, &commit)?;
+            update_symbolic_ref(repo, "HEAD", &branch)
+        }
+        None => checkout(repo, &resolve_commit(repo, revision)?),
+    }
+}
+
+fn clean_state(repo: &Repository) -> Result<(), String> {
+    for (name, _) in list_refs(repo, BISECT_REFS) {
+        delete_ref(repo, &name)?;
+    }
+    for name in BISECT_FILES {
+        let _ = fs::remove_file(state_path(repo, name));
+    }
+    Ok(())
+}
+
+/// Starts bisecting from the current HEAD. The first of `revisions` is the bad commit and the
+/// others good ones, bisecting goes on once both are known.
+///
+/// When already bisecting, the original HEAD is checked out again and the state is cleared first.
+pub fn bisect_start(repo: &Repository, revisions: &[String]) -> Result<BisectOutcome, String> {
+    let commits = revisions
+        .iter()
+        .map(|revision| resolve_commit(repo, revision))
+        .collect::<Result<Vec<String>, String>>()?;
+    let start = match read_state(repo, "BISECT_START").filter(|start| !start.is_empty()) {
+        Some(start) => {
+            checkout_revision(repo, &start)?;
+            start
+        }
+        None => match head_branch(repo) {
+            Some(branch) => branch
+                .strip_prefix("refs/heads/")
+                .unwrap_or(&branch)
+                .to_owned(),
+            None => read_ref(repo, "HEAD").ok_or("Bad HEAD - I need a HEAD")?,
+        },
+    };
+    clean_state(repo)?;
+    let started = (|| {
+        write_state(repo, "BISECT_START", &format!("{}\n", start))?;
+        write_state(repo, "BISECT_TERMS", "bad\ngood\n")?;
+        write_state(repo, "BISECT_NAMES", "\n")?;
+        for (i, commit) in commits.iter().enumerate() {
+            let term = match i {
+                0 => BisectTerm::Bad,
+                _ => BisectTerm::Good,
+            };
+            write_term(repo, term, commit, false)?;
+        }
+        let args = revisions
+            .iter()
+            .map(|revision| format!(" {}", shell_quote(revision)))
+            .collect::<String>();
+        append_log(repo, &format!("git bisect start{}", args))?;
+        bisect_next(repo)
+    })();
+    if started.is_err() {
+        let _ = clean_state(repo);
+    }
+    started
+}
+
+/// Marks `revisions` (HEAD when there are none) as good, bad or skipped, then checks out the
+/// next commit to test. Ranges like `a..b` can be given to skip many commits at once.
+pub fn bisect_mark(
+    repo: &Repository,
+    term: BisectTerm,
+    revisions: &[String],
+) -> Result<BisectOutcome, String> {
+    if !bisect_in_progress(repo) {
+        return Err(String::from("You need to start by \"rit bisect start\""));
+    }
+    if term == BisectTerm::Bad && revisions.len() > 1 {
+        return Err(String::from("'rit bisect bad' can take only one argument."));
+    }
+    let mut commits = Vec::new();
+    for revision in revisions {
+        if term == BisectTerm::Skip && revision.contains("..") {
+            let (include, exclude) = resolve_revision_args(repo, std::slice::from_ref(revision))?;
+            for commit in RevWalk::with_options(repo, &include, &exclude, Default::default())? {
+                commits.push(commit?.hash);
+            }
+        } else {
+            commits.push(resolve_commit(repo, revision)?);
+        }
+    }
+    if revisions.is_empty() {
+        commits.push(read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?);
+    }
+    for commit in commits {
+        write_term(repo, term, &commit, true)?;
+    }
+    bisect_next(repo)
+}
+
+/// Stops bisecting, checking out `revision` or else the HEAD bisecting started from.
+/// Returns false when not bisecting.
+pub fn bisect_reset(repo: &Repository, revision: Option<&str>) -> Result<bool, String> {
+    let Some(start) = read_state(repo, "BISECT_START") else {
+        return Ok(false);
+    };
+    checkout_revision(repo, revision.unwrap_or(&start))?;
+    clean_state(repo)?;
+    Ok(true)
+}
+
+/// The commands marking commits since bisecting started, as they can be replayed
+pub fn bisect_log(repo: &Repository) -> Result<String, String> {
+    match bisect_in_progress(repo) {
+        true => fs::read_to_string(state_path(repo, "BISECT_LOG"))
+            .map_err(|e| format!("Error reading bisect log: {}", e)),
+        false => Err(String::from("We are not bisecting.")),
+    }
+}
+
+/// Starts bisecting over from the `git bisect` commands of a log, see [bisect_log]
+pub fn bisect_replay(repo: &Repository, log: &str) -> Result<BisectOutcome, String> {
+    bisect_reset(repo, None)?;
+    for line in log.lines() {
+        let Some(command) = ["git bisect ", "git-bisect ", "rit bisect "]
+            .iter()
+            .find_map(|prefix| line.trim_start().strip_prefix(prefix))
+        else {
+            continue;
+        };
+        let (word, rest) = command
+            .trim()
+            .split_once(' ')
+            .unwrap_or((command.trim(), ""));
+        if word == "start" {
+            bisect_start(repo, &shell_unquote(rest)?)?;
+            continue;
+        }
+        let term = BisectTerm::from_name(word)
+            .map_err(|_| format!("Replay file contains rubbish (\"{}\")", word))?;
+        write_term(repo, term, &resolve_commit(repo, rest.trim())?, true)?;
+    }
+    bisect_next(repo)
+}
+
+/// Checks out the commit splitting the remaining ones in two, once both good and bad commits
+/// are known
+fn bisect_next(repo: &Repository) -> Result<BisectOutcome, String> {
+    let (bad, good, skip) = read_terms(repo);
+    let status = match (&bad, good.len()) {
+        (None, 0) => String::from("waiting for both good and bad commits"),
+        (Some(_), 0) => String::from("waiting for good commit(s), bad commit known"),
+        (None, 1) => String::from("waiting for bad commit, 1 good commit known"),
+        (None, count) => format!("waiting for bad commit, {} good commits known", count),
+        (Some(_), _) => String::new(),
+    };
+    let Some(bad) = bad.filter(|_| status.is_empty()) else {
+        append_log(repo, &format!("# status: {}", status))?;
+        return Ok(BisectOutcome::Waiting(format!("status: {}", status)));
+    };
+    if !state_path(repo, "BISECT_ANCESTORS_OK").exists() {
+        if let Some(merge_base) = check_merge_bases(repo, &bad, &good, &skip)? {
+            return Ok(BisectOutcome::MergeBase(merge_base));
+        }
+        write_state(repo, "BISECT_ANCESTORS_OK", "")?;
+    }
+
+    // Oldest first, like git
+    let mut candidates = Vec::new();
+    for commit in RevWalk::with_options(
+        repo,
+        std::slice::from_ref(&bad),
+        &good,
+        RevWalkOptions::default(),
+    )? {
+        candidates.push(commit?);
+    }
+    candidates.reverse();
+    let skip: HashSet<&str> = skip.iter().map(String::as_str).collect();
+    let (best, reaches) = find_bisection(&candidates, !skip.is_empty());
+
+    // A skipped commit is replaced by one that is not, somewhat away from it
+    let mut tried = Vec::new();
+    let mut filtered = Vec::new();
+    let mut next = None;
+    for &i in &best {
+        let hash = candidates[i].hash.as_str();
+        if skip.contains(hash) {
+            tried.push(hash.to_owned());
+        } else if tried.is_empty() {
+            next = Some(hash.to_owned());
+            break;
+        } else {
+            filtered.push(hash);
+        }
+    }
+    if next.is_none() && !filtered.is_empty() {
+        next = Some(skip_away(&filtered, &bad).to_owned());
+    }
+
+    let Some(next) = next else {
+        if tried.is_empty() {
+            return Err(format!("{} was both good and bad", bad));
+        }
+        log_only_skipped(repo, &candidates)?;
+        return Ok(BisectOutcome::OnlySkipped(tried));
+    };
+    if next == bad {
+        if !tried.is_empty() {
+            log_only_skipped(repo, &candidates)?;
+            tried.push(bad);
+            return Ok(BisectOutcome::OnlySkipped(tried));
+        }
+        append_log(
+            repo,
+            &format!("# first bad commit: {}", commit_line(repo, &bad)?),
+        )?;
+        return Ok(BisectOutcome::Found(bad));
+    }
+    checkout_expected(repo, &next)?;
+    Ok(BisectOutcome::Testing {
+        commit: next,
+        remaining: candidates.len() - reaches - 1,
+        steps: estimate_steps(candidates.len()),
+    })
+}
+
+fn log_only_skipped(repo: &Repository, candidates: &[CommitInfo]) -> Result<(), String> {
+    append_log(repo, "# only skipped commits left to test")?;
+    for commit in candidates.iter().rev() {
+        append_log(
+            repo,
+            &format!(
+                "# possible first bad commit: {}",
+                commit_line(repo, &commit.hash)?
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// When some good commits are not ancestors of the bad one, their merge bases with it have to
+/// be good for bisecting to make sense. Returns the merge base checked out to be tested, if any.
+fn check_merge_bases(
+    repo: &Repository,
+    bad: &str,
+    good: &[String],
+    skip: &[String],
+) -> Result<Option<String>, String> {
+    let mut not_ancestors = false;
+    for commit in good {
+        not_ancestors |= !is_ancestor(repo, commit, bad)?;
+    }
+    if !not_ancestors {
+        return Ok(None);
+    }
+    let mut bases: Vec<String> = Vec::new();
+    for commit in good {
+        for base in merge_bases(repo, bad, commit)? {
+            if !bases.contains(&base) {
+                bases.push(base);
+            }
+        }
+    }
+    let mut independent = Vec::new();
+    for base in &bases {
+        let mut redundant = false;
+        for other in bases.iter().filter(|other| *other != base) {
+            redundant |= is_ancestor(repo, base, other)?;
+        }
+        if !redundant {
+            independent.push(base.clone());
+        }
+    }
+    for base in independent {
+        if base == bad {
+            if read_state(repo, "BISECT_EXPECTED_REV").as_deref() == Some(bad) {
+                return Err(format!(
+                    "The merge base {} is bad.\nThis means the bug has been fixed between {} and [{}].",
+                    bad,
+                    bad,
+                    good.join(" ")
+                ));
+            }
+            return Err(String::from(
+                "Some good revs are not ancestors of the bad rev.\nrit bisect cannot work properly in this case.\nMaybe you mistook good and bad revs?",
+            ));
+        } else if good.contains(&base) {
+            continue;
+        } else if skip.contains(&base) {
+            eprintln!(
+                "Warning: the merge base between {} and [{}] must be skipped.\nSo we cannot be sure the first bad commit is between {} and {}.\nWe continue anyway.",
+                bad,
+                good.join(" "),
+                base,
+                bad
+            );
+        } else {
+            checkout_expected(repo, &base)?;
+            return Ok(Some(base));
+        }
+    }
+    Ok(None)
+}
+
+/// The commits to test, best first, with how many commits the best one reaches.
+///
+/// Like git, each commit is weighted by the number of candidates it reaches and the best one
+/// splits them the most evenly. Unless `find_all` is set, the first commit found to be about
+/// halfway is taken right away, otherwise every commit is returned by how well it splits them.
+fn find_bisection(candidates: &[CommitInfo], find_all: bool) -> (Vec<usize>, usize) {
+    let count = candidates.len();
+    let index: HashMap<&str, usize> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, commit)| (commit.hash.as_str(), i))
+        .collect();
+    let parents: Vec<Vec<usize>> = candidates
+        .iter()
+        .map(|commit| {
+            commit
+                .parents
+                .iter()
+                .filter_map(|parent| index.get(parent.as_str()).copied())
+                .collect()
+        })
+        .collect();
+    let halfway = |weight: usize| (2 * weight).abs_diff(count) <= 1;
+    let mut weights: Vec<Option<usize>> = parents
+        .iter()
+        .map(|parents| parents.is_empty().then_some(1))
+        .collect();
+    let mut counted = weights.iter().flatten().count();
+
+    // Merges reach commits by many paths, they are counted by walking everything they reach
+    for i in 0..count {
+        if parents[i].len() < 2 {
+            continue;
+        }
+        let mut reached = HashSet::from([i]);
+        let mut queue = VecDeque::from([i]);
+        while let Some(j) = queue.pop_front() {
+            for &parent in &parents[j] {
+                if reached.insert(parent) {
+                    queue.push_back(parent);
+                }
+            }
+        }
+        weights[i] = Some(reached.len());
+        if !find_all && halfway(reached.len()) {
+            return (vec![i], reached.len());
+        }
+        counted += 1;
+    }
+    // Any other commit reaches one more commit than its parent
+    while counted < count {
+        for i in 0..count {
+            if weights[i].is_some() {
+                continue;
+            }
+            let Some(weight) = weights[parents[i][0]] else {
+                continue;
+            };
+            weights[i] = Some(weight + 1);
+            counted += 1;
+            if !find_all && halfway(weight + 1) {
+                return (vec![i], weight + 1);
+            }
+        }
+    }
+
+    let weights: Vec<usize> = weights.into_iter().flatten().collect();
+    let distance = |i: usize| weights[i].min(count - weights[i]);
+    if find_all {
+        let mut best: Vec<usize> = (0..count).collect();
+        best.sort_by(|&a, &b| {
+            distance(b)
+                .cmp(&distance(a))
+                .then_with(|| candidates[a].hash.cmp(&candidates[b].hash))
+        });
+        let reaches = best.first().map_or(0, |&i| weights[i]);
+        return (best, reaches);
+    }
+    // The first of the best ones, the oldest
+    let mut best: Option<usize> = None;
+    for i in 0..count {
+        if best.is_none_or(|best| distance(i) > distance(best)) {
+            best = Some(i);
+        }
+    }
+    match best {
+        Some(best) => (vec![best], weights[best]),
+        None => (Vec::new(), 0),
+    }
+}
+
+/// Picks a commit among the ones that are not skipped, the same way as git: with a pseudo random
+/// number depending on their count, favoring the ones splitting the remaining commits the best
+fn skip_away<'a>(commits: &[&'a str], bad: &str) -> &'a str {
+    let count = commits.len() as i64;
+    let prn =
+        (count as u32).wrapping_mul(1103515245).wrapping_add(12345) / 65536 % PRN_MODULO as u32;
+    let prn = prn as i64;
+    let index = (count * prn / PRN_MODULO) * sqrti(prn) / sqrti(PRN_MODULO);
+    match commits.get(index as usize) {
+        Some(&commit) if commit != bad => commit,
+        Some(_) if index > 0 => commits[index as usize - 1],
+        _ => commits[0],
+    }
+}
+
+/// Integer square root computed with floats like git, so that the same commits are picked
+fn sqrti(value: i64) -> i64 {
+    if value == 0 {
+        return 0;
+    }
+    let value = value as f32;
+    let mut x = value;
+    loop {
+        let y = (x + value / x) / 2.0;
+        let delta = (y - x).abs();
+        x = y;
+        if delta < 0.5 {
+            return x as i64;
+        }
+    }
+}
+
+fn estimate_steps(count: usize) -> usize {
+    if count < 3 {
+        return 0;
+    }
+    let n = count.ilog2() as usize;
+    let e = 1 << n;
+    match e < 3 * (count - e) {
+        true => n,
+        false => n - 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_estimate_steps() {
+        let steps: Vec<usize> = [1, 2, 3, 4, 5, 6, 7, 8, 14, 1000]
+            .into_iter()
+            .map(estimate_steps)
+            .collect();
+        assert_eq!(steps, [0, 0, 1, 1, 1, 2, 2, 2, 3, 9]);
+    }
+}
@@ -0,0 +1,819 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    mem::take,
+    rc::Rc,
+};
+
+use crate::{
+    clean_file, diff, diff_trees, peel_to_commit, peel_to_tree, pretty::now, read_ref,
+    rename_source, rev_parse, split_lines, CommitInfo, DiffHunk, Index, Mailmap, Object,
+    ObjectHeaders, ObjectTypes, Repository, Signature, TreeObject,
+};
+
+/// Hash given to the uncommitted changes of the worktree
+pub const NULL_COMMIT: &str = "0000000000000000000000000000000000000000";
+/// Minimum score of a block of moved lines when none is given to `-M`
+pub const DEFAULT_MOVE_SCORE: usize = 20;
+/// Minimum score of a block of copied lines when none is given to `-C`
+pub const DEFAULT_COPY_SCORE: usize = 40;
+
+#[derive(Clone, Debug)]
+pub struct BlameOptions {
+    /// `-L` ranges, `<start>,<end>` where the end may also be `+<count>` or `-<count>` lines
+    /// from the start, either side defaulting to the start or end of the file when left out
+    pub ranges: Vec<String>,
+    /// Ignore whitespace when comparing lines
+    pub ignore_whitespace: bool,
+    /// Look for lines moved within the file, giving the minimum score of a moved block
+    pub move_score: Option<usize>,
+    /// How hard to look for lines copied from other files: files changed in the same commit
+    /// (1), every file of the parent when the file was created in the commit (2) or every file
+    /// of the parent (3)
+    pub copy_level: usize,
+    /// Minimum score of a block of lines copied from another file
+    pub copy_score: usize,
+}
+impl Default for BlameOptions {
+    fn default() -> Self {
+        Self {
+            ranges: Vec::new(),
+            ignore_whitespace: false,
+            move_score: None,
+            copy_level: 0,
+            copy_score: DEFAULT_COPY_SCORE,
+        }
+    }
+}
+
+/// Consecutive lines of the blamed file that come from the same commit
+#[derive(Clone, Debug)]
+pub struct BlameEntry {
+    pub commit: CommitInfo,
+    /// Path of the file in that commit
+    pub path: String,
+    /// First line in the blamed file, 0-based
+    pub lno: usize,
+    /// First line in the commit's version of the file, 0-based
+    pub s_lno: usize,
+    pub num_lines: usize,
+    /// Parent commit and path the commit's version was compared with
+    pub previous: Option<(String, String)>,
+    /// Whether the lines could not be passed further because the commit is a root
+    pub boundary: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Blame {
+    /// Lines of the blamed file, each keeping its `\n`
+    pub lines: Vec<Vec<u8>>,
+    /// Entries in line order, covering the blamed ranges
+    pub entries: Vec<BlameEntry>,
+}
+
+/// Lines `lno..lno + num_lines` of the blamed file, which are lines `s_lno..` of the suspect
+#[derive(Clone, Debug)]
+struct Entry {
+    lno: usize,
+    s_lno: usize,
+    num_lines: usize,
+    suspect: usize,
+}
+
+/// A version of a file in a commit, holding the entries it is suspected of
+struct Origin {
+    commit: String,
+    path: String,
+    blob: String,
+    previous: Option<usize>,
+    suspects: Vec<Entry>,
+}
+
+/// An entry split around the part that matches a parent's lines
+struct Split {
+    before: Option<Entry>,
+    middle: Entry,
+    after: Option<Entry>,
+}
+
+struct Scoreboard<'a> {
+    repo: &'a Repository,
+    options: &'a BlameOptions,
+    lines: Vec<Vec<u8>>,
+    origins: Vec<Origin>,
+    commit_origins: HashMap<String, Vec<usize>>,
+    commits: HashMap<String, CommitInfo>,
+    mailmap: Mailmap,
+    blobs: HashMap<String, Rc<Vec<Vec<u8>>>>,
+    /// Commits with suspects, most recent first and in insertion order for equal dates
+    queue: BinaryHeap<(i64, Reverse<usize>, String)>,
+    queued: usize,
+    boundaries: HashSet<String>,
+    guilty: Vec<Entry>,
+}
+
+/// Attributes each line of `path` to the commit that introduced it, going back from `rev`, or
+/// from the worktree file when no revision is given.
+///
+/// `on_guilty` is called with the entries as soon as they are attributed, in the order they are
+/// found. Authors and committers are canonicalized with the mailmap.
+pub fn blame(
+    repo: &Repository,
+    path: &str,
+    rev: Option<&str>,
+    options: &BlameOptions,
+    on_guilty: &mut dyn FnMut(&BlameEntry),
+) -> Result<Blame, String> {
+    let mut scoreboard = Scoreboard {
+        repo,
+        options,
+        lines: Vec::new(),
+        origins: Vec::new(),
+        commit_origins: HashMap::new(),
+        commits: HashMap::new(),
+        mailmap: Mailmap::read(repo),
+        blobs: HashMap::new(),
+        queue: BinaryHeap::new(),
+        queued: 0,
+        boundaries: HashSet::new(),
+        guilty: Vec::new(),
+    };
+    let final_origin = match rev {
+        Some(rev) => {
+            let commit = peel_to_commit(repo, &rev_parse(repo, rev)?)?;
+            let blob = scoreboard
+                .blob_of(&commit, path)?
+                .ok_or(format!("No such path {} in {}", path, rev))?;
+            scoreboard.get_origin(&commit, path, &blob)
+        }
+        None => scoreboard.worktree_origin(path)?,
+    };
+    scoreboard.lines = scoreboard.origin_lines(final_origin)?.to_vec();
+    for (start, end) in line_ranges(&options.ranges, scoreboard.lines.len(), path)? {
+        scoreboard.origins[final_origin].suspects.push(Entry {
+            lno: start,
+            s_lno: start,
+            num_lines: end - start,
+            suspect: final_origin,
+        });
+    }
+    let commit = scoreboard.origins[final_origin].commit.clone();
+    scoreboard.push_commit(&commit)?;
+    scoreboard.assign_blame(on_guilty)?;
+
+    let mut guilty = take(&mut scoreboard.guilty);
+    guilty.sort_by_key(|entry| entry.lno);
+    let mut coalesced: Vec<Entry> = Vec::new();
+    for entry in guilty {
+        match coalesced.last_mut() {
+            Some(last)
+                if last.suspect == entry.suspect
+                    && last.s_lno + last.num_lines == entry.s_lno
+                    && last.lno + last.num_lines == entry.lno =>
+            {
+                last.num_lines += entry.num_lines
+            }
+            _ => coalesced.push(entry),
+        }
+    }
+    Ok(Blame {
+        entries: coalesced
+            .iter()
+            .map(|entry| scoreboard.blame_entry(entry))
+            .collect(),
+        lines: scoreboard.lines,
+    })
+}
+
+/// Resolves `-L` ranges into sorted and merged `start..end` line ranges, the whole file when
+/// there are none
+fn line_ranges(
+    specs: &[String],
+    line_count: usize,
+    path: &str,
+) -> Result<Vec<(usize, usize)>, String> {
+    if specs.is_empty() {
+        return Ok(vec![(0, line_count)]);
+    }
+    let number = |value: &str| {
+        value
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid line range, {}", value))
+    };
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let (start, end) = spec.split_once(',').unwrap_or((spec, ""));
+        let start = match start {
+            "" => 1,
+            start => number(start)?.max(1),
+        };
+        let (mut start, mut end) = if let Some(count) = end.strip_prefix('+') {
+            (start, start + number(count)?.max(1) - 1)
+        } else if let Some(count) = end.strip_prefix('-') {
+            (
+                start.saturating_sub(number(count)?.max(1) - 1).max(1),
+                start,
+            )
+        } else if end.is_empty() {
+            (start, line_count)
+        } else {
+            (start, number(end)?)
+        };
+        if end < start {
+            (start, end) = (end.max(1), start);
+        }
+        if start > line_count {
+            return Err(format!("File {} has only {} lines", path, line_count));
+        }
+        ranges.push((start - 1, end.min(line_count)));
+    }
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    Ok(merged)
+}
+
+impl Scoreboard<'_> {
+    /// Origin of the worktree file, in a commit made of the uncommitted changes on top of HEAD
+    fn worktree_origin(&mut self, path: &str) -> Result<usize, String> {
+        let head = read_ref(self.repo, "HEAD")
+            .map(|head| peel_to_commit(self.repo, &head))
+            .transpose()?;
+        if let Some(head) = &head {
+            if self.blob_of(head, path)?.is_none() {
+                return Err(format!("No such path {} in HEAD", path));
+            }
+        }
+        let data = clean_file(self.repo, path, None)?;
+        let blob = Object::new(ObjectTypes::Blob, data.clone())?.calculate_hash()?;
+        self.blobs.insert(blob.clone(), Rc::new(owned_lines(&data)));
+        let identity = Signature {
+            name: String::from("Not Committed Yet"),
+            email: String::from("not.committed.yet"),
+            time: now(),
+            tz_offset: 0,
+            negative_utc: false,
+        };
+        self.commits.insert(
+            NULL_COMMIT.to_owned(),
+            CommitInfo {
+                hash: NULL_COMMIT.to_owned(),
+                tree: String::new(),
+                parents: head.into_iter().collect(),
+                author: identity.clone(),
+                committer: identity,
+                message: format!("Version of {} from {}\n", path, path),
+            },
+        );
+        Ok(self.get_origin(NULL_COMMIT, path, &blob))
+    }
+    fn commit(&mut self, hash: &str) -> Result<&CommitInfo, String> {
+        if !self.commits.contains_key(hash) {
+            let mut commit = CommitInfo::read(self.repo, hash)?;
+            commit.author = self.mailmap.map_identity(&commit.author);
+            commit.committer = self.mailmap.map_identity(&commit.committer);
+            self.commits.insert(hash.to_owned(), commit);
+        }
+        Ok(&self.commits[hash])
+    }
+    /// Hash of the blob at `path` in `commit`, `None` when there is no such file
+    fn blob_of(&mut self, commit: &str, path: &str) -> Result<Option<String>, String> {
+        let Ok(hash) = rev_parse(self.repo, &format!("{}:{}", commit, path)) else {
+            return Ok(None);
+        };
+        if !self.blobs.contains_key(&hash) {
+            match Object::read_from_sha(self.repo, hash.clone())?.header {
+                ObjectHeaders::Blob { data } => {
+                    self.blobs.insert(hash.clone(), Rc::new(owned_lines(&data)));
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(hash))
+    }
+    fn origin_lines(&mut self, origin: usize) -> Result<Rc<Vec<Vec<u8>>>, String> {
+        let blob = self.origins[origin].blob.clone();
+        if !self.blobs.contains_key(&blob) {
+            let data = match Object::read_from_sha(self.repo, blob.clone())?.header {
+                ObjectHeaders::Blob { data } => data,
+                _ => return Err(format!("Expected blob, {}", blob)),
+            };
+            self.blobs.insert(blob.clone(), Rc::new(owned_lines(&data)));
+        }
+        Ok(self.blobs[&blob].clone())
+    }
+    fn get_origin(&mut self, commit: &str, path: &str, blob: &str) -> usize {
+        let origins = self.commit_origins.entry(commit.to_owned()).or_default();
+        if let Some(&origin) = origins
+            .iter()
+            .find(|&&origin| self.origins[origin].path == path)
+        {
+            self.origins[origin].blob = blob.to_owned();
+            return origin;
+        }
+        origins.push(self.origins.len());
+        self.origins.push(Origin {
+            commit: commit.to_owned(),
+            path: path.to_owned(),
+            blob: blob.to_owned(),
+            previous: None,
+            suspects: Vec::new(),
+        });
+        self.origins.len() - 1
+    }
+    fn push_commit(&mut self, commit: &str) -> Result<(), String> {
+        let date = self.commit(commit)?.committer.time;
+        self.queue
+            .push((date, Reverse(self.queued), commit.to_owned()));
+        self.queued += 1;
+        Ok(())
+    }
+    fn blame_entry(&self, entry: &Entry) -> BlameEntry {
+        let origin = &self.origins[entry.suspect];
+        BlameEntry {
+            commit: self.commits[&origin.commit].clone(),
+            path: origin.path.clone(),
+            lno: entry.lno,
+            s_lno: entry.s_lno,
+            num_lines: entry.num_lines,
+            previous: origin.previous.map(|previous| {
+                let previous = &self.origins[previous];
+                (previous.commit.clone(), previous.path.clone())
+            }),
+            boundary: self.boundaries.contains(&origin.commit),
+        }
+    }
+    /// Score of the lines of an entry, the number of alphanumeric characters in them
+    fn score(&self, entry: &Entry) -> usize {
+        1 + self.lines[entry.lno..entry.lno + entry.num_lines]
+            .iter()
+            .flatten()
+            .filter(|byte| byte.is_ascii_alphanumeric())
+            .count()
+    }
+    fn line_diff(&self, a: &[Vec<u8>], b: &[Vec<u8>]) -> Vec<DiffHunk> {
+        if !self.options.ignore_whitespace {
+            return diff(a, b);
+        }
+        let strip = |lines: &[Vec<u8>]| {
+            lines
+                .iter()
+                .map(|line| {
+                    line.iter()
+                        .filter(|byte| !byte.is_ascii_whitespace())
+                        .copied()
+                        .collect::<Vec<u8>>()
+                })
+                .collect::<Vec<Vec<u8>>>()
+        };
+        diff(&strip(a), &strip(b))
+    }
+
+    /// Takes the commits by date, passing the blame of each of their suspects to their parents
+    /// and holding them responsible for what remains
+    fn assign_blame(&mut self, on_guilty: &mut dyn FnMut(&BlameEntry)) -> Result<(), String> {
+        while let Some((_, _, commit)) = self.queue.pop() {
+            while let Some(origin) = self.commit_origins[&commit]
+                .iter()
+                .copied()
+                .find(|&origin| !self.origins[origin].suspects.is_empty())
+            {
+                let parents = self.commit(&commit)?.parents.clone();
+                if parents.is_empty() {
+                    self.boundaries.insert(commit.clone());
+                } else {
+                    self.pass_blame(origin, &parents)?;
+                }
+                for entry in take(&mut self.origins[origin].suspects) {
+                    on_guilty(&self.blame_entry(&entry));
+                    self.guilty.push(entry);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn pass_blame(&mut self, origin: usize, parents: &[String]) -> Result<(), String> {
+        let mut blamed = Vec::new();
+        let mut toosmall = Vec::new();
+        self.find_blame(origin, parents, &mut blamed, &mut toosmall)?;
+        blamed.sort_by_key(|entry| (entry.suspect, entry.lno));
+        for group in blamed.chunk_by(|a, b| a.suspect == b.suspect) {
+            self.queue_blames(group[0].suspect, group.to_vec())?;
+        }
+        let suspects = take(&mut self.origins[origin].suspects);
+        toosmall.extend(suspects);
+        self.origins[origin].suspects = toosmall;
+        Ok(())
+    }
+    fn find_blame(
+        &mut self,
+        origin: usize,
+        parents: &[String],
+        blamed: &mut Vec<Entry>,
+        toosmall: &mut Vec<Entry>,
+    ) -> Result<(), String> {
+        let commit = self.origins[origin].commit.clone();
+        let path = self.origins[origin].path.clone();
+        // Unrenamed paths are looked for in every parent first, as that is the common case
+        let mut parent_origins: Vec<Option<usize>> = vec![None; parents.len()];
+        for pass in 0..2 {
+            for (i, parent) in parents.iter().enumerate() {
+                if parent_origins[i].is_some() {
+                    continue;
+                }
+                let parent_path = match pass {
+                    0 => Some(path.clone()),
+                    _ if commit == NULL_COMMIT => None,
+                    _ => rename_source(
+                        self.repo,
+                        &peel_to_tree(self.repo, parent)?,
+                        &peel_to_tree(self.repo, &commit)?,
+                        &path,
+                    )?,
+                };
+                let Some(parent_path) = parent_path else {
+                    continue;
+                };
+                let Some(blob) = self.blob_of(parent, &parent_path)? else {
+                    continue;
+                };
+                let parent_origin = self.get_origin(parent, &parent_path, &blob);
+                if blob == self.origins[origin].blob {
+                    let suspects = take(&mut self.origins[origin].suspects);
+                    let suspects = suspects
+                        .into_iter()
+                        .map(|entry| Entry {
+                            suspect: parent_origin,
+                            ..entry
+                        })
+                        .collect();
+                    return self.queue_blames(parent_origin, suspects);
+                }
+                if !parent_origins[..i]
+                    .iter()
+                    .flatten()
+                    .any(|&other| self.origins[other].blob == blob)
+                {
+                    parent_origins[i] = Some(parent_origin);
+                }
+            }
+        }
+
+        for &parent_origin in parent_origins.iter().flatten() {
+            if self.origins[origin].previous.is_none() {
+                self.origins[origin].previous = Some(parent_origin);
+            }
+            self.pass_blame_to_parent(origin, parent_origin)?;
+            if self.origins[origin].suspects.is_empty() {
+                return Ok(());
+            }
+        }
+
+        let copying = self.options.copy_level > 0;
+        let move_score = match (self.options.move_score, copying) {
+            (Some(score), _) => score,
+            (None, true) => DEFAULT_MOVE_SCORE,
+            (None, false) => return Ok(()),
+        };
+        let suspects = take(&mut self.origins[origin].suspects);
+        let mut small = Vec::new();
+        self.origins[origin].suspects = self.filter_small(&mut small, suspects, move_score);
+        toosmall.splice(0..0, small);
+        for &parent_origin in parent_origins.iter().flatten() {
+            if self.origins[origin].suspects.is_empty() {
+                break;
+            }
+            self.find_move_in_parent(origin, parent_origin, blamed, toosmall, move_score)?;
+        }
+
+        if !copying {
+            return Ok(());
+        }
+        let copy_score = self.options.copy_score;
+        let mut suspects = take(&mut self.origins[origin].suspects);
+        if copy_score < move_score {
+            suspects = merge_entries(suspects, take(toosmall));
+        }
+        if copy_score != move_score {
+            let mut small = Vec::new();
+            suspects = self.filter_small(&mut small, suspects, copy_score);
+            toosmall.splice(0..0, small);
+        }
+        self.origins[origin].suspects = suspects;
+        for (i, parent) in parents.iter().enumerate() {
+            if self.origins[origin].suspects.is_empty() {
+                break;
+            }
+            self.find_copy_in_parent(origin, parent, parent_origins[i], blamed, toosmall)?;
+        }
+        Ok(())
+    }
+    /// Passes the blame for the lines the parent's version has in common with the target's
+    fn pass_blame_to_parent(&mut self, target: usize, parent: usize) -> Result<(), String> {
+        let parent_lines = self.origin_lines(parent)?;
+        let target_lines = self.origin_lines(target)?;
+        // Regions of the target, with the offset to the parent's lines when they are unchanged
+        let mut regions = Vec::new();
+        let mut start = 0;
+        let mut offset = 0;
+        for hunk in self.line_diff(&parent_lines, &target_lines) {
+            regions.push((start, hunk.b_start, Some(offset)));
+            regions.push((hunk.b_start, hunk.b_end(), None));
+            start = hunk.b_end();
+            offset = hunk.a_end() as isize - hunk.b_end() as isize;
+        }
+        regions.push((start, usize::MAX, Some(offset)));
+
+        let mut passed = Vec::new();
+        let mut kept = Vec::new();
+        for entry in take(&mut self.origins[target].suspects) {
+            let end = entry.s_lno + entry.num_lines;
+            for &(start, region_end, offset) in &regions {
+                let (from, to) = (start.max(entry.s_lno), region_end.min(end));
+                if from >= to {
+                    continue;
+                }
+                let part = Entry {
+                    lno: entry.lno + from - entry.s_lno,
+                    s_lno: from,
+                    num_lines: to - from,
+                    suspect: entry.suspect,
+                };
+                match offset {
+                    Some(offset) => passed.push(Entry {
+                        s_lno: (from as isize + offset) as usize,
+                        suspect: parent,
+                        ..part
+                    }),
+                    None => kept.push(part),
+                }
+            }
+        }
+        self.origins[target].suspects = kept;
+        self.queue_blames(parent, passed)
+    }
+    /// Adds entries to the origin's suspects, queueing its commit if it had none
+    fn queue_blames(&mut self, origin: usize, entries: Vec<Entry>) -> Result<(), String> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let commit = self.origins[origin].commit.clone();
+        let pending = self.commit_origins[&commit]
+            .iter()
+            .any(|&other| !self.origins[other].suspects.is_empty());
+        let suspects = &mut self.origins[origin].suspects;
+        *suspects = merge_entries(take(suspects), entries);
+        if !pending {
+            self.push_commit(&commit)?;
+        }
+        Ok(())
+    }
+    /// Moves the entries scoring at most `min_score` to `small`, returning the others
+    fn filter_small(
+        &self,
+        small: &mut Vec<Entry>,
+        entries: Vec<Entry>,
+        min_score: usize,
+    ) -> Vec<Entry> {
+        let (too_small, kept) = entries
+            .into_iter()
+            .partition(|entry| self.score(entry) <= min_score);
+        small.extend::<Vec<Entry>>(too_small);
+        kept
+    }
+    /// Finds the best scoring block of the entry's lines that also appears in the parent's lines
+    fn find_copy_in_blob(
+        &self,
+        entry: &Entry,
+        parent: usize,
+        parent_lines: &[Vec<u8>],
+    ) -> Option<Split> {
+        let lines = &self.lines[entry.lno..entry.lno + entry.num_lines];
+        let mut best = None;
+        let (mut tlno, mut plno) = (0, 0);
+        let hunks = self.line_diff(parent_lines, lines);
+        for (same, next_plno, next_tlno) in hunks
+            .iter()
+            .map(|hunk| (hunk.b_start, hunk.a_end(), hunk.b_end()))
+            .chain([(entry.num_lines, 0, 0)])
+        {
+            if tlno < same {
+                let potential =
+                    split_overlap(entry, entry.s_lno + tlno, plno, entry.s_lno + same, parent);
+                self.copy_split_if_better(&mut best, potential);
+            }
+            (tlno, plno) = (next_tlno, next_plno);
+        }
+        best
+    }
+    fn copy_split_if_better(&self, best: &mut Option<Split>, potential: Option<Split>) {
+        let Some(potential) = potential else {
+            return;
+        };
+        if let Some(best) = best {
+            if self.score(&potential.middle) < self.score(&best.middle) {
+                return;
+            }
+        }
+        *best = Some(potential);
+    }
+    /// Passes the blame for blocks of lines moved around within the parent's version
+    fn find_move_in_parent(
+        &mut self,
+        target: usize,
+        parent: usize,
+        blamed: &mut Vec<Entry>,
+        toosmall: &mut Vec<Entry>,
+        move_score: usize,
+    ) -> Result<(), String> {
+        let parent_lines = self.origin_lines(parent)?;
+        let mut unblamed = take(&mut self.origins[target].suspects);
+        let mut leftover = Vec::new();
+        let mut small = Vec::new();
+        while !unblamed.is_empty() {
+            let mut next = Vec::new();
+            for entry in unblamed {
+                match self.find_copy_in_blob(&entry, parent, &parent_lines) {
+                    Some(split) if move_score < self.score(&split.middle) => {
+                        split_blame(blamed, &mut next, split)
+                    }
+                    _ => leftover.push(entry),
+                }
+            }
+            unblamed = self.filter_small(&mut small, next, move_score);
+        }
+        toosmall.splice(0..0, small);
+        self.origins[target].suspects = leftover;
+        Ok(())
+    }
+    /// Passes the blame for blocks of lines copied from other files of the parent
+    fn find_copy_in_parent(
+        &mut self,
+        target: usize,
+        parent: &str,
+        parent_origin: Option<usize>,
+        blamed: &mut Vec<Entry>,
+        toosmall: &mut Vec<Entry>,
+    ) -> Result<(), String> {
+        let commit = self.origins[target].commit.clone();
+        let parent_path = parent_origin.map(|origin| self.origins[origin].path.clone());
+        let level = self.options.copy_level;
+        let every_file =
+            level >= 3 || (level >= 2 && parent_path.as_ref() != Some(&self.origins[target].path));
+        let parent_tree = peel_to_tree(self.repo, parent)?;
+        let mut candidates = Vec::new();
+        if every_file {
+            for node in TreeObject::read_from_sha(self.repo, &parent_tree)?.flatten(self.repo)? {
+                candidates.push((node.path, node.hash, node.mode));
+            }
+        } else if commit == NULL_COMMIT {
+            let index = Index::read(self.repo)?;
+            for node in TreeObject::read_from_sha(self.repo, &parent_tree)?.flatten(self.repo)? {
+                if !index
+                    .entries
+                    .iter()
+                    .any(|entry| entry.path == node.path && entry.hash == node.hash)
+                {
+                    candidates.push((node.path, node.hash, node.mode));
+                }
+            }
+        } else {
+            let tree = peel_to_tree(self.repo, &commit)?;
+            for change in diff_trees(self.repo, &parent_tree, &tree)? {
+                if let Some(old) = change.old {
+                    candidates.push((old.path, old.hash, old.mode));
+                }
+            }
+        }
+
+        let mut unblamed = take(&mut self.origins[target].suspects);
+        let mut leftover = Vec::new();
+        let mut small = Vec::new();
+        while !unblamed.is_empty() {
+            let mut splits: Vec<Option<Split>> = unblamed.iter().map(|_| None).collect();
+            for (path, blob, mode) in &candidates {
+                if mode == "160000" || parent_path.as_ref() == Some(path) {
+                    continue;
+                }
+                let origin = self.get_origin(parent, path, blob);
+                let lines = self.origin_lines(origin)?;
+                for (entry, best) in unblamed.iter().zip(splits.iter_mut()) {
+                    let potential = self.find_copy_in_blob(entry, origin, &lines);
+                    self.copy_split_if_better(best, potential);
+                }
+            }
+            let mut next = Vec::new();
+            for (entry, split) in unblamed.into_iter().zip(splits) {
+                match split {
+                    Some(split) if self.options.copy_score < self.score(&split.middle) => {
+                        split_blame(blamed, &mut next, split)
+                    }
+                    _ => leftover.push(entry),
+                }
+            }
+            unblamed = self.filter_small(&mut small, next, self.options.copy_score);
+        }
+        toosmall.splice(0..0, small);
+        self.origins[target].suspects = leftover;
+        Ok(())
+    }
+}
+
+/// Merges two lists of entries ordered by their suspect's lines, keeping the first list's
+/// entries first on ties
+fn merge_entries(first: Vec<Entry>, second: Vec<Entry>) -> Vec<Entry> {
+    let mut merged = Vec::with_capacity(first.len() + second.len());
+    let mut second = second.into_iter().peekable();
+    for entry in first {
+        while let Some(other) = second.next_if(|other| other.s_lno < entry.s_lno) {
+            merged.push(other);
+        }
+        merged.push(entry);
+    }
+    merged.extend(second);
+    merged
+}
+
+fn owned_lines(data: &[u8]) -> Vec<Vec<u8>> {
+    split_lines(data)
+        .into_iter()
+        .map(|line| line.to_vec())
+        .collect()
+}
+
+/// Splits an entry around its suspect's lines `tlno..same`, which are the parent's lines from
+/// `plno`, `None` when they do not overlap
+fn split_overlap(
+    entry: &Entry,
+    tlno: usize,
+    plno: usize,
+    same: usize,
+    parent: usize,
+) -> Option<Split> {
+    let (before, middle_lno, middle_s_lno) = if entry.s_lno < tlno {
+        let before = Entry {
+            num_lines: tlno - entry.s_lno,
+            ..entry.clone()
+        };
+        (Some(before), entry.lno + tlno - entry.s_lno, plno)
+    } else {
+        (None, entry.lno, plno + entry.s_lno - tlno)
+    };
+    let end = entry.s_lno + entry.num_lines;
+    let (after, middle_end) = if same < end {
+        let lno = entry.lno + same - entry.s_lno;
+        let after = Entry {
+            lno,
+            s_lno: same,
+            num_lines: end - same,
+            suspect: entry.suspect,
+        };
+        (Some(after), lno)
+    } else {
+        (None, entry.lno + entry.num_lines)
+    };
+    if middle_end <= middle_lno {
+        return None;
+    }
+    Some(Split {
+        before,
+        middle: Entry {
+            lno: middle_lno,
+            s_lno: middle_s_lno,
+            num_lines: middle_end - middle_lno,
+            suspect: parent,
+        },
+        after,
+    })
+}
+
+/// Blames the middle of the split on the parent, leaving the rest to be looked at again
+fn split_blame(blamed: &mut Vec<Entry>, unblamed: &mut Vec<Entry>, split: Split) {
+    unblamed.extend(split.before);
+    unblamed.extend(split.after);
+    blamed.push(split.middle);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_line_ranges() {
+        let ranges = |specs: &[&str]| {
+            let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+            line_ranges(&specs, 10, "file")
+        };
+        assert_eq!(ranges(&[]), Ok(vec![(0, 10)]));
+        assert_eq!(ranges(&["2,4"]), Ok(vec![(1, 4)]));
+        assert_eq!(ranges(&["4,2"]), Ok(vec![(1, 4)]));
+        assert_eq!(ranges(&["3,+2", "8"]), Ok(vec![(2, 4), (7, 10)]));
+        assert_eq!(ranges(&["5,-2", ",3", "9,20"]), Ok(vec![(0, 5), (8, 10)]));
+        assert!(ranges(&["11,12"]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+
+use crate::{create_path, ObjectTypes, Repository};
+
+/// Format used by `--batch` and `--batch-check` when none is given
+pub const DEFAULT_BATCH_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum BatchAtom {
+    Literal(String),
+    ObjectName,
+    ObjectType,
+    ObjectSize,
+    DiskSize,
+    DeltaBase,
+    Rest,
+}
+
+/// Parsed `--batch=<format>`, printed for every object named on stdin
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchFormat {
+    atoms: Vec<BatchAtom>,
+}
+impl BatchFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut atoms = Vec::new();
+        let mut literal = String::new();
+        let mut rest = format;
+        while let Some(start) = rest.find('%') {
+            literal.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            if let Some(after) = rest.strip_prefix('%') {
+                literal.push('%');
+                rest = after;
+                continue;
+            }
+            let Some(element) = rest.strip_prefix('(') else {
+                literal.push('%');
+                continue;
+            };
+            let end = element
+                .find(')')
+                .ok_or_else(|| format!("format element '{}' does not end in ')'", rest))?;
+            let atom = match &element[..end] {
+                "objectname" => BatchAtom::ObjectName,
+                "objecttype" => BatchAtom::ObjectType,
+                "objectsize" => BatchAtom::ObjectSize,
+                "objectsize:disk" => BatchAtom::DiskSize,
+                "deltabase" => BatchAtom::DeltaBase,
+                "rest" => BatchAtom::Rest,
+                atom => return Err(format!("unknown format element: {}", atom)),
+            };
+            if !literal.is_empty() {
+                atoms.push(BatchAtom::Literal(std::mem::take(&mut literal)));
+            }
+            atoms.push(atom);
+            rest = &element[end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            atoms.push(BatchAtom::Literal(literal));
+        }
+        Ok(Self { atoms })
+    }
+    /// Whether input lines are split at the first whitespace, the remainder being `%(rest)`
+    pub fn uses_rest(&self) -> bool {
+        self.atoms.contains(&BatchAtom::Rest)
+    }
+    pub fn expand(
+        &self,
+        repo: &Repository,
+        hash: &str,
+        object_type: &ObjectTypes,
+        size: usize,
+        rest: &str,
+    ) -> String {
+        let mut line = String::new();
+        for atom in &self.atoms {
+            match atom {
+                BatchAtom::Literal(text) => line.push_str(text),
+                BatchAtom::ObjectName => line.push_str(hash),
+                BatchAtom::ObjectType => line.push_str(&object_type.to_string()),
+                BatchAtom::ObjectSize => line.push_str(&size.to_string()),
+                BatchAtom::DiskSize => {
+                    let path = create_path(
+                        &repo.gitdir,
+                        vec![
+                            String::from("objects"),
+                            hash[..2].to_owned(),
+                            hash[2..].to_owned(),
+                        ],
+                    );
+                    let disk_size = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+                    line.push_str(&disk_size.to_string());
+                }
+                // Only loose objects are stored, none of which is a delta
+                BatchAtom::DeltaBase => line.push_str(&"0".repeat(40)),
+                BatchAtom::Rest => line.push_str(rest),
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_format() {
+        let format = BatchFormat::parse("%(objectname) 100%% %d %(rest)").unwrap();
+        assert!(format.uses_rest());
+        assert_eq!(
+            format.atoms,
+            vec![
+                BatchAtom::ObjectName,
+                BatchAtom::Literal(String::from(" 100% %d ")),
+                BatchAtom::Rest,
+            ]
+        );
+        assert!(!BatchFormat::parse(DEFAULT_BATCH_FORMAT)
+            .unwrap()
+            .uses_rest());
+        assert_eq!(
+            BatchFormat::parse("%(bogus)").unwrap_err(),
+            "unknown format element: bogus"
+        );
+        assert!(BatchFormat::parse("%(objectname").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use clap::ValueEnum;
 
-#[derive(Clone, Debug, ValueEnum)]
+#[derive(Clone, Debug, PartialEq, Eq, ValueEnum)]
 pub enum ObjectTypes {
     Commit,
     Tree,
@@ -1,25 +1,71 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, env, path::PathBuf};
 
 use configparser::ini::Ini;
 use homedir::get_my_home;
 
+use crate::{create_path, Repository};
+
+/// File `name` of git's user configuration directory, `$XDG_CONFIG_HOME/git/<name>` or
+/// `~/.config/git/<name>`, unless `configured` names another one
+pub(crate) fn user_file(
+    repo: &Repository,
+    configured: Option<&str>,
+    name: &str,
+) -> Option<PathBuf> {
+    match configured {
+        Some(file) => match (file.strip_prefix("~/"), get_my_home()) {
+            (Some(file), Ok(Some(home))) => Some(create_path(&home, vec![file.to_owned()])),
+            _ => Some(create_path(&repo.worktree, vec![file.to_owned()])),
+        },
+        None => match (env::var_os("XDG_CONFIG_HOME"), get_my_home()) {
+            (Some(config), _) if !config.is_empty() => Some(PathBuf::from(config)),
+            (_, Ok(Some(home))) => Some(create_path(&home, vec![String::from(".config")])),
+            _ => None,
+        }
+        .map(|config| create_path(&config, vec![String::from("git"), name.to_owned()])),
+    }
+}
+
 #[derive(Default)]
 pub struct Config {
     pub core: CoreConfig,
     pub user: UserConfig,
+    pub mailmap: MailmapConfig,
+    /// `filter.<name>` sections by name, lowercased like every section name
+    pub filters: HashMap<String, FilterConfig>,
 }
 impl Config {
     pub fn from_ini(ini: Ini) -> Self {
-        let mut config = Config::default();
+        Config::default().merge_ini(ini)
+    }
+    /// Loads a repository's config on top of the user's system wide config
+    pub fn from_repository_ini(ini: Ini) -> Self {
+        Self::get_system_config().merge_ini(ini)
+    }
+    /// Overrides the values set in `ini`, keeping the current ones for the others
+    fn merge_ini(mut self, ini: Ini) -> Self {
         if let Some(ini_config) = ini.get_map() {
             if let Some(hashmap) = ini_config.get("core") {
-                config.core = CoreConfig::from_hashmap(hashmap.clone());
+                self.core = self.core.merge_hashmap(hashmap.clone());
             }
             if let Some(hashmap) = ini_config.get("user") {
-                config.user = UserConfig::from_hashmap(hashmap.clone());
+                self.user = self.user.merge_hashmap(hashmap.clone());
+            }
+            if let Some(hashmap) = ini_config.get("mailmap") {
+                self.mailmap = self.mailmap.merge_hashmap(hashmap.clone());
+            }
+            for (section, hashmap) in ini_config {
+                let name = section
+                    .strip_prefix("filter \"")
+                    .and_then(|name| name.strip_suffix('"'));
+                if let Some(name) = name {
+                    let filter = self.filters.remove(name).unwrap_or_default();
+                    self.filters
+                        .insert(name.to_owned(), filter.merge_hashmap(hashmap.clone()));
+                }
             }
         };
-        config
+        self
     }
     /// Creates a [configparser::ini::Ini] from the current Config
     pub fn to_ini(&self) -> Ini {
@@ -30,6 +76,14 @@ impl Config {
         for (k, v) in self.user.to_hashmap() {
             ini.set("user", k, Some(v));
         }
+        for (k, v) in self.mailmap.to_hashmap() {
+            ini.set("mailmap", k, Some(v));
+        }
+        for (name, filter) in &self.filters {
+            for (k, v) in filter.to_hashmap() {
+                ini.set(&format!("filter \"{}\"", name), k, Some(v));
+            }
+        }
         ini
     }
     /// Helper method to get the user's system wide config, returns default if it fails to find it
@@ -53,10 +107,21 @@ pub struct CoreConfig {
     pub repositoryformatversion: u8,
     pub filemode: bool,
     pub bare: bool,
+    /// Ignore file weaker than `info/exclude`, `~/.config/git/ignore` when unset
+    pub excludesfile: Option<String>,
+    /// Attributes file weaker than the `.gitattributes` files, `~/.config/git/attributes` when
+    /// unset
+    pub attributesfile: Option<String>,
+    /// `true`, `input` or `false`, how text files' line endings are converted
+    pub autocrlf: Option<String>,
+    /// `lf`, `crlf` or `native`, line endings of text files in the worktree
+    pub eol: Option<String>,
+    /// `true`, `warn` or `false`, what happens when a conversion would not round trip
+    pub safecrlf: Option<String>,
 }
 impl CoreConfig {
-    fn from_hashmap(hashmap: HashMap<String, Option<String>>) -> Self {
-        let mut config = Self::default();
+    fn merge_hashmap(self, hashmap: HashMap<String, Option<String>>) -> Self {
+        let mut config = self;
         if let Some(Some(val)) = hashmap.get("repositoryformatversion") {
             config.repositoryformatversion = val.parse::<u8>().unwrap();
         }
@@ -66,6 +131,21 @@ impl CoreConfig {
         if let Some(Some(val)) = hashmap.get("bare") {
             config.bare = val.parse::<bool>().unwrap();
         }
+        if let Some(Some(val)) = hashmap.get("excludesfile") {
+            config.excludesfile = Some(val.to_owned());
+        }
+        if let Some(Some(val)) = hashmap.get("attributesfile") {
+            config.attributesfile = Some(val.to_owned());
+        }
+        if let Some(Some(val)) = hashmap.get("autocrlf") {
+            config.autocrlf = Some(val.to_owned());
+        }
+        if let Some(Some(val)) = hashmap.get("eol") {
+            config.eol = Some(val.to_owned());
+        }
+        if let Some(Some(val)) = hashmap.get("safecrlf") {
+            config.safecrlf = Some(val.to_owned());
+        }
         config
     }
     fn to_hashmap(&self) -> HashMap<&str, String> {
@@ -76,6 +156,21 @@ impl CoreConfig {
         );
         hm.insert("filemode", self.filemode.to_string());
         hm.insert("bare", self.bare.to_string());
+        if let Some(excludesfile) = &self.excludesfile {
+            hm.insert("excludesfile", excludesfile.clone());
+        }
+        if let Some(attributesfile) = &self.attributesfile {
+            hm.insert("attributesfile", attributesfile.clone());
+        }
+        if let Some(autocrlf) = &self.autocrlf {
+            hm.insert("autocrlf", autocrlf.clone());
+        }
+        if let Some(eol) = &self.eol {
+            hm.insert("eol", eol.clone());
+        }
+        if let Some(safecrlf) = &self.safecrlf {
+            hm.insert("safecrlf", safecrlf.clone());
+        }
         hm
     }
 }
@@ -86,8 +181,8 @@ pub struct UserConfig {
     pub email: String,
 }
 impl UserConfig {
-    fn from_hashmap(hashmap: HashMap<String, Option<String>>) -> Self {
-        let mut config = Self::default();
+    fn merge_hashmap(self, hashmap: HashMap<String, Option<String>>) -> Self {
+        let mut config = self;
         if let Some(Some(val)) = hashmap.get("name") {
             config.name = val.to_owned();
         }
@@ -112,6 +207,78 @@ impl Default for UserConfig {
     }
 }
 
+#[derive(Clone, Default)]
+pub struct MailmapConfig {
+    /// Mailmap read after the worktree's `.mailmap`
+    pub file: Option<String>,
+}
+impl MailmapConfig {
+    fn merge_hashmap(self, hashmap: HashMap<String, Option<String>>) -> Self {
+        let mut config = self;
+        if let Some(Some(val)) = hashmap.get("file") {
+            config.file = Some(val.to_owned());
+        }
+        config
+    }
+    fn to_hashmap(&self) -> HashMap<&str, String> {
+        let mut hm = HashMap::new();
+        if let Some(file) = &self.file {
+            hm.insert("file", file.clone());
+        }
+        hm
+    }
+}
+
+/// Commands converting the files whose `filter` attribute names the section
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FilterConfig {
+    /// Command run on the worktree content of files to get the content stored
+    pub clean: Option<String>,
+    /// Command run on the stored content of files to get the worktree content
+    pub smudge: Option<String>,
+    /// Long-running command speaking git's filter protocol, used over `clean` and `smudge`
+    pub process: Option<String>,
+    /// Whether failing to run the filter is an error rather than leaving content as is
+    pub required: bool,
+}
+impl FilterConfig {
+    fn merge_hashmap(self, hashmap: HashMap<String, Option<String>>) -> Self {
+        let mut config = self;
+        if let Some(Some(val)) = hashmap.get("clean") {
+            config.clean = Some(val.to_owned());
+        }
+        if let Some(Some(val)) = hashmap.get("smudge") {
+            config.smudge = Some(val.to_owned());
+        }
+        if let Some(Some(val)) = hashmap.get("process") {
+            config.process = Some(val.to_owned());
+        }
+        if let Some(val) = hashmap.get("required") {
+            // Like git, a key without value is true
+            config.required = val.as_deref().is_none_or(|val| {
+                matches!(val.to_lowercase().as_str(), "true" | "yes" | "on" | "1")
+            });
+        }
+        config
+    }
+    fn to_hashmap(&self) -> HashMap<&str, String> {
+        let mut hm = HashMap::new();
+        if let Some(clean) = &self.clean {
+            hm.insert("clean", clean.clone());
+        }
+        if let Some(smudge) = &self.smudge {
+            hm.insert("smudge", smudge.clone());
+        }
+        if let Some(process) = &self.process {
+            hm.insert("process", process.clone());
+        }
+        if self.required {
+            hm.insert("required", String::from("true"));
+        }
+        hm
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
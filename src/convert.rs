@@ -0,0 +1,480 @@
+use std::path::PathBuf;
+
+use crate::{
+    config::FilterConfig,
+    filter::{apply_filter, FilterKind},
+    AttrValue, Attributes, Object, ObjectTypes, Repository,
+};
+
+/// How line endings of a file are converted, git's `crlf_action`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum EolAction {
+    /// Left untouched
+    #[default]
+    Binary,
+    /// `text`: normalized to LF in the repository
+    Text,
+    /// `text eol=lf`, or `text` with `core.autocrlf=input`
+    TextInput,
+    /// `text eol=crlf`, or `text` with `core.autocrlf=true`
+    TextCrlf,
+    /// `text=auto`: like [EolAction::Text], unless the content looks binary
+    Auto,
+    AutoInput,
+    AutoCrlf,
+}
+
+/// What a conversion looks at in a file's content
+#[derive(Debug, Default)]
+struct TextStats {
+    nul: usize,
+    lone_cr: usize,
+    lone_lf: usize,
+    crlf: usize,
+    printable: usize,
+    nonprintable: usize,
+}
+impl TextStats {
+    fn gather(data: &[u8]) -> Self {
+        let mut stats = Self::default();
+        for (i, &b) in data.iter().enumerate() {
+            match b {
+                b'\r' if data.get(i + 1) == Some(&b'\n') => stats.crlf += 1,
+                b'\r' => stats.lone_cr += 1,
+                b'\n' if i > 0 && data[i - 1] == b'\r' => {}
+                b'\n' => stats.lone_lf += 1,
+                0 => {
+                    stats.nul += 1;
+                    stats.nonprintable += 1;
+                }
+                // Backspace, tab, escape and form feed count as printable, as for git
+                0x08 | b'\t' | 0x1b | 0x0c => stats.printable += 1,
+                0x7f => stats.nonprintable += 1,
+                b if b < 0x20 => stats.nonprintable += 1,
+                _ => stats.printable += 1,
+            }
+        }
+        // A DOS end of file marker is not a sign of binary content
+        if data.last() == Some(&0x1a) {
+            stats.nonprintable -= 1;
+        }
+        stats
+    }
+    /// Whether `text=auto` leaves the content alone, as git's `convert_is_binary`
+    fn is_binary(&self) -> bool {
+        self.lone_cr > 0 || self.nul > 0 || (self.printable >> 7) < self.nonprintable
+    }
+}
+
+/// Number of `$Id$` keywords, expanded or not, as git's `count_ident`
+fn count_ident(data: &[u8]) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < data.len() {
+        i += 1;
+        if data[i - 1] != b'$' {
+            continue;
+        }
+        if data.len() - i < 3 {
+            break;
+        }
+        if &data[i..i + 2] != b"Id" {
+            continue;
+        }
+        let c = data[i + 2];
+        i += 3;
+        if c == b'$' {
+            count += 1;
+        }
+        if c != b':' {
+            continue;
+        }
+        // An expanded keyword runs to the next `$` on the same line
+        while i < data.len() {
+            i += 1;
+            match data[i - 1] {
+                b'$' => {
+                    count += 1;
+                    break;
+                }
+                b'\n' => break,
+                _ => {}
+            }
+        }
+    }
+    count
+}
+
+fn find_dollar(data: &[u8]) -> Option<usize> {
+    data.iter().position(|&b| b == b'$')
+}
+
+/// Collapses `$Id: ... $` keywords back to `$Id$`
+fn ident_to_git(data: Vec<u8>) -> Vec<u8> {
+    if count_ident(&data) == 0 {
+        return data;
+    }
+    let mut converted = Vec::with_capacity(data.len());
+    let mut rest = data.as_slice();
+    while let Some(dollar) = find_dollar(rest) {
+        converted.extend_from_slice(&rest[..=dollar]);
+        rest = &rest[dollar + 1..];
+        if rest.len() > 3 && rest.starts_with(b"Id:") {
+            let Some(end) = find_dollar(&rest[3..]).map(|end| end + 3) else {
+                break;
+            };
+            if rest[3..end].contains(&b'\n') {
+                continue;
+            }
+            converted.extend_from_slice(b"Id$");
+            rest = &rest[end + 1..];
+        }
+    }
+    converted.extend_from_slice(rest);
+    converted
+}
+
+/// Expands `$Id$` keywords to `$Id: <blob hash> $`, the hash being that of `data`
+fn ident_to_worktree(data: Vec<u8>) -> Vec<u8> {
+    if count_ident(&data) == 0 {
+        return data;
+    }
+    let hash = Object::hash_data(&ObjectTypes::Blob, &data);
+    let mut converted = Vec::with_capacity(data.len());
+    let mut rest = data.as_slice();
+    while let Some(dollar) = find_dollar(rest) {
+        converted.extend_from_slice(&rest[..=dollar]);
+        rest = &rest[dollar + 1..];
+        if rest.len() < 3 || !rest.starts_with(b"Id") {
+            continue;
+        }
+        match rest[2] {
+            b'$' => rest = &rest[3..],
+            b':' => {
+                let Some(end) = find_dollar(&rest[3..]).map(|end| end + 3) else {
+                    break;
+                };
+                if rest[3..end].contains(&b'\n') {
+                    continue;
+                }
+                // Like git, keywords with inner spaces are left to other version control systems
+                let foreign = end > 4
+                    && rest[4..end]
+                        .iter()
+                        .position(|&b| b == b' ')
+                        .is_some_and(|space| space + 4 < end - 1);
+                if foreign {
+                    continue;
+                }
+                rest = &rest[end + 1..];
+            }
+            _ => continue,
+        }
+        converted.extend_from_slice(b"Id: ");
+        converted.extend_from_slice(hash.as_bytes());
+        converted.extend_from_slice(b" $");
+    }
+    converted.extend_from_slice(rest);
+    converted
+}
+
+/// Conversion of a file's content between the worktree and the repository, from the file's
+/// attributes and the `core.autocrlf` and `core.eol` settings.
+///
+/// Like git, content going to the repository goes through the `filter` driver's clean
+/// command, then the line ending conversion, then `ident`, and the other way round when
+/// going to the worktree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Conversion {
+    /// Worktree root, where filter commands run
+    worktree: PathBuf,
+    /// Path of the file, relative to the worktree root
+    path: String,
+    /// Name and commands of the `filter` attribute's driver, when configured
+    filter: Option<(String, FilterConfig)>,
+    /// Whether the `ident` attribute is set
+    ident: bool,
+    eol: EolAction,
+    /// Whether the worktree uses CRLF line endings, for [EolAction::Text] and [EolAction::Auto]
+    crlf: bool,
+    /// Whether the blob the index has for the file holds a CR
+    indexed_cr: bool,
+    /// Whether conversions that would not round trip are warned about, `core.safecrlf` not
+    /// being `false`
+    safe: bool,
+}
+impl Conversion {
+    /// Conversion of the file at `path`, relative to the worktree root
+    pub fn new(repo: &Repository, attributes: &mut Attributes, path: &str) -> Self {
+        let all = attributes.all(path);
+        let get = |name: &str| {
+            all.iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.clone())
+        };
+        let autocrlf = repo.config.core.autocrlf.as_deref().map(str::to_lowercase);
+        let autocrlf = match autocrlf.as_deref() {
+            Some("input") => Some(false),
+            Some("true" | "yes" | "on" | "1") => Some(true),
+            _ => None,
+        };
+        let text = match get("text") {
+            // The deprecated `crlf` attribute stands for `text`
+            None => match get("crlf") {
+                Some(AttrValue::Value(value)) if value == "input" => Some(EolAction::TextInput),
+                Some(AttrValue::Set) => Some(EolAction::Text),
+                Some(AttrValue::Unset) => Some(EolAction::Binary),
+                _ => None,
+            },
+            Some(AttrValue::Set) => Some(EolAction::Text),
+            Some(AttrValue::Unset) => Some(EolAction::Binary),
+            Some(AttrValue::Value(value)) if value == "auto" => Some(EolAction::Auto),
+            Some(AttrValue::Value(_)) => None,
+        };
+        let eol_attribute = match get("eol") {
+            Some(AttrValue::Value(value)) if value == "lf" => Some(false),
+            Some(AttrValue::Value(value)) if value == "crlf" => Some(true),
+            _ => None,
+        };
+        let eol = match (text, eol_attribute) {
+            (Some(EolAction::Binary), _) => EolAction::Binary,
+            (Some(EolAction::Text | EolAction::TextInput) | None, Some(false)) => {
+                EolAction::TextInput
+            }
+            (Some(EolAction::Text | EolAction::TextInput) | None, Some(true)) => {
+                EolAction::TextCrlf
+            }
+            (Some(EolAction::Auto), Some(false)) => EolAction::AutoInput,
+            (Some(EolAction::Auto), Some(true)) => EolAction::AutoCrlf,
+            (Some(action), _) => action,
+            (None, None) => match autocrlf {
+                None => EolAction::Binary,
+                Some(false) => EolAction::AutoInput,
+                Some(true) => EolAction::AutoCrlf,
+            },
+        };
+        let crlf = match autocrlf {
+            Some(crlf) => crlf,
+            None => repo
+                .config
+                .core
+                .eol
+                .as_deref()
+                .is_some_and(|eol| eol.eq_ignore_ascii_case("crlf")),
+        };
+        let safe = !matches!(
+            repo.config
+                .core
+                .safecrlf
+                .as_deref()
+                .map(str::to_lowercase)
+                .as_deref(),
+            Some("false" | "no" | "off" | "0")
+        );
+        // Like git, drivers that are not configured are ignored
+        let filter = match get("filter") {
+            Some(AttrValue::Value(name)) => {
+                let driver = repo.config.filters.get(&name.to_lowercase());
+                driver.map(|driver| (name, driver.clone()))
+            }
+            _ => None,
+        };
+        Self {
+            worktree: repo.worktree.clone(),
+            path: path.to_owned(),
+            filter,
+            ident: get("ident") == Some(AttrValue::Set),
+            eol,
+            crlf,
+            indexed_cr: false,
+            safe,
+        }
+    }
+    /// Runs the filter driver's command of `kind`, failing when it is required
+    fn filter(&self, kind: FilterKind, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let Some((name, driver)) = &self.filter else {
+            return Ok(data);
+        };
+        match apply_filter(&self.worktree, driver, kind, &self.path, &data) {
+            Some(filtered) => Ok(filtered),
+            None if driver.required => Err(match kind {
+                FilterKind::Clean => format!("{}: clean filter '{}' failed", self.path, name),
+                FilterKind::Smudge => format!("{}: smudge filter {} failed", self.path, name),
+            }),
+            None => Ok(data),
+        }
+    }
+    /// Takes into account the blob the index has for the file: like git, `text=auto` leaves
+    /// CRLF line endings alone when it holds any CR
+    pub fn with_indexed(mut self, repo: &Repository, hash: &str) -> Result<Self, String> {
+        if self.is_auto() {
+            self.indexed_cr = Object::read_data(repo, hash)?.1.contains(&b'\r');
+        }
+        Ok(self)
+    }
+    fn worktree_crlf(&self) -> bool {
+        match self.eol {
+            EolAction::Binary | EolAction::TextInput | EolAction::AutoInput => false,
+            EolAction::TextCrlf | EolAction::AutoCrlf => true,
+            EolAction::Text | EolAction::Auto => self.crlf,
+        }
+    }
+    fn is_auto(&self) -> bool {
+        matches!(
+            self.eol,
+            EolAction::Auto | EolAction::AutoInput | EolAction::AutoCrlf
+        )
+    }
+    /// Whether LF line endings are written as CRLF in the worktree, as git's
+    /// `will_convert_lf_to_crlf`
+    fn converts_to_crlf(&self, stats: &TextStats) -> bool {
+        self.worktree_crlf()
+            && stats.lone_lf > 0
+            // Like git, files with mixed line endings are left alone with `text=auto`
+            && !(self.is_auto() && (stats.lone_cr > 0 || stats.crlf > 0 || stats.is_binary()))
+    }
+    /// Content stored in the repository for the worktree content `data`: CRLF line endings of
+    /// text files become LF
+    pub fn to_git(&self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        self.convert_to_git(data, None)
+    }
+    /// Like [Self::to_git], warning as git's `core.safecrlf` does when checking the file at
+    /// `path` out again would not give back the same line endings
+    pub fn to_git_checked(&self, data: Vec<u8>, path: &str) -> Result<Vec<u8>, String> {
+        self.convert_to_git(data, Some(path))
+    }
+    fn convert_to_git(&self, data: Vec<u8>, checked_path: Option<&str>) -> Result<Vec<u8>, String> {
+        let data = self.filter(FilterKind::Clean, data)?;
+        let data = self.crlf_to_git(data, checked_path);
+        Ok(match self.ident {
+            true => ident_to_git(data),
+            false => data,
+        })
+    }
+    fn crlf_to_git(&self, data: Vec<u8>, checked_path: Option<&str>) -> Vec<u8> {
+        if self.eol == EolAction::Binary || data.is_empty() {
+            return data;
+        }
+        let stats = TextStats::gather(&data);
+        if self.is_auto() && stats.is_binary() {
+            return data;
+        }
+        let convert = stats.crlf > 0 && !(self.is_auto() && self.indexed_cr);
+        if let Some(path) = checked_path.filter(|_| self.safe) {
+            let mut round_trip = TextStats {
+                lone_lf: stats.lone_lf + if convert { stats.crlf } else { 0 },
+                crlf: if convert { 0 } else { stats.crlf },
+                ..stats
+            };
+            if self.converts_to_crlf(&round_trip) {
+                round_trip.crlf += round_trip.lone_lf;
+                round_trip.lone_lf = 0;
+            }
+            if stats.crlf > 0 && round_trip.crlf == 0 {
+                eprintln!(
+                    "warning: in the working copy of '{}', CRLF will be replaced by LF the next time Git touches it",
+                    path
+                );
+            } else if stats.lone_lf > 0 && round_trip.lone_lf == 0 {
+                eprintln!(
+                    "warning: in the working copy of '{}', LF will be replaced by CRLF the next time Git touches it",
+                    path
+                );
+            }
+        }
+        if !convert {
+            return data;
+        }
+        let mut converted = Vec::with_capacity(data.len() - stats.crlf);
+        for (i, &b) in data.iter().enumerate() {
+            if b != b'\r' || data.get(i + 1) != Some(&b'\n') {
+                converted.push(b);
+            }
+        }
+        converted
+    }
+    /// Content written to the worktree for the blob content `data`: LF line endings of text
+    /// files become CRLF when the worktree uses them
+    pub fn to_worktree(&self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let data = match self.ident {
+            true => ident_to_worktree(data),
+            false => data,
+        };
+        let data = self.crlf_to_worktree(data);
+        self.filter(FilterKind::Smudge, data)
+    }
+    fn crlf_to_worktree(&self, data: Vec<u8>) -> Vec<u8> {
+        if !self.worktree_crlf() {
+            return data;
+        }
+        let stats = TextStats::gather(&data);
+        if !self.converts_to_crlf(&stats) {
+            return data;
+        }
+        let mut converted = Vec::with_capacity(data.len() + stats.lone_lf);
+        for (i, &b) in data.iter().enumerate() {
+            if b == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+                converted.push(b'\r');
+            }
+            converted.push(b);
+        }
+        converted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eol_conversion() {
+        let text = Conversion {
+            eol: EolAction::TextCrlf,
+            ..Default::default()
+        };
+        assert_eq!(
+            text.to_git(b"a\r\nb\rc\r\n".to_vec()).unwrap(),
+            b"a\nb\rc\n"
+        );
+        assert_eq!(
+            text.to_worktree(b"a\nb\r\nc".to_vec()).unwrap(),
+            b"a\r\nb\r\nc"
+        );
+        let auto = Conversion {
+            eol: EolAction::Auto,
+            crlf: true,
+            ..Default::default()
+        };
+        assert_eq!(auto.to_git(b"a\r\nb\0".to_vec()).unwrap(), b"a\r\nb\0");
+        assert_eq!(auto.to_worktree(b"a\nb\r\n".to_vec()).unwrap(), b"a\nb\r\n");
+        assert_eq!(auto.to_worktree(b"a\nb\n".to_vec()).unwrap(), b"a\r\nb\r\n");
+        let indexed = Conversion {
+            indexed_cr: true,
+            ..auto.clone()
+        };
+        assert_eq!(
+            indexed.to_git(b"a\r\nb\r\n".to_vec()).unwrap(),
+            b"a\r\nb\r\n"
+        );
+        assert_eq!(
+            Conversion::default().to_git(b"a\r\n".to_vec()).unwrap(),
+            b"a\r\n"
+        );
+    }
+
+    #[test]
+    fn test_ident() {
+        let ident = Conversion {
+            ident: true,
+            ..Default::default()
+        };
+        let blob = b"$Id$ $Id: old $\n$Id: a b c $\n".to_vec();
+        let hash = Object::hash_data(&ObjectTypes::Blob, &blob);
+        let expanded = ident.to_worktree(blob.clone()).unwrap();
+        assert_eq!(
+            String::from_utf8(expanded.clone()).unwrap(),
+            format!("$Id: {0} $ $Id: {0} $\n$Id: a b c $\n", hash)
+        );
+        assert_eq!(ident.to_git(expanded).unwrap(), b"$Id$ $Id$\n$Id$\n");
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    list_refs, peel_to_commit, shorten_ref_name, wildmatch, CommitInfo, Object, ObjectHeaders,
+    ObjectTypes, Repository, Tag,
+};
+
+/// Distance added for going through a merge's other parents, so that names following first
+/// parents are always preferred
+const MERGE_TRAVERSAL_WEIGHT: usize = 65535;
+/// Slack given to the dates of the commits to name, in case of clock skew
+const CUTOFF_DATE_SLOP: i64 = 86400;
+
+#[derive(Clone, Debug)]
+pub struct DescribeOptions {
+    /// Also use lightweight tags, not only annotated ones
+    pub tags: bool,
+    /// Show the distance and abbreviated hash even when the commit is tagged
+    pub long: bool,
+    /// Length of the abbreviated hash, 0 to only show the tag
+    pub abbrev: usize,
+    /// Only use tags whose name matches one of these globs
+    pub patterns: Vec<String>,
+    /// How many tags to consider before settling on the closest one found
+    pub candidates: usize,
+}
+impl Default for DescribeOptions {
+    fn default() -> Self {
+        Self {
+            tags: false,
+            long: false,
+            abbrev: 7,
+            patterns: Vec::new(),
+            candidates: 10,
+        }
+    }
+}
+
+/// A tag that can describe the commit it points to
+struct TagName {
+    name: String,
+    annotated: bool,
+    /// Tagger date of annotated tags
+    date: i64,
+}
+
+/// A tag found while walking from the described commit
+struct Candidate<'a> {
+    tag: &'a TagName,
+    /// Number of walked commits that the tag cannot reach
+    depth: usize,
+    /// Bit marking the commits reachable from the tag
+    flag: u32,
+    found_order: usize,
+}
+
+/// The tags under `refs/tags`, keyed by the commit they point to
+fn tag_names(
+    repo: &Repository,
+    options: &DescribeOptions,
+) -> Result<HashMap<String, TagName>, String> {
+    let mut names: HashMap<String, TagName> = HashMap::new();
+    for (ref_name, hash) in list_refs(repo, "refs/tags/") {
+        let name = shorten_ref_name(&ref_name).to_owned();
+        if !options.patterns.is_empty()
+            && !options
+                .patterns
+                .iter()
+                .any(|pattern| wildmatch(pattern, &name))
+        {
+            continue;
+        }
+        let Ok(commit) = peel_to_commit(repo, &hash) else {
+            continue;
+        };
+        let object = Object::read_from_sha(repo, hash)?;
+        let annotated = matches!(object._type, ObjectTypes::Tag);
+        let date = match object.header {
+            ObjectHeaders::Tag(Tag {
+                tagger: Some(tagger),
+                ..
+            }) => tagger.time,
+            _ => 0,
+        };
+        // Annotated tags win over lightweight ones, and newer ones over older ones
+        let replace = match names.get(&commit) {
+            None => true,
+            Some(existing) => annotated && (!existing.annotated || existing.date < date),
+        };
+        if replace {
+            names.insert(
+                commit,
+                TagName {
+                    name,
+                    annotated,
+                    date,
+                },
+            );
+        }
+    }
+    Ok(names)
+}
+
+fn cached_commit<'a>(
+    repo: &Repository,
+    commits: &'a mut HashMap<String, CommitInfo>,
+    hash: &str,
+) -> Result<&'a CommitInfo, String> {
+    if !commits.contains_key(hash) {
+        commits.insert(hash.to_owned(), CommitInfo::read(repo, hash)?);
+    }
+    Ok(&commits[hash])
+}
+
+/// Commits waiting to be walked, most recent first
+struct DateQueue<'a> {
+    repo: &'a Repository,
+    commits: HashMap<String, CommitInfo>,
+    queue: VecDeque<String>,
+}
+impl DateQueue<'_> {
+    fn commit(&mut self, hash: &str) -> Result<&CommitInfo, String> {
+        cached_commit(self.repo, &mut self.commits, hash)
+    }
+    /// Inserts a commit after the ones that are not older than it
+    fn insert(&mut self, hash: &str) -> Result<(), String> {
+        let date = self.commit(hash)?.committer.time;
+        let position = self
+            .queue
+            .iter()
+            .position(|other| self.commits[other].committer.time < date)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(position, hash.to_owned());
+        Ok(())
+    }
+    /// Walks the commit's parents, queueing the ones not seen yet and marking them with the
+    /// commit's flags
+    fn walk_parents(&mut self, hash: &str, flags: &mut HashMap<String, u32>) -> Result<(), String> {
+        let flag = flags[hash];
+        for parent in self.commit(hash)?.parents.clone() {
+            if !flags.contains_key(&parent) {
+                self.insert(&parent)?;
+            }
+            *flags.entry(parent).or_default() |= flag;
+        }
+        Ok(())
+    }
+}
+
+/// Names a commit after the closest tag it is reachable from, as `<tag>-<count>-g<hash>` where
+/// count is the number of commits it is ahead of the tag, or `<tag>` when it is tagged
+pub fn describe(
+    repo: &Repository,
+    commit: &str,
+    options: &DescribeOptions,
+) -> Result<String, String> {
+    let names = tag_names(repo, options)?;
+    if names.is_empty() {
+        return Err(String::from("No names found, cannot describe anything"));
+    }
+    let suffix = |depth: usize| format!("-{}-g{}", depth, &commit[..options.abbrev.clamp(4, 40)]);
+    if let Some(tag) = names
+        .get(commit)
+        .filter(|tag| options.tags || tag.annotated)
+    {
+        return Ok(match options.long {
+            true => format!("{}{}", tag.name, suffix(0)),
+            false => tag.name.clone(),
+        });
+    }
+    if options.candidates == 0 {
+        return Err(format!("No tag exactly matches {}", commit));
+    }
+
+    let mut queue = DateQueue {
+        repo,
+        commits: HashMap::new(),
+        queue: VecDeque::new(),
+    };
+    // Every walked commit has flags, holding the bits of the candidates that reach it
+    let mut flags: HashMap<String, u32> = HashMap::from([(commit.to_owned(), 0)]);
+    queue.insert(commit)?;
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let (mut annotated, mut unannotated) = (0, 0);
+    let mut seen = 0;
+    let mut gave_up_on = None;
+    while let Some(current) = queue.queue.pop_front() {
+        seen += 1;
+        if let Some(tag) = names.get(&current) {
+            if !options.tags && !tag.annotated {
+                unannotated += 1;
+            } else if candidates.len() < options.candidates.min(31) {
+                let flag = 1 << candidates.len();
+                candidates.push(Candidate {
+                    tag,
+                    depth: seen - 1,
+                    flag,
+                    found_order: candidates.len(),
+                });
+                *flags.get_mut(&current).unwrap() |= flag;
+                if tag.annotated {
+                    annotated += 1;
+                }
+            } else {
+                gave_up_on = Some(current);
+                break;
+            }
+        }
+        let current_flags = flags[&current];
+        for candidate in candidates.iter_mut() {
+            if current_flags & candidate.flag == 0 {
+                candidate.depth += 1;
+            }
+        }
+        // Stop once the last remaining path is already covered by the best candidates
+        if annotated > 0 && queue.queue.is_empty() {
+            let best_depth = candidates.iter().map(|candidate| candidate.depth).min();
+            let best_flags = candidates
+                .iter()
+                .filter(|candidate| Some(candidate.depth) == best_depth)
+                .fold(0, |flags, candidate| flags | candidate.flag);
+            if current_flags & best_flags == best_flags {
+                break;
+            }
+        }
+        queue.walk_parents(&current, &mut flags)?;
+    }
+
+    if candidates.is_empty() {
+        return Err(match unannotated {
+            0 => format!("No tags can describe {}", commit),
+            _ => format!(
+                "No annotated tags can describe {}, however there were unannotated tags: try --tags",
+                commit
+            ),
+        });
+    }
+    candidates.sort_by_key(|candidate| (candidate.depth, candidate.found_order));
+    if let Some(current) = gave_up_on {
+        queue.insert(&current)?;
+    }
+    // Counts the remaining commits the best candidate cannot reach
+    let best = &mut candidates[0];
+    while let Some(current) = queue.queue.pop_front() {
+        if flags[&current] & best.flag != 0 {
+            if queue
+                .queue
+                .iter()
+                .all(|other| flags[other] & best.flag != 0)
+            {
+                break;
+            }
+        } else {
+            best.depth += 1;
+        }
+        queue.walk_parents(&current, &mut flags)?;
+    }
+    Ok(match options.abbrev {
+        0 => best.tag.name.clone(),
+        _ => format!("{}{}", best.tag.name, suffix(best.depth)),
+    })
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NameRevOptions {
+    /// Only name commits after tags
+    pub tags: bool,
+    /// Only use refs matching one of these globs, either in full or from one of their `/`
+    pub refs: Vec<String>,
+    /// Shorten the tag names as much as possible, set when only names are printed
+    pub name_only: bool,
+}
+
+/// How a commit is reached from a ref
+#[derive(Clone, Debug)]
+struct RevName {
+    tip_name: String,
+    tagger_date: i64,
+    /// Number of first parents followed since the tip or the last merge's other parent
+    generation: usize,
+    distance: usize,
+    from_tag: bool,
+}
+impl RevName {
+    fn is_worse_than(&self, other: &RevName) -> bool {
+        // Names based on older tags are preferred, even when they are farther away
+        if self.from_tag && other.from_tag {
+            return self.tagger_date > other.tagger_date
+                || (self.tagger_date == other.tagger_date && self.distance > other.distance);
+        }
+        if self.from_tag != other.from_tag {
+            return other.from_tag;
+        }
+        if self.distance != other.distance {
+            return self.distance > other.distance;
+        }
+        self.tagger_date > other.tagger_date
+    }
+    /// Name of the commit's n-th parent, for n > 1
+    fn parent_name(&self, parent_number: usize) -> String {
+        let tip = self.tip_name.strip_suffix("^0").unwrap_or(&self.tip_name);
+        match self.generation {
+            0 => format!("{}^{}", tip, parent_number),
+            generation => format!("{}~{}^{}", tip, generation, parent_number),
+        }
+    }
+    fn name(&self) -> String {
+        match self.generation {
+            0 => self.tip_name.clone(),
+            generation => format!(
+                "{}~{}",
+                self.tip_name.strip_suffix("^0").unwrap_or(&self.tip_name),
+                generation
+            ),
+        }
+    }
+}
+
+/// A ref to name commits after
+struct Tip {
+    name: String,
+    commit: String,
+    tagger_date: i64,
+    from_tag: bool,
+    /// Whether the ref points to a tag rather than the commit itself
+    peeled: bool,
+}
+
+/// Whether the glob matches the ref name, or the part after one of its `/`, returning whether
+/// the ref name can be shortened in that case
+fn subpath_matches(ref_name: &str, pattern: &str) -> Option<bool> {
+    if wildmatch(pattern, ref_name) {
+        return Some(false);
+    }
+    ref_name
+        .match_indices('/')
+        .any(|(i, _)| wildmatch(pattern, &ref_name[i + 1..]))
+        .then_some(true)
+}
+
+/// Names each commit relative to the refs it is reachable from, such as `main~3^2` for the
+/// second parent of the commit three first parents before `main`, or `None` when it cannot be
+/// reached.
+///
+/// Tags are preferred over branches, then the closest and oldest ref. Objects other than
+/// commits are only named by a ref pointing directly at them.
+pub fn name_revs(
+    repo: &Repository,
+    objects: &[String],
+    options: &NameRevOptions,
+) -> Result<Vec<Option<String>>, String> {
+    let mut infos: HashMap<String, CommitInfo> = HashMap::new();
+    let commit_date = |infos: &mut HashMap<String, CommitInfo>, hash: &str| {
+        cached_commit(repo, infos, hash).map(|commit| commit.committer.time)
+    };
+    let mut cutoff = i64::MAX;
+    for object in objects {
+        if peel_to_commit(repo, object).is_ok_and(|commit| &commit == object) {
+            cutoff = cutoff.min(commit_date(&mut infos, object)?);
+        }
+    }
+    let cutoff = cutoff.saturating_sub(CUTOFF_DATE_SLOP);
+
+    let mut tips = Vec::new();
+    let mut ref_names: HashMap<String, String> = HashMap::new();
+    for (ref_name, hash) in list_refs(repo, "refs/") {
+        let from_tag = ref_name.starts_with("refs/tags/");
+        if options.tags && !from_tag {
+            continue;
+        }
+        let mut shorten = options.tags && options.name_only;
+        if !options.refs.is_empty() {
+            let matches: Vec<bool> = options
+                .refs
+                .iter()
+                .filter_map(|pattern| subpath_matches(&ref_name, pattern))
+                .collect();
+            if matches.is_empty() {
+                continue;
+            }
+            shorten |= matches.contains(&true);
+        }
+        let Ok(commit) = peel_to_commit(repo, &hash) else {
+            continue;
+        };
+        let object = Object::read_from_sha(repo, hash.clone())?;
+        let tagger_date = match object.header {
+            ObjectHeaders::Tag(Tag {
+                tagger: Some(tagger),
+                ..
+            }) => tagger.time,
+            _ => commit_date(&mut infos, &commit)?,
+        };
+        let name = if shorten {
+            shorten_ref_name(&ref_name)
+        } else {
+            ref_name
+                .strip_prefix("refs/heads/")
+                .or(ref_name.strip_prefix("refs/"))
+                .unwrap_or(&ref_name)
+        };
+        ref_names
+            .entry(hash.clone())
+            .or_insert_with(|| name.to_owned());
+        tips.push(Tip {
+            name: name.to_owned(),
+            peeled: commit != hash,
+            commit,
+            tagger_date,
+            from_tag,
+        });
+    }
+    // Better names are spread first, so that worse ones spread less
+    tips.sort_by_key(|tip| (!tip.from_tag, tip.tagger_date));
+
+    let mut names: HashMap<String, RevName> = HashMap::new();
+    for tip in tips {
+        if commit_date(&mut infos, &tip.commit)? < cutoff {
+            continue;
+        }
+        let start = RevName {
+            tip_name: match tip.peeled {
+                true => format!("{}^0", tip.name),
+                false => tip.name,
+            },
+            tagger_date: tip.tagger_date,
+            generation: 0,
+            distance: 0,
+            from_tag: tip.from_tag,
+        };
+        if names
+            .get(&tip.commit)
+            .is_some_and(|name| !name.is_worse_than(&start))
+        {
+            continue;
+        }
+        names.insert(tip.commit.clone(), start);
+        let mut stack = vec![tip.commit];
+        while let Some(commit) = stack.pop() {
+            let name = names[&commit].clone();
+            let parents = cached_commit(repo, &mut infos, &commit)?.parents.clone();
+            let mut named_parents = Vec::new();
+            for (i, parent) in parents.iter().enumerate() {
+                if commit_date(&mut infos, parent)? < cutoff {
+                    continue;
+                }
+                let parent_name = match i {
+                    0 => RevName {
+                        generation: name.generation + 1,
+                        distance: name.distance + 1,
+                        ..name.clone()
+                    },
+                    _ => RevName {
+                        tip_name: name.parent_name(i + 1),
+                        generation: 0,
+                        distance: name.distance + MERGE_TRAVERSAL_WEIGHT,
+                        ..name.clone()
+                    },
+                };
+                if names
+                    .get(parent)
+                    .is_some_and(|existing| !existing.is_worse_than(&parent_name))
+                {
+                    continue;
+                }
+                names.insert(parent.clone(), parent_name);
+                named_parents.push(parent.clone());
+            }
+            // The first parent is walked first
+            stack.extend(named_parents.into_iter().rev());
+        }
+    }
+    Ok(objects
+        .iter()
+        .map(|object| match names.get(object) {
+            Some(name) => Some(name.name()),
+            None => ref_names.get(object).cloned(),
+        })
+        .collect())
+}
@@ -0,0 +1,525 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    merge::detect_renames, Object, ObjectHeaders, Pathspec, Repository, TreeNode, TreeNodeType,
+    TreeObject,
+};
+
+/// A region where `a[a_start..a_start + a_len]` was replaced by `b[b_start..b_start + b_len]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub a_start: usize,
+    pub a_len: usize,
+    pub b_start: usize,
+    pub b_len: usize,
+}
+impl DiffHunk {
+    pub fn a_end(&self) -> usize {
+        self.a_start + self.a_len
+    }
+    pub fn b_end(&self) -> usize {
+        self.b_start + self.b_len
+    }
+}
+
+/// Splits data into lines, each line keeps its trailing `\n` (if any)
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Computes the changed regions between `a` and `b` using the Myers O(ND) algorithm.
+///
+/// Only the regions that differ are returned, in ascending order.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<DiffHunk> {
+    // Trimming the common prefix and suffix keeps the search space small for the usual case
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let mut a_pos = 0;
+    let mut b_pos = 0;
+    for (x, y) in myers_matches(a_mid, b_mid)
+        .into_iter()
+        .chain([(a_mid.len(), b_mid.len())])
+    {
+        if x > a_pos || y > b_pos {
+            hunks.push(DiffHunk {
+                a_start: prefix + a_pos,
+                a_len: x - a_pos,
+                b_start: prefix + b_pos,
+                b_len: y - b_pos,
+            });
+        }
+        a_pos = x + 1;
+        b_pos = y + 1;
+    }
+    hunks
+}
+
+/// Returns the matching `(a_index, b_index)` pairs of the shortest edit script
+fn myers_matches<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    if max == 0 {
+        return Vec::new();
+    }
+    let offset = max as usize;
+    let mut v = vec![0isize; 2 * offset + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut matches = Vec::new();
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + max) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + max) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+    // Whatever remains on the diagonal at d = 0 is a leading snake
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+    matches
+}
+
+/// Renders the changes from `a` to `b` as unified diff hunks with `context` lines around each change
+pub fn unified_diff(a: &[u8], b: &[u8], context: usize) -> String {
+    let a_lines = split_lines(a);
+    let b_lines = split_lines(b);
+    let hunks = diff(&a_lines, &b_lines);
+    let mut output = String::new();
+    let mut hunks = hunks.iter().peekable();
+    while let Some(first) = hunks.next() {
+        let mut group = vec![first];
+        while let Some(next) =
+            hunks.next_if(|next| next.a_start - group[group.len() - 1].a_end() <= 2 * context)
+        {
+            group.push(next);
+        }
+        let last = group[group.len() - 1];
+        let a_from = first.a_start.saturating_sub(context);
+        let a_to = (last.a_end() + context).min(a_lines.len());
+        let b_from = first.b_start - (first.a_start - a_from);
+        let b_to = last.b_end() + (a_to - last.a_end());
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(a_from, a_to - a_from),
+            hunk_range(b_from, b_to - b_from)
+        ));
+        let mut a_pos = a_from;
+        for hunk in group {
+            for line in &a_lines[a_pos..hunk.a_start] {
+                push_line(&mut output, ' ', line);
+            }
+            for line in &a_lines[hunk.a_start..hunk.a_end()] {
+                push_line(&mut output, '-', line);
+            }
+            for line in &b_lines[hunk.b_start..hunk.b_end()] {
+                push_line(&mut output, '+', line);
+            }
+            a_pos = hunk.a_end();
+        }
+        for line in &a_lines[a_pos..a_to] {
+            push_line(&mut output, ' ', line);
+        }
+    }
+    output
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn push_line(output: &mut String, prefix: char, line: &[u8]) {
+    output.push(prefix);
+    output.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        output.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// Whether data looks binary the way git guesses it, a NUL byte within the first 8000 bytes
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&byte| byte == 0)
+}
+
+/// A path that differs between two trees, with its node on each side (`None` when absent)
+#[derive(Clone, Debug)]
+pub struct FileChange {
+    pub path: String,
+    pub old: Option<TreeNode>,
+    pub new: Option<TreeNode>,
+}
+
+/// Files that differ between two trees, sorted by path
+pub fn diff_trees(repo: &Repository, old: &str, new: &str) -> Result<Vec<FileChange>, String> {
+    diff_tree_paths(repo, Some(old), Some(new), &Pathspec::default())
+}
+
+/// Files matching `pathspec` that differ between two trees, `None` standing for the empty tree.
+///
+/// Subtrees with the same hash on both sides, or that cannot hold any matching file, are skipped
+/// without being read.
+pub fn diff_tree_paths(
+    repo: &Repository,
+    old: Option<&str>,
+    new: Option<&str>,
+    pathspec: &Pathspec,
+) -> Result<Vec<FileChange>, String> {
+    let mut changes = Vec::new();
+    diff_subtrees(repo, old, new, "", pathspec, &mut changes)?;
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Path in `old` that `path` of `new` was renamed from, when the path was added by a rename
+pub fn rename_source(
+    repo: &Repository,
+    old: &str,
+    new: &str,
+    path: &str,
+) -> Result<Option<String>, String> {
+    let changes = diff_tree_paths(repo, Some(old), Some(new), &Pathspec::default())?;
+    if !changes
+        .iter()
+        .any(|change| change.path == path && change.old.is_none())
+    {
+        return Ok(None);
+    }
+    let side = |node: &Option<TreeNode>, other: &Option<TreeNode>| {
+        node.as_ref()
+            .filter(|_| other.is_none())
+            .map(|node| (node.path.clone(), node.clone()))
+    };
+    let deleted = changes
+        .iter()
+        .filter_map(|change| side(&change.old, &change.new))
+        .collect();
+    let added = changes
+        .iter()
+        .filter_map(|change| side(&change.new, &change.old))
+        .collect();
+    let renames = detect_renames(repo, &deleted, &added)?;
+    Ok(renames
+        .into_iter()
+        .find(|(_, target)| target == path)
+        .map(|(source, _)| source))
+}
+
+fn diff_subtrees(
+    repo: &Repository,
+    old: Option<&str>,
+    new: Option<&str>,
+    prefix: &str,
+    pathspec: &Pathspec,
+    changes: &mut Vec<FileChange>,
+) -> Result<(), String> {
+    let entries = |hash: Option<&str>| match hash {
+        Some(hash) => TreeObject::read_from_sha(repo, hash).map(|tree| tree.entries),
+        None => Ok(Vec::new()),
+    };
+    let mut nodes: BTreeMap<String, (Option<TreeNode>, Option<TreeNode>)> = BTreeMap::new();
+    for node in entries(old)? {
+        let path = format!("{}{}", prefix, node.path);
+        nodes.entry(path).or_default().0 = Some(node);
+    }
+    for node in entries(new)? {
+        let path = format!("{}{}", prefix, node.path);
+        nodes.entry(path).or_default().1 = Some(node);
+    }
+    let is_tree = |node: &Option<TreeNode>| {
+        node.as_ref()
+            .is_some_and(|node| matches!(node._type, TreeNodeType::Tree))
+    };
+    for (path, (old, new)) in nodes {
+        if let (Some(old), Some(new)) = (&old, &new) {
+            if old.hash == new.hash && old.mode == new.mode {
+                continue;
+            }
+        }
+        let (old_tree, new_tree) = (is_tree(&old), is_tree(&new));
+        if (old_tree || new_tree) && pathspec.may_match_in(&path) {
+            let subtree = |node: &Option<TreeNode>, is_tree: bool| match is_tree {
+                true => node.as_ref().map(|node| node.hash.clone()),
+                false => None,
+            };
+            diff_subtrees(
+                repo,
+                subtree(&old, old_tree).as_deref(),
+                subtree(&new, new_tree).as_deref(),
+                &format!("{}/", path),
+                pathspec,
+                changes,
+            )?;
+        }
+        // A file replaced by a directory (or the reverse) is also a change of the file itself
+        let old = old.filter(|_| !old_tree);
+        let new = new.filter(|_| !new_tree);
+        if (old.is_some() || new.is_some()) && pathspec.matches(&path) {
+            let with_path = |node: TreeNode| TreeNode {
+                path: path.clone(),
+                ..node
+            };
+            let (old, new) = (old.map(with_path), new.map(with_path));
+            changes.push(FileChange { path, old, new });
+        }
+    }
+    Ok(())
+}
+
+fn node_content(repo: &Repository, node: Option<&TreeNode>) -> Result<Vec<u8>, String> {
+    let Some(node) = node else {
+        return Ok(Vec::new());
+    };
+    if node.mode == "160000" {
+        return Ok(format!("Subproject commit {}\n", node.hash).into_bytes());
+    }
+    match Object::read_from_sha(repo, node.hash.clone())?.header {
+        ObjectHeaders::Blob { data } => Ok(data),
+        _ => Err(format!("Expected blob for {}, {}", node.path, node.hash)),
+    }
+}
+
+fn short_hash(node: Option<&TreeNode>) -> &str {
+    node.map_or("0000000", |node| &node.hash[..7])
+}
+
+/// Renders changes as a git style patch (`diff --git` headers followed by unified hunks)
+pub fn format_patch(repo: &Repository, changes: &[FileChange]) -> Result<String, String> {
+    let mut output = String::new();
+    for change in changes {
+        let (old, new) = (change.old.as_ref(), change.new.as_ref());
+        output.push_str(&format!("diff --git a/{} b/{}\n", change.path, change.path));
+        match (old, new) {
+            (None, Some(new)) => output.push_str(&format!("new file mode {}\n", new.mode)),
+            (Some(old), None) => output.push_str(&format!("deleted file mode {}\n", old.mode)),
+            (Some(old), Some(new)) if old.mode != new.mode => {
+                output.push_str(&format!("old mode {}\nnew mode {}\n", old.mode, new.mode))
+            }
+            _ => {}
+        }
+        output.push_str(&format!("index {}..{}", short_hash(old), short_hash(new)));
+        match (old, new) {
+            (Some(old), Some(new)) if old.mode == new.mode => {
+                output.push_str(&format!(" {}\n", old.mode))
+            }
+            _ => output.push('\n'),
+        }
+        let old_data = node_content(repo, old)?;
+        let new_data = node_content(repo, new)?;
+        let old_name = old.map_or(String::from("/dev/null"), |_| format!("a/{}", change.path));
+        let new_name = new.map_or(String::from("/dev/null"), |_| format!("b/{}", change.path));
+        if is_binary(&old_data) || is_binary(&new_data) {
+            output.push_str(&format!(
+                "Binary files {} and {} differ\n",
+                old_name, new_name
+            ));
+        } else if old_data != new_data {
+            output.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+            output.push_str(&unified_diff(&old_data, &new_data, 3));
+        }
+    }
+    Ok(output)
+}
+
+/// Renders changes as a git style diffstat, one line per file followed by a summary
+pub fn format_stat(repo: &Repository, changes: &[FileChange]) -> Result<String, String> {
+    let mut rows = Vec::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for change in changes {
+        let old_data = node_content(repo, change.old.as_ref())?;
+        let new_data = node_content(repo, change.new.as_ref())?;
+        if is_binary(&old_data) || is_binary(&new_data) {
+            rows.push((change.path.as_str(), None, old_data.len(), new_data.len()));
+            continue;
+        }
+        let old_lines = split_lines(&old_data);
+        let new_lines = split_lines(&new_data);
+        let hunks = diff(&old_lines, &new_lines);
+        let added = hunks.iter().map(|hunk| hunk.b_len).sum::<usize>();
+        let removed = hunks.iter().map(|hunk| hunk.a_len).sum::<usize>();
+        insertions += added;
+        deletions += removed;
+        rows.push((change.path.as_str(), Some(added + removed), added, removed));
+    }
+    let name_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+    let max_changes = rows.iter().filter_map(|row| row.1).max().unwrap_or(0);
+    // "Bin" takes the place of the count for binary files
+    let count_width = match rows.iter().any(|row| row.1.is_none()) {
+        true => max_changes.to_string().len().max(3),
+        false => max_changes.to_string().len(),
+    };
+    let graph_width = 80usize.saturating_sub(name_width + count_width + 6).max(10);
+    let scale = |count: usize| match max_changes > graph_width {
+        true if count > 0 => ((count * graph_width * 2 + max_changes) / (max_changes * 2)).max(1),
+        true => 0,
+        false => count,
+    };
+    let mut output = String::new();
+    for (path, changes, added, removed) in &rows {
+        match changes {
+            Some(changes) => output.push_str(
+                format!(
+                    " {:<name_width$} | {:>count_width$} {}{}",
+                    path,
+                    changes,
+                    "+".repeat(scale(*added)),
+                    "-".repeat(scale(*removed)),
+                )
+                .trim_end(),
+            ),
+            None => output.push_str(&format!(
+                " {:<name_width$} | {:>count_width$} {} -> {} bytes",
+                path, "Bin", added, removed
+            )),
+        }
+        output.push('\n');
+    }
+    let plural = |count: usize, singular: &str, plural: &str| match count {
+        1 => format!("{} {}", count, singular),
+        _ => format!("{} {}", count, plural),
+    };
+    output.push_str(&format!(
+        " {}",
+        plural(rows.len(), "file changed", "files changed")
+    ));
+    if insertions > 0 || deletions == 0 {
+        output.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 || insertions == 0 {
+        output.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    output.push('\n');
+    Ok(output)
+}
+
+/// Lists the files created, deleted or whose mode changed, like git's `--summary`
+pub fn format_summary(changes: &[FileChange]) -> String {
+    let mut output = String::new();
+    for change in changes {
+        match (&change.old, &change.new) {
+            (None, Some(new)) => {
+                output.push_str(&format!(" create mode {} {}\n", new.mode, change.path))
+            }
+            (Some(old), None) => {
+                output.push_str(&format!(" delete mode {} {}\n", old.mode, change.path))
+            }
+            (Some(old), Some(new)) if old.mode != new.mode => output.push_str(&format!(
+                " mode change {} => {} {}\n",
+                old.mode, new.mode, change.path
+            )),
+            _ => {}
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let a = split_lines(b"a\nb\nc\nd\n");
+        let b = split_lines(b"a\nc\nd\ne\n");
+        assert_eq!(
+            diff(&a, &b),
+            vec![
+                DiffHunk {
+                    a_start: 1,
+                    a_len: 1,
+                    b_start: 1,
+                    b_len: 0
+                },
+                DiffHunk {
+                    a_start: 4,
+                    a_len: 0,
+                    b_start: 3,
+                    b_len: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let a = split_lines(b"a\nb\n");
+        assert!(diff(&a, &a).is_empty());
+        assert_eq!(
+            diff::<&[u8]>(&[], &a),
+            vec![DiffHunk {
+                a_start: 0,
+                a_len: 0,
+                b_start: 0,
+                b_len: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let a = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let b = b"1\n2\nthree\n4\n5\n6\n7\n8\n9";
+        assert_eq!(
+            unified_diff(a, b, 1),
+            "@@ -2,3 +2,3 @@\n 2\n-3\n+three\n 4\n@@ -8,2 +8,2 @@\n 8\n-9\n+9\n\\ No newline at end of file\n"
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+    thread,
+};
+
+use crate::{config::FilterConfig, shell_quote};
+
+/// Largest payload of a pkt-line, whose 4 byte header gives the length
+const MAX_PACKET_DATA: usize = 65516;
+
+/// Direction of a filter: `clean` when storing content, `smudge` when writing it to the worktree
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FilterKind {
+    Clean,
+    Smudge,
+}
+impl FilterKind {
+    fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Clean => "clean",
+            FilterKind::Smudge => "smudge",
+        }
+    }
+}
+
+fn write_packet(output: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    output.write_all(format!("{:04x}", data.len() + 4).as_bytes())?;
+    output.write_all(data)
+}
+
+fn write_flush(output: &mut impl Write) -> io::Result<()> {
+    output.write_all(b"0000")?;
+    output.flush()
+}
+
+/// Reads a pkt-line, `None` standing for a flush packet
+fn read_packet(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; 4];
+    input.read_exact(&mut header)?;
+    let length = std::str::from_utf8(&header)
+        .ok()
+        .and_then(|header| usize::from_str_radix(header, 16).ok())
+        .filter(|&length| length == 0 || length >= 4)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bad pkt-line header"))?;
+    if length == 0 {
+        return Ok(None);
+    }
+    let mut data = vec![0; length - 4];
+    input.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Reads text packets up to a flush packet, without their trailing newline
+fn read_list(input: &mut impl Read) -> io::Result<Vec<String>> {
+    let mut list = Vec::new();
+    while let Some(packet) = read_packet(input)? {
+        let text = String::from_utf8_lossy(&packet);
+        list.push(text.strip_suffix('\n').unwrap_or(&text).to_owned());
+    }
+    Ok(list)
+}
+
+/// A `filter.<name>.process` command, started once and fed every file of its kinds
+struct FilterProcess {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    /// `clean` and `smudge`, as far as the process supports them
+    capabilities: Vec<String>,
+}
+impl FilterProcess {
+    fn start(worktree: &Path, command: &str) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .args(["-c", command])
+            .current_dir(worktree)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("missing pipes"));
+        };
+        let mut process = Self {
+            command: command.to_owned(),
+            child,
+            stdin,
+            stdout,
+            capabilities: Vec::new(),
+        };
+        process.handshake()?;
+        Ok(process)
+    }
+    fn handshake(&mut self) -> io::Result<()> {
+        write_packet(&mut self.stdin, b"git-filter-client\n")?;
+        write_packet(&mut self.stdin, b"version=2\n")?;
+        write_flush(&mut self.stdin)?;
+        let welcome = read_list(&mut self.stdout)?;
+        if welcome.first().map(String::as_str) != Some("git-filter-server")
+            || !welcome.iter().any(|line| line == "version=2")
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected filter welcome",
+            ));
+        }
+        write_packet(&mut self.stdin, b"capability=clean\n")?;
+        write_packet(&mut self.stdin, b"capability=smudge\n")?;
+        write_flush(&mut self.stdin)?;
+        self.capabilities = read_list(&mut self.stdout)?
+            .iter()
+            .filter_map(|line| line.strip_prefix("capability="))
+            .map(str::to_owned)
+            .collect();
+        Ok(())
+    }
+    /// Filters the content, `Ok(None)` standing for a file the process refused with
+    /// `status=error`
+    fn filter(&mut self, kind: FilterKind, path: &str, data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        write_packet(
+            &mut self.stdin,
+            format!("command={}\n", kind.name()).as_bytes(),
+        )?;
+        write_packet(&mut self.stdin, format!("pathname={}\n", path).as_bytes())?;
+        write_flush(&mut self.stdin)?;
+        for chunk in data.chunks(MAX_PACKET_DATA) {
+            write_packet(&mut self.stdin, chunk)?;
+        }
+        write_flush(&mut self.stdin)?;
+        let mut status = status_of(&read_list(&mut self.stdout)?);
+        let mut filtered = Vec::new();
+        if status.as_deref() == Some("success") {
+            while let Some(packet) = read_packet(&mut self.stdout)? {
+                filtered.extend(packet);
+            }
+            // An empty list keeps the status given before the content
+            status = status_of(&read_list(&mut self.stdout)?).or(status);
+        }
+        match status.as_deref() {
+            Some("success") => Ok(Some(filtered)),
+            Some("error") => Ok(None),
+            // Like git, the process is no longer asked for this kind of filter
+            Some("abort") => {
+                self.capabilities
+                    .retain(|capability| capability != kind.name());
+                Ok(None)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected filter status",
+            )),
+        }
+    }
+}
+
+fn status_of(list: &[String]) -> Option<String> {
+    list.iter()
+        .rev()
+        .find_map(|line| line.strip_prefix("status="))
+        .map(str::to_owned)
+}
+
+/// Filter processes started so far, kept running until rit exits like git does
+static PROCESSES: Mutex<Vec<FilterProcess>> = Mutex::new(Vec::new());
+
+fn run_process(
+    worktree: &Path,
+    command: &str,
+    kind: FilterKind,
+    path: &str,
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let position = match processes
+        .iter()
+        .position(|process| process.command == command)
+    {
+        Some(position) => position,
+        None => match FilterProcess::start(worktree, command) {
+            Ok(process) => {
+                processes.push(process);
+                processes.len() - 1
+            }
+            Err(_) => {
+                eprintln!("error: cannot fork to run subprocess '{}'", command);
+                eprintln!("error: initialization for subprocess '{}' failed", command);
+                return None;
+            }
+        },
+    };
+    let process = &mut processes[position];
+    if !process
+        .capabilities
+        .iter()
+        .any(|capability| capability == kind.name())
+    {
+        return None;
+    }
+    match process.filter(kind, path, data) {
+        Ok(filtered) => filtered,
+        // Like git, a process breaking the protocol is stopped, to be restarted if needed
+        Err(_) => {
+            eprintln!("error: external filter '{}' failed", command);
+            let mut process = processes.remove(position);
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+            None
+        }
+    }
+}
+
+/// Runs a `clean` or `smudge` command through the shell, `%f` standing for the quoted path
+fn run_command(worktree: &Path, command: &str, path: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let command = command.replace("%f", &shell_quote(path));
+    let Ok(mut child) = Command::new("sh")
+        .args(["-c", &command])
+        .current_dir(worktree)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    else {
+        eprintln!("error: cannot fork to run external filter '{}'", command);
+        return None;
+    };
+    let mut stdin = child.stdin.take()?;
+    let mut stdout = child.stdout.take()?;
+    let mut filtered = Vec::new();
+    // The content is fed from another thread, as the command may write before reading it all
+    let read = thread::scope(|scope| {
+        scope.spawn(move || {
+            // Commands are free not to read their input
+            let _ = stdin.write_all(data);
+        });
+        stdout.read_to_end(&mut filtered)
+    });
+    if read.is_err() {
+        eprintln!("error: read from external filter '{}' failed", command);
+    }
+    let status = child.wait().ok()?;
+    if !status.success() {
+        eprintln!(
+            "error: external filter '{}' failed {}",
+            command,
+            status.code().unwrap_or(-1)
+        );
+        eprintln!("error: external filter '{}' failed", command);
+        return None;
+    }
+    read.ok().map(|_| filtered)
+}
+
+/// Runs the driver's filter of `kind` from `worktree` on the content of the file at `path`,
+/// relative to it.
+///
+/// Returns `None` when the file is left unfiltered, because the driver has no such filter or
+/// it failed, which has been reported.
+pub(crate) fn apply_filter(
+    worktree: &Path,
+    driver: &FilterConfig,
+    kind: FilterKind,
+    path: &str,
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    let command = match kind {
+        FilterKind::Clean => driver.clean.as_deref(),
+        FilterKind::Smudge => driver.smudge.as_deref(),
+    };
+    match (command, driver.process.as_deref()) {
+        (Some(command), None) if !command.is_empty() => run_command(worktree, command, path, data),
+        (_, Some(process)) if !process.is_empty() => {
+            run_process(worktree, process, kind, path, data)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pkt_lines() {
+        let mut output = Vec::new();
+        write_packet(&mut output, b"version=2\n").unwrap();
+        write_packet(&mut output, b"").unwrap();
+        write_flush(&mut output).unwrap();
+        assert_eq!(output, b"000eversion=2\n00040000");
+        let mut input = output.as_slice();
+        assert_eq!(read_list(&mut input).unwrap(), vec!["version=2", ""]);
+        assert!(read_packet(&mut b"00z1".as_slice()).is_err());
+    }
+}
@@ -0,0 +1,517 @@
+use std::mem::swap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GraphState {
+    Padding,
+    Skip,
+    PreCommit,
+    Commit,
+    PostMerge,
+    Collapsing,
+}
+
+/// Characters drawn below a merge for its parents, the first one depending on the merge layout
+const MERGE_CHARS: [char; 3] = ['/', '|', '\\'];
+
+/// Draws the branch lines of `log --graph` next to each commit, laid out the same way as git.
+///
+/// Commits are given in order with [Graph::update], the output of each is made of the lines
+/// up to the commit line ([Graph::show_commit]), then the lines drawn next to its text
+/// ([Graph::show_message]).
+pub struct Graph {
+    /// Commit being drawn, empty before the first update
+    commit: String,
+    /// Parents of the commit that are drawn
+    parents: Vec<String>,
+    /// Width of the graph, the text of the commit being aligned after it
+    width: usize,
+    /// How many rows have been added before an octopus merge to make room for its parents
+    expansion_row: usize,
+    state: GraphState,
+    prev_state: GraphState,
+    /// Column of the commit
+    commit_index: usize,
+    prev_commit_index: usize,
+    /// 0 when the first parent of a merge is to its left, so the merge is skewed that way, 1
+    /// otherwise
+    merge_layout: isize,
+    /// Columns added on the right of the commit by a merge, -1 when a parent joins straight away
+    edges_added: isize,
+    prev_edges_added: isize,
+    /// Commit each branch line is heading to, before this commit
+    columns: Vec<String>,
+    /// Commit each branch line is heading to, after this commit
+    new_columns: Vec<String>,
+    /// Column of `new_columns` each screen position is heading to, -1 when empty
+    mapping: Vec<isize>,
+    old_mapping: Vec<isize>,
+    mapping_size: usize,
+}
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Graph {
+    pub fn new() -> Self {
+        Self {
+            commit: String::new(),
+            parents: Vec::new(),
+            width: 0,
+            expansion_row: 0,
+            state: GraphState::Padding,
+            prev_state: GraphState::Padding,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: 0,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: Vec::new(),
+            new_columns: Vec::new(),
+            mapping: Vec::new(),
+            old_mapping: Vec::new(),
+            mapping_size: 0,
+        }
+    }
+    /// Moves on to the next commit to draw, given with its parents shown in the graph
+    pub fn update(&mut self, commit: &str, parents: Vec<String>) {
+        self.commit = commit.to_owned();
+        self.parents = parents;
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+        self.state = if self.state != GraphState::Padding {
+            GraphState::Skip
+        } else if self.needs_pre_commit_line() {
+            GraphState::PreCommit
+        } else {
+            GraphState::Commit
+        };
+    }
+    fn update_state(&mut self, state: GraphState) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+    fn num_dashed_parents(&self) -> isize {
+        self.parents.len() as isize + self.merge_layout - 3
+    }
+    /// Rows needed above an octopus merge to spread its parents, two per dashed parent
+    fn num_expansion_rows(&self) -> isize {
+        self.num_dashed_parents() * 2
+    }
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3
+            && (self.commit_index as isize) < self.columns.len() as isize - 1
+            && (self.expansion_row as isize) < self.num_expansion_rows()
+    }
+    fn is_mapping_correct(&self) -> bool {
+        self.mapping[..self.mapping_size]
+            .iter()
+            .enumerate()
+            .all(|(i, &target)| target < 0 || target as usize == i / 2)
+    }
+    pub fn is_commit_finished(&self) -> bool {
+        self.state == GraphState::Padding
+    }
+    fn update_columns(&mut self) {
+        swap(&mut self.columns, &mut self.new_columns);
+        self.new_columns.clear();
+
+        // At most, every current column plus every parent
+        let max_new_columns = self.columns.len() + self.parents.len();
+        if self.mapping.len() < 2 * max_new_columns {
+            self.mapping.resize(2 * max_new_columns, -1);
+            self.old_mapping.resize(2 * max_new_columns, -1);
+        }
+        self.mapping_size = 2 * max_new_columns;
+        self.mapping[..self.mapping_size].fill(-1);
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        // The commit goes in a new column on the right when none of its children was drawn
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = match self.columns.get(i) {
+                Some(commit) => commit.clone(),
+                None if seen_this => break,
+                None => self.commit.clone(),
+            };
+            if column_commit == self.commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = -1;
+                for parent in self.parents.clone() {
+                    self.insert_into_new_columns(&parent, i as isize);
+                }
+                // The commit always takes up at least 2 spaces
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(&column_commit, -1);
+            }
+        }
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+    }
+    /// Records where the branch line heading to `commit`, coming from column `index` when it is
+    /// a parent of the current commit, ends up
+    fn insert_into_new_columns(&mut self, commit: &str, index: isize) {
+        let i = match self.new_columns.iter().position(|column| column == commit) {
+            Some(i) => i,
+            None => {
+                self.new_columns.push(commit.to_owned());
+                self.new_columns.len() - 1
+            }
+        } as isize;
+        let width = self.width as isize;
+        let mapping_index;
+        if self.parents.len() > 1 && index > -1 && self.merge_layout == -1 {
+            // The first parent of a merge decides whether the merge is drawn skewed to the left
+            let distance = index - i;
+            let shift = if distance > 1 { 2 * distance - 3 } else { 1 };
+            self.merge_layout = if distance > 0 { 0 } else { 1 };
+            self.edges_added = self.parents.len() as isize + self.merge_layout - 2;
+            mapping_index = width + (self.merge_layout - 1) * shift;
+            self.width = (width + 2 * self.merge_layout) as usize;
+        } else if self.edges_added > 0 && i == self.mapping[self.width - 2] {
+            // A merge's edge heading to the last existing column joins it straight away
+            mapping_index = width - 2;
+            self.edges_added = -1;
+        } else {
+            mapping_index = width;
+            self.width += 2;
+        }
+        self.mapping[mapping_index as usize] = i;
+    }
+    fn next_line(&mut self) -> (String, bool) {
+        let mut line = String::new();
+        let shown_commit_line = self.state == GraphState::Commit;
+        match self.state {
+            GraphState::Padding => self.padding_row(&mut line),
+            GraphState::Skip => self.skip_row(&mut line),
+            GraphState::PreCommit => self.pre_commit_row(&mut line),
+            GraphState::Commit => self.commit_row(&mut line),
+            GraphState::PostMerge => self.post_merge_row(&mut line),
+            GraphState::Collapsing => self.collapsing_row(&mut line),
+        }
+        self.pad(&mut line);
+        (line, shown_commit_line)
+    }
+    fn pad(&self, line: &mut String) {
+        while line.len() < self.width {
+            line.push(' ');
+        }
+    }
+    /// Leaves every branch line unchanged
+    fn padding_row(&self, line: &mut String) {
+        for _ in &self.new_columns {
+            line.push_str("| ");
+        }
+    }
+    /// Shows that part of the graph is missing, when commits were drawn without their text
+    fn skip_row(&mut self, line: &mut String) {
+        line.push_str("...");
+        match self.needs_pre_commit_line() {
+            true => self.update_state(GraphState::PreCommit),
+            false => self.update_state(GraphState::Commit),
+        }
+    }
+    /// Moves the branch lines right of an octopus merge further right, to make room for it
+    fn pre_commit_row(&mut self, line: &mut String) {
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if *column == self.commit {
+                seen_this = true;
+                line.push('|');
+                line.push_str(&" ".repeat(self.expansion_row));
+            } else if seen_this && self.expansion_row == 0 {
+                // Lines drawn as `\` below the previous merge keep going that way
+                match self.prev_state == GraphState::PostMerge && self.prev_commit_index < i {
+                    true => line.push('\\'),
+                    false => line.push('|'),
+                }
+            } else if seen_this {
+                line.push('\\');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.update_state(GraphState::Commit);
+        }
+    }
+    /// Dashes from an octopus merge to the columns of its parents after the first two
+    fn octopus_merge(&self, line: &mut String) {
+        let dashed_parents = self.num_dashed_parents();
+        for i in 0..dashed_parents {
+            line.push('-');
+            line.push(if i == dashed_parents - 1 { '.' } else { '-' });
+        }
+    }
+    fn commit_row(&mut self, line: &mut String) {
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = match self.columns.get(i) {
+                Some(commit) => commit,
+                None if seen_this => break,
+                None => &self.commit,
+            };
+            if *column_commit == self.commit {
+                seen_this = true;
+                line.push('*');
+                if self.parents.len() > 2 {
+                    self.octopus_merge(line);
+                }
+            } else if seen_this && self.edges_added > 1 {
+                line.push('\\');
+            } else if seen_this && self.edges_added == 1 {
+                // Without a pre-commit row, lines drawn as `\` below the previous merge keep
+                // going that way
+                match self.prev_state == GraphState::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    true => line.push('\\'),
+                    false => line.push('|'),
+                }
+            } else if self.prev_state == GraphState::Collapsing
+                && self.old_mapping[2 * i + 1] == i as isize
+                && self.mapping[2 * i] < i as isize
+            {
+                line.push('/');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+        if self.parents.len() > 1 {
+            self.update_state(GraphState::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.update_state(GraphState::Padding);
+        } else {
+            self.update_state(GraphState::Collapsing);
+        }
+    }
+    /// Edges from a merge to each of its parents
+    fn post_merge_row(&mut self, line: &mut String) {
+        let first_parent = self.parents.first();
+        let mut parent_column_seen = false;
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column_commit = match self.columns.get(i) {
+                Some(commit) => commit,
+                None if seen_this => break,
+                None => &self.commit,
+            };
+            if *column_commit == self.commit {
+                seen_this = true;
+                let mut index = self.merge_layout as usize;
+                for j in 0..self.parents.len() {
+                    line.push(MERGE_CHARS[index]);
+                    if index == 2 {
+                        if self.edges_added > 0 || j < self.parents.len() - 1 {
+                            line.push(' ');
+                        }
+                    } else {
+                        index += 1;
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                match self.edges_added > 0 {
+                    true => line.push('\\'),
+                    false => line.push('|'),
+                }
+                line.push(' ');
+            } else {
+                line.push('|');
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    match parent_column_seen {
+                        true => line.push('_'),
+                        false => line.push(' '),
+                    }
+                }
+            }
+            if Some(column_commit) == first_parent {
+                parent_column_seen = true;
+            }
+        }
+        match self.is_mapping_correct() {
+            true => self.update_state(GraphState::Padding),
+            false => self.update_state(GraphState::Collapsing),
+        }
+    }
+    /// Moves branch lines left until each one is in its column, crossing at most one line over
+    /// the others per row
+    fn collapsing_row(&mut self, line: &mut String) {
+        let mut used_horizontal = false;
+        let mut horizontal_edge: isize = -1;
+        let mut horizontal_edge_target: isize = -1;
+        swap(&mut self.mapping, &mut self.old_mapping);
+        self.mapping[..self.mapping_size].fill(-1);
+
+        for i in 0..self.mapping_size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+            // Columns are inserted from the left, so lines only ever move to the left
+            let (target_position, position) = (target * 2, i as isize);
+            if target_position == position {
+                self.mapping[i] = target;
+            } else if self.mapping[i - 1] < 0 {
+                self.mapping[i - 1] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge = position;
+                    horizontal_edge_target = target;
+                    let mut j = target_position + 3;
+                    while j < position - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            } else if self.mapping[i - 1] == target {
+                // Joins the line on its left, heading to the same commit
+            } else {
+                // Crosses over the line on its left
+                self.mapping[i - 2] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge_target = target;
+                    horizontal_edge = position - 1;
+                    let mut j = target_position + 3;
+                    while j < position - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            }
+        }
+        self.old_mapping[..self.mapping_size].copy_from_slice(&self.mapping[..self.mapping_size]);
+        if self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.mapping[i];
+            let position = i as isize;
+            if target < 0 {
+                line.push(' ');
+            } else if target * 2 == position {
+                line.push('|');
+            } else if target == horizontal_edge_target && position != horizontal_edge - 1 {
+                // Only the first segment of a horizontal line continues on the next row
+                if position != target * 2 + 3 {
+                    self.mapping[i] = -1;
+                }
+                used_horizontal = true;
+                line.push('_');
+            } else {
+                if used_horizontal && position < horizontal_edge {
+                    self.mapping[i] = -1;
+                }
+                line.push('/');
+            }
+        }
+        if self.is_mapping_correct() {
+            self.update_state(GraphState::Padding);
+        }
+    }
+    /// A row that leaves the branch lines unchanged, used between entries
+    fn padding_line(&mut self) -> String {
+        if self.state != GraphState::Commit {
+            return self.next_line().0;
+        }
+        let mut line = String::new();
+        for column in &self.columns {
+            line.push('|');
+            match *column == self.commit && self.parents.len() > 2 {
+                true => line.push_str(&" ".repeat((self.parents.len() - 2) * 2)),
+                false => line.push(' '),
+            }
+        }
+        self.pad(&mut line);
+        self.prev_state = GraphState::Padding;
+        line
+    }
+    /// The graph to print on an otherwise empty line
+    pub fn show_padding(&mut self) -> String {
+        self.padding_line()
+    }
+    /// The rows leading to the commit, ending with the commit row without its newline, to be
+    /// followed by the first line of the commit's text
+    pub fn show_commit(&mut self) -> String {
+        if self.is_commit_finished() {
+            return self.padding_line();
+        }
+        let mut output = String::new();
+        while !self.is_commit_finished() {
+            let (line, shown_commit_line) = self.next_line();
+            output.push_str(&line);
+            if shown_commit_line {
+                break;
+            }
+            output.push('\n');
+        }
+        output
+    }
+    /// The text of the commit, printed after [Graph::show_commit], with the graph drawn before
+    /// each of its lines and the rows the commit still needs after it
+    pub fn show_message(&mut self, message: &str) -> String {
+        let mut output = String::new();
+        let mut lines = message.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            output.push_str(line);
+            if lines.peek().is_some() {
+                output.push_str(&self.next_line().0);
+            }
+        }
+        if !self.is_commit_finished() {
+            let terminated = message.ends_with('\n');
+            if !terminated {
+                output.push('\n');
+            }
+            loop {
+                output.push_str(&self.next_line().0);
+                if self.is_commit_finished() {
+                    break;
+                }
+                output.push('\n');
+            }
+            if terminated {
+                output.push('\n');
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fork_and_merge() {
+        let commits = [
+            ("m", vec!["a", "b"]),
+            ("b", vec!["base"]),
+            ("a", vec!["base"]),
+            ("base", vec![]),
+        ];
+        let mut graph = Graph::new();
+        let mut output = String::new();
+        for (commit, parents) in commits {
+            graph.update(commit, parents.into_iter().map(String::from).collect());
+            output.push_str(&graph.show_commit());
+            output.push_str(&graph.show_message(commit));
+            output.push('\n');
+        }
+        assert_eq!(output, "*   m\n|\\  \n| * b\n* | a\n|/  \n* base\n");
+    }
+}
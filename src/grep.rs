@@ -0,0 +1,331 @@
+use std::{fs, thread};
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::{is_binary, worktree_path, Object, Pathspec, Repository, TreeNodeType, TreeObject};
+
+/// How the pattern given to `grep` is read
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GrepSyntax {
+    /// POSIX basic regex, `\(`, `\|`, `\{`, `\+` and `\?` being the special ones
+    #[default]
+    Basic,
+    /// POSIX extended regex
+    Extended,
+    /// The pattern is matched literally
+    Fixed,
+    /// Perl-like regex, as far as the `regex` crate goes
+    Perl,
+}
+
+/// Pattern searched in each line by `grep`
+#[derive(Clone, Debug)]
+pub struct GrepPattern {
+    regex: Regex,
+    /// Only matches surrounded by non-word characters count
+    word: bool,
+}
+impl GrepPattern {
+    pub fn new(
+        pattern: &str,
+        syntax: GrepSyntax,
+        ignore_case: bool,
+        word: bool,
+    ) -> Result<Self, String> {
+        let translated = match syntax {
+            GrepSyntax::Basic => posix_to_regex(pattern, true),
+            GrepSyntax::Extended => posix_to_regex(pattern, false),
+            GrepSyntax::Fixed => Ok(regex::escape(pattern)),
+            GrepSyntax::Perl => Ok(pattern.to_owned()),
+        };
+        let regex = translated.and_then(|translated| {
+            RegexBuilder::new(&translated)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| e.to_string())
+        });
+        match regex {
+            Ok(regex) => Ok(Self { regex, word }),
+            Err(e) => Err(format!("command line, '{}': {}", pattern, e)),
+        }
+    }
+    /// Whether the line, without its newline, matches
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        if !self.word {
+            return self.regex.is_match(line);
+        }
+        let is_word = |i: usize| line[i].is_ascii_alphanumeric() || line[i] == b'_';
+        let mut start = 0;
+        // Like git, a match inside a word is retried one character further
+        while start <= line.len() {
+            let Some(found) = self.regex.find_at(line, start) else {
+                return false;
+            };
+            if (found.start() == 0 || !is_word(found.start() - 1))
+                && (found.end() == line.len() || !is_word(found.end()))
+                && !found.is_empty()
+            {
+                return true;
+            }
+            start = found.start() + 1;
+        }
+        false
+    }
+}
+
+/// Translates a POSIX regex, basic or extended, to the syntax of the `regex` crate
+fn posix_to_regex(pattern: &str, basic: bool) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut translated = String::new();
+    let mut i = 0;
+    // Whether an operator would have nothing to apply to, making `*` literal in basic regexes
+    let mut at_start = true;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        let special = match c {
+            '\\' => {
+                let Some(&escaped) = chars.get(i) else {
+                    return Err(String::from("Trailing backslash"));
+                };
+                i += 1;
+                match escaped {
+                    '(' | ')' | '{' | '}' | '|' | '+' | '?' if basic => Some(escaped),
+                    '<' | '>' => {
+                        translated.push_str("\\b");
+                        continue;
+                    }
+                    'w' | 'W' | 's' | 'S' | 'b' | 'B' => {
+                        translated.push('\\');
+                        translated.push(escaped);
+                        at_start = false;
+                        continue;
+                    }
+                    '1'..='9' => return Err(String::from("Back-references are not supported")),
+                    _ => {
+                        translated.push_str(&regex::escape(&escaped.to_string()));
+                        at_start = false;
+                        continue;
+                    }
+                }
+            }
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' if !basic => Some(c),
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => None,
+            '*' if basic && at_start => None,
+            '^' if basic && !at_start => None,
+            '$' if basic
+                && !(i == chars.len()
+                    || chars[i..].starts_with(&['\\', ')'])
+                    || chars[i..].starts_with(&['\\', '|'])) =>
+            {
+                None
+            }
+            '[' => {
+                i = push_bracket(&chars, i, &mut translated)?;
+                at_start = false;
+                continue;
+            }
+            c => Some(c),
+        };
+        match special {
+            Some(c) => translated.push(c),
+            None => translated.push_str(&regex::escape(&c.to_string())),
+        }
+        at_start = matches!(special, Some('(' | '|' | '^'));
+    }
+    Ok(translated)
+}
+
+/// Copies the bracket expression starting after the `[` at `chars[start - 1]`, escaping what
+/// the `regex` crate would read differently, and returns the index following it
+fn push_bracket(chars: &[char], start: usize, translated: &mut String) -> Result<usize, String> {
+    let mut i = start;
+    translated.push('[');
+    if chars.get(i) == Some(&'^') {
+        translated.push('^');
+        i += 1;
+    }
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ']' if !first => {
+                translated.push(']');
+                return Ok(i + 1);
+            }
+            '[' if matches!(chars.get(i + 1), Some(':' | '.' | '=')) => {
+                let delimiter = chars[i + 1];
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&end| chars[end] == delimiter && chars[end + 1] == ']')
+                    .ok_or("Unmatched [, [^, [:, [., or [=")?;
+                translated.extend(&chars[i..end + 2]);
+                i = end + 2;
+            }
+            // Backslashes are literal in POSIX brackets
+            '\\' | '[' | ']' | '&' | '~' => {
+                translated.push('\\');
+                translated.push(c);
+                i += 1;
+            }
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                translated.push_str("\\-");
+                i += 1;
+            }
+            c => {
+                translated.push(c);
+                i += 1;
+            }
+        }
+        first = false;
+    }
+    Err(String::from("Unmatched [, [^, [:, [., or [="))
+}
+
+/// A file searched by [grep_files], with its path relative to the worktree root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrepFile {
+    pub path: String,
+    /// Blob holding the content, the worktree file being read when `None`
+    pub hash: Option<String>,
+}
+
+/// Lines of a file matching a pattern
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrepResult {
+    pub path: String,
+    /// Binary files are reported without their lines
+    pub binary: bool,
+    /// Matching lines, numbered from 1, without their newline
+    pub lines: Vec<(usize, Vec<u8>)>,
+}
+
+fn grep_file(
+    repo: &Repository,
+    file: &GrepFile,
+    pattern: &GrepPattern,
+) -> Result<Option<GrepResult>, String> {
+    let data = match &file.hash {
+        Some(hash) => Object::read_data(repo, hash)?.1,
+        None => {
+            let path = worktree_path(repo, &file.path);
+            // Like git, files missing from the worktree, or no longer regular files, are skipped
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_file() => {
+                    fs::read(&path).map_err(|e| format!("unable to read {}: {}", file.path, e))?
+                }
+                _ => return Ok(None),
+            }
+        }
+    };
+    let data = data.strip_suffix(b"\n").unwrap_or(&data);
+    let lines: Vec<(usize, Vec<u8>)> = data
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| pattern.is_match(line))
+        .map(|(i, line)| (i + 1, line.to_vec()))
+        .collect();
+    Ok((!lines.is_empty()).then(|| GrepResult {
+        path: file.path.clone(),
+        binary: is_binary(data),
+        lines,
+    }))
+}
+
+/// Searches the files for the pattern, spread over as many threads as there are CPUs.
+///
+/// Returns the files with matches, in the order they were given.
+pub fn grep_files(
+    repo: &Repository,
+    files: &[GrepFile],
+    pattern: &GrepPattern,
+) -> Result<Vec<GrepResult>, String> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = files.len().div_ceil(threads).max(1);
+    let chunks: Vec<Result<Vec<GrepResult>, String>> = thread::scope(|scope| {
+        let handles: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut results = Vec::new();
+                    for file in chunk {
+                        results.extend(grep_file(repo, file, pattern)?);
+                    }
+                    Ok(results)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("grep thread panicked"))
+            .collect()
+    });
+    let mut results = Vec::new();
+    for chunk in chunks {
+        results.extend(chunk?);
+    }
+    Ok(results)
+}
+
+/// Regular files of a tree matching `pathspec`, in tree order, skipping the subtrees that cannot
+/// hold any
+pub fn grep_tree_files(
+    repo: &Repository,
+    tree: &str,
+    base: &str,
+    pathspec: &Pathspec,
+) -> Result<Vec<GrepFile>, String> {
+    let mut files = Vec::new();
+    for entry in TreeObject::read_from_sha(repo, tree)?.entries {
+        let path = format!("{}{}", base, entry.path);
+        match entry._type {
+            TreeNodeType::Tree if pathspec.may_match_in(&path) => {
+                files.extend(grep_tree_files(
+                    repo,
+                    &entry.hash,
+                    &format!("{}/", path),
+                    pathspec,
+                )?);
+            }
+            TreeNodeType::Blob
+                if matches!(entry.mode.as_str(), "100644" | "100755")
+                    && pathspec.matches(&path) =>
+            {
+                files.push(GrepFile {
+                    path,
+                    hash: Some(entry.hash),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_grep_pattern() {
+        let matches = |pattern: &str, syntax: GrepSyntax, word: bool, line: &str| {
+            GrepPattern::new(pattern, syntax, false, word)
+                .unwrap()
+                .is_match(line.as_bytes())
+        };
+        assert!(matches("a+b", GrepSyntax::Basic, false, "(a+b)"));
+        assert!(!matches("a+b", GrepSyntax::Extended, false, "(a+b)"));
+        assert!(matches("\\(fo\\)\\{2\\}", GrepSyntax::Basic, false, "fofo"));
+        assert!(matches("foo\\|baz", GrepSyntax::Basic, false, "baz"));
+        assert!(matches("*a", GrepSyntax::Basic, false, "*a"));
+        assert!(matches(
+            "[[:digit:]]\\",
+            GrepSyntax::Fixed,
+            false,
+            "[[:digit:]]\\"
+        ));
+        assert!(matches("b[\\]", GrepSyntax::Extended, false, "b\\"));
+        assert!(matches("foo", GrepSyntax::Basic, true, "foobar foo"));
+        assert!(!matches("foo", GrepSyntax::Basic, true, "foo_bar"));
+        assert!(GrepPattern::new("a\\{", GrepSyntax::Basic, false, false).is_err());
+    }
+}
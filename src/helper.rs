@@ -1,4 +1,5 @@
 use std::{
+    cmp::Ordering,
     fs,
     path::{Path, PathBuf},
 };
@@ -24,3 +25,358 @@ pub fn hex_to_hex_byte(s: &str) -> Result<Vec<u8>, String> {
         })
         .collect()
 }
+
+/// Matches `text` against a shell glob, where `*` and `?` also match `/`, `[...]` is a set of
+/// characters (negated by a leading `!` or `^`, with `a-z` style ranges) and `\` escapes
+pub fn wildmatch(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_matches(&pattern, &text, false, true)
+}
+
+/// Like [wildmatch], but `*`, `?` and sets do not match `/`, while a `**` standing for a whole
+/// path component matches any number of directories, as in gitignore files
+pub fn wildmatch_pathname(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_matches(&pattern, &text, true, true)
+}
+
+/// `component_start` tells whether the pattern so far is empty or ends with `/`
+fn glob_matches(pattern: &[char], text: &[char], pathname: bool, component_start: bool) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let stars = pattern.iter().take_while(|&&c| c == '*').count();
+            let rest = &pattern[stars..];
+            if pathname && stars > 1 && component_start && matches!(rest.first(), None | Some('/'))
+            {
+                // `**/` also matches no directory at all
+                if !rest.is_empty() && glob_matches(&rest[1..], text, pathname, true) {
+                    return true;
+                }
+                return (0..=text.len())
+                    .any(|skip| glob_matches(rest, &text[skip..], pathname, false));
+            }
+            (0..=text.len())
+                .take_while(|&skip| !pathname || skip == 0 || text[skip - 1] != '/')
+                .any(|skip| glob_matches(rest, &text[skip..], pathname, false))
+        }
+        Some('?') => match text.split_first() {
+            Some((&c, text_rest)) if !(pathname && c == '/') => {
+                glob_matches(&pattern[1..], text_rest, pathname, false)
+            }
+            _ => false,
+        },
+        Some('[') => {
+            let Some((&c, text_rest)) = text.split_first() else {
+                return false;
+            };
+            let mut i = 1;
+            let negated = matches!(pattern.get(i), Some('!' | '^'));
+            if negated {
+                i += 1;
+            }
+            let mut matched = false;
+            let mut first = true;
+            while i < pattern.len() && (first || pattern[i] != ']') {
+                first = false;
+                let low = pattern[i];
+                if pattern.get(i + 1) == Some(&'-')
+                    && pattern.get(i + 2).is_some_and(|&high| high != ']')
+                {
+                    matched |= low <= c && c <= pattern[i + 2];
+                    i += 3;
+                } else {
+                    matched |= low == c;
+                    i += 1;
+                }
+            }
+            // An unterminated set is matched literally
+            if i >= pattern.len() {
+                return c == '[' && glob_matches(&pattern[1..], text_rest, pathname, false);
+            }
+            matched != negated
+                && !(pathname && c == '/')
+                && glob_matches(&pattern[i + 1..], text_rest, pathname, false)
+        }
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1])
+                && glob_matches(&pattern[2..], &text[1..], pathname, pattern[1] == '/')
+        }
+        Some(&c) => {
+            text.first() == Some(&c) && glob_matches(&pattern[1..], &text[1..], pathname, c == '/')
+        }
+    }
+}
+
+/// Quotes a path the way git shows it when it has control, `"`, `\` or non-ASCII characters:
+/// between double quotes, with C escapes and octal bytes
+pub fn quote_path(path: &str) -> String {
+    if !path
+        .bytes()
+        .any(|b| !(0x20..0x7f).contains(&b) || b == b'"' || b == b'\\')
+    {
+        return path.to_owned();
+    }
+    let mut quoted = String::from("\"");
+    for b in path.bytes() {
+        match b {
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            0x0b => quoted.push_str("\\v"),
+            0x0c => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b if !(0x20..0x7f).contains(&b) => quoted.push_str(&format!("\\{:03o}", b)),
+            b => quoted.push(b as char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Joins `path` to the directory `prefix` (empty or ending with `/`) resolving `.` and `..`, or
+/// `None` when it leads out of the worktree.
+///
+/// Paths naming a directory through a trailing `/`, `.` or `..` keep a trailing `/`, the root
+/// being the empty path.
+pub fn normalize_path(prefix: &str, path: &str) -> Option<String> {
+    let mut components: Vec<&str> = Vec::new();
+    let joined = format!("{}{}", prefix, path);
+    for component in joined.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            component => components.push(component),
+        }
+    }
+    let directory = path.is_empty()
+        || path.ends_with('/')
+        || path == "."
+        || path == ".."
+        || path.ends_with("/.")
+        || path.ends_with("/..");
+    let mut normalized = components.join("/");
+    if directory && !normalized.is_empty() {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// `path`, relative to the worktree root, as seen from the directory `prefix`
+pub fn relative_path(path: &str, prefix: &str) -> String {
+    let mut path = path;
+    let mut prefix = prefix;
+    while let Some((directory, rest)) = prefix.split_once('/') {
+        let path_rest = path.strip_prefix(directory).and_then(|rest| match rest {
+            "" => Some(rest),
+            rest => rest.strip_prefix('/'),
+        });
+        match path_rest {
+            Some(path_rest) => {
+                path = path_rest;
+                prefix = rest;
+            }
+            None => break,
+        }
+    }
+    let relative = format!("{}{}", "../".repeat(prefix.matches('/').count()), path);
+    match relative.is_empty() {
+        true => String::from("./"),
+        false => relative,
+    }
+}
+
+/// Quotes `arg` for the shell between single quotes, as git does in its logs
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''").replace('!', "'\\!'"))
+}
+
+/// Splits text made of arguments quoted by [shell_quote] back into the arguments
+pub fn shell_unquote(text: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = text.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let mut arg = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("Unterminated quote in {}", text)),
+                    }
+                },
+                '\\' => arg.push(
+                    chars
+                        .next()
+                        .ok_or(format!("Trailing backslash in {}", text))?,
+                ),
+                c if c.is_whitespace() => break,
+                _ => return Err(format!("Unquoted argument in {}", text)),
+            }
+        }
+        args.push(arg);
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+    Ok(args)
+}
+
+/// Cleans up a message like git does: trailing whitespace is removed, runs of blank lines are
+/// collapsed, leading and trailing blank lines dropped, and `#` lines too when `strip_comments`
+pub fn strip_space(text: &str, strip_comments: bool) -> String {
+    let mut result = String::new();
+    let mut blank_lines = 0;
+    for line in text.lines() {
+        if strip_comments && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if blank_lines > 0 && !result.is_empty() {
+            result.push('\n');
+        }
+        blank_lines = 0;
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+/// Compares names containing version numbers the way `strverscmp` does, so that `v1.9` comes
+/// before `v1.10`, and digits after leading zeros are read as a fraction
+pub fn version_cmp(a: &str, b: &str) -> Ordering {
+    // States of the scan: plain text, integral part, fractional part, leading zeros
+    const NORMAL: usize = 0;
+    const INTEGRAL: usize = 3;
+    const FRACTIONAL: usize = 6;
+    const ZEROS: usize = 9;
+    const NEXT_STATE: [usize; 12] = [
+        NORMAL, INTEGRAL, ZEROS, NORMAL, INTEGRAL, INTEGRAL, NORMAL, FRACTIONAL, FRACTIONAL,
+        NORMAL, FRACTIONAL, ZEROS,
+    ];
+    // By state and the kind of the differing characters: compare them, compare the lengths of
+    // the digit runs, or a fixed result
+    const CMP: i8 = 2;
+    const LEN: i8 = 3;
+    const RESULT: [i8; 36] = [
+        CMP, CMP, CMP, CMP, LEN, CMP, CMP, CMP, CMP, // normal
+        CMP, -1, -1, 1, LEN, LEN, 1, LEN, LEN, // integral
+        CMP, CMP, CMP, CMP, CMP, CMP, CMP, CMP, CMP, // fractional
+        CMP, 1, 1, -1, CMP, CMP, -1, CMP, CMP, // leading zeros
+    ];
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let at = |text: &[u8], i: usize| text.get(i).copied().unwrap_or(0);
+    let kind = |c: u8| (c == b'0') as usize + c.is_ascii_digit() as usize;
+    let mut i = 0;
+    let mut state = NORMAL + kind(at(a, 0));
+    while at(a, i) == at(b, i) {
+        if at(a, i) == 0 {
+            return Ordering::Equal;
+        }
+        state = NEXT_STATE[state];
+        i += 1;
+        state += kind(at(a, i));
+    }
+    let (c1, c2) = (at(a, i), at(b, i));
+    match RESULT[state * 3 + kind(c2)] {
+        CMP => c1.cmp(&c2),
+        LEN => {
+            let mut j = i + 1;
+            while at(a, j).is_ascii_digit() {
+                if !at(b, j).is_ascii_digit() {
+                    return Ordering::Greater;
+                }
+                j += 1;
+            }
+            if at(b, j).is_ascii_digit() {
+                Ordering::Less
+            } else {
+                c1.cmp(&c2)
+            }
+        }
+        result => result.cmp(&0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wildmatch() {
+        assert!(wildmatch("v1.*", "v1.2.3"));
+        assert!(wildmatch("*", "release/v1"));
+        assert!(wildmatch("v?.[0-9]", "v1.5"));
+        assert!(wildmatch("[!a]b", "cb"));
+        assert!(wildmatch("a\\*", "a*"));
+        assert!(!wildmatch("v1.*", "v2.0"));
+        assert!(!wildmatch("[^a-c]", "b"));
+        assert!(!wildmatch("a\\*", "ab"));
+    }
+
+    #[test]
+    fn test_wildmatch_pathname() {
+        assert!(wildmatch_pathname("*.o", "a.o"));
+        assert!(!wildmatch_pathname("*.o", "dir/a.o"));
+        assert!(!wildmatch_pathname("a?b", "a/b"));
+        assert!(wildmatch_pathname("**/foo", "foo"));
+        assert!(wildmatch_pathname("**/foo", "a/b/foo"));
+        assert!(wildmatch_pathname("a/**/b", "a/b"));
+        assert!(wildmatch_pathname("a/**/b", "a/x/y/b"));
+        assert!(wildmatch_pathname("abc/**", "abc/x/y"));
+        assert!(!wildmatch_pathname("abc/**", "abc"));
+        assert!(!wildmatch_pathname("a**b", "a/b"));
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("src/", "main.rs").unwrap(), "src/main.rs");
+        assert_eq!(normalize_path("src/", "../README").unwrap(), "README");
+        assert_eq!(normalize_path("src/", ".").unwrap(), "src/");
+        assert_eq!(normalize_path("", ".").unwrap(), "");
+        assert_eq!(normalize_path("a/b/", "../c/").unwrap(), "a/c/");
+        assert!(normalize_path("src/", "../..").is_none());
+        assert_eq!(relative_path("src/main.rs", "src/"), "main.rs");
+        assert_eq!(relative_path("docs/a.md", "src/deep/"), "../../docs/a.md");
+        assert_eq!(relative_path("README", ""), "README");
+        assert_eq!(relative_path("src", "src/"), "./");
+    }
+
+    #[test]
+    fn test_quote_path() {
+        assert_eq!(quote_path("plain/name.txt"), "plain/name.txt");
+        assert_eq!(quote_path("tab\there"), "\"tab\\there\"");
+        assert_eq!(quote_path("caf\u{e9}"), "\"caf\\303\\251\"");
+        assert_eq!(quote_path("a\"b"), "\"a\\\"b\"");
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        let args = ["HEAD~2", "it's", "wow!", ""];
+        let quoted = args.map(shell_quote).join(" ");
+        assert_eq!(quoted, "'HEAD~2' 'it'\\''s' 'wow'\\!'' ''");
+        assert_eq!(shell_unquote(&quoted).unwrap(), args);
+    }
+
+    #[test]
+    fn test_version_cmp() {
+        let mut names = [
+            "v1.10", "v1.2-rc1", "v1.9", "v1.02", "v1.2", "v10.0", "v1.001",
+        ];
+        names.sort_by(|a, b| version_cmp(a, b));
+        assert_eq!(
+            names,
+            ["v1.001", "v1.02", "v1.2", "v1.2-rc1", "v1.9", "v1.10", "v10.0"]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::user_file, create_path, wildmatch, wildmatch_pathname, Repository};
+
+/// Pattern of a gitignore or gitattributes line, matched like git does in both
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PathPattern {
+    pattern: String,
+    /// Directory of the file the pattern comes from, relative to the worktree root
+    base: String,
+    /// `pattern/`, only matching directories
+    directory_only: bool,
+    /// Matched against the path relative to `base` when the pattern has a `/`, against the
+    /// file name otherwise
+    anchored: bool,
+}
+impl PathPattern {
+    pub(crate) fn parse(pattern: &str, base: &str) -> Option<Self> {
+        let (directory_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pattern: pattern.strip_prefix('/').unwrap_or(pattern).to_owned(),
+            base: base.to_owned(),
+            directory_only,
+            anchored: pattern.contains('/'),
+        })
+    }
+    pub(crate) fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        if !self.anchored {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            return wildmatch(&self.pattern, name);
+        }
+        let relative = match self.base.as_str() {
+            "" => Some(path),
+            base => path
+                .strip_prefix(base)
+                .and_then(|path| path.strip_prefix('/')),
+        };
+        relative.is_some_and(|relative| wildmatch_pathname(&self.pattern, relative))
+    }
+}
+
+/// A line of a gitignore file
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct IgnorePattern {
+    pattern: PathPattern,
+    /// `!pattern`, re-including what an earlier pattern excluded
+    negated: bool,
+}
+impl IgnorePattern {
+    fn parse(line: &str, base: &str) -> Option<Self> {
+        let line = trim_trailing_spaces(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        Some(Self {
+            pattern: PathPattern::parse(pattern, base)?,
+            negated,
+        })
+    }
+}
+
+/// Drops trailing spaces, unless escaped by a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = 0;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ' ' => continue,
+            '\\' => match chars.next() {
+                Some((i, c)) => end = i + c.len_utf8(),
+                None => return line,
+            },
+            _ => end = i + c.len_utf8(),
+        }
+    }
+    &line[..end]
+}
+
+fn parse_patterns(content: &str, base: &str) -> Vec<IgnorePattern> {
+    content
+        .lines()
+        .filter_map(|line| IgnorePattern::parse(line, base))
+        .collect()
+}
+
+/// Last pattern of `patterns` matching the path, which decides whether it is excluded
+fn last_match<'a>(
+    patterns: &'a [IgnorePattern],
+    path: &str,
+    is_dir: bool,
+) -> Option<&'a IgnorePattern> {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.pattern.matches(path, is_dir))
+}
+
+/// Patterns deciding which untracked paths are left aside.
+///
+/// Like git, patterns given on the command line come first, then the `.gitignore` files from
+/// the deepest directory up, then the exclude files, the last one added first.
+#[derive(Debug, Default)]
+pub struct Excludes {
+    worktree: PathBuf,
+    command_line: Vec<IgnorePattern>,
+    /// Whether the `.gitignore` file of each directory is read
+    per_directory: bool,
+    /// Patterns of the `.gitignore` files read so far, by directory
+    directories: HashMap<String, Vec<IgnorePattern>>,
+    files: Vec<Vec<IgnorePattern>>,
+}
+impl Excludes {
+    /// Excludes nothing until patterns are added
+    pub fn new(repo: &Repository) -> Self {
+        Self {
+            worktree: repo.worktree.clone(),
+            ..Default::default()
+        }
+    }
+    /// `.gitignore` files, `info/exclude` and `core.excludesFile`, as `--exclude-standard` reads
+    pub fn standard(repo: &Repository) -> Self {
+        let mut excludes = Self::new(repo);
+        excludes.per_directory = true;
+        if let Some(path) = user_file(repo, repo.config.core.excludesfile.as_deref(), "ignore") {
+            excludes.add_file(&path);
+        }
+        excludes.add_file(&create_path(
+            &repo.gitdir,
+            vec![String::from("info"), String::from("exclude")],
+        ));
+        excludes
+    }
+    /// Adds a pattern given on the command line, stronger than those of the ignore files
+    pub fn add_pattern(&mut self, pattern: &str) {
+        self.command_line.extend(IgnorePattern::parse(pattern, ""));
+    }
+    /// Adds the patterns of an exclude file, a missing file adding nothing
+    pub fn add_file(&mut self, path: &Path) {
+        if let Ok(content) = fs::read_to_string(path) {
+            self.files.push(parse_patterns(&content, ""));
+        }
+    }
+    /// Whether the path, relative to the worktree root, is excluded
+    pub fn is_excluded(&mut self, path: &str, is_dir: bool) -> bool {
+        if let Some(pattern) = last_match(&self.command_line, path, is_dir) {
+            return !pattern.negated;
+        }
+        if self.per_directory {
+            let mut directory = path;
+            while !directory.is_empty() {
+                directory = directory.rsplit_once('/').map_or("", |(parent, _)| parent);
+                if let Some(pattern) = last_match(self.directory_patterns(directory), path, is_dir)
+                {
+                    return !pattern.negated;
+                }
+            }
+        }
+        self.files
+            .iter()
+            .rev()
+            .find_map(|patterns| last_match(patterns, path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+    fn directory_patterns(&mut self, directory: &str) -> &[IgnorePattern] {
+        if !self.directories.contains_key(directory) {
+            let path = create_path(
+                &self.worktree,
+                vec![directory.to_owned(), String::from(".gitignore")],
+            );
+            let patterns = fs::read_to_string(path)
+                .map(|content| parse_patterns(&content, directory))
+                .unwrap_or_default();
+            self.directories.insert(directory.to_owned(), patterns);
+        }
+        &self.directories[directory]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ignore_patterns() {
+        let patterns = parse_patterns(
+            "# comment\n*.o\n!keep.o\nbuild/\n/root.txt\ndocs/*.html\ntrailing  \n",
+            "",
+        );
+        let excluded = |path: &str, is_dir: bool| {
+            last_match(&patterns, path, is_dir).is_some_and(|pattern| !pattern.negated)
+        };
+        assert!(excluded("a.o", false));
+        assert!(excluded("src/deep/a.o", false));
+        assert!(!excluded("src/keep.o", false));
+        assert!(excluded("src/build", true));
+        assert!(!excluded("src/build", false));
+        assert!(excluded("root.txt", false));
+        assert!(!excluded("src/root.txt", false));
+        assert!(excluded("docs/index.html", false));
+        assert!(!excluded("docs/api/index.html", false));
+        assert!(excluded("trailing", false));
+        assert_eq!(trim_trailing_spaces("a\\ "), "a\\ ");
+    }
+}
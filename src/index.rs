@@ -0,0 +1,310 @@
+use std::{fs, os::unix::fs::MetadataExt};
+
+use hex::encode;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    create_path, hex_to_hex_byte, peel_to_tree, read_ref, Repository, TreeNode, TreeObject,
+};
+
+const INDEX_SIGNATURE: &[u8] = b"DIRC";
+const NAME_MASK: u16 = 0x0fff;
+const EXTENDED_FLAG: u16 = 0x4000;
+const ASSUME_VALID_FLAG: u16 = 0x8000;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    /// Unix mode, e.g. `0o100644`
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub hash: String,
+    /// 0 for a normal entry, 1 to 3 for the base, ours and theirs versions of a conflicted path
+    pub stage: u8,
+    pub assume_valid: bool,
+    /// Only present in version 3 indexes (skip-worktree and intent-to-add bits)
+    pub extended_flags: u16,
+    pub path: String,
+}
+impl IndexEntry {
+    pub fn new(path: String, mode: u32, hash: String, stage: u8) -> Self {
+        Self {
+            path,
+            mode,
+            hash,
+            stage,
+            ..Default::default()
+        }
+    }
+    pub fn from_tree_node(node: &TreeNode, stage: u8) -> Result<Self, String> {
+        let mode = u32::from_str_radix(&node.mode, 8)
+            .map_err(|e| format!("Invalid mode, {}: {}", node.mode, e))?;
+        Ok(Self::new(node.path.clone(), mode, node.hash.clone(), stage))
+    }
+    pub fn to_tree_node(&self) -> Result<TreeNode, String> {
+        TreeNode::new(
+            format!("{:o}", self.mode),
+            self.path.clone(),
+            self.hash.clone(),
+        )
+    }
+    /// Records the stat information of the worktree file, so later checks can skip rehashing it
+    pub fn update_stat(&mut self, metadata: &fs::Metadata) {
+        self.ctime = (metadata.ctime() as u32, metadata.ctime_nsec() as u32);
+        self.mtime = (metadata.mtime() as u32, metadata.mtime_nsec() as u32);
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.size() as u32;
+    }
+    /// Whether the stat information recorded matches the worktree file
+    pub fn stat_matches(&self, metadata: &fs::Metadata) -> bool {
+        self.mtime == (metadata.mtime() as u32, metadata.mtime_nsec() as u32)
+            && self.ctime == (metadata.ctime() as u32, metadata.ctime_nsec() as u32)
+            && self.ino == metadata.ino() as u32
+            && self.size == metadata.size() as u32
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Index {
+    pub version: u32,
+    pub entries: Vec<IndexEntry>,
+}
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: 2,
+            entries: Vec::new(),
+        }
+    }
+}
+impl Index {
+    fn path(repo: &Repository) -> std::path::PathBuf {
+        create_path(&repo.gitdir, vec![String::from("index")])
+    }
+    /// Reads the index of the repository.
+    ///
+    /// Repositories without an index file (e.g. only ever checked out by rit) are treated as if
+    /// the index matched HEAD.
+    pub fn read(repo: &Repository) -> Result<Self, String> {
+        let path = Self::path(repo);
+        if !path.exists() {
+            return match read_ref(repo, "HEAD") {
+                Some(head) => Self::from_tree(repo, &peel_to_tree(repo, &head)?),
+                None => Ok(Self::default()),
+            };
+        }
+        let data =
+            fs::read(&path).map_err(|e| format!("Error reading index, {:?}: {}", path, e))?;
+        Self::from_data(&data)
+    }
+    pub fn from_data(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 32 || &data[..4] != INDEX_SIGNATURE {
+            return Err(String::from("Index file is corrupted, bad signature"));
+        }
+        let (content, checksum) = data.split_at(data.len() - 20);
+        let mut hasher = Sha1::new();
+        hasher.update(content);
+        if hasher.finalize().as_slice() != checksum {
+            return Err(String::from("Index file is corrupted, bad checksum"));
+        }
+
+        let read_u32 = |pos: usize| u32::from_be_bytes(content[pos..pos + 4].try_into().unwrap());
+        let read_u16 = |pos: usize| u16::from_be_bytes(content[pos..pos + 2].try_into().unwrap());
+        let version = read_u32(4);
+        if !(2..=3).contains(&version) {
+            return Err(format!("Index version {} is not supported", version));
+        }
+        let count = read_u32(8) as usize;
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = 12;
+        for _ in 0..count {
+            let start = pos;
+            if pos + 62 > content.len() {
+                return Err(String::from("Index file is corrupted, truncated entry"));
+            }
+            let stat = (0..10).map(|i| read_u32(pos + i * 4)).collect::<Vec<u32>>();
+            let hash = encode(&content[pos + 40..pos + 60]);
+            let flags = read_u16(pos + 60);
+            pos += 62;
+            let extended_flags = if flags & EXTENDED_FLAG != 0 {
+                pos += 2;
+                read_u16(pos - 2)
+            } else {
+                0
+            };
+            let path_length = content[pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or("Index file is corrupted, unterminated path")?;
+            let path = String::from_utf8(content[pos..pos + path_length].to_vec())
+                .map_err(|e| format!("Index contains a non UTF-8 path: {}", e))?;
+            // Entries are padded with 1 to 8 NUL bytes to a multiple of 8
+            pos = start + ((pos - start + path_length + 8) & !7);
+            entries.push(IndexEntry {
+                ctime: (stat[0], stat[1]),
+                mtime: (stat[2], stat[3]),
+                dev: stat[4],
+                ino: stat[5],
+                mode: stat[6],
+                uid: stat[7],
+                gid: stat[8],
+                size: stat[9],
+                hash,
+                stage: ((flags >> 12) & 0x3) as u8,
+                assume_valid: flags & ASSUME_VALID_FLAG != 0,
+                extended_flags,
+                path,
+            });
+        }
+        // Extensions (cached trees, resolve undo...) are optional and dropped
+        Ok(Self { version, entries })
+    }
+    pub fn serialize(&self) -> Result<Vec<u8>, String> {
+        let mut entries = self.entries.clone();
+        entries.sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+        let version: u32 = if entries.iter().any(|entry| entry.extended_flags != 0) {
+            3
+        } else {
+            2
+        };
+        let mut data = Vec::new();
+        data.extend_from_slice(INDEX_SIGNATURE);
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for entry in &entries {
+            let start = data.len();
+            for value in [
+                entry.ctime.0,
+                entry.ctime.1,
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.append(&mut hex_to_hex_byte(&entry.hash)?);
+            let mut flags = (entry.path.len().min(NAME_MASK as usize) as u16)
+                | ((entry.stage as u16 & 0x3) << 12);
+            if entry.assume_valid {
+                flags |= ASSUME_VALID_FLAG;
+            }
+            if entry.extended_flags != 0 {
+                flags |= EXTENDED_FLAG;
+            }
+            data.extend_from_slice(&flags.to_be_bytes());
+            if entry.extended_flags != 0 {
+                data.extend_from_slice(&entry.extended_flags.to_be_bytes());
+            }
+            data.extend_from_slice(entry.path.as_bytes());
+            let padding = 8 - (data.len() - start) % 8;
+            data.resize(data.len() + padding, 0);
+        }
+        let mut hasher = Sha1::new();
+        hasher.update(&data);
+        data.extend_from_slice(hasher.finalize().as_slice());
+        Ok(data)
+    }
+    pub fn write(&self, repo: &Repository) -> Result<(), String> {
+        let path = Self::path(repo);
+        fs::write(&path, self.serialize()?)
+            .map_err(|e| format!("Error writing index, {:?}: {}", path, e))
+    }
+    /// Builds an index holding every file of a tree at stage 0, without stat information
+    pub fn from_tree(repo: &Repository, tree_hash: &str) -> Result<Self, String> {
+        let entries = TreeObject::read_from_sha(repo, tree_hash)?
+            .flatten(repo)?
+            .iter()
+            .map(|node| IndexEntry::from_tree_node(node, 0))
+            .collect::<Result<Vec<IndexEntry>, String>>()?;
+        Ok(Self {
+            version: 2,
+            entries,
+        })
+    }
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|entry| entry.stage != 0)
+    }
+    /// Paths with entries in stages 1 to 3, sorted and deduplicated
+    pub fn conflicted_paths(&self) -> Vec<String> {
+        let mut paths = self
+            .entries
+            .iter()
+            .filter(|entry| entry.stage != 0)
+            .map(|entry| entry.path.clone())
+            .collect::<Vec<String>>();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+    pub fn get(&self, path: &str) -> Option<&IndexEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path && entry.stage == 0)
+    }
+    /// Stage 0 entries as tree nodes
+    pub fn tree_nodes(&self) -> Result<Vec<TreeNode>, String> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.stage == 0)
+            .map(|entry| entry.to_tree_node())
+            .collect()
+    }
+    /// Writes the trees recorded by the index, failing if there are unresolved conflicts
+    pub fn write_tree(&self, repo: &Repository) -> Result<String, String> {
+        if self.has_conflicts() {
+            return Err(format!(
+                "Cannot write a tree with unmerged paths: {}",
+                self.conflicted_paths().join(", ")
+            ));
+        }
+        TreeObject::write_from_nodes(repo, &self.tree_nodes()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_index_roundtrip() {
+        let index = Index {
+            version: 2,
+            entries: vec![
+                IndexEntry::new(
+                    String::from("a.txt"),
+                    0o100644,
+                    String::from("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"),
+                    0,
+                ),
+                IndexEntry::new(
+                    String::from("dir/conflicted"),
+                    0o100755,
+                    String::from("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"),
+                    2,
+                ),
+            ],
+        };
+        let data = index.serialize().unwrap();
+        // 12 byte header, 2 entries padded to 8 bytes, 20 byte checksum
+        assert_eq!(data.len(), 12 + 72 + 80 + 20);
+        let parsed = Index::from_data(&data).unwrap();
+        assert_eq!(parsed.entries, index.entries);
+        assert_eq!(
+            parsed.conflicted_paths(),
+            vec![String::from("dir/conflicted")]
+        );
+    }
+}
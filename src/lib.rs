@@ -1,15 +1,104 @@
+mod archive;
+mod attributes;
+mod bisect;
+mod blame;
+mod cat_file;
 mod cli;
 mod config;
 mod consts;
+mod convert;
+mod describe;
+mod diff;
+mod filter;
+mod graph;
+mod grep;
 mod helper;
+mod ignore;
+mod index;
+mod mailmap;
+mod merge;
 mod object;
+mod pathspec;
+mod pretty;
+mod rebase;
+mod refs;
 mod repository;
+mod revision;
+mod revwalk;
+mod sequencer;
+mod signature;
+mod stash;
+mod tag;
+mod worktree;
 
+pub use archive::{write_archive, ArchiveFormat, ArchiveOptions};
+pub use attributes::{is_valid_attr_name, AttrValue, Attributes};
+pub use bisect::{
+    bisect_in_progress, bisect_log, bisect_mark, bisect_replay, bisect_reset, bisect_start,
+    BisectOutcome, BisectTerm,
+};
+pub use blame::{
+    blame, Blame, BlameEntry, BlameOptions, DEFAULT_COPY_SCORE, DEFAULT_MOVE_SCORE, NULL_COMMIT,
+};
+pub use cat_file::{BatchFormat, DEFAULT_BATCH_FORMAT};
 pub use cli::ObjectTypes;
 pub use config::Config;
 pub use consts::{GIT_DIR_PATH, RIT_DIR_PATH};
-pub use helper::{create_dir, create_path, hex_to_hex_byte};
+pub use convert::Conversion;
+pub use describe::{describe, name_revs, DescribeOptions, NameRevOptions};
+pub use diff::{
+    diff, diff_tree_paths, diff_trees, format_patch, format_stat, format_summary, is_binary,
+    rename_source, split_lines, unified_diff, DiffHunk, FileChange,
+};
+pub use graph::Graph;
+pub use grep::{grep_files, grep_tree_files, GrepFile, GrepPattern, GrepResult, GrepSyntax};
+pub use helper::{
+    create_dir, create_path, hex_to_hex_byte, normalize_path, quote_path, relative_path,
+    shell_quote, strip_space, version_cmp, wildmatch, wildmatch_pathname,
+};
+pub use ignore::Excludes;
+pub use index::{Index, IndexEntry};
+pub use mailmap::Mailmap;
+pub use merge::{
+    is_ancestor, merge_bases, merge_commits, merge_file, merge_message, merge_trees,
+    reachable_commits, ConflictStyle, MergeFavor, MergeFileOptions, MergeFileResult,
+    TreeMergeOptions, TreeMergeResult,
+};
 pub use object::{
-    create_ref, resolve_ref, Object, ObjectHeaders, TreeNode, TreeNodeType, TreeObject,
+    resolve_ref, Commit, Object, ObjectHeaders, Tag, TreeNode, TreeNodeType, TreeObject,
+};
+pub use pathspec::Pathspec;
+pub use pretty::{format_commit, format_date, parse_date, CommitInfo, DateMode, PrettyFormat};
+pub use rebase::{
+    parse_todo, rebase_abort, rebase_continue, rebase_in_progress, rebase_skip, rebase_start,
+    rebase_todo, RebaseCommand, RebaseOutcome, RebaseStep,
+};
+pub use refs::{
+    append_reflog, check_ref_format, delete_ref, head_branch, list_refs, read_ref, read_reflog,
+    read_symbolic_ref, resolve_ref_name, shorten_ref_name, update_ref, update_ref_no_deref,
+    update_symbolic_ref, write_reflog, ReflogEntry,
 };
 pub use repository::Repository;
+pub use revision::{
+    commit_parents, expand_ref_name, peel, peel_to_commit, peel_to_tree, rev_parse,
+};
+pub use revwalk::{
+    all_ref_commits, resolve_revision_args, HistorySimplification, RevWalk, RevWalkOptions,
+    SortOrder,
+};
+pub use sequencer::{
+    apply_commit_changes, commit_on_head, commit_subject, replay_commit, sequencer_abort,
+    sequencer_continue, sequencer_in_progress, sequencer_start, ReplayAction, ReplayOptions,
+    SequencerReport,
+};
+pub use signature::Signature;
+pub use stash::{
+    stash_apply, stash_commit, stash_drop, stash_list, stash_position, stash_save, StashOptions,
+    STASH_REF,
+};
+pub use tag::{fsck_tag, list_tags, tag_message, verify_signature, TagSort, TagSortKey};
+pub use worktree::{
+    clean_file, file_mode, has_uncommitted_changes, hash_file, is_modified, read_file,
+    refresh_index, remove_file, reset_merge, reset_to_tree, switch_tree, untracked_files,
+    update_worktree, worktree_path, write_file,
+};
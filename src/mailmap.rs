@@ -0,0 +1,160 @@
+use std::{collections::HashMap, fs};
+
+use homedir::get_my_home;
+
+use crate::{create_path, Repository, Signature};
+
+/// Replacement name and email, each kept as is when `None`
+type Replacement = (Option<String>, Option<String>);
+
+#[derive(Clone, Debug, Default)]
+struct MailmapEntry {
+    replacement: Replacement,
+    /// Replacements only applying to a name used with the email, by lowercase name
+    by_name: HashMap<String, Replacement>,
+}
+
+/// Canonical names and emails of the people in the history, read from `.mailmap` files.
+///
+/// Each line is one of
+/// - `Proper Name <commit@email>`
+/// - `<proper@email> <commit@email>`
+/// - `Proper Name <proper@email> <commit@email>`
+/// - `Proper Name <proper@email> Commit Name <commit@email>`
+///
+/// Names and emails are matched regardless of case, later lines win and `#` starts a comment.
+#[derive(Clone, Debug, Default)]
+pub struct Mailmap {
+    /// By lowercase commit email
+    entries: HashMap<String, MailmapEntry>,
+}
+impl Mailmap {
+    pub fn parse(content: &str) -> Self {
+        let mut mailmap = Self::default();
+        mailmap.add(content);
+        mailmap
+    }
+    /// Reads `.mailmap` at the root of the worktree, then the file set by `mailmap.file`
+    pub fn read(repo: &Repository) -> Self {
+        let mut paths = vec![create_path(&repo.worktree, vec![String::from(".mailmap")])];
+        if let Some(file) = &repo.config.mailmap.file {
+            let path = match (file.strip_prefix("~/"), get_my_home()) {
+                (Some(file), Ok(Some(home))) => create_path(&home, vec![file.to_owned()]),
+                _ => create_path(&repo.worktree, vec![file.to_owned()]),
+            };
+            paths.push(path);
+        }
+        let mut mailmap = Self::default();
+        for path in paths {
+            if let Ok(content) = fs::read_to_string(path) {
+                mailmap.add(&content);
+            }
+        }
+        mailmap
+    }
+    fn add(&mut self, content: &str) {
+        for line in content.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let Some((new_name, new_email, rest)) = parse_name_and_email(line, false) else {
+                continue;
+            };
+            let (old_name, old_email) = match parse_name_and_email(rest, true) {
+                Some((old_name, old_email, _)) => (old_name, Some(old_email)),
+                None => (None, None),
+            };
+            let (new_email, old_email) = match old_email {
+                Some(old_email) => (Some(new_email), old_email),
+                None => (None, new_email),
+            };
+            let entry = self
+                .entries
+                .entry(old_email.to_ascii_lowercase())
+                .or_default();
+            match old_name {
+                Some(old_name) => {
+                    entry
+                        .by_name
+                        .insert(old_name.to_ascii_lowercase(), (new_name, new_email));
+                }
+                None => {
+                    if new_name.is_some() {
+                        entry.replacement.0 = new_name;
+                    }
+                    if new_email.is_some() {
+                        entry.replacement.1 = new_email;
+                    }
+                }
+            }
+        }
+    }
+    /// The canonical name and email of someone who used `name` and `email`
+    pub fn map(&self, name: &str, email: &str) -> (String, String) {
+        let Some(entry) = self.entries.get(&email.to_ascii_lowercase()) else {
+            return (name.to_owned(), email.to_owned());
+        };
+        let (new_name, new_email) = entry
+            .by_name
+            .get(&name.to_ascii_lowercase())
+            .unwrap_or(&entry.replacement);
+        (
+            new_name.as_deref().unwrap_or(name).to_owned(),
+            new_email.as_deref().unwrap_or(email).to_owned(),
+        )
+    }
+    pub fn map_identity(&self, identity: &Signature) -> Signature {
+        let (name, email) = self.map(&identity.name, &identity.email);
+        Signature {
+            name,
+            email,
+            ..identity.clone()
+        }
+    }
+}
+
+/// Splits `Name <email> rest` into the name (`None` when empty), the email and the rest
+fn parse_name_and_email(
+    text: &str,
+    allow_empty_email: bool,
+) -> Option<(Option<String>, String, &str)> {
+    let left = text.find('<')?;
+    let right = left + 1 + text[left + 1..].find('>')?;
+    if !allow_empty_email && right == left + 1 {
+        return None;
+    }
+    let name = text[..left].trim();
+    Some((
+        (!name.is_empty()).then(|| name.to_owned()),
+        text[left + 1..right].to_owned(),
+        &text[right + 1..],
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let mailmap = Mailmap::parse(
+            "# comment\n\
+             Jane Doe <jane@new.x> <jane@old.x>\n\
+             Jane Doe <jane@new.x> J. Doe <jdoe@work.x>\n\
+             <bob@new.x> <bob@x.x>\n\
+             Bobby <bob@new.x> bob <bob@x.x>\n\
+             Alice Liddell <alice@x.x> # trailing comment\n",
+        );
+        let map = |name: &str, email: &str| {
+            let (name, email) = mailmap.map(name, email);
+            format!("{} <{}>", name, email)
+        };
+        assert_eq!(map("jane", "JANE@OLD.X"), "Jane Doe <jane@new.x>");
+        assert_eq!(map("J. Doe", "jdoe@work.x"), "Jane Doe <jane@new.x>");
+        assert_eq!(map("John", "jdoe@work.x"), "John <jdoe@work.x>");
+        assert_eq!(map("Bob", "bob@x.x"), "Bobby <bob@new.x>");
+        assert_eq!(map("Robert", "bob@x.x"), "Robert <bob@new.x>");
+        assert_eq!(map("alice", "alice@x.x"), "Alice Liddell <alice@x.x>");
+        assert_eq!(map("Zed", "zed@x.x"), "Zed <zed@x.x>");
+    }
+}
@@ -1,17 +1,34 @@
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env::current_dir,
-    fs::{remove_dir_all, File},
-    io::{ErrorKind, Read, Write},
+    fs::{self, remove_dir_all, File},
+    io::{BufRead, BufWriter, ErrorKind, Read, Write},
     path::{Path, PathBuf},
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use itertools::Itertools;
-use regex::Regex;
 use rit::{
-    create_dir, create_path, create_ref, resolve_ref, Object, ObjectHeaders, ObjectTypes,
-    Repository, TreeNode, TreeNodeType, TreeObject, GIT_DIR_PATH, RIT_DIR_PATH,
+    all_ref_commits, bisect_in_progress, bisect_log, bisect_mark, bisect_replay, bisect_reset,
+    bisect_start, blame, check_ref_format, commit_parents, create_dir, create_path, delete_ref,
+    describe, diff_trees, file_mode, format_commit, format_date, format_patch, format_stat,
+    format_summary, fsck_tag, grep_files, grep_tree_files, has_uncommitted_changes, head_branch,
+    is_ancestor, is_modified, is_valid_attr_name, list_refs, list_tags, merge_commits, merge_file,
+    merge_message, name_revs, normalize_path, parse_date, parse_todo, peel, peel_to_commit,
+    peel_to_tree, quote_path, read_file, read_ref, rebase_abort, rebase_continue, rebase_skip,
+    rebase_start, rebase_todo, relative_path, reset_merge, resolve_ref, resolve_revision_args,
+    rev_parse, sequencer_abort, sequencer_continue, sequencer_start, shell_quote, shorten_ref_name,
+    stash_apply, stash_commit, stash_drop, stash_list, stash_position, stash_save, strip_space,
+    switch_tree, tag_message, untracked_files, update_ref, update_symbolic_ref, verify_signature,
+    worktree_path, write_archive, ArchiveFormat, ArchiveOptions, AttrValue, Attributes,
+    BatchFormat, BisectOutcome, BisectTerm, BlameEntry, BlameOptions, Commit, CommitInfo,
+    ConflictStyle, Conversion, DateMode, DescribeOptions, Excludes, Graph, GrepFile, GrepPattern,
+    GrepSyntax, HistorySimplification, Index, IndexEntry, Mailmap, MergeFavor, MergeFileOptions,
+    NameRevOptions, Object, ObjectHeaders, ObjectTypes, Pathspec, PrettyFormat, RebaseOutcome,
+    ReplayAction, ReplayOptions, Repository, RevWalk, RevWalkOptions, SequencerReport, Signature,
+    SortOrder, StashOptions, Tag, TagSort, TreeMergeOptions, TreeNode, TreeNodeType, TreeObject,
+    DEFAULT_BATCH_FORMAT, DEFAULT_COPY_SCORE, DEFAULT_MOVE_SCORE, GIT_DIR_PATH, RIT_DIR_PATH,
 };
 
 #[derive(Debug, Parser)]
@@ -28,32 +45,353 @@ struct Cli {
 enum Commands {
     Init {},
     Clean {},
+    /// Shows the type, size or content of objects
     CatFile {
-        #[arg()]
-        _type: ObjectTypes,
-        #[arg(id = "OBJECT")]
-        hash: String,
+        /// Show the type of the object
+        #[arg(short = 't', action, group = "cat_file_mode")]
+        show_type: bool,
+        /// Show the size of the object
+        #[arg(short = 's', action, group = "cat_file_mode")]
+        size: bool,
+        /// Exit with zero status if the object exists, printing nothing
+        #[arg(short = 'e', action, group = "cat_file_mode")]
+        exists: bool,
+        /// Pretty-print the content of the object
+        #[arg(short = 'p', action, group = "cat_file_mode")]
+        pretty: bool,
+        /// Print the info and the content of every object named on stdin
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true,
+              default_missing_value = DEFAULT_BATCH_FORMAT, group = "cat_file_mode")]
+        batch: Option<String>,
+        /// Print the info of every object named on stdin
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true,
+              default_missing_value = DEFAULT_BATCH_FORMAT, group = "cat_file_mode")]
+        batch_check: Option<String>,
+        /// Do not flush the output after every object in batch modes
+        #[arg(long, action)]
+        buffer: bool,
+        /// `<object>`, or `<type> <object>` to show the content of the object peeled to a type
+        args: Vec<String>,
     },
-    /// Computes the object hash and optionally creates a blob from a file
+    /// Computes the object hash and optionally creates an object from files or stdin
     HashObject {
-        #[arg()]
-        _type: ObjectTypes,
+        /// Type of the object
+        #[arg(short = 't', value_name = "TYPE", default_value = "blob")]
+        object_type: String,
         /// Actually writes the object into the database
         #[arg(short, long, action)]
         write: bool,
-        /// Read the object from path
-        path: PathBuf,
+        /// Read the object from stdin, before any file
+        #[arg(long, action)]
+        stdin: bool,
+        /// Read the paths of the files to hash from stdin, one per line
+        #[arg(long, action, conflicts_with_all = ["stdin", "path", "paths"])]
+        stdin_paths: bool,
+        /// Hash the content as is, without the filters of its path
+        #[arg(long, action, conflicts_with = "path")]
+        no_filters: bool,
+        /// Apply the filters of this path instead of those of the file hashed
+        #[arg(long, value_name = "FILE")]
+        path: Option<String>,
+        /// Hash the content even when it is not a well-formed object of its type
+        #[arg(long, action)]
+        literally: bool,
+        /// Files to read the objects from
+        paths: Vec<PathBuf>,
+    },
+    /// Stages the content of worktree files in the index, new and deleted files included
+    Add {
+        /// Add ignored files too
+        #[arg(short, long, action)]
+        force: bool,
+        /// Only show what would be added or removed
+        #[arg(short = 'n', long, action)]
+        dry_run: bool,
+        /// Show the paths added or removed
+        #[arg(short, long, action)]
+        verbose: bool,
+        /// Files to add, directories standing for the files inside them
+        pathspecs: Vec<String>,
     },
+    /// Shows the commit history, starting from HEAD unless revisions are given
     Log {
-        // Hash of commit to start at
+        #[command(flatten)]
+        walk: RevWalkArgs,
+        /// Pretty format (oneline, short, medium, full, format:<string>, tformat:<string>) or
+        /// `dot` for a graphviz digraph
+        #[arg(long, visible_alias = "pretty")]
+        format: Option<String>,
+        /// Shorthand for --format=oneline
+        #[arg(long, action)]
+        oneline: bool,
+        /// Date format (default, iso, iso-strict, rfc, short, relative, raw, unix)
+        #[arg(long)]
+        date: Option<String>,
+        /// Draw the branch structure next to the commits, implies --topo-order
+        #[arg(long, action)]
+        graph: bool,
+        /// Show names and emails as recorded instead of canonicalizing them with .mailmap
+        #[arg(long, action)]
+        no_mailmap: bool,
+    },
+    /// Summarizes the history for release notes, grouping commit subjects by author
+    Shortlog {
+        /// Only show the number of commits of each author
+        #[arg(short, long, action)]
+        summary: bool,
+        /// Sort by number of commits instead of by author
+        #[arg(short, long, action)]
+        numbered: bool,
+        /// Show the email of each author
+        #[arg(short, long, action)]
+        email: bool,
+        #[arg(value_name = "REVISION", default_value = "HEAD")]
+        revisions: Vec<String>,
+    },
+    /// Lists commits reachable from the given revisions, most recent first
+    RevList {
+        #[command(flatten)]
+        walk: RevWalkArgs,
+        /// Print the parents after each commit
+        #[arg(long, action)]
+        parents: bool,
+        /// Print the number of commits instead of listing them
+        #[arg(long, action)]
+        count: bool,
+    },
+    /// Shows the commit that last changed each line of a file, starting from the worktree file
+    /// unless a revision is given
+    Blame {
+        /// Only blame lines <start>,<end>, where <end> may be +<count> or -<count> lines from
+        /// <start>, can be given multiple times
+        #[arg(short = 'L', value_name = "START,END", action = clap::ArgAction::Append)]
+        ranges: Vec<String>,
+        /// Ignore whitespace when comparing lines with the parent's
+        #[arg(short = 'w', action)]
+        ignore_whitespace: bool,
+        /// Detect lines moved within the file, blocks scoring less than the given number of
+        /// alphanumeric characters stay with the commit
+        #[arg(short = 'M', value_name = "SCORE", num_args = 0..=1, require_equals = true,
+              default_missing_value = "")]
+        moves: Option<String>,
+        /// Detect lines copied from files changed in the same commit, given twice to look in
+        /// every file of the parent when the file is created and three times to always do
+        #[arg(short = 'C', value_name = "SCORE", num_args = 0..=1, require_equals = true,
+              default_missing_value = "", action = clap::ArgAction::Append)]
+        copies: Vec<String>,
+        /// Machine readable output, showing the commit details once
+        #[arg(long, action, group = "blame_output")]
+        porcelain: bool,
+        /// Like --porcelain, showing the commit details for every line
+        #[arg(long, action, group = "blame_output")]
+        line_porcelain: bool,
+        /// Print the entries as they are found, in the porcelain format without the lines
+        #[arg(long, action, group = "blame_output")]
+        incremental: bool,
+        file: String,
+        #[arg(id = "REVISION")]
+        rev: Option<String>,
+    },
+    /// Names a commit after the closest annotated tag it is reachable from, as
+    /// <tag>-<count>-g<hash> where count is the number of commits since the tag
+    Describe {
+        /// Also use lightweight tags
+        #[arg(long, action)]
+        tags: bool,
+        /// Show the count and hash even when the commit is tagged
+        #[arg(long, action)]
+        long: bool,
+        /// Append a mark, `-dirty` by default, when the worktree has uncommitted changes
+        #[arg(long, value_name = "MARK", num_args = 0..=1, require_equals = true,
+              default_missing_value = "-dirty", conflicts_with = "COMMIT")]
+        dirty: Option<String>,
+        /// Length of the abbreviated hash, 0 to only show the tag
+        #[arg(long, default_value_t = 7)]
+        abbrev: usize,
+        /// Only use tags matching the glob, can be given multiple times
+        #[arg(long = "match", value_name = "PATTERN", action = clap::ArgAction::Append)]
+        patterns: Vec<String>,
         #[arg(id = "COMMIT")]
-        hash: String,
+        commit: Option<String>,
+    },
+    /// Names commits relative to the refs they are reachable from, like main~3^2
+    NameRev {
+        /// Only use tags to name the commits
+        #[arg(long, action)]
+        tags: bool,
+        /// Only use refs matching the glob, can be given multiple times
+        #[arg(long = "refs", value_name = "PATTERN", action = clap::ArgAction::Append)]
+        refs: Vec<String>,
+        /// Only print the names, not the revisions given
+        #[arg(long, action)]
+        name_only: bool,
+        #[arg(id = "COMMIT", required = true)]
+        commits: Vec<String>,
+    },
+    /// Finds the commit that introduced a bug with a binary search through the history
+    Bisect {
+        #[command(subcommand)]
+        command: BisectCommands,
+    },
+    /// Shows the files in the index and the worktree, the cached ones by default
+    LsFiles {
+        /// Show the files in the index
+        #[arg(short, long, action)]
+        cached: bool,
+        /// Show the files of the index missing from the worktree
+        #[arg(short, long, action)]
+        deleted: bool,
+        /// Show the files of the index modified in the worktree
+        #[arg(short, long, action)]
+        modified: bool,
+        /// Show the untracked files
+        #[arg(short, long, action)]
+        others: bool,
+        /// Show the mode, hash and stage of the index entries
+        #[arg(short, long, action)]
+        stage: bool,
+        /// Only show the unmerged entries, with their stage
+        #[arg(short, long, action)]
+        unmerged: bool,
+        /// Leave aside the untracked files matching the pattern
+        #[arg(short = 'x', long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Leave aside the untracked files matching the patterns of the file
+        #[arg(short = 'X', long, value_name = "FILE")]
+        exclude_from: Vec<PathBuf>,
+        /// Leave aside the untracked files ignored by `.gitignore`, `info/exclude` and
+        /// `core.excludesFile`
+        #[arg(long, action)]
+        exclude_standard: bool,
+        /// Show paths relative to the worktree root rather than to the current directory
+        #[arg(long, action)]
+        full_name: bool,
+        /// Terminate paths with NUL rather than newline, without quoting them
+        #[arg(short = 'z', action)]
+        nul_terminated: bool,
+        /// Only show the files matching these pathspecs, the current directory by default
+        pathspecs: Vec<String>,
     },
+    /// Shows the entries of a tree, of the tree of a commit or of a tag
     LsTree {
+        /// Only show trees
+        #[arg(short = 'd', action)]
+        trees_only: bool,
+        /// Recurse into subtrees
+        #[arg(short, action)]
+        recursive: bool,
+        /// Show trees even when recursing into them
+        #[arg(short = 't', action)]
+        show_trees: bool,
+        /// Show the size of blobs
+        #[arg(short, long, action)]
+        long: bool,
+        /// Only show the paths
+        #[arg(long, action, alias = "name-status", conflicts_with_all = ["long", "object_only"])]
+        name_only: bool,
+        /// Only show the hashes
+        #[arg(long, action, conflicts_with = "long")]
+        object_only: bool,
+        /// Show paths relative to the worktree root rather than to the current directory
+        #[arg(long, action)]
+        full_name: bool,
+        /// Ignore the current directory, as if run from the worktree root
+        #[arg(long, action)]
+        full_tree: bool,
+        /// Terminate entries with NUL rather than newline, without quoting paths
+        #[arg(short = 'z', action)]
+        nul_terminated: bool,
         #[arg(id = "tree-ish")]
         hash: String,
+        /// Only show these paths, trees being recursed into to reach them
+        pathspecs: Vec<String>,
+    },
+    /// Looks for lines matching a pattern in the tracked files of the worktree, the index or
+    /// trees
+    Grep {
+        /// Prefix matching lines with their number
+        #[arg(short = 'n', long, action)]
+        line_number: bool,
+        /// Ignore case differences between the pattern and the files
         #[arg(short, long, action)]
-        recursive: bool,
+        ignore_case: bool,
+        /// Only match the pattern at word boundaries
+        #[arg(short, long, action)]
+        word_regexp: bool,
+        /// Only show the names of the files with matches
+        #[arg(short = 'l', long, action, alias = "name-only")]
+        files_with_matches: bool,
+        /// Show the number of matching lines of each file instead of the lines
+        #[arg(short, long, action)]
+        count: bool,
+        /// Read the pattern as a POSIX basic regex, the default
+        #[arg(short = 'G', long, action, group = "grep_syntax")]
+        basic_regexp: bool,
+        /// Read the pattern as a POSIX extended regex
+        #[arg(short = 'E', long, action, group = "grep_syntax")]
+        extended_regexp: bool,
+        /// Match the pattern literally
+        #[arg(short = 'F', long, action, group = "grep_syntax")]
+        fixed_strings: bool,
+        /// Read the pattern as a Perl-like regex
+        #[arg(short = 'P', long, action, group = "grep_syntax")]
+        perl_regexp: bool,
+        /// Search the files of the index rather than of the worktree
+        #[arg(long, action)]
+        cached: bool,
+        /// Show paths relative to the worktree root rather than to the current directory
+        #[arg(long, action)]
+        full_name: bool,
+        pattern: String,
+        /// Trees to search, given as tree-ish revisions, followed by the pathspecs to search
+        args: Vec<String>,
+        /// Only search the files matching these pathspecs, the current directory by default
+        #[arg(id = "PATH", last = true)]
+        pathspecs: Vec<String>,
+    },
+    /// Shows the gitattributes of paths
+    CheckAttr {
+        /// Show every attribute set, unset or given a value for the paths
+        #[arg(short, long, action)]
+        all: bool,
+        /// Read the `.gitattributes` files of the index rather than of the worktree
+        #[arg(long, action)]
+        cached: bool,
+        /// Read the paths from stdin, one per line
+        #[arg(long, action)]
+        stdin: bool,
+        /// Separate output fields, and paths read from stdin, with NUL rather than newline
+        #[arg(short = 'z', action)]
+        nul_terminated: bool,
+        /// Attributes to show, then paths unless `--` separates them (a single attribute
+        /// without it), or paths only with `--all`
+        args: Vec<String>,
+        /// Paths to show the attributes of
+        #[arg(id = "PATH", last = true)]
+        paths: Vec<String>,
+    },
+    /// Writes an archive of the files of a tree, of the tree of a commit or of a tag
+    Archive {
+        /// Format of the archive (tar, tgz, tar.gz or zip), guessed from the name of the output
+        /// file by default, or tar
+        #[arg(long)]
+        format: Option<String>,
+        /// Prepend this to the paths in the archive, a directory when it ends with `/`
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Write the archive to this file rather than to stdout
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Read attributes from the worktree rather than from the tree archived
+        #[arg(long, action)]
+        worktree_attributes: bool,
+        /// List the paths archived on stderr
+        #[arg(short, long, action)]
+        verbose: bool,
+        #[arg(id = "tree-ish")]
+        tree_ish: String,
+        /// Only archive these paths
+        pathspecs: Vec<String>,
     },
     Checkout {
         #[arg(id = "COMMIT")]
@@ -68,18 +406,374 @@ enum Commands {
         #[arg(long)]
         head: bool,
     },
+    /// Creates a tag <NAME> pointing to <OBJECT> (HEAD by default), or lists, deletes or verifies
+    /// tags. Lists all tags when no name is given
     Tag {
         /// Creates a new tag object
         #[arg(id = "a", short)]
         annotate: bool,
-        #[arg()]
-        name: String,
-        // The hash of the blob, commit or tree the tag refers to
-        #[arg(id = "OBJECT")]
-        hash: String,
+        /// Replaces an existing tag of the same name
+        #[arg(short, long, action)]
+        force: bool,
+        /// Lists the tags matching any of the given patterns
+        #[arg(short, long, action)]
+        list: bool,
+        /// Deletes the given tags
+        #[arg(short, long, action, conflicts_with_all = ["list", "verify"])]
+        delete: bool,
+        /// Verifies the gpg signature of the given tags
+        #[arg(short, long, action, conflicts_with = "list")]
+        verify: bool,
+        /// Prints the first <n> lines (1 by default) of each tag's message when listing, implies -l.
+        /// Like git, only a number attached to the flag (`-n3`) is a count, `-n v*` lists `v*`
+        #[arg(short = 'n', value_name = "n", num_args = 0..=1, default_missing_value = "1")]
+        lines: Option<String>,
+        /// Sorts listed tags by refname, version:refname, creatordate, taggerdate or objectname,
+        /// reversed when prefixed with `-`. The last key given is the primary one
+        #[arg(long, value_name = "KEY", action = clap::ArgAction::Append)]
+        sort: Vec<String>,
         /// Inclue a message with the tag, implies -a
         #[arg(short, long)]
         message: Option<String>,
+        /// <NAME> [<OBJECT>] when creating a tag, patterns when listing, names otherwise
+        #[arg(value_name = "ARGS")]
+        args: Vec<String>,
+    },
+    /// Writes the tag object read from stdin, once it passes strict checks
+    Mktag {},
+    /// Checks that a ref name is valid, exits with 1 when it is not
+    CheckRefFormat {
+        /// Accepts names with a single component, like `main`
+        #[arg(long, action)]
+        allow_onelevel: bool,
+        /// Removes leading slashes and collapses repeated ones, then prints the name if valid
+        #[arg(long, action)]
+        normalize: bool,
+        #[arg(value_name = "REFNAME")]
+        name: String,
+    },
+    /// Three-way merge of a file, writing the result into CURRENT
+    MergeFile {
+        /// Labels used in the conflict markers, in the order current, base, other
+        #[arg(short = 'L', num_args = 1, action = clap::ArgAction::Append)]
+        labels: Vec<String>,
+        /// Send the result to stdout instead of overwriting CURRENT
+        #[arg(short = 'p', long, action)]
+        stdout: bool,
+        /// Show the base section in conflicts
+        #[arg(long, action, conflicts_with = "zdiff3")]
+        diff3: bool,
+        /// Like --diff3, but moves lines common to both sides out of the conflict
+        #[arg(long, action)]
+        zdiff3: bool,
+        /// Resolve conflicts by favoring our side
+        #[arg(long, action, group = "favor")]
+        ours: bool,
+        /// Resolve conflicts by favoring their side
+        #[arg(long, action, group = "favor")]
+        theirs: bool,
+        /// Resolve conflicts by keeping both sides
+        #[arg(long, action, group = "favor")]
+        union: bool,
+        #[arg(long, default_value_t = 7)]
+        marker_size: usize,
+        /// Do not warn about conflicts
+        #[arg(short, long, action)]
+        quiet: bool,
+        current: PathBuf,
+        base: PathBuf,
+        other: PathBuf,
+    },
+    /// Joins the history of another commit into the current branch
+    Merge {
+        #[arg(id = "COMMIT", required_unless_present_any = ["abort", "continue"])]
+        commit: Option<String>,
+        /// Create a merge commit even when the merge resolves as a fast-forward
+        #[arg(long, action, conflicts_with = "ff_only")]
+        no_ff: bool,
+        /// Refuse to merge unless the merge resolves as a fast-forward
+        #[arg(long, action)]
+        ff_only: bool,
+        /// Update the index and worktree with the merge result, without creating a commit
+        #[arg(long, action)]
+        squash: bool,
+        /// Message of the merge commit
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Abort the current conflicted merge, restoring HEAD's state
+        #[arg(long, action, conflicts_with_all = ["COMMIT", "continue"])]
+        abort: bool,
+        /// Conclude the current merge once the conflicts are resolved in the index
+        #[arg(id = "continue", long = "continue", action, conflicts_with = "COMMIT")]
+        _continue: bool,
+    },
+    /// Applies the changes introduced by existing commits onto HEAD
+    CherryPick {
+        #[arg(id = "COMMIT", required_unless_present_any = ["abort", "continue"])]
+        commits: Vec<String>,
+        /// Append a line recording which commit was cherry-picked to the message
+        #[arg(short = 'x', action)]
+        record_origin: bool,
+        /// Parent number (starting from 1) of the mainline when picking a merge
+        #[arg(short, long)]
+        mainline: Option<usize>,
+        /// Resume after resolving conflicts
+        #[arg(id = "continue", long = "continue", action, conflicts_with_all = ["COMMIT", "abort"])]
+        _continue: bool,
+        /// Cancel the operation and return to the pre-sequence state
+        #[arg(long, action, conflicts_with = "COMMIT")]
+        abort: bool,
+    },
+    /// Creates commits reverting the changes introduced by existing commits
+    Revert {
+        #[arg(id = "COMMIT", required_unless_present_any = ["abort", "continue"])]
+        commits: Vec<String>,
+        /// Parent number (starting from 1) of the mainline when reverting a merge
+        #[arg(short, long)]
+        mainline: Option<usize>,
+        /// Resume after resolving conflicts
+        #[arg(id = "continue", long = "continue", action, conflicts_with_all = ["COMMIT", "abort"])]
+        _continue: bool,
+        /// Cancel the operation and return to the pre-sequence state
+        #[arg(long, action, conflicts_with = "COMMIT")]
+        abort: bool,
+    },
+    /// Replays the commits of the current branch that are not in UPSTREAM on top of it
+    Rebase {
+        #[arg(id = "UPSTREAM", required_unless_present_any = ["abort", "continue", "skip"])]
+        upstream: Option<String>,
+        /// Replay onto this commit instead of UPSTREAM
+        #[arg(long)]
+        onto: Option<String>,
+        /// Run the steps of this todo list (pick, reword, squash, fixup, drop, exec) instead of picking every commit
+        #[arg(long)]
+        todo_file: Option<PathBuf>,
+        /// Resume after resolving conflicts
+        #[arg(id = "continue", long = "continue", action, conflicts_with_all = ["UPSTREAM", "abort", "skip"])]
+        _continue: bool,
+        /// Skip the commit that could not be applied
+        #[arg(long, action, conflicts_with_all = ["UPSTREAM", "abort"])]
+        skip: bool,
+        /// Cancel the rebase and check out the original branch
+        #[arg(long, action, conflicts_with = "UPSTREAM")]
+        abort: bool,
+    },
+    /// Saves local changes away and resets to HEAD, `push` when no subcommand is given
+    #[command(args_conflicts_with_subcommands = true)]
+    Stash {
+        #[command(subcommand)]
+        command: Option<StashCommands>,
+        /// Description of the entry
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Also stash untracked files
+        #[arg(short = 'u', long, action)]
+        include_untracked: bool,
+    },
+}
+
+/// Revisions and history limiting options shared by the commands walking commits
+#[derive(Debug, Args)]
+struct RevWalkArgs {
+    /// Commits to start from (`<rev>`, `^<rev>`, `<a>..<b>`, `<a>...<b>`)
+    #[arg(id = "REVISION")]
+    revisions: Vec<String>,
+    /// Revisions following this flag are negated, `--not A` being the same as `^A`
+    #[arg(long = "not", num_args = 0.., value_name = "REVISION")]
+    not: Vec<String>,
+    /// Start from HEAD and every ref
+    #[arg(long, action)]
+    all: bool,
+    /// Show no parent before all of its children, keeping lines of history together
+    #[arg(long, action, group = "order")]
+    topo_order: bool,
+    /// Show no parent before all of its children, otherwise by commit date
+    #[arg(long, action, group = "order")]
+    date_order: bool,
+    /// Output the commits in reverse order
+    #[arg(long, action)]
+    reverse: bool,
+    /// Only follow the first parent of merge commits
+    #[arg(long, action)]
+    first_parent: bool,
+    /// Only commits more recent than this date
+    #[arg(long, visible_alias = "after")]
+    since: Option<String>,
+    /// Only commits older than this date
+    #[arg(long, visible_alias = "before")]
+    until: Option<String>,
+    /// Only commits whose author matches this regex
+    #[arg(long)]
+    author: Vec<String>,
+    /// Only commits whose message matches this regex
+    #[arg(long)]
+    grep: Vec<String>,
+    /// Only merge commits
+    #[arg(long, action, conflicts_with = "no_merges")]
+    merges: bool,
+    /// No merge commits
+    #[arg(long, action)]
+    no_merges: bool,
+    /// Limit the number of commits output
+    #[arg(short = 'n', long)]
+    max_count: Option<usize>,
+    /// Walk every parent of merges when limiting to paths
+    #[arg(long, action)]
+    full_history: bool,
+    /// Also remove merges that bring no change of their own once history is limited to paths
+    #[arg(long, action)]
+    simplify_merges: bool,
+    /// Keep following the single path given across renames
+    #[arg(long, action, requires = "PATH")]
+    follow: bool,
+    /// Only commits changing these paths
+    #[arg(id = "PATH", last = true)]
+    paths: Vec<String>,
+    #[arg(skip)]
+    rewrite_parents: bool,
+}
+impl RevWalkArgs {
+    fn walk(self, repo: &Repository) -> RevWalk<'_> {
+        let mut revisions = self.revisions;
+        if revisions.is_empty() && self.not.is_empty() && !self.all {
+            revisions.push(String::from("HEAD"));
+        }
+        if !self.not.is_empty() {
+            revisions.push(String::from("--not"));
+            revisions.extend(self.not);
+        }
+        let (mut include, exclude) =
+            resolve_revision_args(repo, &revisions).unwrap_or_else(|e| panic!("{}", e));
+        if self.all {
+            include.extend(all_ref_commits(repo));
+        }
+        let date = |date: Option<String>| {
+            date.map(|date| parse_date(&date).unwrap_or_else(|e| panic!("{}", e)))
+        };
+        let options = RevWalkOptions {
+            order: match (self.topo_order, self.date_order) {
+                (true, _) => SortOrder::Topo,
+                (_, true) => SortOrder::Date,
+                _ => SortOrder::Default,
+            },
+            reverse: self.reverse,
+            first_parent: self.first_parent,
+            since: date(self.since),
+            until: date(self.until),
+            authors: self.author,
+            grep: self.grep,
+            min_parents: self.merges.then_some(2),
+            max_parents: self.no_merges.then_some(1),
+            max_count: self.max_count,
+            simplification: match (self.simplify_merges, self.full_history) {
+                (true, _) => HistorySimplification::SimplifyMerges,
+                (_, true) => HistorySimplification::FullHistory,
+                _ => HistorySimplification::Default,
+            },
+            follow: self.follow,
+            pathspec: Pathspec::new(repo, &self.paths, false).unwrap_or_else(|e| panic!("{}", e)),
+            rewrite_parents: self.rewrite_parents,
+        };
+        if options.follow && options.pathspec.single_path().is_none() {
+            panic!("--follow requires exactly one pathspec");
+        }
+        RevWalk::with_options(repo, &include, &exclude, options).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum StashCommands {
+    /// Saves the local changes in a new stash entry
+    Push {
+        /// Description of the entry
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Also stash untracked files
+        #[arg(short = 'u', long, action)]
+        include_untracked: bool,
+    },
+    /// Same as push, with the description given as arguments
+    Save {
+        /// Also stash untracked files
+        #[arg(short = 'u', long, action)]
+        include_untracked: bool,
+        message: Vec<String>,
+    },
+    /// Lists the stash entries
+    List {},
+    /// Shows the changes recorded in a stash entry, as a diffstat by default
+    Show {
+        /// Show the changes as a patch
+        #[arg(short, long, action)]
+        patch: bool,
+        #[arg(id = "STASH", default_value = "stash@{0}")]
+        stash: String,
+    },
+    /// Applies a stash entry onto the worktree
+    Apply {
+        /// Also restore the staged changes
+        #[arg(long, action)]
+        index: bool,
+        #[arg(id = "STASH", default_value = "stash@{0}")]
+        stash: String,
+    },
+    /// Applies a stash entry then drops it, it is kept on conflicts
+    Pop {
+        /// Also restore the staged changes
+        #[arg(long, action)]
+        index: bool,
+        #[arg(id = "STASH", default_value = "stash@{0}")]
+        stash: String,
+    },
+    /// Removes a stash entry
+    Drop {
+        #[arg(id = "STASH", default_value = "stash@{0}")]
+        stash: String,
+    },
+    /// Creates a branch at the commit the entry was based on and applies it there
+    Branch {
+        name: String,
+        #[arg(id = "STASH", default_value = "stash@{0}")]
+        stash: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BisectCommands {
+    /// Starts bisecting, optionally given the bad commit followed by good ones
+    Start {
+        #[arg(value_name = "REVISION")]
+        revisions: Vec<String>,
+    },
+    /// Marks a commit, HEAD by default, as containing the bug
+    Bad {
+        #[arg(value_name = "REVISION")]
+        revision: Option<String>,
+    },
+    /// Marks commits, HEAD by default, as not containing the bug
+    Good {
+        #[arg(value_name = "REVISION")]
+        revisions: Vec<String>,
+    },
+    /// Marks commits or ranges, HEAD by default, as not testable
+    Skip {
+        #[arg(value_name = "REVISION")]
+        revisions: Vec<String>,
+    },
+    /// Stops bisecting and checks out the original HEAD, or COMMIT
+    Reset {
+        #[arg(id = "COMMIT")]
+        commit: Option<String>,
+    },
+    /// Shows the commits marked so far
+    Log {},
+    /// Starts bisecting over from a log
+    Replay { file: PathBuf },
+    /// Marks each commit to test from the exit status of a command: 0 is good, 125 skips the
+    /// commit, and others below 128 are bad
+    Run {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
     },
 }
 
@@ -98,162 +792,1215 @@ fn main() {
         Commands::Clean {} => {
             Repository::clean_worktree(current_dir().unwrap(), git_dir_path).unwrap();
         }
-        Commands::CatFile {
-            hash: object,
-            _type,
+        Commands::CatFile {
+            show_type,
+            size,
+            exists,
+            pretty,
+            batch,
+            batch_check,
+            buffer,
+            args,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let mut stdout = BufWriter::new(std::io::stdout().lock());
+            if let Some(format) = batch.as_ref().or(batch_check.as_ref()) {
+                if !args.is_empty() {
+                    panic!("batch modes take no arguments");
+                }
+                let format = BatchFormat::parse(format).unwrap_or_else(|e| panic!("{}", e));
+                for line in std::io::stdin().lines() {
+                    let line = line.unwrap();
+                    let (name, rest) = match format.uses_rest() {
+                        true => line
+                            .split_once([' ', '\t'])
+                            .map(|(name, rest)| (name, rest.trim_start()))
+                            .unwrap_or((&line, "")),
+                        false => (line.as_str(), ""),
+                    };
+                    let object = rev_parse(&repo, name).and_then(|hash| {
+                        Object::read_data(&repo, &hash)
+                            .map(|(object_type, data)| (hash, object_type, data))
+                    });
+                    match object {
+                        Ok((hash, object_type, data)) => {
+                            let info = format.expand(&repo, &hash, &object_type, data.len(), rest);
+                            writeln!(stdout, "{}", info).unwrap();
+                            if batch.is_some() {
+                                stdout.write_all(&data).unwrap();
+                                writeln!(stdout).unwrap();
+                            }
+                        }
+                        Err(e) if e.ends_with("is ambiguous") => {
+                            writeln!(stdout, "{} ambiguous", name).unwrap()
+                        }
+                        Err(_) => writeln!(stdout, "{} missing", name).unwrap(),
+                    }
+                    if !buffer {
+                        stdout.flush().unwrap();
+                    }
+                }
+            } else if show_type || size || exists || pretty {
+                let name = match args.as_slice() {
+                    [name] => name,
+                    [] => {
+                        let flag = [(show_type, 't'), (size, 's'), (exists, 'e'), (pretty, 'p')]
+                            .into_iter()
+                            .find_map(|(given, flag)| given.then_some(flag))
+                            .unwrap();
+                        panic!("<object> required with '-{}'", flag)
+                    }
+                    _ => panic!("too many arguments"),
+                };
+                let hash = rev_parse(&repo, name)
+                    .unwrap_or_else(|_| panic!("Not a valid object name {}", name));
+                let object = Object::read_data(&repo, &hash);
+                if exists {
+                    std::process::exit(if object.is_ok() { 0 } else { 1 });
+                }
+                let (object_type, data) = object.unwrap_or_else(|_| match pretty {
+                    true => panic!("Not a valid object name {}", name),
+                    false => panic!("rit cat-file: could not get object info"),
+                });
+                if show_type {
+                    writeln!(stdout, "{}", object_type.to_string()).unwrap();
+                } else if size {
+                    writeln!(stdout, "{}", data.len()).unwrap();
+                } else if object_type == ObjectTypes::Tree {
+                    for entry in TreeObject::from_data(data).unwrap().entries {
+                        writeln!(
+                            stdout,
+                            "{} {} {}\t{}",
+                            entry.mode, entry._type, entry.hash, entry.path
+                        )
+                        .unwrap();
+                    }
+                } else {
+                    stdout.write_all(&data).unwrap();
+                }
+            } else {
+                let [type_name, name] = args.as_slice() else {
+                    panic!(
+                        "only two arguments allowed in <type> <object> mode, not {}",
+                        args.len()
+                    );
+                };
+                let target = match type_name.as_str() {
+                    "commit" | "tree" | "blob" | "tag" => ObjectTypes::from_string(type_name),
+                    _ => panic!("invalid object type \"{}\"", type_name),
+                };
+                let hash = rev_parse(&repo, name)
+                    .unwrap_or_else(|_| panic!("Not a valid object name {}", name));
+                let (_, data) = peel(&repo, &hash, Some(target))
+                    .and_then(|hash| Object::read_data(&repo, &hash))
+                    .unwrap_or_else(|_| panic!("rit cat-file {}: bad file", name));
+                stdout.write_all(&data).unwrap();
+            }
+        }
+        Commands::HashObject {
+            object_type,
+            write,
+            stdin,
+            stdin_paths,
+            no_filters,
+            path: filter_path,
+            literally,
+            paths,
+        } => {
+            let object_type = match object_type.as_str() {
+                "commit" | "tree" | "blob" | "tag" => ObjectTypes::from_string(&object_type),
+                _ => panic!("invalid object type \"{}\"", object_type),
+            };
+            let repo = Repository::find_worktree_root(current_dir().unwrap(), git_dir_path);
+            let repo = match write {
+                true => Some(repo.unwrap()),
+                false => repo,
+            };
+            // Inside a repository, blobs go through the conversion of the path they are hashed for
+            let hash_object = |data: Vec<u8>, path: Option<&str>| {
+                // Like git, warnings name the path as given, joined to the prefix
+                let given = match (&repo, path) {
+                    (Some(repo), Some(path)) if !no_filters => {
+                        match Path::new(path).strip_prefix(&repo.worktree) {
+                            Ok(relative) => Some(relative.to_string_lossy().into_owned()),
+                            Err(_) if path.starts_with('/') => None,
+                            Err(_) => Some(format!("{}{}", repo.current_prefix(), path)),
+                        }
+                    }
+                    _ => None,
+                };
+                let path = given.as_deref().and_then(|given| normalize_path("", given));
+                if !literally {
+                    Object::check_data(&object_type, &data).unwrap_or_else(|e| panic!("{}", e));
+                }
+                let hash = Object::hash_object(
+                    repo.as_ref(),
+                    &object_type,
+                    data,
+                    path.as_deref().zip(given.as_deref()),
+                    write,
+                )
+                .unwrap_or_else(|e| panic!("{}", e));
+                println!("{}", hash);
+            };
+            let hash_file = |path: &Path| {
+                if path.is_dir() {
+                    panic!("Unable to hash {}", path.display());
+                }
+                let data = std::fs::read(path).unwrap_or_else(|e| {
+                    panic!("could not open '{}' for reading: {}", path.display(), e)
+                });
+                let path = filter_path
+                    .clone()
+                    .unwrap_or(path.to_string_lossy().into_owned());
+                hash_object(data, Some(&path));
+            };
+            if stdin {
+                let mut data = Vec::new();
+                std::io::stdin().read_to_end(&mut data).unwrap();
+                hash_object(data, filter_path.as_deref());
+            }
+            if stdin_paths {
+                for line in std::io::stdin().lines() {
+                    hash_file(Path::new(&line.unwrap()));
+                }
+            }
+            for path in paths {
+                hash_file(&path);
+            }
+        }
+        Commands::Add {
+            force,
+            dry_run,
+            verbose,
+            pathspecs,
+        } => {
+            if pathspecs.is_empty() {
+                eprintln!("Nothing specified, nothing added.");
+                eprintln!("hint: Maybe you wanted to say 'git add .'?");
+                eprintln!("hint: Turn this message off by running");
+                eprintln!("hint: \"git config advice.addEmptyPathspec false\"");
+                return;
+            }
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let pathspec =
+                Pathspec::new(&repo, &pathspecs, false).unwrap_or_else(|e| panic!("{}", e));
+            let mut index = Index::read(&repo).unwrap();
+            let mut excludes = match force {
+                true => Excludes::new(&repo),
+                false => Excludes::standard(&repo),
+            };
+            // Nested repositories, listed as `dir/`, are left aside
+            let untracked: Vec<String> = untracked_files(&repo, &index, &mut excludes)
+                .unwrap()
+                .into_iter()
+                .filter(|path| !path.ends_with('/'))
+                .collect();
+            // Like git, ignored paths are only reported when a pathspec names them or a file
+            // inside them, the topmost ignored directory standing for its content
+            let mut ignored = BTreeSet::new();
+            for literal in pathspec.literals() {
+                let literal = literal.trim_end_matches('/');
+                let mut end = 0;
+                while end < literal.len() {
+                    end = literal[end + 1..]
+                        .find('/')
+                        .map_or(literal.len(), |i| end + 1 + i);
+                    let path = &literal[..end];
+                    let Ok(metadata) = fs::symlink_metadata(worktree_path(&repo, path)) else {
+                        break;
+                    };
+                    let tracked = index.entries.iter().any(|entry| {
+                        entry.path == path || entry.path.starts_with(&format!("{}/", path))
+                    });
+                    if !tracked && excludes.is_excluded(path, metadata.is_dir()) {
+                        ignored.insert(path.to_owned());
+                        break;
+                    }
+                }
+            }
+            let mut tracked: Vec<&str> = index
+                .entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect();
+            tracked.dedup();
+            // Like git, pathspecs naming a file that exists, ignored or not, always match
+            for arg in &pathspecs {
+                let single = Pathspec::new(&repo, std::slice::from_ref(arg), false).unwrap();
+                let matched = tracked.iter().any(|path| single.matches(path))
+                    || untracked.iter().any(|path| single.matches(path))
+                    || single.literals().any(|literal| {
+                        !literal.is_empty()
+                            && fs::symlink_metadata(worktree_path(&repo, literal)).is_ok()
+                    });
+                if !matched {
+                    panic!("pathspec '{}' did not match any files", arg);
+                }
+            }
+            let mut exit_code = 0;
+            if !ignored.is_empty() {
+                eprintln!("The following paths are ignored by one of your .gitignore files:");
+                ignored.iter().for_each(|path| eprintln!("{}", path));
+                eprintln!("hint: Use -f if you really want to add them.");
+                eprintln!("hint: Turn this message off by running");
+                eprintln!("hint: \"git config advice.addIgnoredFile false\"");
+                exit_code = 1;
+            }
+            let mut changes: Vec<(String, Option<fs::Metadata>)> = Vec::new();
+            for path in tracked.iter().filter(|path| pathspec.matches(path)) {
+                let unchanged = index
+                    .get(path)
+                    .is_some_and(|entry| !is_modified(&repo, entry));
+                match fs::symlink_metadata(worktree_path(&repo, path)) {
+                    Ok(metadata) if metadata.is_dir() => {}
+                    Ok(metadata) if !unchanged => changes.push((path.to_string(), Some(metadata))),
+                    Ok(_) => {}
+                    Err(_) => changes.push((path.to_string(), None)),
+                }
+            }
+            for path in untracked.iter().filter(|path| pathspec.matches(path)) {
+                let metadata = fs::symlink_metadata(worktree_path(&repo, path)).unwrap();
+                changes.push((path.clone(), Some(metadata)));
+            }
+            changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let mut attributes = Attributes::new(&repo);
+            for (path, metadata) in changes {
+                let Some(metadata) = metadata else {
+                    if dry_run || verbose {
+                        println!("remove '{}'", path);
+                    }
+                    if !dry_run {
+                        index.entries.retain(|entry| entry.path != path);
+                    }
+                    continue;
+                };
+                let data =
+                    read_file(&worktree_path(&repo, &path)).unwrap_or_else(|e| panic!("{}", e));
+                let data = match metadata.is_symlink() {
+                    true => data,
+                    false => {
+                        let mut conversion = Conversion::new(&repo, &mut attributes, &path);
+                        if let Some(entry) = index.get(&path) {
+                            conversion = conversion.with_indexed(&repo, &entry.hash).unwrap();
+                        }
+                        match dry_run {
+                            true => conversion.to_git(data),
+                            false => conversion.to_git_checked(data, &path),
+                        }
+                        .unwrap_or_else(|e| panic!("{}", e))
+                    }
+                };
+                if dry_run || verbose {
+                    println!("add '{}'", path);
+                }
+                if dry_run {
+                    continue;
+                }
+                let hash = Object::new(ObjectTypes::Blob, data)
+                    .and_then(|object| object.write_to_repo(&repo))
+                    .unwrap();
+                let mut entry = IndexEntry::new(path.clone(), file_mode(&metadata), hash, 0);
+                entry.update_stat(&metadata);
+                index.entries.retain(|entry| entry.path != path);
+                index.entries.push(entry);
+            }
+            if !dry_run {
+                index
+                    .entries
+                    .sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+                index.write(&repo).unwrap();
+            }
+            std::process::exit(exit_code);
+        }
+        Commands::Log {
+            mut walk,
+            format,
+            oneline,
+            date,
+            graph,
+            no_mailmap,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            walk.topo_order |= graph && !walk.date_order;
+            walk.rewrite_parents = graph;
+            let mut walk = walk.walk(&repo);
+            let mailmap = (!no_mailmap).then(|| Mailmap::read(&repo));
+            let format = match (oneline, format.as_deref()) {
+                (true, _) => String::from("oneline"),
+                (false, Some(format)) => format.to_owned(),
+                (false, None) => String::from("medium"),
+            };
+            if format != "dot" {
+                let format = PrettyFormat::from_name(&format).unwrap_or_else(|e| panic!("{}", e));
+                let date_mode = date
+                    .map(|date| DateMode::from_name(&date).unwrap_or_else(|e| panic!("{}", e)))
+                    .unwrap_or_default();
+                if graph {
+                    let mut graph = Graph::new();
+                    let mut missing_newline = false;
+                    let mut output = String::new();
+                    let mut shown_one = false;
+                    while let Some(commit) = walk.next() {
+                        let mut commit = commit.unwrap_or_else(|e| panic!("{}", e));
+                        commit.parents = walk.rewritten_parents(&commit);
+                        let mut parents = commit.parents.clone();
+                        parents.retain(|parent| {
+                            walk.is_shown(parent).unwrap_or_else(|e| panic!("{}", e))
+                        });
+                        graph.update(&commit.hash, parents);
+                        // Separators and terminators get the graph drawn on their own line
+                        if shown_one && format.is_separated() {
+                            if !missing_newline {
+                                output.push_str(&graph.show_padding());
+                            }
+                            output.push('\n');
+                        }
+                        output.push_str(&graph.show_commit());
+                        let mut text = format_commit(&commit, &format, date_mode, mailmap.as_ref());
+                        if !format.is_separated() {
+                            text.pop();
+                        }
+                        missing_newline = !text.ends_with('\n');
+                        output.push_str(&graph.show_message(&text));
+                        if !format.is_separated() {
+                            if !missing_newline {
+                                output.push_str(&graph.show_padding());
+                            }
+                            output.push('\n');
+                        }
+                        shown_one = true;
+                        print!("{}", output);
+                        output.clear();
+                    }
+                    return;
+                }
+                for (i, commit) in walk.enumerate() {
+                    let commit = commit.unwrap_or_else(|e| panic!("{}", e));
+                    if i > 0 && format.is_separated() {
+                        println!();
+                    }
+                    print!(
+                        "{}",
+                        format_commit(&commit, &format, date_mode, mailmap.as_ref())
+                    );
+                }
+                return;
+            }
+            let mut commit_graphviz = String::from("digraph rit{\nnode[shape=rect]\n");
+            for commit in walk {
+                let commit = commit.unwrap_or_else(|e| panic!("{}", e));
+                let mut message = commit.message.trim().to_owned();
+                message = message.replace('\\', "\\\\");
+                message = message.replace('\"', "\\\"");
+                let commit_label = format!(
+                    "c_{} [label=\"{}; {}\"]",
+                    commit.hash,
+                    &commit.hash[..7],
+                    message
+                );
+                commit_graphviz.push_str(&commit_label);
+                commit_graphviz.push('\n');
+                for parent in &commit.parents {
+                    let parent_edge = format!("c_{} -> c_{}", commit.hash, parent);
+                    commit_graphviz.push_str(&parent_edge);
+                    commit_graphviz.push('\n');
+                }
+            }
+            commit_graphviz.push('}');
+            println!("{}", commit_graphviz);
+        }
+        Commands::Shortlog {
+            summary,
+            numbered,
+            email,
+            revisions,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let (include, exclude) =
+                resolve_revision_args(&repo, &revisions).unwrap_or_else(|e| panic!("{}", e));
+            let walk = RevWalk::with_options(&repo, &include, &exclude, RevWalkOptions::default())
+                .unwrap_or_else(|e| panic!("{}", e));
+            let mailmap = Mailmap::read(&repo);
+            let mut authors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for commit in walk {
+                let commit = commit.unwrap_or_else(|e| panic!("{}", e));
+                let author = mailmap.map_identity(&commit.author);
+                let author = match email {
+                    true => format!("{} <{}>", author.name, author.email),
+                    false => author.name,
+                };
+                let subject = commit.subject();
+                // Like git, the `[PATCH ...]` prefix of mailed patches is dropped
+                let subject = match subject
+                    .strip_prefix("[PATCH")
+                    .and_then(|s| s.split_once(']'))
+                {
+                    Some((_, rest)) => rest.trim_start().to_owned(),
+                    None if subject.is_empty() => String::from("<none>"),
+                    None => subject,
+                };
+                authors.entry(author).or_default().push(subject);
+            }
+            let mut authors = Vec::from_iter(authors);
+            if numbered {
+                authors.sort_by_key(|(_, subjects)| Reverse(subjects.len()));
+            }
+            for (author, subjects) in authors {
+                if summary {
+                    println!("{:>6}\t{}", subjects.len(), author);
+                    continue;
+                }
+                println!("{} ({}):", author, subjects.len());
+                // Oldest first
+                for subject in subjects.iter().rev() {
+                    println!("      {}", subject);
+                }
+                println!();
+            }
+        }
+        Commands::RevList {
+            mut walk,
+            parents,
+            count,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let mut total = 0;
+            walk.rewrite_parents = parents;
+            let mut walk = walk.walk(&repo);
+            while let Some(commit) = walk.next() {
+                let commit = commit.unwrap_or_else(|e| panic!("{}", e));
+                total += 1;
+                if count {
+                    continue;
+                }
+                match parents {
+                    true => println!(
+                        "{}",
+                        std::iter::once(commit.hash.clone())
+                            .chain(walk.rewritten_parents(&commit))
+                            .join(" ")
+                    ),
+                    false => println!("{}", commit.hash),
+                }
+            }
+            if count {
+                println!("{}", total);
+            }
+        }
+        Commands::Blame {
+            ranges,
+            ignore_whitespace,
+            moves,
+            copies,
+            porcelain,
+            line_porcelain,
+            incremental,
+            file,
+            rev,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let score = |value: &str, default: usize| match value {
+                "" => default,
+                value => value
+                    .parse::<usize>()
+                    .unwrap_or_else(|_| panic!("Invalid score, {}", value)),
+            };
+            let options = BlameOptions {
+                ranges,
+                ignore_whitespace,
+                move_score: moves.map(|value| score(&value, DEFAULT_MOVE_SCORE)),
+                copy_level: copies.len(),
+                copy_score: copies
+                    .iter()
+                    .rev()
+                    .find(|value| !value.is_empty())
+                    .map_or(DEFAULT_COPY_SCORE, |value| score(value, DEFAULT_COPY_SCORE)),
+            };
+            let mut shown = HashSet::new();
+            let blame = blame(&repo, &file, rev.as_deref(), &options, &mut |entry| {
+                if incremental {
+                    println!(
+                        "{} {} {} {}",
+                        entry.commit.hash,
+                        entry.s_lno + 1,
+                        entry.lno + 1,
+                        entry.num_lines
+                    );
+                    print_blame_details(&mut shown, entry, false);
+                    print_blame_filename(entry);
+                }
+            })
+            .unwrap_or_else(|e| panic!("{}", e));
+            if incremental {
+                return;
+            }
+
+            let mut stdout = std::io::stdout().lock();
+            let missing_newline =
+                |entry: &BlameEntry| !blame.lines[entry.lno + entry.num_lines - 1].ends_with(b"\n");
+            if porcelain || line_porcelain {
+                // Commits blamed for more than one path always show which path each entry is for
+                let mut paths: HashMap<&str, HashSet<&str>> = HashMap::new();
+                for entry in &blame.entries {
+                    paths
+                        .entry(&entry.commit.hash)
+                        .or_default()
+                        .insert(&entry.path);
+                }
+                for entry in &blame.entries {
+                    let hash = &entry.commit.hash;
+                    for i in 0..entry.num_lines {
+                        let (s_lno, lno) = (entry.s_lno + 1 + i, entry.lno + 1 + i);
+                        if i == 0 {
+                            println!("{} {} {} {}", hash, s_lno, lno, entry.num_lines);
+                        } else {
+                            println!("{} {} {}", hash, s_lno, lno);
+                        }
+                        if (i == 0 || line_porcelain)
+                            && (print_blame_details(&mut shown, entry, line_porcelain)
+                                || paths[hash.as_str()].len() > 1)
+                        {
+                            print_blame_filename(entry);
+                        }
+                        stdout.write_all(b"\t").unwrap();
+                        stdout.write_all(&blame.lines[entry.lno + i]).unwrap();
+                    }
+                    if missing_newline(entry) {
+                        stdout.write_all(b"\n").unwrap();
+                    }
+                }
+                return;
+            }
+
+            let show_name = blame.entries.iter().any(|entry| entry.path != file);
+            let longest_file = blame.entries.iter().map(|entry| entry.path.len()).max();
+            let longest_author = blame
+                .entries
+                .iter()
+                .map(|entry| entry.commit.author.name.chars().count())
+                .max()
+                .unwrap_or(0);
+            let max_digits = blame
+                .entries
+                .iter()
+                .map(|entry| entry.lno + entry.num_lines)
+                .max()
+                .unwrap_or(0)
+                .to_string()
+                .len();
+            for entry in &blame.entries {
+                let author = &entry.commit.author;
+                for i in 0..entry.num_lines {
+                    let mut line = match entry.boundary {
+                        true => format!("^{}", &entry.commit.hash[..7]),
+                        false => entry.commit.hash[..8].to_owned(),
+                    };
+                    if show_name {
+                        let width = longest_file.unwrap_or(0);
+                        line.push_str(&format!(" {:<width$}", entry.path));
+                    }
+                    line.push_str(&format!(
+                        " ({}{} {} {:>max_digits$}) ",
+                        author.name,
+                        " ".repeat(longest_author - author.name.chars().count()),
+                        format_date(author.time, author.tz_offset, DateMode::Iso),
+                        entry.lno + 1 + i
+                    ));
+                    stdout.write_all(line.as_bytes()).unwrap();
+                    stdout.write_all(&blame.lines[entry.lno + i]).unwrap();
+                }
+                if missing_newline(entry) {
+                    stdout.write_all(b"\n").unwrap();
+                }
+            }
+        }
+        Commands::Describe {
+            tags,
+            long,
+            dirty,
+            abbrev,
+            patterns,
+            commit,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            if long && abbrev == 0 {
+                panic!("--long is incompatible with --abbrev=0");
+            }
+            let revision = commit.as_deref().unwrap_or("HEAD");
+            let commit = rev_parse(&repo, revision)
+                .and_then(|hash| peel_to_commit(&repo, &hash))
+                .unwrap_or_else(|e| panic!("{}", e));
+            let options = DescribeOptions {
+                tags,
+                long,
+                abbrev,
+                patterns,
+                ..Default::default()
+            };
+            let name = describe(&repo, &commit, &options).unwrap_or_else(|e| panic!("{}", e));
+            let dirty = dirty.filter(|_| has_uncommitted_changes(&repo).unwrap());
+            println!("{}{}", name, dirty.unwrap_or_default());
+        }
+        Commands::NameRev {
+            tags,
+            refs,
+            name_only,
+            commits,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let mut revisions = Vec::new();
+            let mut hashes = Vec::new();
+            for revision in commits {
+                match rev_parse(&repo, &revision) {
+                    Ok(hash) => {
+                        revisions.push(revision);
+                        hashes.push(hash);
+                    }
+                    Err(_) => eprintln!("Could not get sha1 for {}. Skipping.", revision),
+                }
+            }
+            let options = NameRevOptions {
+                tags,
+                refs,
+                name_only,
+            };
+            let names = name_revs(&repo, &hashes, &options).unwrap_or_else(|e| panic!("{}", e));
+            for (revision, name) in revisions.iter().zip(names) {
+                let name = name.unwrap_or(String::from("undefined"));
+                match name_only {
+                    true => println!("{}", name),
+                    false => println!("{} {}", revision, name),
+                }
+            }
+        }
+        Commands::Bisect { command } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let outcome = match command {
+                BisectCommands::Start { revisions } => bisect_start(&repo, &revisions),
+                BisectCommands::Bad { revision } => {
+                    bisect_mark(&repo, BisectTerm::Bad, &Vec::from_iter(revision))
+                }
+                BisectCommands::Good { revisions } => {
+                    bisect_mark(&repo, BisectTerm::Good, &revisions)
+                }
+                BisectCommands::Skip { revisions } => {
+                    bisect_mark(&repo, BisectTerm::Skip, &revisions)
+                }
+                BisectCommands::Reset { commit } => {
+                    let previous_branch = head_branch(&repo);
+                    let previous = read_ref(&repo, "HEAD");
+                    if !bisect_reset(&repo, commit.as_deref()).unwrap_or_else(|e| panic!("{}", e)) {
+                        println!("We are not bisecting.");
+                        return;
+                    }
+                    let head = read_ref(&repo, "HEAD").unwrap();
+                    let subject = |commit: &str| CommitInfo::read(&repo, commit).unwrap().subject();
+                    if let Some(previous) =
+                        previous.filter(|previous| previous_branch.is_none() && *previous != head)
+                    {
+                        eprintln!(
+                            "Previous HEAD position was {} {}",
+                            &previous[..7],
+                            subject(&previous)
+                        );
+                    }
+                    match head_branch(&repo) {
+                        Some(branch) if Some(&branch) == previous_branch.as_ref() => {
+                            eprintln!("Already on '{}'", shorten_ref_name(&branch))
+                        }
+                        Some(branch) => {
+                            eprintln!("Switched to branch '{}'", shorten_ref_name(&branch))
+                        }
+                        None => eprintln!("HEAD is now at {} {}", &head[..7], subject(&head)),
+                    }
+                    return;
+                }
+                BisectCommands::Log {} => {
+                    print!("{}", bisect_log(&repo).unwrap_or_else(|e| panic!("{}", e)));
+                    return;
+                }
+                BisectCommands::Replay { file } => {
+                    let log = std::fs::read_to_string(&file).unwrap_or_else(|e| {
+                        panic!("Cannot read file {:?} for replaying: {}", file, e)
+                    });
+                    bisect_replay(&repo, &log)
+                }
+                BisectCommands::Run { command } => {
+                    if !bisect_in_progress(&repo) {
+                        panic!("You need to start by \"rit bisect start\"");
+                    }
+                    let has_good = list_refs(&repo, "refs/bisect/")
+                        .iter()
+                        .any(|(name, _)| name.starts_with("refs/bisect/good-"));
+                    if read_ref(&repo, "refs/bisect/bad").is_none() || !has_good {
+                        panic!("You need to give at least one good and one bad revision");
+                    }
+                    let command = command
+                        .iter()
+                        .map(|arg| format!(" {}", shell_quote(arg)))
+                        .collect::<String>();
+                    loop {
+                        println!("running {}", command);
+                        let status = std::process::Command::new("sh")
+                            .arg("-c")
+                            .arg(&command)
+                            .current_dir(&repo.worktree)
+                            .status()
+                            .unwrap_or_else(|e| panic!("Error running{}: {}", command, e));
+                        let code = status.code().unwrap_or(-1);
+                        let term = BisectTerm::from_exit_code(code).unwrap_or_else(|| {
+                            panic!(
+                                "bisect run failed: exit code {} from '{}' is < 0 or >= 128",
+                                code, command
+                            )
+                        });
+                        let outcome =
+                            bisect_mark(&repo, term, &[]).unwrap_or_else(|e| panic!("{}", e));
+                        print_bisect_outcome(&repo, &outcome);
+                        match outcome {
+                            BisectOutcome::Found(_) => {
+                                println!("bisect found first bad commit");
+                                return;
+                            }
+                            BisectOutcome::OnlySkipped(_) => {
+                                eprintln!("error: bisect run cannot continue any more");
+                                std::process::exit(2);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            .unwrap_or_else(|e| panic!("{}", e));
+            print_bisect_outcome(&repo, &outcome);
+            if let BisectOutcome::OnlySkipped(_) = outcome {
+                std::process::exit(2);
+            }
+        }
+        Commands::LsFiles {
+            cached,
+            deleted,
+            modified,
+            others,
+            stage,
+            unmerged,
+            exclude,
+            exclude_from,
+            exclude_standard,
+            full_name,
+            nul_terminated,
+            pathspecs,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let pathspec =
+                Pathspec::new(&repo, &pathspecs, true).unwrap_or_else(|e| panic!("{}", e));
+            let prefix = match full_name {
+                true => String::new(),
+                false => repo.current_prefix(),
+            };
+            let index = Index::read(&repo).unwrap();
+            let stage = stage || unmerged;
+            let cached = cached || !(stage || deleted || modified || others);
+            let mut stdout = BufWriter::new(std::io::stdout().lock());
+            let mut print_path = |path: &str, entry: Option<&IndexEntry>| {
+                if let Some(entry) = entry.filter(|_| stage) {
+                    write!(
+                        stdout,
+                        "{:06o} {} {}\t",
+                        entry.mode, entry.hash, entry.stage
+                    )
+                    .unwrap();
+                }
+                let path = relative_path(path, &prefix);
+                match nul_terminated {
+                    true => write!(stdout, "{}\0", path).unwrap(),
+                    false => writeln!(stdout, "{}", quote_path(&path)).unwrap(),
+                }
+            };
+            if others {
+                let mut excludes = match exclude_standard {
+                    true => Excludes::standard(&repo),
+                    false => Excludes::new(&repo),
+                };
+                for pattern in &exclude {
+                    excludes.add_pattern(pattern);
+                }
+                for path in &exclude_from {
+                    excludes.add_file(path);
+                }
+                for path in untracked_files(&repo, &index, &mut excludes).unwrap() {
+                    if pathspec.matches(&path) {
+                        print_path(&path, None);
+                    }
+                }
+            }
+            for entry in &index.entries {
+                if !pathspec.matches(&entry.path) {
+                    continue;
+                }
+                if (cached || stage) && (!unmerged || entry.stage > 0) {
+                    print_path(&entry.path, Some(entry));
+                }
+                if deleted && std::fs::symlink_metadata(worktree_path(&repo, &entry.path)).is_err()
+                {
+                    print_path(&entry.path, Some(entry));
+                }
+                if modified && is_modified(&repo, entry) {
+                    print_path(&entry.path, Some(entry));
+                }
+            }
+        }
+        Commands::LsTree {
+            trees_only,
+            recursive,
+            show_trees,
+            long,
+            name_only,
+            object_only,
+            full_name,
+            full_tree,
+            nul_terminated,
+            hash,
+            pathspecs,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let prefix = match full_tree {
+                true => String::new(),
+                false => repo.current_prefix(),
+            };
+            let tree = rev_parse(&repo, &hash)
+                .unwrap_or_else(|_| panic!("Not a valid object name {}", hash));
+            let tree = peel_to_tree(&repo, &tree).unwrap_or_else(|_| panic!("not a tree object"));
+            let mut pathspecs = pathspecs
+                .iter()
+                .map(|spec| {
+                    normalize_path(&prefix, spec).unwrap_or_else(|| {
+                        panic!(
+                            "{}: '{}' is outside repository at '{}'",
+                            spec,
+                            spec,
+                            repo.worktree.display()
+                        )
+                    })
+                })
+                .collect::<Vec<String>>();
+            if pathspecs.is_empty() && !prefix.is_empty() {
+                pathspecs.push(prefix.clone());
+            }
+            let options = LsTreeOptions {
+                recursive,
+                show_trees: show_trees || (trees_only && recursive),
+                trees_only,
+            };
+            let mut stdout = BufWriter::new(std::io::stdout().lock());
+            for entry in ls_tree_entries(&repo, &tree, "", &pathspecs, &options).unwrap() {
+                let path = match full_name || full_tree {
+                    true => entry.path.clone(),
+                    false => relative_path(&entry.path, &prefix),
+                };
+                let path = match nul_terminated {
+                    true => path,
+                    false => quote_path(&path),
+                };
+                if name_only {
+                    write!(stdout, "{}", path).unwrap();
+                } else if object_only {
+                    write!(stdout, "{}", entry.hash).unwrap();
+                } else if long {
+                    let size = match entry._type {
+                        TreeNodeType::Blob => Object::read_data(&repo, &entry.hash)
+                            .unwrap()
+                            .1
+                            .len()
+                            .to_string(),
+                        _ => String::from("-"),
+                    };
+                    write!(
+                        stdout,
+                        "{} {} {} {:>7}\t{}",
+                        entry.mode, entry._type, entry.hash, size, path
+                    )
+                    .unwrap();
+                } else {
+                    write!(
+                        stdout,
+                        "{} {} {}\t{}",
+                        entry.mode, entry._type, entry.hash, path
+                    )
+                    .unwrap();
+                }
+                stdout
+                    .write_all(if nul_terminated { b"\0" } else { b"\n" })
+                    .unwrap();
+            }
+        }
+        Commands::Grep {
+            line_number,
+            ignore_case,
+            word_regexp,
+            files_with_matches,
+            count,
+            basic_regexp: _,
+            extended_regexp,
+            fixed_strings,
+            perl_regexp,
+            cached,
+            full_name,
+            pattern,
+            args,
+            pathspecs,
         } => {
-            let object_identifier = object;
             let repo =
                 Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
-            let object = Object::read_from_sha(&repo, object_identifier).unwrap();
-            println!("{:?}", object.header);
-            println!("{:?}", object.header.serialize());
-            // println!(
-            //     "{:?}",
-            //     String::from_utf8(object.header.serialize()).unwrap()
-            // );
-            // println!();
-            // println!("{}", String::from_utf8(object.header.serialize()).unwrap());
-        }
-        Commands::HashObject { _type, write, path } => {
-            // TODO: Handle not passing in a valid path file?
-            let mut object_file = File::open(path).unwrap();
-            let mut raw_file_contents: Vec<u8> = Vec::new();
-            object_file.read_to_end(&mut raw_file_contents).unwrap();
-
-            let object = Object::new(_type, raw_file_contents).unwrap();
-            let hash = if !write {
-                object.calculate_hash().unwrap()
-            } else {
-                let repo =
-                    Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
-                object.write_to_repo(&repo).unwrap()
+            let syntax = match (extended_regexp, fixed_strings, perl_regexp) {
+                (true, _, _) => GrepSyntax::Extended,
+                (_, true, _) => GrepSyntax::Fixed,
+                (_, _, true) => GrepSyntax::Perl,
+                _ => GrepSyntax::Basic,
             };
-            println!("{}", hash);
-        }
-        Commands::Log { hash } => {
-            // Only takes in full commit hashes for now
-            // TODO:Convert the given hash value (As it can be in short form)
-            // println!("DEBUG, {}", hash);
-            let repo =
-                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
-            let mut commits_queue: VecDeque<String> = VecDeque::from([hash]);
-            let mut commit_graphviz = String::from("digraph rit{\nnode[shape=rect]\n");
-            let mut seen_hashes: HashSet<String> = HashSet::new();
-            while !commits_queue.is_empty() {
-                let current_commit_hash = commits_queue.pop_front().unwrap();
-                if seen_hashes.contains(&current_commit_hash) {
-                    continue;
+            let pattern = GrepPattern::new(&pattern, syntax, ignore_case, word_regexp)
+                .unwrap_or_else(|e| panic!("{}", e));
+            // Leading arguments naming trees are revisions, the others are pathspecs
+            let mut revisions = Vec::new();
+            let mut pathspecs = pathspecs;
+            for (i, arg) in args.iter().enumerate() {
+                match rev_parse(&repo, arg).and_then(|hash| peel_to_tree(&repo, &hash)) {
+                    Ok(tree) => revisions.push((arg.clone(), tree)),
+                    Err(_) if arg.starts_with(':') || Path::new(arg).exists() => {
+                        pathspecs.splice(0..0, args[i..].iter().cloned());
+                        break;
+                    }
+                    Err(_) => panic!(
+                        "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                        arg
+                    ),
                 }
-
-                let commit = Object::read_from_sha(&repo, current_commit_hash.clone()).unwrap();
-                if let ObjectHeaders::Commit {
-                    fields,
-                    message,
-                    order: _,
-                } = commit.header
-                {
-                    let mut message = message.trim().to_owned();
-                    message = message.replace('\\', "\\\\");
-                    message = message.replace('\"', "\\\"");
-                    let commit_label = format!(
-                        "c_{} [label=\"{}; {}\"]",
-                        current_commit_hash,
-                        String::from_utf8(current_commit_hash.as_bytes()[..7].to_ascii_lowercase())
-                            .unwrap(),
-                        message
+            }
+            if cached && !revisions.is_empty() {
+                panic!("both --cached and trees are given");
+            }
+            let pathspec =
+                Pathspec::new(&repo, &pathspecs, true).unwrap_or_else(|e| panic!("{}", e));
+            let prefix = match full_name {
+                true => String::new(),
+                false => repo.current_prefix(),
+            };
+            let mut searches = Vec::new();
+            if revisions.is_empty() {
+                let index = Index::read(&repo).unwrap();
+                let mut files: Vec<GrepFile> = Vec::new();
+                for entry in &index.entries {
+                    if entry.mode & 0o170000 != 0o100000
+                        || !pathspec.matches(&entry.path)
+                        || files.last().is_some_and(|file| file.path == entry.path)
+                    {
+                        continue;
+                    }
+                    files.push(GrepFile {
+                        path: entry.path.clone(),
+                        hash: cached.then(|| entry.hash.clone()),
+                    });
+                }
+                searches.push((String::new(), files));
+            }
+            for (revision, tree) in revisions {
+                let files = grep_tree_files(&repo, &tree, "", &pathspec).unwrap();
+                searches.push((format!("{}:", revision), files));
+            }
+            let mut found = false;
+            let mut stdout = BufWriter::new(std::io::stdout().lock());
+            for (label, files) in searches {
+                for result in grep_files(&repo, &files, &pattern).unwrap() {
+                    found = true;
+                    let name = format!(
+                        "{}{}",
+                        label,
+                        quote_path(&relative_path(&result.path, &prefix))
                     );
-                    commit_graphviz.push_str(&commit_label);
-                    commit_graphviz.push('\n');
-                    seen_hashes.insert(current_commit_hash.clone());
-
-                    if let Some(parents) = fields.get("parent") {
-                        for parent in parents {
-                            let parent_edge = format!("c_{} -> c_{}", current_commit_hash, parent);
-                            commit_graphviz.push_str(&parent_edge);
-                            commit_graphviz.push('\n');
-                            commits_queue.push_back(parent.to_owned());
+                    if files_with_matches {
+                        writeln!(stdout, "{}", name).unwrap();
+                    } else if count {
+                        writeln!(stdout, "{}:{}", name, result.lines.len()).unwrap();
+                    } else if result.binary {
+                        writeln!(stdout, "Binary file {} matches", name).unwrap();
+                    } else {
+                        for (number, line) in result.lines {
+                            write!(stdout, "{}:", name).unwrap();
+                            if line_number {
+                                write!(stdout, "{}:", number).unwrap();
+                            }
+                            stdout.write_all(&line).unwrap();
+                            writeln!(stdout).unwrap();
                         }
                     }
-                } else {
-                    panic!("Object parsed is not a commit, {:?}", commit);
-                };
+                }
+            }
+            stdout.flush().unwrap();
+            if !found {
+                std::process::exit(1);
             }
-            commit_graphviz.push('}');
-            println!("{}", commit_graphviz);
         }
-        Commands::LsTree { hash, recursive } => {
+        Commands::CheckAttr {
+            all,
+            cached,
+            stdin,
+            nul_terminated,
+            mut args,
+            mut paths,
+        } => {
+            // Like git, without `--` there is one attribute, or only attributes with `--stdin`
+            let names = match all {
+                true if !paths.is_empty() && !args.is_empty() => {
+                    panic!("Attributes and --all both specified")
+                }
+                true => {
+                    args.append(&mut paths);
+                    paths = args;
+                    Vec::new()
+                }
+                false if args.is_empty() => panic!("No attribute specified"),
+                false if paths.is_empty() && !stdin => {
+                    paths = args.split_off(1);
+                    args
+                }
+                false => args,
+            };
+            match stdin {
+                true if !paths.is_empty() => panic!("Can't specify files with --stdin"),
+                false if paths.is_empty() => panic!("No file specified"),
+                _ => {}
+            }
+            if let Some(name) = names.iter().find(|name| !is_valid_attr_name(name)) {
+                panic!("{}: not a valid attribute name", name);
+            }
             let repo =
                 Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
-            let tree = if let ObjectHeaders::Tree(tree) =
-                Object::read_from_sha(&repo, hash.clone()).unwrap().header
-            {
-                tree
-            } else {
-                panic!("Expect hash to lead to a tree object, {}", hash)
-            };
-            let tree_nodes = if recursive {
-                fn recurse_tree(
-                    repo: &Repository,
-                    base_path: &str,
-                    cur_tree: TreeObject,
-                ) -> Vec<(TreeNode, String)> {
-                    cur_tree
-                        .entries
+            let mut attributes = match cached {
+                true => Attributes::from_index(&repo, &Index::read(&repo).unwrap()).unwrap(),
+                false => Attributes::new(&repo),
+            };
+            if stdin {
+                let separator = if nul_terminated { b'\0' } else { b'\n' };
+                for line in std::io::stdin().lock().split(separator) {
+                    paths.push(String::from_utf8_lossy(&line.unwrap()).into_owned());
+                }
+            }
+            let prefix = repo.current_prefix();
+            let mut stdout = BufWriter::new(std::io::stdout().lock());
+            for path in paths {
+                let full_path = normalize_path(&prefix, &path).unwrap_or_else(|| {
+                    panic!(
+                        "'{}' is outside repository at '{}'",
+                        path,
+                        repo.worktree.display()
+                    )
+                });
+                let states: Vec<(String, Option<AttrValue>)> = match all {
+                    true => attributes
+                        .all(&full_path)
                         .into_iter()
-                        .map(|tree_node| {
-                            if let TreeNodeType::Tree = &tree_node._type {
-                                let tree = if let ObjectHeaders::Tree(tree) =
-                                    Object::read_from_sha(repo, tree_node.hash.clone())
-                                        .unwrap()
-                                        .header
-                                {
-                                    tree
-                                } else {
-                                    panic!(
-                                        "Excepct hash to lead to a tree object, {}",
-                                        tree_node.hash
-                                    )
-                                };
-                                // NOTE: since we start with "", "/" is added after the path to have old_dir/new_dir pattern
-                                recurse_tree(
-                                    repo,
-                                    &[base_path, &tree_node.path, "/"].concat(),
-                                    tree,
-                                )
-                            } else {
-                                vec![(tree_node, base_path.to_owned())]
-                            }
-                        })
-                        .reduce(|mut acc, mut next| {
-                            acc.append(&mut next);
-                            acc
-                        })
-                        .unwrap()
+                        .map(|(name, value)| (name, Some(value)))
+                        .collect(),
+                    false => names
+                        .iter()
+                        .map(|name| (name.clone(), attributes.get(&full_path, name)))
+                        .collect(),
+                };
+                for (name, value) in states {
+                    let value = match &value {
+                        None => "unspecified",
+                        Some(AttrValue::Set) => "set",
+                        Some(AttrValue::Unset) => "unset",
+                        Some(AttrValue::Value(value)) => value,
+                    };
+                    match nul_terminated {
+                        true => write!(stdout, "{}\0{}\0{}\0", path, name, value),
+                        false => writeln!(stdout, "{}: {}: {}", quote_path(&path), name, value),
+                    }
+                    .unwrap();
                 }
-                recurse_tree(&repo, "", tree)
-            } else {
-                tree.entries
-                    .into_iter()
-                    .map(|tree_node| (tree_node, String::from("")))
-                    .collect::<Vec<(TreeNode, String)>>()
+            }
+            stdout.flush().unwrap();
+        }
+        Commands::Archive {
+            format,
+            prefix,
+            output,
+            worktree_attributes,
+            verbose,
+            tree_ish,
+            pathspecs,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let format = match format.as_deref() {
+                Some(name) => ArchiveFormat::from_name(name).unwrap_or_else(|e| panic!("{}", e)),
+                None => output
+                    .as_deref()
+                    .and_then(ArchiveFormat::from_path)
+                    .unwrap_or_default(),
             };
-            println!(
-                "{}",
-                tree_nodes
-                    .iter()
-                    .map(|(tree_node, base_path)| {
-                        format!(
-                            "{} {} {}\t{}{}",
-                            tree_node.mode,
-                            tree_node._type,
-                            tree_node.hash,
-                            base_path,
-                            tree_node.path
-                        )
-                    })
-                    .join("\n")
-            );
+            let hash = rev_parse(&repo, &tree_ish)
+                .unwrap_or_else(|_| panic!("not a valid object name: {}", tree_ish));
+            let commit = peel_to_commit(&repo, &hash)
+                .ok()
+                .map(|commit| CommitInfo::read(&repo, &commit).unwrap());
+            let mut tree = peel_to_tree(&repo, &hash)
+                .unwrap_or_else(|_| panic!("not a tree object: {}", hash));
+            // Like git, only the current directory is archived from a subdirectory
+            let current_prefix = repo.current_prefix();
+            if !current_prefix.is_empty() {
+                let spec = format!("{}:{}", tree, current_prefix.trim_end_matches('/'));
+                tree = rev_parse(&repo, &spec)
+                    .and_then(|hash| peel_to_tree(&repo, &hash))
+                    .unwrap_or_else(|_| panic!("current working directory is untracked"));
+            }
+            let pathspec =
+                Pathspec::parse("", &pathspecs, false).unwrap_or_else(|e| panic!("{}", e));
+            if !pathspecs.is_empty() {
+                let nodes = TreeObject::read_from_sha(&repo, &tree)
+                    .and_then(|tree| tree.flatten(&repo))
+                    .unwrap();
+                for spec in pathspecs.iter().filter(|spec| !spec.is_empty()) {
+                    let single = Pathspec::parse("", std::slice::from_ref(spec), false).unwrap();
+                    if !nodes.iter().any(|node| single.matches(&node.path)) {
+                        panic!("pathspec '{}' did not match any files", spec);
+                    }
+                }
+            }
+            let options = ArchiveOptions {
+                format,
+                prefix,
+                worktree_attributes,
+                verbose,
+            };
+            let commit = commit.as_ref();
+            match output {
+                Some(path) => {
+                    let file = File::create(&path).unwrap_or_else(|e| {
+                        panic!("could not create archive file '{}': {}", path, e)
+                    });
+                    let output = BufWriter::new(file);
+                    write_archive(&repo, &tree, commit, &pathspec, &options, output)
+                }
+                None => {
+                    let output = BufWriter::new(std::io::stdout().lock());
+                    write_archive(&repo, &tree, commit, &pathspec, &options, output)
+                }
+            }
+            .unwrap_or_else(|e| panic!("{}", e));
         }
         Commands::Checkout {
             hash,
@@ -263,11 +2010,8 @@ fn main() {
             let repo =
                 Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
             let object = Object::read_from_sha(&repo, hash.clone()).unwrap();
-            let tree_hash = if let ObjectHeaders::Commit { fields, .. } = object.header {
-                fields.get("tree").unwrap_or_else(|| {
-                    panic!("Commit has no tree(hash) field, fields: {:?}", fields)
-                })[0]
-                    .to_owned()
+            let tree_hash = if let ObjectHeaders::Commit(commit) = object.header {
+                commit.tree
             } else {
                 panic!("Given hash, {}, is not a commit, {:?}", hash, object);
             };
@@ -309,6 +2053,11 @@ fn main() {
                             } else {
                                 panic!("Expected blob from tree node, {:?}", cur_entry)
                             };
+                            let repo_path = [base_path, &cur_entry.path].concat();
+                            let blob_contents =
+                                Conversion::new(repo, &mut Attributes::new(repo), &repo_path)
+                                    .to_worktree(blob_contents)
+                                    .unwrap_or_else(|e| panic!("{}", e));
                             let blob_path = create_path(
                                 checkout_path,
                                 vec![base_path.to_owned(), cur_entry.path.to_owned()],
@@ -379,59 +2128,822 @@ fn main() {
         }
         Commands::Tag {
             annotate,
+            force,
+            list,
+            delete,
+            verify,
+            lines,
+            sort,
+            message,
+            args,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let mut failed = false;
+            if delete || verify {
+                for name in &args {
+                    let Some(hash) = read_ref(&repo, &format!("refs/tags/{}", name)) else {
+                        eprintln!("error: tag '{}' not found.", name);
+                        failed = true;
+                        continue;
+                    };
+                    if delete {
+                        delete_ref(&repo, &format!("refs/tags/{}", name)).unwrap();
+                        println!("Deleted tag '{}' (was {})", name, &hash[..7]);
+                        continue;
+                    }
+                    let (kind, data) = Object::read_data(&repo, &hash).unwrap();
+                    if kind != ObjectTypes::Tag {
+                        eprintln!(
+                            "error: {}: cannot verify a non-tag object of type {}.",
+                            name,
+                            kind.to_string()
+                        );
+                        failed = true;
+                        continue;
+                    }
+                    // The signed payload is the stored object up to its signature, byte for byte
+                    let offset = Tag::signature_offset(&data).unwrap_or(data.len());
+                    let (payload, signature) = data.split_at(offset);
+                    std::io::stdout().write_all(payload).unwrap();
+                    if signature.is_empty() {
+                        eprintln!("error: no signature found");
+                        failed = true;
+                        continue;
+                    }
+                    match verify_signature(payload, &String::from_utf8_lossy(signature)) {
+                        Ok(report) => eprint!("{}", report),
+                        Err(report) => {
+                            eprint!("{}", report);
+                            failed = true;
+                        }
+                    }
+                }
+            } else if list || lines.is_some() || args.is_empty() {
+                let mut args = args;
+                let lines = lines.map(|lines| {
+                    lines.parse::<usize>().unwrap_or_else(|_| {
+                        args.insert(0, lines);
+                        1
+                    })
+                });
+                let sorts = sort
+                    .iter()
+                    .map(|key| TagSort::parse(key))
+                    .collect::<Result<Vec<TagSort>, String>>()
+                    .unwrap_or_else(|e| panic!("{}", e));
+                for (name, hash) in list_tags(&repo, &args, &sorts).unwrap() {
+                    match lines {
+                        Some(count) => {
+                            let message = tag_message(&repo, &hash).unwrap();
+                            let lines = message.lines().take(count).join("\n    ");
+                            println!("{:<15} {}", name, lines);
+                        }
+                        None => println!("{}", name),
+                    }
+                }
+            } else {
+                if args.len() > 2 {
+                    panic!("too many arguments");
+                }
+                let name = &args[0];
+                let ref_name = format!("refs/tags/{}", name);
+                if !check_ref_format(&ref_name, false) {
+                    panic!("'{}' is not a valid tag name.", name);
+                }
+                let old = read_ref(&repo, &ref_name);
+                if old.is_some() && !force {
+                    panic!("tag '{}' already exists", name);
+                }
+                let target = args.get(1).map(String::as_str).unwrap_or("HEAD");
+                let object = rev_parse(&repo, target)
+                    .unwrap_or_else(|_| panic!("Failed to resolve '{}' as a valid ref.", target));
+                let hash = if annotate || message.is_some() {
+                    let kind = Object::read_from_sha(&repo, object.clone()).unwrap()._type;
+                    if kind == ObjectTypes::Tag {
+                        eprintln!("hint: You have created a nested tag. The object referred to by your new tag is");
+                        eprintln!("hint: already a tag. If you meant to tag the object that it points to, use:");
+                        eprintln!("hint: ");
+                        eprintln!("hint: \trit tag -f {} {}^{{}}", name, target);
+                    }
+                    Object {
+                        header: ObjectHeaders::Tag(Tag::new(
+                            object,
+                            kind,
+                            name.clone(),
+                            Signature::committer(&repo).unwrap(),
+                            strip_space(&message.unwrap_or_default(), true),
+                        )),
+                        _type: ObjectTypes::Tag,
+                    }
+                    .write_to_repo(&repo)
+                    .unwrap()
+                } else {
+                    object
+                };
+                update_ref(&repo, &ref_name, &hash).unwrap();
+                if let Some(old) = old.filter(|old| old != &hash) {
+                    println!("Updated tag '{}' (was {})", name, &old[..7]);
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Mktag {} => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data).unwrap();
+            let (object, kind) = fsck_tag(&data).unwrap_or_else(|e| {
+                eprintln!("error: tag input does not pass fsck: {}", e);
+                panic!("tag on stdin did not pass our strict fsck check")
+            });
+            let tagged = Object::read_from_sha(&repo, object.clone())
+                .unwrap_or_else(|_| panic!("could not read tagged object '{}'", object));
+            if tagged._type != kind {
+                panic!(
+                    "object '{}' tagged as '{}', but is a '{}' type",
+                    object,
+                    kind.to_string(),
+                    tagged._type.to_string()
+                );
+            }
+            println!(
+                "{}",
+                Object::write_data(&repo, &ObjectTypes::Tag, &data).unwrap()
+            );
+        }
+        Commands::CheckRefFormat {
+            allow_onelevel,
+            normalize,
             name,
-            hash,
+        } => {
+            let name = if normalize {
+                let mut normalized = String::new();
+                for c in name.trim_start_matches('/').chars() {
+                    if c != '/' || !normalized.ends_with('/') {
+                        normalized.push(c);
+                    }
+                }
+                normalized
+            } else {
+                name
+            };
+            if !check_ref_format(&name, allow_onelevel) {
+                std::process::exit(1);
+            }
+            if normalize {
+                println!("{}", name);
+            }
+        }
+        Commands::MergeFile {
+            labels,
+            stdout,
+            diff3,
+            zdiff3,
+            ours,
+            theirs,
+            union,
+            marker_size,
+            quiet,
+            current,
+            base,
+            other,
+        } => {
+            let read_file = |path: &PathBuf| -> Vec<u8> {
+                std::fs::read(path)
+                    .unwrap_or_else(|e| panic!("Unable to read file, {:?}: {}", path, e))
+            };
+            let label = |idx: usize, path: &PathBuf| -> String {
+                labels
+                    .get(idx)
+                    .cloned()
+                    .unwrap_or_else(|| path.to_string_lossy().to_string())
+            };
+            let options = MergeFileOptions {
+                style: if zdiff3 {
+                    ConflictStyle::ZDiff3
+                } else if diff3 {
+                    ConflictStyle::Diff3
+                } else {
+                    ConflictStyle::Merge
+                },
+                favor: if ours {
+                    Some(MergeFavor::Ours)
+                } else if theirs {
+                    Some(MergeFavor::Theirs)
+                } else if union {
+                    Some(MergeFavor::Union)
+                } else {
+                    None
+                },
+                ours_label: label(0, &current),
+                base_label: label(1, &base),
+                theirs_label: label(2, &other),
+                marker_size,
+            };
+            let result = merge_file(
+                &read_file(&base),
+                &read_file(&current),
+                &read_file(&other),
+                &options,
+            );
+            if stdout {
+                std::io::stdout().write_all(&result.data).unwrap();
+            } else {
+                std::fs::write(&current, &result.data)
+                    .unwrap_or_else(|e| panic!("Unable to write file, {:?}: {}", current, e));
+            }
+            if !result.is_clean() && !quiet {
+                eprintln!("warning: conflicts in {}", current.display());
+            }
+            // Same as git, the exit status is the number of conflicts
+            std::process::exit(result.conflicts.min(127) as i32);
+        }
+        Commands::Merge {
+            commit,
+            no_ff,
+            ff_only,
+            squash,
             message,
+            abort,
+            _continue,
         } => {
             let repo =
                 Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
-            let tagged_object = Object::read_from_sha(&repo, hash.clone()).unwrap();
-            let name_regex = Regex::new(r"^[a-zA-Z0-9-_]+$").unwrap();
-            if !name_regex.is_match(&name) {
-                panic!("Please provide a valid name, {}", &name);
-            }
-            // TODO: Only hashing commit types
-            let hash = if annotate {
-                let fields = HashMap::from([
-                    (
-                        String::from("object"),
-                        vec![tagged_object.calculate_hash().unwrap()],
+            let state_path = |name: &str| create_path(&repo.gitdir, vec![name.to_owned()]);
+            let clear_merge_state = || {
+                for name in ["MERGE_HEAD", "MERGE_MSG", "MERGE_MODE"] {
+                    let _ = std::fs::remove_file(state_path(name));
+                }
+            };
+            let head = read_ref(&repo, "HEAD").expect("HEAD does not point to a commit yet");
+            let head_tree = peel_to_tree(&repo, &head).unwrap();
+
+            if abort {
+                if !state_path("MERGE_HEAD").exists() {
+                    panic!("There is no merge to abort (MERGE_HEAD missing).");
+                }
+                reset_merge(&repo, &head_tree).unwrap();
+                clear_merge_state();
+                return;
+            }
+            if _continue {
+                let merge_head = std::fs::read_to_string(state_path("MERGE_HEAD"))
+                    .expect("There is no merge in progress (MERGE_HEAD missing).");
+                let index = Index::read(&repo).unwrap();
+                let tree = index
+                    .write_tree(&repo)
+                    .unwrap_or_else(|e| panic!("Unable to conclude the merge, {}", e));
+                let message = std::fs::read_to_string(state_path("MERGE_MSG"))
+                    .unwrap_or_default()
+                    .lines()
+                    .filter(|line| !line.starts_with('#'))
+                    .join("\n");
+                let commit = Object {
+                    header: ObjectHeaders::new_commit(
+                        tree,
+                        vec![head, merge_head.trim().to_owned()],
+                        &Signature::author(&repo).unwrap(),
+                        &Signature::committer(&repo).unwrap(),
+                        message.trim_end().to_owned() + "\n",
                     ),
-                    (String::from("type"), vec![String::from("commit")]),
-                    (String::from("tag"), vec![name.clone()]),
-                    (
-                        String::from("tagger"),
-                        vec![format!(
-                            "{} <{}>",
-                            repo.config.user.name.clone(),
-                            repo.config.user.email.clone()
-                        )
-                        .to_string()],
+                    _type: ObjectTypes::Commit,
+                }
+                .write_to_repo(&repo)
+                .unwrap();
+                update_ref(&repo, "HEAD", &commit).unwrap();
+                clear_merge_state();
+                println!("{}", commit);
+                return;
+            }
+
+            if state_path("MERGE_HEAD").exists() {
+                panic!("You have not concluded your merge (MERGE_HEAD exists).");
+            }
+            let commit_name = commit.unwrap();
+            let theirs = peel_to_commit(&repo, &rev_parse(&repo, &commit_name).unwrap()).unwrap();
+            let theirs_tree = peel_to_tree(&repo, &theirs).unwrap();
+            if is_ancestor(&repo, &theirs, &head).unwrap() {
+                println!("Already up to date.");
+                return;
+            }
+            let write_squash_message = || {
+                std::fs::write(
+                    state_path("SQUASH_MSG"),
+                    format!("Squashed commit of the following:\n\ncommit {}\n", theirs),
+                )
+                .unwrap();
+            };
+
+            if is_ancestor(&repo, &head, &theirs).unwrap() && !no_ff {
+                switch_tree(&repo, &head_tree, &theirs_tree).unwrap_or_else(|e| panic!("{}", e));
+                println!("Updating {}..{}", &head[..7], &theirs[..7]);
+                if squash {
+                    write_squash_message();
+                    println!("Squash commit -- not updating HEAD");
+                } else {
+                    std::fs::write(state_path("ORIG_HEAD"), format!("{}\n", head)).unwrap();
+                    update_ref(&repo, "HEAD", &theirs).unwrap();
+                    println!("Fast-forward");
+                }
+                return;
+            }
+            if ff_only {
+                panic!("Not possible to fast-forward, aborting.");
+            }
+
+            let options = TreeMergeOptions {
+                theirs_label: commit_name.clone(),
+                ..Default::default()
+            };
+            let result = merge_commits(&repo, &head, &theirs, &options).unwrap();
+            result
+                .checkout(&repo, &head_tree)
+                .unwrap_or_else(|e| panic!("{}", e));
+            std::fs::write(state_path("ORIG_HEAD"), format!("{}\n", head)).unwrap();
+            for conflict in &result.conflicts {
+                println!("{}", conflict);
+            }
+            if squash {
+                write_squash_message();
+                if result.is_clean() {
+                    println!("Squash commit -- not updating HEAD");
+                    return;
+                }
+                println!("Automatic merge failed; fix conflicts and then commit the result.");
+                std::process::exit(1);
+            }
+
+            let message = message.unwrap_or_else(|| merge_message(&repo, &commit_name));
+            if !result.is_clean() {
+                let conflicted = Index::read(&repo).unwrap().conflicted_paths();
+                std::fs::write(state_path("MERGE_HEAD"), format!("{}\n", theirs)).unwrap();
+                std::fs::write(
+                    state_path("MERGE_MSG"),
+                    format!(
+                        "{}\n\n# Conflicts:\n{}",
+                        message,
+                        conflicted
+                            .iter()
+                            .map(|path| format!("#\t{}\n", path))
+                            .join("")
                     ),
-                ]);
-                let order = vec![
-                    String::from("object"),
-                    String::from("type"),
-                    String::from("tag"),
-                    String::from("tagger"),
-                ];
-                let message = match message {
-                    None => String::from(""),
-                    Some(m) => m,
+                )
+                .unwrap();
+                std::fs::write(state_path("MERGE_MODE"), if no_ff { "no-ff" } else { "" }).unwrap();
+                println!("Automatic merge failed; fix conflicts and then commit the result.");
+                std::process::exit(1);
+            }
+            let commit = Object {
+                header: ObjectHeaders::new_commit(
+                    result.write_tree(&repo).unwrap(),
+                    vec![head, theirs],
+                    &Signature::author(&repo).unwrap(),
+                    &Signature::committer(&repo).unwrap(),
+                    message + "\n",
+                ),
+                _type: ObjectTypes::Commit,
+            }
+            .write_to_repo(&repo)
+            .unwrap();
+            update_ref(&repo, "HEAD", &commit).unwrap();
+            println!("Merge made by the 'recursive' strategy.");
+        }
+        Commands::CherryPick {
+            commits,
+            record_origin,
+            mainline,
+            _continue,
+            abort,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let options = ReplayOptions {
+                record_origin,
+                mainline,
+            };
+            run_sequencer(
+                &repo,
+                ReplayAction::Pick,
+                commits,
+                options,
+                _continue,
+                abort,
+            );
+        }
+        Commands::Revert {
+            commits,
+            mainline,
+            _continue,
+            abort,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let options = ReplayOptions {
+                record_origin: false,
+                mainline,
+            };
+            run_sequencer(
+                &repo,
+                ReplayAction::Revert,
+                commits,
+                options,
+                _continue,
+                abort,
+            );
+        }
+        Commands::Rebase {
+            upstream,
+            onto,
+            todo_file,
+            _continue,
+            skip,
+            abort,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            if abort {
+                rebase_abort(&repo).unwrap_or_else(|e| panic!("{}", e));
+                return;
+            }
+            let outcome = if _continue {
+                rebase_continue(&repo)
+            } else if skip {
+                rebase_skip(&repo)
+            } else {
+                let upstream = upstream.unwrap();
+                let upstream = peel_to_commit(&repo, &rev_parse(&repo, &upstream).unwrap())
+                    .unwrap_or_else(|e| panic!("{}", e));
+                let onto = match onto {
+                    Some(onto) => peel_to_commit(&repo, &rev_parse(&repo, &onto).unwrap())
+                        .unwrap_or_else(|e| panic!("{}", e)),
+                    None => upstream.clone(),
                 };
-                let tag_object = Object {
-                    header: ObjectHeaders::Tag {
-                        fields,
-                        order,
-                        message,
-                    },
-                    _type: ObjectTypes::Tag,
+                let head = read_ref(&repo, "HEAD").expect("HEAD does not point to a commit yet");
+                let steps = match todo_file {
+                    Some(todo_file) => {
+                        let todo = std::fs::read_to_string(&todo_file).unwrap_or_else(|e| {
+                            panic!("Could not read todo file {:?}: {}", todo_file, e)
+                        });
+                        parse_todo(&repo, &todo).unwrap_or_else(|e| panic!("{}", e))
+                    }
+                    None => {
+                        if onto == upstream && is_ancestor(&repo, &upstream, &head).unwrap() {
+                            println!(
+                                "Current branch {} is up to date.",
+                                head_branch(&repo)
+                                    .map(|branch| shorten_ref_name(&branch).to_owned())
+                                    .unwrap_or(String::from("HEAD"))
+                            );
+                            return;
+                        }
+                        rebase_todo(&repo, &upstream, &head).unwrap()
+                    }
                 };
-                tag_object.write_to_repo(&repo).unwrap()
-            } else {
-                hash
+                rebase_start(&repo, &onto, &steps)
+            }
+            .unwrap_or_else(|e| panic!("{}", e));
+            match outcome {
+                RebaseOutcome::Finished(head_name) => {
+                    eprintln!("Successfully rebased and updated {}.", head_name);
+                }
+                RebaseOutcome::Conflicts { commit, conflicts } => {
+                    for conflict in conflicts {
+                        println!("{}", conflict);
+                    }
+                    let subject = match Object::read_from_sha(&repo, commit.clone()).unwrap().header
+                    {
+                        ObjectHeaders::Commit(commit) => {
+                            commit.message.lines().next().unwrap_or("").to_owned()
+                        }
+                        _ => String::from(""),
+                    };
+                    eprintln!("error: could not apply {}... {}", &commit[..7], subject);
+                    eprintln!("hint: Resolve all conflicts manually, mark them as resolved and run \"rit rebase --continue\".");
+                    eprintln!("hint: You can instead skip this commit: run \"rit rebase --skip\".");
+                    eprintln!("hint: To abort and get back to the state before \"rit rebase\", run \"rit rebase --abort\".");
+                    std::process::exit(1);
+                }
+                RebaseOutcome::ExecFailed { command, .. } => {
+                    eprintln!(
+                        "warning: execution failed: {}\nYou can fix the problem, and then run\n\n  rit rebase --continue\n",
+                        command
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Stash {
+            command,
+            message,
+            include_untracked,
+        } => {
+            let repo =
+                Repository::find_worktree_root(current_dir().unwrap(), git_dir_path).unwrap();
+            let command = command.unwrap_or(StashCommands::Push {
+                message,
+                include_untracked,
+            });
+            let save = |options: StashOptions| match stash_save(&repo, &options)
+                .unwrap_or_else(|e| panic!("{}", e))
+            {
+                Some(_) => println!(
+                    "Saved working directory and index state {}",
+                    stash_list(&repo)[0].1
+                ),
+                None => println!("No local changes to save"),
+            };
+            let stash_entry = |stash: &str| {
+                let position = stash_position(stash).unwrap_or_else(|e| panic!("{}", e));
+                let commit = stash_commit(&repo, position).unwrap_or_else(|e| panic!("{}", e));
+                (position, commit)
+            };
+            let apply = |commit: &str, index: bool| {
+                let result = stash_apply(&repo, commit, index).unwrap_or_else(|e| panic!("{}", e));
+                for conflict in &result.conflicts {
+                    println!("{}", conflict);
+                }
+                result.is_clean()
+            };
+            let drop = |position: usize| {
+                let commit = stash_drop(&repo, position).unwrap_or_else(|e| panic!("{}", e));
+                println!("Dropped refs/stash@{{{}}} ({})", position, commit);
             };
-            create_ref(&repo, name, hash).unwrap();
+            match command {
+                StashCommands::Push {
+                    message,
+                    include_untracked,
+                } => save(StashOptions {
+                    message,
+                    include_untracked,
+                }),
+                StashCommands::Save {
+                    include_untracked,
+                    message,
+                } => save(StashOptions {
+                    message: Some(message.join(" ")).filter(|message| !message.is_empty()),
+                    include_untracked,
+                }),
+                StashCommands::List {} => {
+                    for (position, (_, description)) in stash_list(&repo).iter().enumerate() {
+                        println!("stash@{{{}}}: {}", position, description);
+                    }
+                }
+                StashCommands::Show { patch, stash } => {
+                    let (_, commit) = stash_entry(&stash);
+                    let base = commit_parents(&repo, &commit).unwrap()[0].clone();
+                    let changes = diff_trees(
+                        &repo,
+                        &peel_to_tree(&repo, &base).unwrap(),
+                        &peel_to_tree(&repo, &commit).unwrap(),
+                    )
+                    .unwrap();
+                    if patch {
+                        print!("{}", format_patch(&repo, &changes).unwrap());
+                    } else {
+                        print!("{}", format_stat(&repo, &changes).unwrap());
+                    }
+                }
+                StashCommands::Apply { index, stash } => {
+                    let (_, commit) = stash_entry(&stash);
+                    if !apply(&commit, index) {
+                        std::process::exit(1);
+                    }
+                }
+                StashCommands::Pop { index, stash } => {
+                    let (position, commit) = stash_entry(&stash);
+                    if !apply(&commit, index) {
+                        println!("The stash entry is kept in case you need it again.");
+                        std::process::exit(1);
+                    }
+                    drop(position);
+                }
+                StashCommands::Drop { stash } => drop(stash_entry(&stash).0),
+                StashCommands::Branch { name, stash } => {
+                    let (position, commit) = stash_entry(&stash);
+                    let branch = format!("refs/heads/{}", name);
+                    if read_ref(&repo, &branch).is_some() {
+                        panic!("A branch named '{}' already exists", name);
+                    }
+                    let base = commit_parents(&repo, &commit).unwrap()[0].clone();
+                    let head =
+                        read_ref(&repo, "HEAD").expect("HEAD does not point to a commit yet");
+                    switch_tree(
+                        &repo,
+                        &peel_to_tree(&repo, &head).unwrap(),
+                        &peel_to_tree(&repo, &base).unwrap(),
+                    )
+                    .unwrap_or_else(|e| panic!("{}", e));
+                    update_ref(&repo, &branch, &base).unwrap();
+                    update_symbolic_ref(&repo, "HEAD", &branch).unwrap();
+                    println!("Switched to a new branch '{}'", name);
+                    if !apply(&commit, true) {
+                        std::process::exit(1);
+                    }
+                    drop(position);
+                }
+            }
+        }
+    }
+}
+
+/// Prints the details of the entry's commit for `blame --porcelain`, unless they were already
+/// shown and `repeat` is not set, returning whether they were printed
+fn print_blame_details(shown: &mut HashSet<String>, entry: &BlameEntry, repeat: bool) -> bool {
+    if !shown.insert(entry.commit.hash.clone()) && !repeat {
+        return false;
+    }
+    let commit = &entry.commit;
+    for (role, person) in [("author", &commit.author), ("committer", &commit.committer)] {
+        println!("{} {}", role, person.name);
+        println!("{}-mail <{}>", role, person.email);
+        println!("{}-time {}", role, person.time);
+        println!("{}-tz {}", role, person.timezone());
+    }
+    println!("summary {}", commit.subject());
+    if entry.boundary {
+        println!("boundary");
+    }
+    true
+}
+
+fn print_blame_filename(entry: &BlameEntry) {
+    if let Some((commit, path)) = &entry.previous {
+        println!("previous {} {}", commit, path);
+    }
+    println!("filename {}", entry.path);
+}
+
+/// Prints where bisecting is at, with the details of the first bad commit once found
+fn print_bisect_outcome(repo: &Repository, outcome: &BisectOutcome) {
+    match outcome {
+        BisectOutcome::Waiting(status) => println!("{}", status),
+        BisectOutcome::Testing {
+            commit,
+            remaining,
+            steps,
+        } => {
+            println!(
+                "Bisecting: {} {} left to test after this (roughly {} {})",
+                remaining,
+                if *remaining == 1 {
+                    "revision"
+                } else {
+                    "revisions"
+                },
+                steps,
+                if *steps == 1 { "step" } else { "steps" }
+            );
+            let commit = CommitInfo::read(repo, commit).unwrap();
+            println!("[{}] {}", commit.hash, commit.subject());
+        }
+        BisectOutcome::MergeBase(commit) => {
+            println!("Bisecting: a merge base must be tested");
+            let commit = CommitInfo::read(repo, commit).unwrap();
+            println!("[{}] {}", commit.hash, commit.subject());
+        }
+        BisectOutcome::Found(commit) => {
+            println!("{} is the first bad commit", commit);
+            let commit = CommitInfo::read(repo, commit).unwrap();
+            print!(
+                "{}",
+                format_commit(&commit, &PrettyFormat::Medium, DateMode::default(), None)
+            );
+            if let Some(parent) = commit.parents.first() {
+                let changes =
+                    diff_trees(repo, &peel_to_tree(repo, parent).unwrap(), &commit.tree).unwrap();
+                println!();
+                print!("{}", format_stat(repo, &changes).unwrap());
+                print!("{}", format_summary(&changes));
+            }
+        }
+        BisectOutcome::OnlySkipped(commits) => {
+            println!("There are only 'skip'ped commits left to test.");
+            println!("The first bad commit could be any of:");
+            for commit in commits {
+                println!("{}", commit);
+            }
+            println!("We cannot bisect more!");
+        }
+    }
+}
+
+/// Shared driver of `cherry-pick` and `revert`
+fn run_sequencer(
+    repo: &Repository,
+    action: ReplayAction,
+    commits: Vec<String>,
+    options: ReplayOptions,
+    _continue: bool,
+    abort: bool,
+) {
+    let (command, verb) = match action {
+        ReplayAction::Pick => ("cherry-pick", "apply"),
+        ReplayAction::Revert => ("revert", "revert"),
+    };
+    if abort {
+        sequencer_abort(repo).unwrap_or_else(|e| panic!("{}", e));
+        return;
+    }
+    let report: SequencerReport = if _continue {
+        sequencer_continue(repo)
+    } else {
+        let commits = commits
+            .iter()
+            .map(|commit| peel_to_commit(repo, &rev_parse(repo, commit)?))
+            .collect::<Result<Vec<String>, String>>()
+            .unwrap_or_else(|e| panic!("{}", e));
+        sequencer_start(repo, action, &commits, &options)
+    }
+    .unwrap_or_else(|e| panic!("{}", e));
+    for commit in &report.created {
+        let object = Object::read_from_sha(repo, commit.clone()).unwrap();
+        if let ObjectHeaders::Commit(Commit { message, .. }) = object.header {
+            println!(
+                "[{} {}] {}",
+                head_branch(repo)
+                    .map(|branch| shorten_ref_name(&branch).to_owned())
+                    .unwrap_or(String::from("detached HEAD")),
+                &commit[..7],
+                message.lines().next().unwrap_or("")
+            );
+        }
+    }
+    if let Some((commit, reasons)) = report.stopped {
+        for reason in reasons {
+            println!("{}", reason);
+        }
+        let subject = match Object::read_from_sha(repo, commit.clone()).unwrap().header {
+            ObjectHeaders::Commit(commit) => commit.message.lines().next().unwrap_or("").to_owned(),
+            _ => String::from(""),
+        };
+        eprintln!("error: could not {} {}... {}", verb, &commit[..7], subject);
+        eprintln!("hint: after resolving the conflicts, mark the corrected paths in the index");
+        eprintln!("hint: and run 'rit {} --continue'", command);
+        std::process::exit(1);
+    }
+}
+
+struct LsTreeOptions {
+    recursive: bool,
+    show_trees: bool,
+    trees_only: bool,
+}
+
+/// Entries of the tree `ls-tree` shows, with paths relative to the root tree.
+///
+/// Like git, `pathspecs` are literal: a path matches when it is one of them or inside one, and
+/// trees are recursed into when a pathspec goes deeper than them.
+fn ls_tree_entries(
+    repo: &Repository,
+    tree: &str,
+    base: &str,
+    pathspecs: &[String],
+    options: &LsTreeOptions,
+) -> Result<Vec<TreeNode>, String> {
+    let mut entries = Vec::new();
+    for mut entry in TreeObject::read_from_sha(repo, tree)?.entries {
+        let path = format!("{}{}", base, entry.path);
+        let is_tree = matches!(entry._type, TreeNodeType::Tree);
+        let interesting = pathspecs.is_empty()
+            || pathspecs.iter().any(|spec| {
+                let inside = path.strip_prefix(spec.as_str()).is_some_and(|rest| {
+                    spec.is_empty()
+                        || rest.is_empty()
+                        || spec.ends_with('/')
+                        || rest.starts_with('/')
+                });
+                let leads_to = is_tree
+                    && spec
+                        .strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'));
+                inside || leads_to
+            });
+        if !interesting {
+            continue;
+        }
+        let recurse = is_tree
+            && (options.recursive
+                || pathspecs.iter().any(|spec| {
+                    spec.strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+                }));
+        let shown = match entry._type {
+            TreeNodeType::Blob => !options.trees_only,
+            TreeNodeType::Tree => !recurse || options.show_trees,
+            TreeNodeType::Commit => true,
+        };
+        let hash = entry.hash.clone();
+        entry.path = path.clone();
+        if shown {
+            entries.push(entry);
+        }
+        if recurse {
+            let base = format!("{}/", path);
+            entries.extend(ls_tree_entries(repo, &hash, &base, pathspecs, options)?);
         }
     }
+    Ok(entries)
 }
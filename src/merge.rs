@@ -0,0 +1,31 @@
+mod base;
+mod file;
+mod tree;
+pub use base::{is_ancestor, merge_bases, reachable_commits};
+pub use file::{merge_file, ConflictStyle, MergeFavor, MergeFileOptions, MergeFileResult};
+pub(crate) use tree::detect_renames;
+pub use tree::{merge_commits, merge_trees, TreeMergeOptions, TreeMergeResult};
+
+use crate::{expand_ref_name, head_branch, shorten_ref_name, Repository};
+
+/// Default message of a merge commit, e.g. `Merge branch 'feature' into develop`
+pub fn merge_message(repo: &Repository, name: &str) -> String {
+    let full_name = expand_ref_name(repo, name).unwrap_or_default();
+    let short_name = shorten_ref_name(&full_name);
+    let merged = if full_name.starts_with("refs/heads/") {
+        format!("branch '{}'", short_name)
+    } else if full_name.starts_with("refs/tags/") {
+        format!("tag '{}'", short_name)
+    } else if full_name.starts_with("refs/remotes/") {
+        format!("remote-tracking branch '{}'", short_name)
+    } else {
+        format!("commit '{}'", name)
+    };
+    // Same as git, merges into the main branch do not mention it
+    match head_branch(repo) {
+        Some(branch) if branch != "refs/heads/main" && branch != "refs/heads/master" => {
+            format!("Merge {} into {}", merged, shorten_ref_name(&branch))
+        }
+        _ => format!("Merge {}", merged),
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{commit_parents, Repository};
+
+/// Commits reachable from `start` (itself included), in breadth first order
+fn ancestors(
+    repo: &Repository,
+    start: &[String],
+    parents_cache: &mut HashMap<String, Vec<String>>,
+) -> Result<Vec<String>, String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut order = Vec::new();
+    let mut queue: VecDeque<String> = start.iter().cloned().collect();
+    while let Some(hash) = queue.pop_front() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        if !parents_cache.contains_key(&hash) {
+            let parents = commit_parents(repo, &hash)?;
+            parents_cache.insert(hash.clone(), parents);
+        }
+        queue.extend(parents_cache[&hash].iter().cloned());
+        order.push(hash);
+    }
+    Ok(order)
+}
+
+/// Best common ancestors of two commits, i.e. the common ancestors that are not an ancestor of
+/// another common ancestor. Criss-cross merges can have more than one.
+pub fn merge_bases(repo: &Repository, a: &str, b: &str) -> Result<Vec<String>, String> {
+    best_common_ancestors(repo, &[a.to_owned()], &[b.to_owned()])
+}
+
+/// Best common ancestors of the commits reachable from `a` and those reachable from `b`, the
+/// merge bases of a commit whose parents would be `a` with one whose parents would be `b`
+pub(crate) fn best_common_ancestors(
+    repo: &Repository,
+    a: &[String],
+    b: &[String],
+) -> Result<Vec<String>, String> {
+    let mut parents_cache = HashMap::new();
+    let from_b = ancestors(repo, b, &mut parents_cache)?
+        .into_iter()
+        .collect::<HashSet<String>>();
+    let common = ancestors(repo, a, &mut parents_cache)?
+        .into_iter()
+        .filter(|hash| from_b.contains(hash))
+        .collect::<Vec<String>>();
+    let common_parents = common
+        .iter()
+        .flat_map(|hash| parents_cache[hash].clone())
+        .collect::<Vec<String>>();
+    let redundant = ancestors(repo, &common_parents, &mut parents_cache)?
+        .into_iter()
+        .collect::<HashSet<String>>();
+    Ok(common
+        .into_iter()
+        .filter(|hash| !redundant.contains(hash))
+        .collect())
+}
+
+/// Whether `ancestor` is reachable from `descendant` (a commit is its own ancestor)
+pub fn is_ancestor(repo: &Repository, ancestor: &str, descendant: &str) -> Result<bool, String> {
+    Ok(
+        ancestors(repo, &[descendant.to_owned()], &mut HashMap::new())?
+            .iter()
+            .any(|hash| hash == ancestor),
+    )
+}
+
+/// Every commit reachable from `starts`, themselves included
+pub fn reachable_commits(repo: &Repository, starts: &[String]) -> Result<HashSet<String>, String> {
+    Ok(ancestors(repo, starts, &mut HashMap::new())?
+        .into_iter()
+        .collect())
+}
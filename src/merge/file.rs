@@ -0,0 +1,395 @@
+use crate::diff::{diff, split_lines};
+
+/// How conflicting regions are presented in the merged output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Only our and their side are shown
+    #[default]
+    Merge,
+    /// The base section is shown between our and their side
+    Diff3,
+    /// Same as [ConflictStyle::Diff3], but lines common to both sides are moved out of the conflict
+    ZDiff3,
+}
+
+/// Resolves conflicts by picking a side instead of emitting conflict markers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeFavor {
+    Ours,
+    Theirs,
+    /// Both sides are kept, ours first
+    Union,
+}
+
+#[derive(Clone, Debug)]
+pub struct MergeFileOptions {
+    pub style: ConflictStyle,
+    pub favor: Option<MergeFavor>,
+    pub ours_label: String,
+    pub base_label: String,
+    pub theirs_label: String,
+    pub marker_size: usize,
+}
+impl Default for MergeFileOptions {
+    fn default() -> Self {
+        Self {
+            style: ConflictStyle::default(),
+            favor: None,
+            ours_label: String::from(""),
+            base_label: String::from(""),
+            theirs_label: String::from(""),
+            marker_size: 7,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MergeFileResult {
+    pub data: Vec<u8>,
+    /// Number of conflicts left in [MergeFileResult::data], always 0 when a favor is used
+    pub conflicts: usize,
+}
+impl MergeFileResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+enum Region<'a> {
+    /// Lines left untouched by both sides
+    Unchanged(Vec<&'a [u8]>),
+    /// Lines changed by one side, or changed identically by both
+    Resolved(Vec<&'a [u8]>),
+    Conflict {
+        ours: Vec<&'a [u8]>,
+        base: Vec<&'a [u8]>,
+        theirs: Vec<&'a [u8]>,
+    },
+}
+
+/// Line based three-way merge of `ours` and `theirs`, using `base` as their common ancestor
+pub fn merge_file(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    options: &MergeFileOptions,
+) -> MergeFileResult {
+    let base_lines = split_lines(base);
+    let ours_lines = split_lines(ours);
+    let theirs_lines = split_lines(theirs);
+    let mut regions = merge_regions(&base_lines, &ours_lines, &theirs_lines);
+    regions = match options.style {
+        ConflictStyle::Merge => refine_conflicts(regions),
+        ConflictStyle::ZDiff3 => trim_conflicts(regions),
+        ConflictStyle::Diff3 => regions,
+    };
+    if options.style != ConflictStyle::Diff3 {
+        regions = simplify_conflicts(regions);
+    }
+    render(regions, options)
+}
+
+fn merge_regions<'a>(base: &[&'a [u8]], ours: &[&'a [u8]], theirs: &[&'a [u8]]) -> Vec<Region<'a>> {
+    let ours_hunks = diff(base, ours);
+    let theirs_hunks = diff(base, theirs);
+    let mut regions = Vec::new();
+    let mut base_pos = 0;
+    let mut i = 0;
+    let mut j = 0;
+    while i < ours_hunks.len() || j < theirs_hunks.len() {
+        // Changes that touch or overlap each other in the base are treated as conflicting
+        let ours_only = j >= theirs_hunks.len()
+            || (i < ours_hunks.len() && ours_hunks[i].a_end() < theirs_hunks[j].a_start);
+        let theirs_only = i >= ours_hunks.len()
+            || (j < theirs_hunks.len() && theirs_hunks[j].a_end() < ours_hunks[i].a_start);
+        if ours_only || theirs_only {
+            let (hunk, lines) = if ours_only {
+                i += 1;
+                (&ours_hunks[i - 1], ours)
+            } else {
+                j += 1;
+                (&theirs_hunks[j - 1], theirs)
+            };
+            regions.push(Region::Unchanged(base[base_pos..hunk.a_start].to_vec()));
+            regions.push(Region::Resolved(lines[hunk.b_start..hunk.b_end()].to_vec()));
+            base_pos = hunk.a_end();
+            continue;
+        }
+
+        let lo = ours_hunks[i].a_start.min(theirs_hunks[j].a_start);
+        let mut hi = ours_hunks[i].a_end().max(theirs_hunks[j].a_end());
+        let (first_ours, first_theirs) = (i, j);
+        i += 1;
+        j += 1;
+        loop {
+            if i < ours_hunks.len() && ours_hunks[i].a_start <= hi {
+                hi = hi.max(ours_hunks[i].a_end());
+                i += 1;
+            } else if j < theirs_hunks.len() && theirs_hunks[j].a_start <= hi {
+                hi = hi.max(theirs_hunks[j].a_end());
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        // Lines outside of the hunks map 1 to 1 onto the base, so the side's range can be
+        // derived from the first and last hunk in the group
+        let side_range = |hunks: &[crate::diff::DiffHunk]| {
+            let first = &hunks[0];
+            let last = &hunks[hunks.len() - 1];
+            (
+                first.b_start - (first.a_start - lo),
+                last.b_end() + (hi - last.a_end()),
+            )
+        };
+        let (ours_start, ours_end) = side_range(&ours_hunks[first_ours..i]);
+        let (theirs_start, theirs_end) = side_range(&theirs_hunks[first_theirs..j]);
+
+        regions.push(Region::Unchanged(base[base_pos..lo].to_vec()));
+        let ours_side = ours[ours_start..ours_end].to_vec();
+        let theirs_side = theirs[theirs_start..theirs_end].to_vec();
+        if ours_side == theirs_side {
+            regions.push(Region::Resolved(ours_side));
+        } else {
+            regions.push(Region::Conflict {
+                ours: ours_side,
+                base: base[lo..hi].to_vec(),
+                theirs: theirs_side,
+            });
+        }
+        base_pos = hi;
+    }
+    regions.push(Region::Unchanged(base[base_pos..].to_vec()));
+    regions
+}
+
+/// Splits each conflict on the lines both sides agree on, leaving only the lines that truly differ
+fn refine_conflicts(regions: Vec<Region>) -> Vec<Region> {
+    let mut refined = Vec::new();
+    for region in regions {
+        let (ours, base, theirs) = match region {
+            Region::Conflict { ours, base, theirs } => (ours, base, theirs),
+            region => {
+                refined.push(region);
+                continue;
+            }
+        };
+        let hunks = diff(&ours, &theirs);
+        if hunks.is_empty() {
+            refined.push(Region::Resolved(ours));
+            continue;
+        }
+        let mut ours_pos = 0;
+        for (idx, hunk) in hunks.iter().enumerate() {
+            refined.push(Region::Unchanged(ours[ours_pos..hunk.a_start].to_vec()));
+            refined.push(Region::Conflict {
+                ours: ours[hunk.a_start..hunk.a_end()].to_vec(),
+                // The base only makes sense for the whole conflict, keep it on the first piece
+                base: if idx == 0 { base.clone() } else { Vec::new() },
+                theirs: theirs[hunk.b_start..hunk.b_end()].to_vec(),
+            });
+            ours_pos = hunk.a_end();
+        }
+        refined.push(Region::Unchanged(ours[ours_pos..].to_vec()));
+    }
+    refined
+}
+
+/// Moves the leading and trailing lines common to both sides out of each conflict
+fn trim_conflicts(regions: Vec<Region>) -> Vec<Region> {
+    let mut trimmed = Vec::new();
+    for region in regions {
+        let (ours, base, theirs) = match region {
+            Region::Conflict { ours, base, theirs } => (ours, base, theirs),
+            region => {
+                trimmed.push(region);
+                continue;
+            }
+        };
+        let prefix = ours
+            .iter()
+            .zip(theirs.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = ours[prefix..]
+            .iter()
+            .rev()
+            .zip(theirs[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        trimmed.push(Region::Resolved(ours[..prefix].to_vec()));
+        trimmed.push(Region::Conflict {
+            ours: ours[prefix..ours.len() - suffix].to_vec(),
+            base,
+            theirs: theirs[prefix..theirs.len() - suffix].to_vec(),
+        });
+        trimmed.push(Region::Resolved(ours[ours.len() - suffix..].to_vec()));
+    }
+    trimmed
+}
+
+/// Joins conflicts separated by at most 3 untouched lines, or by lines without any alphanumeric
+/// characters, as several tiny conflicts are harder to resolve than a single one
+fn simplify_conflicts(regions: Vec<Region>) -> Vec<Region> {
+    let mut simplified: Vec<Region> = Vec::new();
+    let mut regions = regions
+        .into_iter()
+        .filter(|region| match region {
+            Region::Unchanged(lines) | Region::Resolved(lines) => !lines.is_empty(),
+            Region::Conflict { .. } => true,
+        })
+        .peekable();
+    while let Some(region) = regions.next() {
+        if let Region::Unchanged(gap) = &region {
+            let mergeable = gap.len() <= 3
+                || !gap
+                    .iter()
+                    .any(|line| line.iter().any(|b| b.is_ascii_alphanumeric()));
+            if mergeable
+                && matches!(simplified.last(), Some(Region::Conflict { .. }))
+                && matches!(regions.peek(), Some(Region::Conflict { .. }))
+            {
+                let Some(Region::Conflict {
+                    ours: next_ours,
+                    base: next_base,
+                    theirs: next_theirs,
+                }) = regions.next()
+                else {
+                    unreachable!()
+                };
+                if let Some(Region::Conflict { ours, base, theirs }) = simplified.last_mut() {
+                    ours.extend(gap.iter().chain(next_ours.iter()));
+                    base.extend(gap.iter().chain(next_base.iter()));
+                    theirs.extend(gap.iter().chain(next_theirs.iter()));
+                }
+                continue;
+            }
+        }
+        simplified.push(region);
+    }
+    simplified
+}
+
+fn render(regions: Vec<Region>, options: &MergeFileOptions) -> MergeFileResult {
+    fn push_lines(data: &mut Vec<u8>, lines: &[&[u8]]) {
+        for line in lines {
+            data.extend_from_slice(line);
+        }
+    }
+    fn push_marker(data: &mut Vec<u8>, marker: u8, size: usize, label: &str) {
+        // Markers always start on their own line
+        if data.last().is_some_and(|&b| b != b'\n') {
+            data.push(b'\n');
+        }
+        data.extend(std::iter::repeat_n(marker, size));
+        if !label.is_empty() {
+            data.push(b' ');
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(b'\n');
+    }
+
+    let mut data = Vec::new();
+    let mut conflicts = 0;
+    for region in regions {
+        match region {
+            Region::Unchanged(lines) | Region::Resolved(lines) => push_lines(&mut data, &lines),
+            Region::Conflict { ours, base, theirs } => match options.favor {
+                Some(MergeFavor::Ours) => push_lines(&mut data, &ours),
+                Some(MergeFavor::Theirs) => push_lines(&mut data, &theirs),
+                Some(MergeFavor::Union) => {
+                    push_lines(&mut data, &ours);
+                    if data.last().is_some_and(|&b| b != b'\n') && !theirs.is_empty() {
+                        data.push(b'\n');
+                    }
+                    push_lines(&mut data, &theirs);
+                }
+                None => {
+                    conflicts += 1;
+                    let size = options.marker_size;
+                    push_marker(&mut data, b'<', size, &options.ours_label);
+                    push_lines(&mut data, &ours);
+                    if options.style != ConflictStyle::Merge {
+                        push_marker(&mut data, b'|', size, &options.base_label);
+                        push_lines(&mut data, &base);
+                    }
+                    push_marker(&mut data, b'=', size, "");
+                    push_lines(&mut data, &theirs);
+                    push_marker(&mut data, b'>', size, &options.theirs_label);
+                }
+            },
+        }
+    }
+    MergeFileResult { data, conflicts }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(style: ConflictStyle) -> MergeFileOptions {
+        MergeFileOptions {
+            style,
+            ours_label: String::from("ours"),
+            base_label: String::from("base"),
+            theirs_label: String::from("theirs"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clean_merge() {
+        let base = b"a\nb\nc\nd\ne\n";
+        let ours = b"A\nb\nc\nd\ne\n";
+        let theirs = b"a\nb\nc\nd\nE\n";
+        let result = merge_file(base, ours, theirs, &MergeFileOptions::default());
+        assert!(result.is_clean());
+        assert_eq!(result.data, b"A\nb\nc\nd\nE\n");
+    }
+
+    #[test]
+    fn test_conflict_styles() {
+        let base = b"a\nb\nc\n";
+        let ours = b"a\nx\nc\n";
+        let theirs = b"a\ny\nc\n";
+        let result = merge_file(base, ours, theirs, &options(ConflictStyle::Merge));
+        assert_eq!(result.conflicts, 1);
+        assert_eq!(
+            String::from_utf8(result.data).unwrap(),
+            "a\n<<<<<<< ours\nx\n=======\ny\n>>>>>>> theirs\nc\n"
+        );
+        let result = merge_file(base, ours, theirs, &options(ConflictStyle::Diff3));
+        assert_eq!(
+            String::from_utf8(result.data).unwrap(),
+            "a\n<<<<<<< ours\nx\n||||||| base\nb\n=======\ny\n>>>>>>> theirs\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_zdiff3_trims_common_lines() {
+        let base = b"a\nb\n";
+        let ours = b"a\nx\ny\nz\n";
+        let theirs = b"a\nx\nq\nz\n";
+        let result = merge_file(base, ours, theirs, &options(ConflictStyle::ZDiff3));
+        assert_eq!(
+            String::from_utf8(result.data).unwrap(),
+            "a\nx\n<<<<<<< ours\ny\n||||||| base\nb\n=======\nq\n>>>>>>> theirs\nz\n"
+        );
+    }
+
+    #[test]
+    fn test_favor() {
+        let base = b"a\nb\nc\n";
+        let ours = b"a\nx\nc\n";
+        let theirs = b"a\ny\nc\n";
+        let mut opts = MergeFileOptions {
+            favor: Some(MergeFavor::Union),
+            ..Default::default()
+        };
+        let result = merge_file(base, ours, theirs, &opts);
+        assert!(result.is_clean());
+        assert_eq!(result.data, b"a\nx\ny\nc\n");
+        opts.favor = Some(MergeFavor::Theirs);
+        assert_eq!(merge_file(base, ours, theirs, &opts).data, b"a\ny\nc\n");
+    }
+}
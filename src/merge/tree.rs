@@ -0,0 +1,832 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    diff, merge_bases, merge_file, peel_to_tree, split_lines, worktree, ConflictStyle, Index,
+    IndexEntry, MergeFavor, MergeFileOptions, Object, ObjectHeaders, ObjectTypes, Repository,
+    TreeNode, TreeObject,
+};
+
+use super::base::best_common_ancestors;
+
+/// Minimum similarity for a deleted and an added file to be paired as a rename, same as git's default
+const RENAME_THRESHOLD: f64 = 0.5;
+/// Above this many candidate pairs, only exact renames are detected
+const RENAME_LIMIT: usize = 1000 * 1000;
+
+#[derive(Clone, Debug)]
+pub struct TreeMergeOptions {
+    pub ours_label: String,
+    pub theirs_label: String,
+    pub base_label: String,
+    pub style: ConflictStyle,
+    pub favor: Option<MergeFavor>,
+    pub detect_renames: bool,
+}
+impl Default for TreeMergeOptions {
+    fn default() -> Self {
+        Self {
+            ours_label: String::from("HEAD"),
+            theirs_label: String::from("theirs"),
+            base_label: String::from("merged common ancestors"),
+            style: ConflictStyle::default(),
+            favor: None,
+            detect_renames: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TreeMergeResult {
+    /// Index of the merge, conflicted paths are recorded in stages 1 to 3
+    pub entries: Vec<IndexEntry>,
+    /// Files the worktree should hold, conflicted files contain the conflict markers
+    pub worktree: Vec<TreeNode>,
+    /// One message per conflict, worded like git's
+    pub conflicts: Vec<String>,
+}
+impl TreeMergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+    pub fn index(&self) -> Index {
+        Index {
+            version: 2,
+            entries: self.entries.clone(),
+        }
+    }
+    /// Writes the tree of the worktree files, conflicted files are written with their markers
+    pub fn write_tree(&self, repo: &Repository) -> Result<String, String> {
+        TreeObject::write_from_nodes(repo, &self.worktree)
+    }
+    /// Writes the merge result into the index and the worktree, which are expected to be at `head_tree`.
+    ///
+    /// Fails without touching anything if the index has changes or if worktree changes would be
+    /// overwritten.
+    pub fn checkout(&self, repo: &Repository, head_tree: &str) -> Result<(), String> {
+        let head_nodes = TreeObject::read_from_sha(repo, head_tree)?.flatten(repo)?;
+        let index = Index::read(repo)?;
+        let head_entries = head_nodes
+            .iter()
+            .map(|node| (node.path.as_str(), (node.mode.as_str(), node.hash.as_str())))
+            .collect::<BTreeMap<&str, (&str, &str)>>();
+        let index_nodes = index.tree_nodes()?;
+        let index_entries = index_nodes
+            .iter()
+            .map(|node| (node.path.as_str(), (node.mode.as_str(), node.hash.as_str())))
+            .collect::<BTreeMap<&str, (&str, &str)>>();
+        if index.has_conflicts() || index_entries != head_entries {
+            return Err(String::from(
+                "Your index contains uncommitted changes, please commit or stash them",
+            ));
+        }
+        let overwritten = worktree::overwritten_paths(repo, &head_nodes, &self.worktree);
+        if !overwritten.is_empty() {
+            return Err(format!(
+                "Your local changes to the following files would be overwritten by merge:\n\t{}",
+                overwritten.join("\n\t")
+            ));
+        }
+        worktree::update_worktree(repo, &head_nodes, &self.worktree)?;
+        let mut index = self.index();
+        worktree::refresh_index(repo, &mut index);
+        index.write(repo)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Ours,
+    Theirs,
+    Both,
+}
+
+struct MergeState<'a> {
+    repo: &'a Repository,
+    options: &'a TreeMergeOptions,
+    entries: Vec<IndexEntry>,
+    worktree: BTreeMap<String, (TreeNode, Side)>,
+    conflicts: Vec<String>,
+}
+
+fn with_path(node: &TreeNode, path: &str) -> TreeNode {
+    let mut node = node.clone();
+    node.path = path.to_owned();
+    node
+}
+
+fn same_version(a: Option<&TreeNode>, b: Option<&TreeNode>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => a.mode == b.mode && a.hash == b.hash,
+        _ => false,
+    }
+}
+
+fn is_regular_file(node: &TreeNode) -> bool {
+    node.mode.starts_with("100")
+}
+
+fn blob_data(repo: &Repository, hash: &str) -> Result<Vec<u8>, String> {
+    match Object::read_from_sha(repo, hash.to_owned())?.header {
+        ObjectHeaders::Blob { data } => Ok(data),
+        _ => Err(format!("Expected hash to lead to a blob, {}", hash)),
+    }
+}
+
+fn write_blob(repo: &Repository, data: Vec<u8>) -> Result<String, String> {
+    Object {
+        header: ObjectHeaders::Blob { data },
+        _type: ObjectTypes::Blob,
+    }
+    .write_to_repo(repo)
+}
+
+/// Share of `b`'s content that also appears in `a`, relative to the bigger of the two
+fn similarity(a: &[u8], b: &[u8]) -> f64 {
+    let max_len = a.len().max(b.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+    let a_lines = split_lines(a);
+    let b_lines = split_lines(b);
+    let changed: usize = diff(&a_lines, &b_lines)
+        .iter()
+        .flat_map(|hunk| &b_lines[hunk.b_start..hunk.b_end()])
+        .map(|line| line.len())
+        .sum();
+    (b.len() - changed) as f64 / max_len as f64
+}
+
+/// Pairs files deleted from `base` with files added in `side`, returning base path to side path
+pub(crate) fn detect_renames(
+    repo: &Repository,
+    base: &BTreeMap<String, TreeNode>,
+    side: &BTreeMap<String, TreeNode>,
+) -> Result<BTreeMap<String, String>, String> {
+    let deleted = base
+        .values()
+        .filter(|node| is_regular_file(node) && !side.contains_key(&node.path))
+        .collect::<Vec<&TreeNode>>();
+    let mut added = side
+        .values()
+        .filter(|node| is_regular_file(node) && !base.contains_key(&node.path))
+        .collect::<Vec<&TreeNode>>();
+    let mut renames = BTreeMap::new();
+
+    // Exact renames first, they are cheap and always the best pairing
+    let mut inexact = Vec::new();
+    for source in deleted {
+        match added.iter().position(|node| node.hash == source.hash) {
+            Some(idx) => {
+                renames.insert(source.path.clone(), added.remove(idx).path.clone());
+            }
+            None => inexact.push(source),
+        }
+    }
+    if inexact.is_empty() || added.is_empty() || inexact.len() * added.len() > RENAME_LIMIT {
+        return Ok(renames);
+    }
+
+    let added_data = added
+        .iter()
+        .map(|node| blob_data(repo, &node.hash))
+        .collect::<Result<Vec<Vec<u8>>, String>>()?;
+    let mut candidates = Vec::new();
+    for source in inexact {
+        let source_data = blob_data(repo, &source.hash)?;
+        for (idx, data) in added_data.iter().enumerate() {
+            let score = similarity(&source_data, data);
+            if score >= RENAME_THRESHOLD {
+                candidates.push((score, source.path.clone(), idx));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut used = HashSet::new();
+    for (_, source, idx) in candidates {
+        if renames.contains_key(&source) || used.contains(&idx) {
+            continue;
+        }
+        used.insert(idx);
+        renames.insert(source, added[idx].path.clone());
+    }
+    Ok(renames)
+}
+
+impl MergeState<'_> {
+    fn add_clean(&mut self, node: &TreeNode, side: Side) -> Result<(), String> {
+        self.entries.push(IndexEntry::from_tree_node(node, 0)?);
+        self.worktree
+            .insert(node.path.clone(), (node.clone(), side));
+        Ok(())
+    }
+    fn add_stage(&mut self, node: Option<&TreeNode>, path: &str, stage: u8) -> Result<(), String> {
+        if let Some(node) = node {
+            self.entries
+                .push(IndexEntry::from_tree_node(&with_path(node, path), stage)?);
+        }
+        Ok(())
+    }
+    fn add_conflict(
+        &mut self,
+        path: &str,
+        versions: [Option<&TreeNode>; 3],
+        worktree_node: &TreeNode,
+        side: Side,
+    ) -> Result<(), String> {
+        for (stage, node) in versions.into_iter().enumerate() {
+            self.add_stage(node, path, stage as u8 + 1)?;
+        }
+        self.worktree
+            .insert(path.to_owned(), (with_path(worktree_node, path), side));
+        Ok(())
+    }
+
+    /// Resolves one path given its base, our and their version, all already moved to `path`
+    fn resolve_path(
+        &mut self,
+        path: &str,
+        base: Option<&TreeNode>,
+        ours: Option<&TreeNode>,
+        theirs: Option<&TreeNode>,
+        labels: (String, String),
+    ) -> Result<(), String> {
+        let (ours_label, theirs_label) = (&self.options.ours_label, &self.options.theirs_label);
+        if same_version(ours, theirs) {
+            return match ours {
+                Some(node) => self.add_clean(node, Side::Both),
+                None => Ok(()),
+            };
+        }
+        if same_version(base, ours) {
+            return match theirs {
+                Some(node) => self.add_clean(node, Side::Theirs),
+                None => Ok(()),
+            };
+        }
+        if same_version(base, theirs) {
+            return match ours {
+                Some(node) => self.add_clean(node, Side::Ours),
+                None => Ok(()),
+            };
+        }
+        match (ours, theirs) {
+            (Some(ours_node), Some(theirs_node))
+                if is_regular_file(ours_node) && is_regular_file(theirs_node) =>
+            {
+                let (mode, mode_conflict) = if ours_node.mode == theirs_node.mode {
+                    (ours_node.mode.clone(), false)
+                } else if base.is_some_and(|base| base.mode == ours_node.mode) {
+                    (theirs_node.mode.clone(), false)
+                } else if base.is_some_and(|base| base.mode == theirs_node.mode) {
+                    (ours_node.mode.clone(), false)
+                } else {
+                    (ours_node.mode.clone(), true)
+                };
+                let (hash, content_clean) = if ours_node.hash == theirs_node.hash {
+                    (ours_node.hash.clone(), true)
+                } else {
+                    let base_data = match base {
+                        Some(base) if is_regular_file(base) => blob_data(self.repo, &base.hash)?,
+                        _ => Vec::new(),
+                    };
+                    let result = merge_file(
+                        &base_data,
+                        &blob_data(self.repo, &ours_node.hash)?,
+                        &blob_data(self.repo, &theirs_node.hash)?,
+                        &MergeFileOptions {
+                            style: self.options.style,
+                            favor: self.options.favor,
+                            ours_label: labels.0,
+                            base_label: self.options.base_label.clone(),
+                            theirs_label: labels.1,
+                            ..Default::default()
+                        },
+                    );
+                    let clean = result.is_clean();
+                    (write_blob(self.repo, result.data)?, clean)
+                };
+                let merged = TreeNode::new(mode, path.to_owned(), hash)?;
+                if content_clean && !mode_conflict {
+                    return self.add_clean(&merged, Side::Both);
+                }
+                self.conflicts.push(if mode_conflict && content_clean {
+                    format!("CONFLICT (mode): Conflicting modes for {}", path)
+                } else if base.is_none() {
+                    format!("CONFLICT (add/add): Merge conflict in {}", path)
+                } else {
+                    format!("CONFLICT (content): Merge conflict in {}", path)
+                });
+                self.add_conflict(path, [base, ours, theirs], &merged, Side::Both)
+            }
+            (Some(ours_node), Some(theirs_node)) => {
+                // Symbolic links, submodules and type changes cannot be merged line by line
+                self.conflicts.push(format!(
+                    "CONFLICT ({}): Merge conflict in {}",
+                    if ours_node.mode != theirs_node.mode {
+                        "type change"
+                    } else {
+                        "content"
+                    },
+                    path
+                ));
+                self.add_conflict(path, [base, ours, theirs], ours_node, Side::Ours)
+            }
+            (Some(modified), None) | (None, Some(modified)) => {
+                let (deleted_in, modified_in, side) = if ours.is_some() {
+                    (theirs_label, ours_label, Side::Ours)
+                } else {
+                    (ours_label, theirs_label, Side::Theirs)
+                };
+                self.conflicts.push(format!(
+                    "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  Version {} of {} left in tree.",
+                    path, deleted_in, modified_in, modified_in, path
+                ));
+                self.add_conflict(path, [base, ours, theirs], modified, side)
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn resolve_item(
+        &mut self,
+        base: Option<&TreeNode>,
+        ours: Option<&TreeNode>,
+        theirs: Option<&TreeNode>,
+    ) -> Result<(), String> {
+        let (ours_label, theirs_label) = (
+            self.options.ours_label.clone(),
+            self.options.theirs_label.clone(),
+        );
+        let renamed = |side: Option<&TreeNode>| matches!((base, side), (Some(base), Some(side)) if base.path != side.path);
+        let (ours_renamed, theirs_renamed) = (renamed(ours), renamed(theirs));
+
+        if let (Some(base_node), Some(ours_node), Some(theirs_node)) = (base, ours, theirs) {
+            if ours_renamed && theirs_renamed && ours_node.path != theirs_node.path {
+                self.conflicts.push(format!(
+                    "CONFLICT (rename/rename): {} renamed to {} in {} and to {} in {}.",
+                    base_node.path, ours_node.path, ours_label, theirs_node.path, theirs_label
+                ));
+                self.add_conflict(
+                    &ours_node.path,
+                    [Some(base_node), Some(ours_node), None],
+                    ours_node,
+                    Side::Ours,
+                )?;
+                return self.add_conflict(
+                    &theirs_node.path,
+                    [Some(base_node), None, Some(theirs_node)],
+                    theirs_node,
+                    Side::Theirs,
+                );
+            }
+        }
+
+        let path = if ours_renamed {
+            ours.map(|node| node.path.clone())
+        } else if theirs_renamed {
+            theirs.map(|node| node.path.clone())
+        } else {
+            base.or(ours).or(theirs).map(|node| node.path.clone())
+        }
+        .unwrap_or_default();
+        let base_node = base.map(|node| with_path(node, &path));
+        let ours_node = ours.map(|node| with_path(node, &path));
+        let theirs_node = theirs.map(|node| with_path(node, &path));
+        let base_path = base.map(|node| node.path.clone()).unwrap_or_default();
+
+        if (ours_renamed && theirs.is_none()) || (theirs_renamed && ours.is_none()) {
+            let (renamed_in, deleted_in, node, side) = if ours_renamed {
+                (&ours_label, &theirs_label, ours_node.as_ref(), Side::Ours)
+            } else {
+                (
+                    &theirs_label,
+                    &ours_label,
+                    theirs_node.as_ref(),
+                    Side::Theirs,
+                )
+            };
+            self.conflicts.push(format!(
+                "CONFLICT (rename/delete): {} renamed to {} in {}, but deleted in {}.",
+                base_path, path, renamed_in, deleted_in
+            ));
+            let node = node.unwrap();
+            return self.add_conflict(
+                &path,
+                [base_node.as_ref(), ours_node.as_ref(), theirs_node.as_ref()],
+                node,
+                side,
+            );
+        }
+
+        // Labels point at the original paths when a rename is involved, like git does
+        let labels = if ours_renamed || theirs_renamed {
+            (
+                format!("{}:{}", ours_label, ours.map_or(&path, |node| &node.path)),
+                format!(
+                    "{}:{}",
+                    theirs_label,
+                    theirs.map_or(&path, |node| &node.path)
+                ),
+            )
+        } else {
+            (ours_label, theirs_label)
+        };
+        self.resolve_path(
+            &path,
+            base_node.as_ref(),
+            ours_node.as_ref(),
+            theirs_node.as_ref(),
+            labels,
+        )
+    }
+
+    /// Resolves the path a file of `base_path` was renamed to while the other side, `added_side`,
+    /// added its own file there.
+    ///
+    /// Like git, a clean rename is merged with the added file as if both sides had added it, and
+    /// when the rename is conflicted already, the added file is moved to `<path>~<side>`.
+    fn resolve_rename_add(
+        &mut self,
+        base_path: &str,
+        added: &TreeNode,
+        added_side: Side,
+    ) -> Result<(), String> {
+        let path = added.path.clone();
+        let (ours_label, theirs_label) = (
+            self.options.ours_label.clone(),
+            self.options.theirs_label.clone(),
+        );
+        let renamed_clean = self
+            .entries
+            .iter()
+            .any(|entry| entry.path == path && entry.stage == 0);
+        if renamed_clean {
+            let (renamed, _) = self.worktree.remove(&path).unwrap();
+            self.entries.retain(|entry| entry.path != path);
+            let (ours, theirs) = match added_side {
+                Side::Theirs => (&renamed, added),
+                _ => (added, &renamed),
+            };
+            return self.resolve_path(
+                &path,
+                None,
+                Some(ours),
+                Some(theirs),
+                (ours_label, theirs_label),
+            );
+        }
+        let (renamed_in, added_in, stage) = match added_side {
+            Side::Theirs => (ours_label, theirs_label, 3),
+            _ => (theirs_label, ours_label, 2),
+        };
+        let new_path = format!("{}~{}", path, added_in.replace('/', "_"));
+        self.conflicts.push(format!(
+            "CONFLICT (rename/add): Rename {}->{} in {}. {} added in {}",
+            base_path, path, renamed_in, path, added_in
+        ));
+        self.add_stage(Some(added), &new_path, stage)?;
+        self.worktree
+            .insert(new_path.clone(), (with_path(added, &new_path), added_side));
+        Ok(())
+    }
+
+    /// Moves files that are in the way of a directory to `<path>~<side>`, like git
+    fn resolve_directory_file_conflicts(&mut self) -> Result<(), String> {
+        let blocking = self
+            .worktree
+            .keys()
+            .filter(|path| {
+                let dir_prefix = format!("{}/", path);
+                self.worktree
+                    .range(dir_prefix.clone()..)
+                    .next()
+                    .is_some_and(|(next, _)| next.starts_with(&dir_prefix))
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        for path in blocking {
+            let (node, side) = self.worktree.remove(&path).unwrap();
+            let (label, stage) = match side {
+                Side::Theirs => (self.options.theirs_label.clone(), 3),
+                _ => (self.options.ours_label.clone(), 2),
+            };
+            let new_path = format!("{}~{}", path, label.replace('/', "_"));
+            self.conflicts.push(format!(
+                "CONFLICT (file/directory): directory in the way of {} from {}; moving it to {} instead.",
+                path, label, new_path
+            ));
+            self.entries.retain(|entry| entry.path != path);
+            self.add_stage(Some(&node), &new_path, stage)?;
+            self.worktree
+                .insert(new_path.clone(), (with_path(&node, &new_path), side));
+        }
+        Ok(())
+    }
+}
+
+fn flatten_tree(
+    repo: &Repository,
+    tree: Option<&str>,
+) -> Result<BTreeMap<String, TreeNode>, String> {
+    Ok(match tree {
+        Some(tree) => TreeObject::read_from_sha(repo, tree)?
+            .flatten(repo)?
+            .into_iter()
+            .map(|node| (node.path.clone(), node))
+            .collect(),
+        None => BTreeMap::new(),
+    })
+}
+
+/// Three-way merge of two trees, `base` being their common ancestor tree (if any)
+pub fn merge_trees(
+    repo: &Repository,
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    options: &TreeMergeOptions,
+) -> Result<TreeMergeResult, String> {
+    let base = flatten_tree(repo, base)?;
+    let ours = flatten_tree(repo, Some(ours))?;
+    let theirs = flatten_tree(repo, Some(theirs))?;
+    let (ours_renames, theirs_renames) = if options.detect_renames {
+        (
+            detect_renames(repo, &base, &ours)?,
+            detect_renames(repo, &base, &theirs)?,
+        )
+    } else {
+        (BTreeMap::new(), BTreeMap::new())
+    };
+
+    let mut state = MergeState {
+        repo,
+        options,
+        entries: Vec::new(),
+        worktree: BTreeMap::new(),
+        conflicts: Vec::new(),
+    };
+    let ours_targets: HashSet<&str> = ours_renames.values().map(String::as_str).collect();
+    let theirs_targets: HashSet<&str> = theirs_renames.values().map(String::as_str).collect();
+    let mut used_ours: HashSet<&str> = HashSet::new();
+    let mut used_theirs: HashSet<&str> = HashSet::new();
+    for (path, base_node) in &base {
+        let ours_node = ours.get(ours_renames.get(path).unwrap_or(path));
+        let theirs_node = theirs.get(theirs_renames.get(path).unwrap_or(path));
+        used_ours.extend(ours_node.map(|node| node.path.as_str()));
+        used_theirs.extend(theirs_node.map(|node| node.path.as_str()));
+        // Files the other side added on its own where one side renamed this one
+        let added_theirs = ours_node
+            .filter(|node| node.path != *path)
+            .and_then(|node| theirs.get(&node.path))
+            .filter(|added| !theirs_targets.contains(added.path.as_str()));
+        let added_ours = theirs_node
+            .filter(|node| node.path != *path)
+            .and_then(|node| ours.get(&node.path))
+            .filter(|added| !ours_targets.contains(added.path.as_str()));
+        used_theirs.extend(added_theirs.map(|node| node.path.as_str()));
+        used_ours.extend(added_ours.map(|node| node.path.as_str()));
+        state.resolve_item(Some(base_node), ours_node, theirs_node)?;
+        if let Some(added) = added_theirs {
+            state.resolve_rename_add(path, added, Side::Theirs)?;
+        }
+        if let Some(added) = added_ours {
+            state.resolve_rename_add(path, added, Side::Ours)?;
+        }
+    }
+    for (path, ours_node) in &ours {
+        if used_ours.contains(path.as_str()) {
+            continue;
+        }
+        let theirs_node = theirs
+            .get(path)
+            .filter(|_| !used_theirs.contains(path.as_str()));
+        used_theirs.extend(theirs_node.map(|node| node.path.as_str()));
+        state.resolve_item(None, Some(ours_node), theirs_node)?;
+    }
+    for (path, theirs_node) in &theirs {
+        if !used_theirs.contains(path.as_str()) {
+            state.resolve_item(None, None, Some(theirs_node))?;
+        }
+    }
+    state.resolve_directory_file_conflicts()?;
+
+    let mut entries = state.entries;
+    entries.sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+    Ok(TreeMergeResult {
+        entries,
+        worktree: state.worktree.into_values().map(|(node, _)| node).collect(),
+        conflicts: state.conflicts,
+    })
+}
+
+/// Builds the tree to use as merge base, merging the merge bases together when there are
+/// several of them (criss-cross merges), like git's recursive strategy.
+///
+/// Only the merged trees are written, the virtual commit joining the bases merged so far is
+/// stood for by the list of those bases.
+fn virtual_base(
+    repo: &Repository,
+    bases: &[String],
+    options: &TreeMergeOptions,
+) -> Result<Option<String>, String> {
+    let Some((first, rest)) = bases.split_first() else {
+        return Ok(None);
+    };
+    let mut merged = vec![first.clone()];
+    let mut current_tree = peel_to_tree(repo, first)?;
+    for next in rest {
+        let sub_bases = best_common_ancestors(repo, &merged, std::slice::from_ref(next))?;
+        let virtual_options = TreeMergeOptions {
+            ours_label: String::from("Temporary merge branch 1"),
+            theirs_label: String::from("Temporary merge branch 2"),
+            ..options.clone()
+        };
+        let result = merge_trees(
+            repo,
+            virtual_base(repo, &sub_bases, options)?.as_deref(),
+            &current_tree,
+            &peel_to_tree(repo, next)?,
+            &virtual_options,
+        )?;
+        current_tree = result.write_tree(repo)?;
+        merged.push(next.clone());
+    }
+    Ok(Some(current_tree))
+}
+
+/// Merges two commits using their merge base(s), without touching the index or the worktree
+pub fn merge_commits(
+    repo: &Repository,
+    ours: &str,
+    theirs: &str,
+    options: &TreeMergeOptions,
+) -> Result<TreeMergeResult, String> {
+    let bases = merge_bases(repo, ours, theirs)?;
+    let base_tree = virtual_base(repo, &bases, options)?;
+    merge_trees(
+        repo,
+        base_tree.as_deref(),
+        &peel_to_tree(repo, ours)?,
+        &peel_to_tree(repo, theirs)?,
+        options,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::repository::test::TempRepo;
+
+    type Files<'a> = &'a [(&'a str, &'a str)];
+
+    fn merge(repo: &TempRepo, base: Files, ours: Files, theirs: Files) -> TreeMergeResult {
+        let options = TreeMergeOptions {
+            theirs_label: String::from("side"),
+            ..TreeMergeOptions::default()
+        };
+        let base = repo.tree(base);
+        merge_trees(
+            repo,
+            Some(&base),
+            &repo.tree(ours),
+            &repo.tree(theirs),
+            &options,
+        )
+        .unwrap()
+    }
+
+    fn stages(result: &TreeMergeResult) -> Vec<(&str, u8)> {
+        result
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.stage))
+            .collect()
+    }
+
+    fn content(repo: &TempRepo, result: &TreeMergeResult, path: &str) -> String {
+        let node = result
+            .worktree
+            .iter()
+            .find(|node| node.path == path)
+            .unwrap();
+        String::from_utf8(blob_data(repo, &node.hash).unwrap()).unwrap()
+    }
+
+    const LINES: &str = "1\n2\n3\n4\n5\n6\n";
+
+    #[test]
+    fn test_clean_merge() {
+        let repo = TempRepo::new();
+        let ours = [("f", "1o\n2\n3\n4\n5\n6\n"), ("g", "g\n")];
+        let result = merge(
+            &repo,
+            &[("f", LINES)],
+            &ours,
+            &[("f", "1\n2\n3\n4\n5\n6t\n")],
+        );
+        assert!(result.is_clean());
+        assert_eq!(stages(&result), [("f", 0), ("g", 0)]);
+        assert_eq!(content(&repo, &result, "f"), "1o\n2\n3\n4\n5\n6t\n");
+    }
+
+    #[test]
+    fn test_modify_delete() {
+        let repo = TempRepo::new();
+        let result = merge(&repo, &[("f", LINES)], &[("f", "1o\n2\n3\n4\n5\n6\n")], &[]);
+        assert_eq!(stages(&result), [("f", 1), ("f", 2)]);
+        assert!(result.conflicts[0].starts_with("CONFLICT (modify/delete): f deleted in side"));
+        assert_eq!(content(&repo, &result, "f"), "1o\n2\n3\n4\n5\n6\n");
+    }
+
+    #[test]
+    fn test_add_add() {
+        let repo = TempRepo::new();
+        let result = merge(&repo, &[], &[("f", "a\n")], &[("f", "b\n")]);
+        assert_eq!(stages(&result), [("f", 2), ("f", 3)]);
+        assert_eq!(
+            result.conflicts,
+            ["CONFLICT (add/add): Merge conflict in f"]
+        );
+        assert_eq!(
+            content(&repo, &result, "f"),
+            "<<<<<<< HEAD\na\n=======\nb\n>>>>>>> side\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_rename() {
+        let repo = TempRepo::new();
+        let result = merge(&repo, &[("a", LINES)], &[("b", LINES)], &[("c", LINES)]);
+        assert_eq!(stages(&result), [("b", 1), ("b", 2), ("c", 1), ("c", 3)]);
+        assert_eq!(
+            result.conflicts,
+            ["CONFLICT (rename/rename): a renamed to b in HEAD and to c in side."]
+        );
+    }
+
+    #[test]
+    fn test_rename_delete() {
+        let repo = TempRepo::new();
+        let result = merge(&repo, &[("a", LINES)], &[("b", LINES)], &[]);
+        assert_eq!(stages(&result), [("b", 1), ("b", 2)]);
+        assert_eq!(
+            result.conflicts,
+            ["CONFLICT (rename/delete): a renamed to b in HEAD, but deleted in side."]
+        );
+        assert_eq!(content(&repo, &result, "b"), LINES);
+    }
+
+    #[test]
+    fn test_rename_add() {
+        let repo = TempRepo::new();
+        let theirs = [("a", LINES), ("b", "other\n")];
+        let result = merge(&repo, &[("a", LINES)], &[("b", LINES)], &theirs);
+        // Like git, the renamed file and the added one conflict as add/add
+        assert_eq!(stages(&result), [("b", 2), ("b", 3)]);
+        assert_eq!(
+            result.conflicts,
+            ["CONFLICT (add/add): Merge conflict in b"]
+        );
+        assert!(content(&repo, &result, "b").ends_with("=======\nother\n>>>>>>> side\n"));
+    }
+
+    #[test]
+    fn test_file_directory() {
+        let repo = TempRepo::new();
+        let result = merge(&repo, &[], &[("d", "file\n")], &[("d/x", "x\n")]);
+        assert_eq!(stages(&result), [("d/x", 0), ("d~HEAD", 2)]);
+        assert_eq!(
+            result.conflicts,
+            ["CONFLICT (file/directory): directory in the way of d from HEAD; moving it to d~HEAD instead."]
+        );
+        assert_eq!(content(&repo, &result, "d~HEAD"), "file\n");
+    }
+
+    #[test]
+    fn test_criss_cross_merge() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("f", LINES)], &[], "base\n");
+        let ours = repo.commit(&[("f", "1o\n2\n3\n4\n5\n6\n")], &[&base], "ours\n");
+        let theirs = repo.commit(&[("f", "1\n2\n3\n4\n5\n6t\n")], &[&base], "theirs\n");
+        let both = "1o\n2\n3\n4\n5\n6t\n";
+        let ours_merge = repo.commit(&[("f", both)], &[&ours, &theirs], "merge\n");
+        let edited = "1o\n2\n3t\n4\n5\n6t\n";
+        let theirs_merge = repo.commit(&[("f", edited)], &[&theirs, &ours], "merge\n");
+        let object_count = || {
+            fs::read_dir(repo.gitdir.join("objects"))
+                .unwrap()
+                .flat_map(|dir| fs::read_dir(dir.unwrap().path()).unwrap())
+                .count()
+        };
+        let before = object_count();
+        let options = TreeMergeOptions::default();
+        let result = merge_commits(&repo, &ours_merge, &theirs_merge, &options).unwrap();
+        assert!(result.is_clean());
+        assert_eq!(content(&repo, &result, "f"), edited);
+        // The virtual merge base of `ours` and `theirs` has the tree of `ours_merge`, and no commit
+        assert_eq!(object_count(), before);
+    }
+}
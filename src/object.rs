@@ -1,5 +1,4 @@
 use std::{
-    collections::HashMap,
     fs::{self, File},
     io::{self, Read, Write},
     path::Path,
@@ -10,59 +9,52 @@ use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use hex::encode;
 use sha1::{Digest, Sha1};
 
-use crate::{create_path, ObjectTypes};
-use crate::{hex_to_hex_byte, Repository};
+use crate::{create_path, Attributes, Conversion, ObjectTypes};
+use crate::{hex_to_hex_byte, Repository, Signature};
 
+mod commit;
+mod tag;
 mod tree;
+pub use commit::Commit;
+pub use tag::Tag;
 pub use tree::{TreeNode, TreeNodeType, TreeObject};
 
 // TODO: Choice of picking between hashing algos
 // Because git itself is trying to migrate over to SHA-256 (SHA2)
 #[derive(Clone, Debug)]
 pub enum ObjectHeaders {
-    Commit {
-        fields: HashMap<String, Vec<String>>,
-        order: Vec<String>,
-        message: String,
-    },
+    Commit(Commit),
     Tree(TreeObject),
-    Tag {
-        fields: HashMap<String, Vec<String>>,
-        order: Vec<String>,
-        message: String,
-    },
-    Blob {
-        data: Vec<u8>,
-    },
+    Tag(Tag),
+    Blob { data: Vec<u8> },
 }
 impl ObjectHeaders {
+    /// Builds a commit with the headers in the order git writes them
+    pub fn new_commit(
+        tree: String,
+        parents: Vec<String>,
+        author: &Signature,
+        committer: &Signature,
+        message: String,
+    ) -> Self {
+        Self::Commit(Commit::new(
+            tree,
+            parents,
+            author.clone(),
+            committer.clone(),
+            message,
+        ))
+    }
     pub fn serialize(&self) -> Vec<u8> {
         match self {
             Self::Blob { data } => data.clone(),
-            Self::Commit {
-                fields,
-                order,
-                message,
-            }
-            | Self::Tag {
-                fields,
-                order,
-                message,
-            } => {
-                let mut data: Vec<String> = Vec::new();
-                for key in order {
-                    for value in fields.get(key).unwrap() {
-                        data.push(format!("{} {}", key, value.replace('\n', "\n ")));
-                    }
-                }
-                data.push(String::from(""));
-                data.push(message.to_owned());
-                data.join("\n").as_bytes().to_owned()
-            }
+            Self::Commit(commit) => commit.serialize(),
+            Self::Tag(tag) => tag.serialize(),
             Self::Tree(tree) => {
                 let mut data: Vec<u8> = Vec::new();
                 for entry in &tree.entries {
-                    data.append(&mut entry.mode.as_bytes().to_owned());
+                    // Modes are stored without leading zeros, "040000" is written as "40000"
+                    data.append(&mut entry.mode.trim_start_matches('0').as_bytes().to_owned());
                     data.push(0x20);
                     data.append(&mut entry.path.as_bytes().to_owned());
                     data.push(0x00);
@@ -71,50 +63,87 @@ impl ObjectHeaders {
                 }
                 data
             }
-            _ => Vec::new(),
         }
     }
     fn deserialize(object_type: ObjectTypes, data: Vec<u8>) -> Result<Self, String> {
         match object_type {
             ObjectTypes::Blob => Ok(Self::Blob { data }),
-            ObjectTypes::Commit | ObjectTypes::Tag => {
-                let data = String::from_utf8(data).unwrap();
-                let lines_slice = data.split('\n').collect::<Vec<&str>>();
-                let mut fields: HashMap<String, Vec<String>> = HashMap::new();
-                let mut order: Vec<String> = Vec::new();
-                let mut start = 0;
-                while !lines_slice[start].is_empty() {
-                    // Extracts key and parses value
-                    let mut end = start + 1;
-                    let mut intial_line = lines_slice[start].splitn(2, ' ');
-                    let key = intial_line.next().unwrap();
-                    let mut value = vec![intial_line.next().unwrap()];
-                    while !lines_slice[end].is_empty() && lines_slice[end].starts_with(' ') {
-                        value.push(lines_slice[end].strip_prefix(' ').unwrap());
-                        end += 1;
-                    }
-                    let value = value.join("\n");
-                    let key = key.to_owned();
-                    fields
-                        .entry(key.clone())
-                        .and_modify(|values| values.push(value.clone()))
-                        .or_insert(vec![value]);
-                    if !order.contains(&key) {
-                        order.push(key.clone());
-                    }
-                    start = end;
-                }
-                let message = lines_slice[start + 1..].join("\n");
+            ObjectTypes::Commit => Ok(Self::Commit(Commit::from_data(&data)?)),
+            ObjectTypes::Tag => Ok(Self::Tag(Tag::from_data(&data)?)),
+            ObjectTypes::Tree => Ok(Self::Tree(TreeObject::from_data(data)?)),
+        }
+    }
+}
 
-                Ok(Self::Commit {
-                    fields,
-                    order,
-                    message,
-                })
+/// Whether commit and tag data with this `encoding` header is read as ISO-8859-1, one char per
+/// byte, rather than UTF-8. Encodings other than these two then still serialize back unchanged.
+fn is_single_byte(encoding: Option<&str>) -> bool {
+    encoding.is_some_and(|encoding| {
+        !encoding.eq_ignore_ascii_case("utf-8") && !encoding.eq_ignore_ascii_case("utf8")
+    })
+}
+
+/// Bytes of serialized commit or tag data, see [is_single_byte]
+fn encode_text(data: String, encoding: Option<&str>) -> Vec<u8> {
+    match is_single_byte(encoding) {
+        true => data
+            .chars()
+            .flat_map(|c| match u8::try_from(c) {
+                Ok(byte) => vec![byte],
+                Err(_) => c.to_string().into_bytes(),
+            })
+            .collect(),
+        false => data.into_bytes(),
+    }
+}
+
+/// Splits commit and tag data into its headers, with continuation lines unfolded, and message
+fn split_headers(data: &[u8]) -> Result<(Vec<(String, String)>, String), String> {
+    let header_end = match data.starts_with(b"\n") {
+        true => 0,
+        false => data
+            .windows(2)
+            .position(|pair| pair == b"\n\n")
+            .unwrap_or(data.len()),
+    };
+    let encoding = data[..header_end]
+        .split(|&byte| byte == b'\n')
+        .find_map(|line| line.strip_prefix(b"encoding "))
+        .map(String::from_utf8_lossy);
+    let data: String = match is_single_byte(encoding.as_deref()) {
+        true => data.iter().map(|&byte| char::from(byte)).collect(),
+        false => String::from_utf8(data.to_vec())
+            .map_err(|e| format!("Object is not valid UTF-8, {}", e))?,
+    };
+    let data = data.as_str();
+    let (header, message) = match data.strip_prefix('\n') {
+        Some(message) => ("", message),
+        None => data
+            .split_once("\n\n")
+            .unwrap_or((data.strip_suffix('\n').unwrap_or(data), "")),
+    };
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in header.split('\n').filter(|line| !line.is_empty()) {
+        match (line.strip_prefix(' '), headers.last_mut()) {
+            (Some(continuation), Some((_, value))) => {
+                value.push('\n');
+                value.push_str(continuation);
+            }
+            _ => {
+                let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+                headers.push((key.to_owned(), value.to_owned()));
             }
-            ObjectTypes::Tree => Ok(Self::Tree(TreeObject::from_data(data)?)),
         }
     }
+    Ok((headers, message.to_owned()))
+}
+
+/// Writes a header line, folding multi-line values into continuation lines
+fn write_header(data: &mut String, key: &str, value: &str) {
+    data.push_str(key);
+    data.push(' ');
+    data.push_str(&value.replace('\n', "\n "));
+    data.push('\n');
 }
 #[derive(Debug)]
 pub struct Object {
@@ -129,6 +158,11 @@ impl Object {
         })
     }
     pub fn read_from_sha(repo: &Repository, hash: String) -> Result<Self, String> {
+        let (object_type, content) = Self::read_data(repo, &hash)?;
+        Self::new(object_type, content)
+    }
+    /// Type and content of an object exactly as stored, without parsing it
+    pub fn read_data(repo: &Repository, hash: &str) -> Result<(ObjectTypes, Vec<u8>), String> {
         // TODO: hash should be computed by the object itself
         // Using SHA-1 for now
         // There have been talks to shift to SHA-2
@@ -178,20 +212,106 @@ impl Object {
             ));
         }
 
-        Self::new(ObjectTypes::from_string(&header), content)
+        Ok((ObjectTypes::from_string(&header), content))
     }
     pub fn calculate_hash(&self) -> Result<String, String> {
-        let header = self._type.to_string();
-        let data = self.header.serialize();
+        Ok(Self::hash_data(&self._type, &self.header.serialize()))
+    }
+    pub fn write_to_repo(&self, repo: &Repository) -> Result<String, String> {
+        Self::write_data(repo, &self._type, &self.header.serialize())
+    }
+    /// Checks `data` is well-formed for its type as leniently as git does before hashing it,
+    /// failing with git's message otherwise
+    pub fn check_data(object_type: &ObjectTypes, data: &[u8]) -> Result<(), String> {
+        let hex_line = |line: &[u8], key: &[u8]| {
+            line.len() == key.len() + 41
+                && line.starts_with(key)
+                && line[key.len()..key.len() + 40]
+                    .iter()
+                    .all(u8::is_ascii_hexdigit)
+                && line[key.len() + 40] == b'\n'
+        };
+        match object_type {
+            ObjectTypes::Blob => Ok(()),
+            ObjectTypes::Commit => {
+                // git wants more than the `tree` line, then well-formed `parent` lines
+                if data.len() <= 46 || !hex_line(&data[..46], b"tree ") {
+                    return Err(String::from("corrupt commit"));
+                }
+                let mut rest = &data[46..];
+                while rest.len() > 47 && rest.starts_with(b"parent ") {
+                    if rest.len() <= 48 || !hex_line(&rest[..48], b"parent ") {
+                        return Err(String::from("corrupt commit"));
+                    }
+                    rest = &rest[48..];
+                }
+                Ok(())
+            }
+            ObjectTypes::Tag => {
+                let corrupt = || Err(String::from("corrupt tag"));
+                if data.len() < 64 || !hex_line(&data[..48], b"object ") {
+                    return corrupt();
+                }
+                let Some(rest) = data[48..].strip_prefix(b"type ") else {
+                    return corrupt();
+                };
+                let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                    return corrupt();
+                };
+                if !matches!(&rest[..end], b"commit" | b"tree" | b"blob" | b"tag") {
+                    return corrupt();
+                }
+                let rest = &rest[end + 1..];
+                if rest.len() <= 4 || !rest.starts_with(b"tag ") || !rest.contains(&b'\n') {
+                    return corrupt();
+                }
+                Ok(())
+            }
+            ObjectTypes::Tree => {
+                let mut rest = data;
+                while !rest.is_empty() {
+                    if rest.len() < 23 || rest[rest.len() - 21] != 0 {
+                        return Err(String::from("too-short tree object"));
+                    }
+                    let Some(space) = rest.iter().position(|&b| !(b'0'..=b'7').contains(&b)) else {
+                        return Err(String::from("malformed mode in tree entry"));
+                    };
+                    if rest[space] != b' ' {
+                        return Err(String::from("malformed mode in tree entry"));
+                    }
+                    let name_length = rest[space + 1..]
+                        .iter()
+                        .position(|&b| b == 0)
+                        .expect("NUL checked above");
+                    if name_length == 0 {
+                        return Err(String::from("empty filename in tree entry"));
+                    }
+                    let entry_length = space + 1 + name_length + 1 + 20;
+                    if rest.len() < entry_length {
+                        return Err(String::from("too-short tree file"));
+                    }
+                    rest = &rest[entry_length..];
+                }
+                Ok(())
+            }
+        }
+    }
+    /// `<type> <length>\0<data>`, the content git hashes and stores
+    fn loose_content(object_type: &ObjectTypes, data: &[u8]) -> Vec<u8> {
+        let header = object_type.to_string();
         let content_length = data.len().to_string();
-        let final_content = [
+        [
             header.as_bytes(),
             b"\x20",
             content_length.as_bytes(),
             b"\x00",
-            data.as_slice(),
+            data,
         ]
-        .concat();
+        .concat()
+    }
+    /// Hash of an object made of `data` as is
+    pub fn hash_data(object_type: &ObjectTypes, data: &[u8]) -> String {
+        let final_content = Self::loose_content(object_type, data);
         // DANGER
         // This is due to rust-analyzer not being able to get the correct types
         // Also the generic array does not have the correct length
@@ -206,21 +326,44 @@ impl Object {
             .as_slice()
             .try_into()
             .expect("Wrong length");
-        Ok(encode(hash))
+        encode(hash)
     }
-    pub fn write_to_repo(&self, repo: &Repository) -> Result<String, String> {
-        let header = self._type.to_string();
-        let data = self.header.serialize();
-        let content_length = data.len().to_string();
-        let final_content = [
-            header.as_bytes(),
-            b"\x20",
-            content_length.as_bytes(),
-            b"\x00",
-            data.as_slice(),
-        ]
-        .concat();
-        let hash: Vec<char> = self.calculate_hash()?.chars().collect();
+    /// Hash of an object made of `data`, also stored in `repo` only when `write` is set, like
+    /// `git hash-object`.
+    ///
+    /// Blobs hashed for `path`, as `(path relative to the worktree root, path as given)`, first go
+    /// through the conversion of that path. Warnings name the path as given.
+    pub fn hash_object(
+        repo: Option<&Repository>,
+        object_type: &ObjectTypes,
+        data: Vec<u8>,
+        path: Option<(&str, &str)>,
+        write: bool,
+    ) -> Result<String, String> {
+        let data = match (repo, path) {
+            (Some(repo), Some((path, given))) if *object_type == ObjectTypes::Blob => {
+                let conversion = Conversion::new(repo, &mut Attributes::new(repo), path);
+                match write {
+                    true => conversion.to_git_checked(data, given)?,
+                    false => conversion.to_git(data)?,
+                }
+            }
+            _ => data,
+        };
+        match (repo, write) {
+            (Some(repo), true) => Self::write_data(repo, object_type, &data),
+            (None, true) => Err(String::from("not a git repository")),
+            (_, false) => Ok(Self::hash_data(object_type, &data)),
+        }
+    }
+    /// Writes an object made of `data` as is, e.g. when the exact bytes given have to be kept
+    pub fn write_data(
+        repo: &Repository,
+        object_type: &ObjectTypes,
+        data: &[u8],
+    ) -> Result<String, String> {
+        let final_content = Self::loose_content(object_type, data);
+        let hash: Vec<char> = Self::hash_data(object_type, data).chars().collect();
 
         let directory: String = hash[..2].iter().collect();
         let filename: String = hash[2..].iter().collect();
@@ -276,23 +419,11 @@ pub fn resolve_ref(repo: &Repository, full_ref_path: &Path) -> Result<String, St
         Ok(_ref)
     }
 }
-pub fn create_ref(repo: &Repository, name: String, hash: String) -> Result<(), String> {
-    // Check if the hash we are pointing to exists
-    let full_ref_path = create_path(
-        &repo.gitdir,
-        vec![String::from("refs"), String::from("tags"), name.clone()],
-    );
-    let mut ref_file =
-        File::create(full_ref_path).map_err(|e| format!("Error creating ref, {}: {}", &name, e))?;
-    ref_file
-        .write_all(hash.as_bytes())
-        .map_err(|e| format!("Failed to write into ref, {}: {}", &name, e))
-}
-
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use crate::repository::test::TempRepo;
 
     #[test]
     fn test_hex() {
@@ -316,6 +447,39 @@ mod test {
         assert!("no\n".starts_with(' '));
     }
 
+    #[test]
+    fn test_hash_object() {
+        let repo = TempRepo::new();
+        let object_path = |hash: &str| {
+            repo.gitdir
+                .join("objects")
+                .join(&hash[..2])
+                .join(&hash[2..])
+        };
+        let hash_object = |data: &[u8], path, write| {
+            Object::hash_object(Some(&repo), &ObjectTypes::Blob, data.to_vec(), path, write)
+        };
+        let hash = hash_object(b"data\n", None, false).unwrap();
+        assert_eq!(hash, Object::hash_data(&ObjectTypes::Blob, b"data\n"));
+        assert!(!object_path(&hash).exists());
+        assert_eq!(hash_object(b"data\n", None, true), Ok(hash.clone()));
+        assert!(object_path(&hash).exists());
+
+        // Filters apply only when hashing for a path, which --no-filters leaves out
+        fs::write(repo.worktree.join(".gitattributes"), "*.txt text\n").unwrap();
+        let path = Some(("a.txt", "a.txt"));
+        assert_eq!(hash_object(b"data\r\n", path, false), Ok(hash));
+        assert_ne!(
+            hash_object(b"data\r\n", None, false),
+            hash_object(b"data\n", None, false)
+        );
+        let unfiltered = Some(("a.bin", "a.bin"));
+        assert_eq!(
+            hash_object(b"data\r\n", unfiltered, false),
+            hash_object(b"data\r\n", None, false)
+        );
+    }
+
     #[test]
     fn test_deserialize_commit() {
         let data = String::from(
@@ -324,7 +488,7 @@ parent 206941306e8a8af65b66eaaaea388a7ae24d49a0
 author Thibault Polge <thibault@thb.lt> 1527025023 +0200
 committer Thibault Polge <thibault@thb.lt> 1527025044 +0200
 gpgsig -----BEGIN PGP SIGNATURE-----
-
+ 
  iQIzBAABCAAdFiEExwXquOM8bWb4Q2zVGxM2FxoLkGQFAlsEjZQACgkQGxM2FxoL
  kGQdcBAAqPP+ln4nGDd2gETXjvOpOxLzIMEw4A9gU6CzWzm+oB8mEIKyaH0UFIPh
  rNUZ1j7/ZGFNeBDtT55LPdPIQw4KKlcf6kC8MPWP3qSu3xHqx12C5zyai2duFZUU
@@ -344,9 +508,106 @@ Create first draft",
         )
         .as_bytes()
         .to_owned();
-        let object = ObjectHeaders::deserialize(ObjectTypes::Commit, data).unwrap();
-        println!("{:?}", object);
-        println!("{:?}", object.serialize());
-        assert!(false);
+        let object = ObjectHeaders::deserialize(ObjectTypes::Commit, data.clone()).unwrap();
+        let ObjectHeaders::Commit(commit) = &object else {
+            panic!("Not a commit, {:?}", object);
+        };
+        assert_eq!(commit.tree, "29ff16c9c14e2652b22f8b78bb08a5a07930c147");
+        assert_eq!(commit.parents, ["206941306e8a8af65b66eaaaea388a7ae24d49a0"]);
+        assert_eq!(commit.author.time, 1527025023);
+        assert_eq!(commit.committer.tz_offset, 120);
+        assert_eq!(commit.encoding, None);
+        assert!(commit
+            .gpgsig
+            .as_ref()
+            .unwrap()
+            .ends_with("\n-----END PGP SIGNATURE-----"));
+        assert_eq!(commit.message, "Create first draft");
+        assert_eq!(object.serialize(), data);
+    }
+
+    #[test]
+    fn test_commit_round_trip() {
+        let tree = "tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147\n";
+        let identities = "author Jane Doe <jane@x.x> 1700000000 +0000\n\
+                          committer Jane Doe <jane@x.x> 1700000000 +0000\n";
+        let reordered = format!(
+            "{}{}x-unknown value\nencoding UTF-8\n\nM\n",
+            tree, identities
+        );
+        let signed = format!(
+            "{}{}gpgsig -----BEGIN PGP SIGNATURE-----\n abc\n -----END PGP SIGNATURE-----\n\
+             mergetag object 206941306e8a8af65b66eaaaea388a7ae24d49a0\n type commit\n\n\
+             Merge\n",
+            tree, identities
+        );
+        let latin1 = [
+            tree.as_bytes(),
+            b"author J\xf6rg <j@x.x> 1700000000 +0100\n",
+            b"committer J\xf6rg <j@x.x> 1700000000 +0100\n",
+            b"encoding ISO-8859-1\n\nCaf\xe9\n",
+        ]
+        .concat();
+        for data in [reordered.into_bytes(), signed.into_bytes(), latin1.clone()] {
+            let object = ObjectHeaders::deserialize(ObjectTypes::Commit, data.clone()).unwrap();
+            assert_eq!(object.serialize(), data);
+        }
+        let ObjectHeaders::Commit(commit) =
+            ObjectHeaders::deserialize(ObjectTypes::Commit, latin1).unwrap()
+        else {
+            panic!("Not a commit");
+        };
+        assert_eq!(commit.encoding.as_deref(), Some("ISO-8859-1"));
+        assert_eq!(commit.author.name, "J\u{f6}rg");
+        assert_eq!(commit.message, "Caf\u{e9}\n");
+
+        // New headers go where git writes them: encoding, others, then gpgsig
+        let mut commit = Commit::new(
+            commit.tree,
+            Vec::new(),
+            commit.author,
+            commit.committer,
+            String::from("M\n"),
+        );
+        commit.gpgsig = Some(String::from("sig"));
+        commit
+            .extra_headers
+            .push((String::from("x-unknown"), String::from("value")));
+        commit.encoding = Some(String::from("UTF-8"));
+        let data = String::from_utf8(commit.serialize()).unwrap();
+        assert!(data.contains("+0100\nencoding UTF-8\nx-unknown value\ngpgsig sig\n\nM\n"));
+    }
+
+    #[test]
+    fn test_deserialize_tag() {
+        let data = "object 29ff16c9c14e2652b22f8b78bb08a5a07930c147\n\
+                    type tree\n\
+                    tag v1.0\n\
+                    tagger Jane Doe <jane@x.x> 1700000000 +0000\n\
+                    x-unknown some\n  value\n\
+                    \n\
+                    Release\n\
+                    -----BEGIN PGP SIGNATURE-----\n\
+                    abc\n\
+                    -----END PGP SIGNATURE-----\n"
+            .as_bytes()
+            .to_owned();
+        let object = ObjectHeaders::deserialize(ObjectTypes::Tag, data.clone()).unwrap();
+        let ObjectHeaders::Tag(tag) = &object else {
+            panic!("Not a tag, {:?}", object);
+        };
+        assert_eq!(tag.kind, ObjectTypes::Tree);
+        assert_eq!(tag.name, "v1.0");
+        assert_eq!(
+            tag.extra_headers,
+            [(String::from("x-unknown"), String::from("some\n value"))]
+        );
+        assert_eq!(tag.message, "Release\n");
+        assert!(tag
+            .signature
+            .as_ref()
+            .unwrap()
+            .starts_with("-----BEGIN PGP SIGNATURE-----"));
+        assert_eq!(object.serialize(), data);
     }
 }
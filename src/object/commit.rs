@@ -0,0 +1,118 @@
+use crate::Signature;
+
+use super::{encode_text, split_headers, write_header};
+
+/// A commit object. `tree`, `parent`s, `author` and `committer` come first, as git requires,
+/// the headers following them are written back in the order they were read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: String,
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// Encoding of the message when it is not UTF-8
+    pub encoding: Option<String>,
+    /// Other headers following `committer`, e.g. `mergetag`
+    pub extra_headers: Vec<(String, String)>,
+    /// Signature over the commit without this header
+    pub gpgsig: Option<String>,
+    pub message: String,
+    /// Keys of the headers following `committer`, as read
+    header_order: Vec<String>,
+}
+impl Commit {
+    pub fn new(
+        tree: String,
+        parents: Vec<String>,
+        author: Signature,
+        committer: Signature,
+        message: String,
+    ) -> Self {
+        Self {
+            tree,
+            parents,
+            author,
+            committer,
+            encoding: None,
+            extra_headers: Vec::new(),
+            gpgsig: None,
+            message,
+            header_order: Vec::new(),
+        }
+    }
+    pub fn from_data(data: &[u8]) -> Result<Self, String> {
+        let (headers, message) = split_headers(data)?;
+        let mut headers = headers.into_iter().peekable();
+        let mut next = |key: &str| {
+            headers
+                .next_if(|(name, _)| name == key)
+                .map(|(_, value)| value)
+        };
+        let tree = next("tree").ok_or("Commit has no tree header")?;
+        let mut parents = Vec::new();
+        while let Some(parent) = next("parent") {
+            parents.push(parent);
+        }
+        let author = next("author").ok_or("Commit has no author header")?;
+        let committer = next("committer").ok_or("Commit has no committer header")?;
+        let (mut encoding, mut gpgsig) = (None, None);
+        let mut extra_headers = Vec::new();
+        let mut header_order = Vec::new();
+        for (key, value) in headers {
+            header_order.push(key.clone());
+            match key.as_str() {
+                "encoding" if encoding.is_none() => encoding = Some(value),
+                "gpgsig" if gpgsig.is_none() => gpgsig = Some(value),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+        Ok(Self {
+            tree,
+            parents,
+            author: Signature::parse(&author)?,
+            committer: Signature::parse(&committer)?,
+            encoding,
+            extra_headers,
+            gpgsig,
+            message,
+            header_order,
+        })
+    }
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = String::new();
+        write_header(&mut data, "tree", &self.tree);
+        for parent in &self.parents {
+            write_header(&mut data, "parent", parent);
+        }
+        write_header(&mut data, "author", &self.author.to_string());
+        write_header(&mut data, "committer", &self.committer.to_string());
+        // Headers go back where they were read, new ones where git puts them
+        let mut encoding = self.encoding.as_deref();
+        let mut gpgsig = self.gpgsig.as_deref();
+        let mut extra_headers = self.extra_headers.iter();
+        for key in &self.header_order {
+            let header = match key.as_str() {
+                "encoding" if encoding.is_some() => encoding.take().map(|value| (key, value)),
+                "gpgsig" if gpgsig.is_some() => gpgsig.take().map(|value| (key, value)),
+                _ => extra_headers
+                    .next()
+                    .map(|(key, value)| (key, value.as_str())),
+            };
+            if let Some((key, value)) = header {
+                write_header(&mut data, key, value);
+            }
+        }
+        if let Some(value) = encoding {
+            write_header(&mut data, "encoding", value);
+        }
+        for (key, value) in extra_headers {
+            write_header(&mut data, key, value);
+        }
+        if let Some(value) = gpgsig {
+            write_header(&mut data, "gpgsig", value);
+        }
+        data.push('\n');
+        data.push_str(&self.message);
+        encode_text(data, self.encoding.as_deref())
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{ObjectTypes, Signature};
+
+use super::{encode_text, split_headers, write_header};
+
+/// Lines starting the signature git appends to the message of signed tags
+const SIGNATURE_MARKERS: [&str; 3] = [
+    "-----BEGIN PGP SIGNATURE-----",
+    "-----BEGIN PGP MESSAGE-----",
+    "-----BEGIN SSH SIGNATURE-----",
+];
+
+/// An annotated tag object: `object`, `type`, `tag`, `tagger` (absent in very old tags), the
+/// other headers in their original order, then the message followed by the signature of
+/// signed tags
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub object: String,
+    pub kind: ObjectTypes,
+    pub name: String,
+    pub tagger: Option<Signature>,
+    /// Headers following `tagger`
+    pub extra_headers: Vec<(String, String)>,
+    pub message: String,
+    pub signature: Option<String>,
+}
+impl Tag {
+    pub fn new(
+        object: String,
+        kind: ObjectTypes,
+        name: String,
+        tagger: Signature,
+        message: String,
+    ) -> Self {
+        Self {
+            object,
+            kind,
+            name,
+            tagger: Some(tagger),
+            extra_headers: Vec::new(),
+            message,
+            signature: None,
+        }
+    }
+    /// Offset in tag data of the signature, like git the last line starting with a signature
+    /// marker. What precedes it is the payload that was signed.
+    pub fn signature_offset(data: &[u8]) -> Option<usize> {
+        let mut offset = 0;
+        let mut signature_offset = None;
+        for line in data.split_inclusive(|&byte| byte == b'\n') {
+            if SIGNATURE_MARKERS
+                .iter()
+                .any(|marker| line.starts_with(marker.as_bytes()))
+            {
+                signature_offset = Some(offset);
+            }
+            offset += line.len();
+        }
+        signature_offset
+    }
+    pub fn from_data(data: &[u8]) -> Result<Self, String> {
+        let (data, signature) = match Self::signature_offset(data) {
+            Some(offset) => (
+                &data[..offset],
+                Some(String::from_utf8_lossy(&data[offset..]).into_owned()),
+            ),
+            None => (data, None),
+        };
+        let (headers, message) = split_headers(data)?;
+        let mut headers = headers.into_iter().peekable();
+        let mut next = |key: &str| {
+            headers
+                .next_if(|(name, _)| name == key)
+                .map(|(_, value)| value)
+        };
+        let object = next("object");
+        let kind = next("type");
+        let name = next("tag");
+        let tagger = next("tagger")
+            .map(|tagger| Signature::parse(&tagger))
+            .transpose()?;
+        let extra_headers: Vec<_> = headers.collect();
+        let kind = match kind.as_deref() {
+            Some(kind @ ("commit" | "tree" | "blob" | "tag")) => ObjectTypes::from_string(kind),
+            Some(kind) => return Err(format!("Tag has an unknown type, {}", kind)),
+            None => return Err(String::from("Tag has no type header")),
+        };
+        Ok(Self {
+            object: object.ok_or("Tag has no object header")?,
+            kind,
+            name: name.ok_or("Tag has no tag header")?,
+            tagger,
+            extra_headers,
+            message,
+            signature,
+        })
+    }
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = String::new();
+        write_header(&mut data, "object", &self.object);
+        write_header(&mut data, "type", &self.kind.to_string());
+        write_header(&mut data, "tag", &self.name);
+        if let Some(tagger) = &self.tagger {
+            write_header(&mut data, "tagger", &tagger.to_string());
+        }
+        for (key, value) in &self.extra_headers {
+            write_header(&mut data, key, value);
+        }
+        data.push('\n');
+        data.push_str(&self.message);
+        if let Some(signature) = &self.signature {
+            data.push_str(signature);
+        }
+        let encoding = self.extra_headers.iter().find(|(key, _)| key == "encoding");
+        encode_text(data, encoding.map(|(_, value)| value.as_str()))
+    }
+}
@@ -1,7 +1,9 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
 use hex::encode;
 
+use crate::{Object, ObjectHeaders, ObjectTypes, Repository};
+
 #[derive(Debug, Clone)]
 pub enum TreeNodeType {
     Blob,
@@ -39,7 +41,7 @@ pub struct TreeNode {
     pub _type: TreeNodeType,
 }
 impl TreeNode {
-    fn new(mode: String, path: String, hash: String) -> Result<Self, String> {
+    pub fn new(mode: String, path: String, hash: String) -> Result<Self, String> {
         let mut mode = mode;
         // If the mode given is a folder (which is only 5 bytes/chars long)
         if mode.len() < 6 {
@@ -78,15 +80,72 @@ impl TreeObject {
             let hash = encode(data.by_ref().take(20).collect::<Vec<u8>>());
             entries.push(TreeNode::new(mode, path, hash)?);
         }
-        entries.sort_by(|a, b| {
+        let mut tree = Self { entries };
+        tree.sort_entries();
+        Ok(tree)
+    }
+    /// Sorts the entries the way git stores them, where trees are compared as if their path ended with `/`
+    fn sort_entries(&mut self) {
+        self.entries.sort_by(|a, b| {
             let process_path = |node: &TreeNode| -> String {
                 match node._type {
-                    TreeNodeType::Tree => node.path.clone() + "\\",
+                    TreeNodeType::Tree => node.path.clone() + "/",
                     _ => node.path.clone(),
                 }
             };
             process_path(a).cmp(&process_path(b))
         });
-        Ok(Self { entries })
+    }
+    pub fn read_from_sha(repo: &Repository, hash: &str) -> Result<Self, String> {
+        match Object::read_from_sha(repo, hash.to_owned())?.header {
+            ObjectHeaders::Tree(tree) => Ok(tree),
+            _ => Err(format!("Expected hash to lead to a tree object, {}", hash)),
+        }
+    }
+    /// Recursively lists every non-tree entry, with paths relative to this tree
+    pub fn flatten(&self, repo: &Repository) -> Result<Vec<TreeNode>, String> {
+        let mut nodes = Vec::new();
+        for entry in &self.entries {
+            match entry._type {
+                TreeNodeType::Tree => {
+                    for mut node in Self::read_from_sha(repo, &entry.hash)?.flatten(repo)? {
+                        node.path = format!("{}/{}", entry.path, node.path);
+                        nodes.push(node);
+                    }
+                }
+                _ => nodes.push(entry.clone()),
+            }
+        }
+        Ok(nodes)
+    }
+    /// Writes the nested trees needed to hold `nodes`, whose paths are relative to the root tree.
+    ///
+    /// Returns the hash of the root tree.
+    pub fn write_from_nodes(repo: &Repository, nodes: &[TreeNode]) -> Result<String, String> {
+        let mut subtrees: BTreeMap<&str, Vec<TreeNode>> = BTreeMap::new();
+        let mut tree = Self {
+            entries: Vec::new(),
+        };
+        for node in nodes {
+            match node.path.split_once('/') {
+                Some((dir, rest)) => {
+                    let mut node = node.clone();
+                    node.path = rest.to_owned();
+                    subtrees.entry(dir).or_default().push(node);
+                }
+                None => tree.entries.push(node.clone()),
+            }
+        }
+        for (dir, nodes) in subtrees {
+            let hash = Self::write_from_nodes(repo, &nodes)?;
+            tree.entries
+                .push(TreeNode::new(String::from("40000"), dir.to_owned(), hash)?);
+        }
+        tree.sort_entries();
+        Object {
+            header: ObjectHeaders::Tree(tree),
+            _type: ObjectTypes::Tree,
+        }
+        .write_to_repo(repo)
     }
 }
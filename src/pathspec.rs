@@ -0,0 +1,323 @@
+use std::cell::RefCell;
+
+use crate::{
+    attributes::is_valid_attr_name, normalize_path, wildmatch, wildmatch_pathname, AttrValue,
+    Attributes, Repository,
+};
+
+/// Characters of the short magic form `:<magic>pattern`, of which only `/` and `!` (or `^`) mean
+/// something
+const SHORT_MAGIC: &str = "!\"#%&',-/:;<=>@_`~^";
+
+/// One pathspec, as git parses `[:(<magic>,...)]<pattern>`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct PathspecItem {
+    /// Pattern relative to the worktree root, with a trailing `/` when naming a directory
+    pattern: String,
+    /// Length of the leading part of `pattern` that is the current directory, matched exactly
+    /// even with `icase`
+    prefix_len: usize,
+    /// Length of the leading part of `pattern` without wildcards, compared literally
+    nowildcard_len: usize,
+    /// `glob`: wildcards do not match `/`, except for `**`
+    glob: bool,
+    icase: bool,
+    exclude: bool,
+    /// `attr:` requirements, all of which a path has to meet, `None` standing for `!name`
+    attributes: Vec<(String, Option<AttrValue>)>,
+}
+impl PathspecItem {
+    fn parse(prefix: &str, arg: &str) -> Result<Self, String> {
+        let mut item = Self::default();
+        let (mut top, mut literal) = (false, false);
+        let mut attributes = None;
+        let mut rest = arg;
+        if let Some(magic) = arg.strip_prefix(":(") {
+            let end = magic
+                .find(')')
+                .ok_or_else(|| format!("Missing ')' at the end of pathspec magic in '{}'", arg))?;
+            for word in magic[..end].split(',') {
+                match word {
+                    "" => {}
+                    "top" => top = true,
+                    "literal" => literal = true,
+                    "glob" => item.glob = true,
+                    "icase" => item.icase = true,
+                    "exclude" => item.exclude = true,
+                    word => match word.strip_prefix("attr:") {
+                        Some(_) if attributes.is_some() => {
+                            return Err(String::from("Only one 'attr:' specification is allowed."))
+                        }
+                        Some(spec) => attributes = Some(parse_attr_spec(spec)?),
+                        None => {
+                            return Err(format!("Invalid pathspec magic '{}' in '{}'", word, arg))
+                        }
+                    },
+                }
+            }
+            rest = &magic[end + 1..];
+        } else if let Some(magic) = arg.strip_prefix(':') {
+            rest = magic;
+            for c in magic.chars() {
+                match c {
+                    ':' => {
+                        rest = &rest[1..];
+                        break;
+                    }
+                    '/' => top = true,
+                    '!' | '^' => item.exclude = true,
+                    c if SHORT_MAGIC.contains(c) => {
+                        return Err(format!("Unimplemented pathspec magic '{}' in '{}'", c, arg))
+                    }
+                    _ => break,
+                }
+                rest = &rest[1..];
+            }
+        }
+        if literal && item.glob {
+            return Err(format!("{}: 'literal' and 'glob' are incompatible", arg));
+        }
+        let prefix = if top { "" } else { prefix };
+        item.pattern = normalize_path(prefix, rest)
+            // Absolute paths are not resolved against the worktree
+            .filter(|_| !rest.starts_with('/'))
+            .ok_or_else(|| format!("{}: '{}' is outside repository", arg, rest))?;
+        if item.pattern.starts_with(prefix) {
+            item.prefix_len = prefix.len();
+        }
+        item.nowildcard_len = match literal {
+            true => item.pattern.len(),
+            false => item
+                .pattern
+                .find(['*', '?', '[', '\\'])
+                .unwrap_or(item.pattern.len()),
+        };
+        item.attributes = attributes.unwrap_or_default();
+        Ok(item)
+    }
+    /// Whether `text` starts with `start`, ignoring case past `prefix_len` with `icase`
+    fn starts_with(&self, text: &str, start: &str) -> bool {
+        let (text, start) = (text.as_bytes(), start.as_bytes());
+        if text.len() < start.len() {
+            return false;
+        }
+        let exact = match self.icase {
+            true => self.prefix_len.min(start.len()),
+            false => start.len(),
+        };
+        text[..exact] == start[..exact]
+            && text[exact..start.len()].eq_ignore_ascii_case(&start[exact..])
+    }
+    fn matches_path(&self, path: &str) -> bool {
+        let pattern = self.pattern.as_str();
+        if pattern.is_empty() {
+            return true;
+        }
+        if self.starts_with(path, pattern) {
+            let rest = &path[pattern.len()..];
+            if rest.is_empty() || pattern.ends_with('/') || rest.starts_with('/') {
+                return true;
+            }
+        }
+        if self.nowildcard_len == pattern.len()
+            || !self.starts_with(path, &pattern[..self.nowildcard_len])
+        {
+            return false;
+        }
+        let (pattern, path) = match self.icase {
+            true => (pattern.to_lowercase(), path.to_lowercase()),
+            false => (pattern.to_owned(), path.to_owned()),
+        };
+        match self.glob {
+            true => wildmatch_pathname(&pattern, &path),
+            false => wildmatch(&pattern, &path),
+        }
+    }
+    fn matches(&self, path: &str, attributes: Option<&RefCell<Attributes>>) -> bool {
+        self.matches_path(path)
+            && self.attributes.iter().all(|(name, value)| {
+                attributes.and_then(|attributes| attributes.borrow_mut().get(path, name)) == *value
+            })
+    }
+    fn may_match_in(&self, dir: &str) -> bool {
+        if self.matches_path(dir) {
+            return true;
+        }
+        let dir = format!("{}/", dir);
+        let literal = &self.pattern[..self.nowildcard_len];
+        self.starts_with(literal, &dir)
+            || (self.nowildcard_len < self.pattern.len() && self.starts_with(&dir, literal))
+    }
+}
+
+/// Parses the requirements of `attr:<spec>`, space separated `name`, `-name`, `!name` or
+/// `name=value`
+fn parse_attr_spec(spec: &str) -> Result<Vec<(String, Option<AttrValue>)>, String> {
+    if spec.is_empty() {
+        return Err(String::from("attr spec must not be empty"));
+    }
+    spec.split(' ')
+        .filter(|attribute| !attribute.is_empty())
+        .map(|attribute| {
+            let (name, value) = match attribute.split_once('=') {
+                Some((name, value)) => (name, Some(AttrValue::Value(value.to_owned()))),
+                None => match attribute.as_bytes()[0] {
+                    b'-' => (&attribute[1..], Some(AttrValue::Unset)),
+                    b'!' => (&attribute[1..], None),
+                    _ => (attribute, Some(AttrValue::Set)),
+                },
+            };
+            match is_valid_attr_name(name) {
+                true => Ok((name.to_owned(), value)),
+                false => Err(format!("invalid attribute name {}", name)),
+            }
+        })
+        .collect()
+}
+
+/// Paths given on the command line, with the magic git understands: `:(top)` or `:/`,
+/// `:(exclude)` or `:!`, `:(icase)`, `:(glob)`, `:(literal)` and `:(attr:<spec>)`.
+///
+/// Without magic, a path matches when it is one of the patterns or inside one, or when a
+/// pattern with wildcards matches it, `*` also matching `/`.
+#[derive(Clone, Debug, Default)]
+pub struct Pathspec {
+    items: Vec<PathspecItem>,
+    /// Attributes looked up for `attr:` magic
+    attributes: Option<RefCell<Attributes>>,
+}
+impl Pathspec {
+    /// Parses pathspecs given from the directory `prefix`, empty or ending with `/`.
+    ///
+    /// Like git, when there are only exclusions, they apply to the whole worktree, or to the
+    /// current directory when `prefer_cwd`, which also restricts paths to it when there are no
+    /// pathspecs at all.
+    pub fn parse(prefix: &str, args: &[String], prefer_cwd: bool) -> Result<Self, String> {
+        let mut items = args
+            .iter()
+            .map(|arg| PathspecItem::parse(prefix, arg))
+            .collect::<Result<Vec<PathspecItem>, String>>()?;
+        if (prefer_cwd || !items.is_empty()) && items.iter().all(|item| item.exclude) {
+            let base = if prefer_cwd { prefix } else { "" };
+            items.push(PathspecItem::parse(base, "")?);
+        }
+        Ok(Self {
+            items,
+            attributes: None,
+        })
+    }
+    /// Parses pathspecs given from the current directory of `repo`
+    pub fn new(repo: &Repository, args: &[String], prefer_cwd: bool) -> Result<Self, String> {
+        let mut pathspec = Self::parse(&repo.current_prefix(), args, prefer_cwd).map_err(|e| {
+            match e.ends_with("is outside repository") {
+                true => format!("{} at '{}'", e, repo.worktree.display()),
+                false => e,
+            }
+        })?;
+        if pathspec
+            .items
+            .iter()
+            .any(|item| !item.attributes.is_empty())
+        {
+            pathspec.attributes = Some(RefCell::new(Attributes::new(repo)));
+        }
+        Ok(pathspec)
+    }
+    /// Matches `path`, relative to the worktree root, and what is inside it
+    pub fn literal(path: &str) -> Self {
+        Self {
+            items: vec![PathspecItem {
+                pattern: path.to_owned(),
+                nowildcard_len: path.len(),
+                ..Default::default()
+            }],
+            attributes: None,
+        }
+    }
+    /// Whether every path matches, no pathspec being given
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    /// Path of the single pathspec given, as `--follow` needs
+    pub fn single_path(&self) -> Option<&str> {
+        match self.items.as_slice() {
+            [item] if !item.exclude => Some(item.pattern.trim_end_matches('/')),
+            _ => None,
+        }
+    }
+    /// Whether `path`, relative to the worktree root, matches
+    pub fn matches(&self, path: &str) -> bool {
+        let attributes = self.attributes.as_ref();
+        let (excludes, includes): (Vec<&PathspecItem>, Vec<&PathspecItem>) =
+            self.items.iter().partition(|item| item.exclude);
+        (includes.is_empty() || includes.iter().any(|item| item.matches(path, attributes)))
+            && !excludes.iter().any(|item| item.matches(path, attributes))
+    }
+    /// Leading parts of the included pathspecs before any wildcard, relative to the worktree root
+    pub fn literals(&self) -> impl Iterator<Item = &str> {
+        self.items
+            .iter()
+            .filter(|item| !item.exclude)
+            .map(|item| &item.pattern[..item.nowildcard_len])
+    }
+    /// Whether the directory `dir`, relative to the worktree root, may hold matching paths
+    pub fn may_match_in(&self, dir: &str) -> bool {
+        let mut includes = self.items.iter().filter(|item| !item.exclude).peekable();
+        includes.peek().is_none() || includes.any(|item| item.may_match_in(dir))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pathspec(prefix: &str, args: &[&str]) -> Pathspec {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Pathspec::parse(prefix, &args, false).unwrap()
+    }
+
+    #[test]
+    fn test_pathspec_matches() {
+        let paths = pathspec("", &["src/object", "README.md"]);
+        assert!(paths.matches("README.md"));
+        assert!(paths.matches("src/object/tree.rs"));
+        assert!(!paths.matches("src/object.rs"));
+        assert!(!paths.matches("src"));
+        assert!(paths.may_match_in("src"));
+        assert!(pathspec("", &[]).matches("anything"));
+
+        let paths = pathspec("src/", &["*.rs", ":/README.md", ":!main.rs"]);
+        assert!(paths.matches("src/object/tree.rs"));
+        assert!(paths.matches("README.md"));
+        assert!(!paths.matches("src/main.rs"));
+        assert!(!paths.matches("lib.rs"));
+        assert!(!paths.may_match_in("docs"));
+
+        assert!(!pathspec("", &[":(glob)src/*.rs"]).matches("src/object/tree.rs"));
+        assert!(pathspec("", &[":(glob)src/**/*.rs"]).matches("src/object/tree.rs"));
+        assert!(pathspec("", &[":(icase)readme.MD"]).matches("README.md"));
+        assert!(!pathspec("", &[":(literal)*.rs"]).matches("main.rs"));
+        assert!(pathspec("", &[":^*.o"]).matches("main.rs"));
+        assert!(pathspec("src/", &[".."]).matches("main.rs"));
+    }
+
+    #[test]
+    fn test_pathspec_magic_errors() {
+        let error = |arg: &str| Pathspec::parse("", &[arg.to_owned()], false).unwrap_err();
+        assert_eq!(
+            error(":(top"),
+            "Missing ')' at the end of pathspec magic in ':(top'"
+        );
+        assert_eq!(
+            error(":(foo)x"),
+            "Invalid pathspec magic 'foo' in ':(foo)x'"
+        );
+        assert_eq!(error(":#x"), "Unimplemented pathspec magic '#' in ':#x'");
+        assert_eq!(
+            error(":(literal,glob)x"),
+            ":(literal,glob)x: 'literal' and 'glob' are incompatible"
+        );
+        assert_eq!(error(":(attr:)x"), "attr spec must not be empty");
+        assert_eq!(error("../x"), "../x: '../x' is outside repository");
+    }
+}
@@ -0,0 +1,571 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+
+use crate::{Mailmap, Object, ObjectHeaders, Repository, Signature};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// How dates are displayed, the `--date=` modes of git
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateMode {
+    /// `Sun Oct 18 22:58:30 2026 +0000`
+    #[default]
+    Default,
+    /// `2026-10-18 22:58:30 +0000`
+    Iso,
+    /// `2026-10-18T22:58:30+00:00`
+    IsoStrict,
+    /// `Sun, 18 Oct 2026 22:58:30 +0000`
+    Rfc,
+    /// `2026-10-18`
+    Short,
+    /// `2 hours ago`
+    Relative,
+    /// `1792364239 +0000`
+    Raw,
+    /// `1792364239`
+    Unix,
+}
+impl DateMode {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "default" => Ok(Self::Default),
+            "iso" | "iso8601" => Ok(Self::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(Self::IsoStrict),
+            "rfc" | "rfc2822" => Ok(Self::Rfc),
+            "short" => Ok(Self::Short),
+            "relative" => Ok(Self::Relative),
+            "raw" => Ok(Self::Raw),
+            "unix" => Ok(Self::Unix),
+            _ => Err(format!("Unknown date format, {}", name)),
+        }
+    }
+}
+
+/// Offset in seconds of a `+hhmm`/`-hhmm` timezone
+pub(crate) fn timezone_offset(timezone: &str) -> i64 {
+    let sign = if timezone.starts_with('-') { -1 } else { 1 };
+    let digits = timezone.trim_start_matches(['+', '-']);
+    let hours = digits
+        .get(..2)
+        .and_then(|h| h.parse::<i64>().ok())
+        .unwrap_or(0);
+    let minutes = digits
+        .get(2..4)
+        .and_then(|m| m.parse::<i64>().ok())
+        .unwrap_or(0);
+    sign * (hours * 3600 + minutes * 60)
+}
+
+/// `+hhmm`/`-hhmm` timezone of an offset in minutes
+pub(crate) fn format_timezone(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("{}{:02}{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+}
+
+/// Converts days since the unix epoch into a `(year, month, day)` civil date
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's algorithm, eras of 400 years starting on March 1st
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a `(year, month, day)` civil date into days since the unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+pub(crate) fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Parses the dates accepted by `--since`/`--until`: unix timestamps (`@` prefixed or not,
+/// optionally followed by a `+hhmm` timezone), `YYYY-MM-DD[ HH:MM[:SS]][ +hhmm]` (UTC unless a
+/// timezone is given), `now`, `yesterday` and relative dates like `2 weeks ago` or `3.days.ago`
+pub fn parse_date(date: &str) -> Result<i64, String> {
+    parse_date_and_offset(date).map(|(timestamp, _)| timestamp)
+}
+
+/// Same as [parse_date], along with the offset in minutes of the timezone given (UTC if none)
+pub(crate) fn parse_date_and_offset(date: &str) -> Result<(i64, i32), String> {
+    let invalid = || format!("Invalid date, {}", date);
+    let trimmed = date.trim();
+    let (timestamp, timezone) = trimmed.split_once(' ').unwrap_or((trimmed, "+0000"));
+    let timestamp = timestamp.strip_prefix('@').unwrap_or(timestamp);
+    let timezone = timezone.trim();
+    if !timestamp.is_empty()
+        && timestamp.chars().all(|c| c.is_ascii_digit())
+        && timezone.starts_with(['+', '-'])
+    {
+        let timestamp = timestamp.parse().map_err(|_| invalid())?;
+        return Ok((timestamp, (timezone_offset(timezone) / 60) as i32));
+    }
+    match trimmed {
+        "now" => return Ok((now(), 0)),
+        "yesterday" => return Ok((now() - 86400, 0)),
+        _ => {}
+    }
+    let words = trimmed
+        .split(['.', ' '])
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>();
+    if let [count, unit, "ago"] = words[..] {
+        let count = count.parse::<i64>().map_err(|_| invalid())?;
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            "month" => 30 * 86400,
+            "year" => 365 * 86400,
+            _ => return Err(invalid()),
+        };
+        return Ok((now() - count * seconds, 0));
+    }
+    let mut parts = trimmed.split([' ', 'T']).filter(|part| !part.is_empty());
+    let mut date_part = parts.next().ok_or_else(invalid)?.split('-');
+    let next_number = |part: Option<&str>| part.and_then(|part| part.parse::<i64>().ok());
+    let year = next_number(date_part.next()).ok_or_else(invalid)?;
+    let month = next_number(date_part.next()).ok_or_else(invalid)?;
+    let day = next_number(date_part.next()).ok_or_else(invalid)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let mut seconds = 0;
+    let mut offset = 0;
+    for part in parts {
+        if part.starts_with(['+', '-']) || part == "Z" {
+            offset = timezone_offset(part);
+        } else {
+            let (time, zone) = match part.find(['+', '-', 'Z']) {
+                Some(index) => part.split_at(index),
+                None => (part, ""),
+            };
+            offset = timezone_offset(&zone.replace(':', ""));
+            let mut fields = time.split(':').map(|field| field.parse::<i64>().ok());
+            for unit in [3600, 60, 1] {
+                match fields.next() {
+                    Some(Some(value)) => seconds += value * unit,
+                    Some(None) => return Err(invalid()),
+                    None => break,
+                }
+            }
+        }
+    }
+    Ok((
+        days_from_civil(year, month as u32, day as u32) * 86400 + seconds - offset,
+        (offset / 60) as i32,
+    ))
+}
+
+fn relative_date(timestamp: i64) -> String {
+    let seconds = now() - timestamp;
+    if seconds < 0 {
+        return String::from("in the future");
+    }
+    let plural = |count: i64, unit: &str| match count {
+        1 => format!("{} {} ago", count, unit),
+        _ => format!("{} {}s ago", count, unit),
+    };
+    let days = (seconds + 43200) / 86400;
+    match seconds {
+        s if s < 90 => plural(s, "second"),
+        s if s < 90 * 60 => plural((s + 30) / 60, "minute"),
+        s if s < 36 * 3600 => plural((s + 1800) / 3600, "hour"),
+        _ if days < 14 => plural(days, "day"),
+        _ if days < 70 => plural((days + 3) / 7, "week"),
+        _ if days < 365 => plural((days + 15) / 30, "month"),
+        _ if days < 1825 => {
+            let months = (days * 12 * 2 + 365) / (365 * 2);
+            let (years, months) = (months / 12, months % 12);
+            match months {
+                0 => plural(years, "year"),
+                _ => format!(
+                    "{} {}, {}",
+                    years,
+                    if years == 1 { "year" } else { "years" },
+                    plural(months, "month")
+                ),
+            }
+        }
+        _ => plural((days + 183) / 365, "year"),
+    }
+}
+
+/// Formats a timestamp shown in the timezone `tz_offset` minutes away from UTC
+pub fn format_date(timestamp: i64, tz_offset: i32, mode: DateMode) -> String {
+    let timezone = format_timezone(tz_offset);
+    let local = timestamp + tz_offset as i64 * 60;
+    let days = local.div_euclid(86400);
+    let seconds = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[days.rem_euclid(7) as usize];
+    let month_name = MONTHS[month as usize - 1];
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    match mode {
+        DateMode::Default => format!(
+            "{} {} {} {} {} {}",
+            weekday, month_name, day, time, year, timezone
+        ),
+        DateMode::Iso => format!("{}-{:02}-{:02} {} {}", year, month, day, time, timezone),
+        DateMode::IsoStrict => format!(
+            "{}-{:02}-{:02}T{}{}:{}",
+            year,
+            month,
+            day,
+            time,
+            &timezone[..timezone.len().min(3)],
+            timezone.get(3..5).unwrap_or("00")
+        ),
+        DateMode::Rfc => format!(
+            "{}, {} {} {} {} {}",
+            weekday, day, month_name, year, time, timezone
+        ),
+        DateMode::Short => format!("{}-{:02}-{:02}", year, month, day),
+        DateMode::Relative => relative_date(timestamp),
+        DateMode::Raw => format!("{} {}", timestamp, timezone),
+        DateMode::Unix => timestamp.to_string(),
+    }
+}
+
+/// How commits are displayed, the `--pretty=` formats of git
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum PrettyFormat {
+    #[default]
+    Medium,
+    Oneline,
+    Short,
+    Full,
+    /// `format:` (`terminated` false, separates entries) or `tformat:` (terminates entries)
+    Format {
+        template: String,
+        terminated: bool,
+    },
+}
+impl PrettyFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "medium" => Ok(Self::Medium),
+            "oneline" => Ok(Self::Oneline),
+            "short" => Ok(Self::Short),
+            "full" => Ok(Self::Full),
+            _ => match (name.strip_prefix("format:"), name.strip_prefix("tformat:")) {
+                (Some(template), _) => Ok(Self::Format {
+                    template: template.to_owned(),
+                    terminated: false,
+                }),
+                (_, Some(template)) => Ok(Self::Format {
+                    template: template.to_owned(),
+                    terminated: true,
+                }),
+                // Like git, a format with a placeholder is taken as `tformat:`
+                _ if name.contains('%') => Ok(Self::Format {
+                    template: name.to_owned(),
+                    terminated: true,
+                }),
+                _ => Err(format!("Invalid pretty format, {}", name)),
+            },
+        }
+    }
+    /// Whether entries are separated by a newline, rather than each ending with one
+    pub fn is_separated(&self) -> bool {
+        match self {
+            Self::Oneline => false,
+            Self::Format { terminated, .. } => !terminated,
+            _ => true,
+        }
+    }
+}
+
+/// The parts of a commit that are displayed
+#[derive(Clone, Debug)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub tree: String,
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub committer: Signature,
+    pub message: String,
+}
+impl CommitInfo {
+    pub fn read(repo: &Repository, hash: &str) -> Result<Self, String> {
+        let ObjectHeaders::Commit(commit) = Object::read_from_sha(repo, hash.to_owned())?.header
+        else {
+            return Err(format!("Object {} is not a commit", hash));
+        };
+        Ok(Self {
+            hash: hash.to_owned(),
+            tree: commit.tree,
+            parents: commit.parents,
+            author: commit.author,
+            committer: commit.committer,
+            message: commit.message,
+        })
+    }
+    /// First paragraph of the message, joined into one line
+    pub fn subject(&self) -> String {
+        self.message
+            .trim_start_matches('\n')
+            .split("\n\n")
+            .next()
+            .unwrap_or("")
+            .lines()
+            .map(|line| line.trim())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+    /// Everything after the first paragraph of the message
+    pub fn body(&self) -> String {
+        match self.message.trim_start_matches('\n').split_once("\n\n") {
+            Some((_, body)) => {
+                let body = body.trim_start_matches('\n').trim_end();
+                if body.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\n", body)
+                }
+            }
+            None => String::new(),
+        }
+    }
+}
+
+fn expand_placeholders(
+    commit: &CommitInfo,
+    template: &str,
+    date_mode: DateMode,
+    mailmap: Option<&Mailmap>,
+) -> String {
+    let short = |hash: &str| hash[..hash.len().min(7)].to_owned();
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let mut placeholder = String::new();
+        if let Some(&next) = chars.peek() {
+            placeholder.push(next);
+            chars.next();
+            if matches!(next, 'a' | 'c') {
+                if let Some(&field) = chars.peek() {
+                    placeholder.push(field);
+                    chars.next();
+                }
+            } else if next == 'x' {
+                while placeholder.len() < 3 {
+                    match chars.peek() {
+                        Some(&digit) if digit.is_ascii_hexdigit() => {
+                            placeholder.push(digit);
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+        let person = |key: char| match key {
+            'a' => &commit.author,
+            _ => &commit.committer,
+        };
+        let expanded = match placeholder.as_str() {
+            "H" => commit.hash.clone(),
+            "h" => short(&commit.hash),
+            "T" => commit.tree.clone(),
+            "t" => short(&commit.tree),
+            "P" => commit.parents.join(" "),
+            "p" => commit.parents.iter().map(|p| short(p)).join(" "),
+            "s" => commit.subject(),
+            "b" => commit.body(),
+            "B" => commit.message.clone(),
+            "n" => String::from("\n"),
+            "%" => String::from("%"),
+            _ if placeholder.starts_with('x') && placeholder.len() == 3 => {
+                let byte = u8::from_str_radix(&placeholder[1..], 16).unwrap_or_default();
+                char::from(byte).to_string()
+            }
+            "an" | "cn" => person(placeholder.as_bytes()[0] as char).name.clone(),
+            "ae" | "ce" => person(placeholder.as_bytes()[0] as char).email.clone(),
+            "aN" | "cN" | "aE" | "cE" => {
+                let person = mapped_identity(person(placeholder.as_bytes()[0] as char), mailmap);
+                match placeholder.ends_with('N') {
+                    true => person.name,
+                    false => person.email,
+                }
+            }
+            "ad" | "cd" => {
+                let person = person(placeholder.as_bytes()[0] as char);
+                format_date(person.time, person.tz_offset, date_mode)
+            }
+            "at" | "ct" => person(placeholder.as_bytes()[0] as char).time.to_string(),
+            "aI" | "cI" => {
+                let person = person(placeholder.as_bytes()[0] as char);
+                format_date(person.time, person.tz_offset, DateMode::IsoStrict)
+            }
+            // Unknown placeholders are kept as they are, like git does
+            _ => format!("%{}", placeholder),
+        };
+        output.push_str(&expanded);
+    }
+    output
+}
+
+fn indented_message(message: &str) -> String {
+    message
+        .trim_start_matches('\n')
+        .trim_end()
+        .lines()
+        .map(|line| format!("    {}\n", line.trim_end()))
+        .collect()
+}
+
+fn mapped_identity(person: &Signature, mailmap: Option<&Mailmap>) -> Signature {
+    match mailmap {
+        Some(mailmap) => mailmap.map_identity(person),
+        None => person.clone(),
+    }
+}
+
+/// Renders a commit in the given format, see [PrettyFormat::is_separated] for how entries are joined.
+///
+/// Names and emails are canonicalized by `mailmap` in the headers and for `%aN`, `%aE`, `%cN`
+/// and `%cE`.
+pub fn format_commit(
+    commit: &CommitInfo,
+    format: &PrettyFormat,
+    date_mode: DateMode,
+    mailmap: Option<&Mailmap>,
+) -> String {
+    let merge_line = match commit.parents.len() {
+        0 | 1 => String::new(),
+        _ => format!(
+            "Merge: {}\n",
+            commit
+                .parents
+                .iter()
+                .map(|parent| parent[..7].to_owned())
+                .join(" ")
+        ),
+    };
+    let person_line = |label: &str, person: &Signature| {
+        let person = mapped_identity(person, mailmap);
+        format!("{}: {} <{}>\n", label, person.name, person.email)
+    };
+    match format {
+        PrettyFormat::Oneline => format!("{} {}\n", &commit.hash[..7], commit.subject()),
+        PrettyFormat::Medium => format!(
+            "commit {}\n{}{}Date:   {}\n\n{}",
+            commit.hash,
+            merge_line,
+            person_line("Author", &commit.author),
+            format_date(commit.author.time, commit.author.tz_offset, date_mode),
+            indented_message(&commit.message)
+        ),
+        PrettyFormat::Short => format!(
+            "commit {}\n{}{}\n{}",
+            commit.hash,
+            merge_line,
+            person_line("Author", &commit.author),
+            indented_message(&commit.subject())
+        ),
+        PrettyFormat::Full => format!(
+            "commit {}\n{}{}{}\n{}",
+            commit.hash,
+            merge_line,
+            person_line("Author", &commit.author),
+            person_line("Commit", &commit.committer),
+            indented_message(&commit.message)
+        ),
+        PrettyFormat::Format {
+            template,
+            terminated,
+        } => {
+            let mut output = expand_placeholders(commit, template, date_mode, mailmap);
+            if *terminated {
+                output.push('\n');
+            }
+            output
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(
+            format_date(1792364310, 0, DateMode::Default),
+            "Sun Oct 18 22:58:30 2026 +0000"
+        );
+        assert_eq!(
+            format_date(1792364310, -330, DateMode::IsoStrict),
+            "2026-10-18T17:28:30-05:30"
+        );
+        assert_eq!(
+            format_date(951782400, 60, DateMode::Rfc),
+            "Tue, 29 Feb 2000 01:00:00 +0100"
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1792364310"), Ok(1792364310));
+        assert_eq!(parse_date("2026-10-18 22:58:30"), Ok(1792364310));
+        assert_eq!(parse_date("2026-10-18T17:28:30-05:30"), Ok(1792364310));
+        assert_eq!(parse_date("2000-02-29"), Ok(951782400));
+        assert!(parse_date("last tuesday").is_err());
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let person = Signature {
+            name: String::from("a"),
+            email: String::from("a@b"),
+            time: 0,
+            tz_offset: 0,
+            negative_utc: false,
+        };
+        let commit = CommitInfo {
+            hash: String::from("0123456789abcdef0123456789abcdef01234567"),
+            tree: String::new(),
+            parents: vec![],
+            author: person.clone(),
+            committer: person,
+            message: String::from("subject\n"),
+        };
+        let expand = |template| expand_placeholders(&commit, template, DateMode::Default, None);
+        assert_eq!(expand("%h%x09%s%n"), "0123456\tsubject\n");
+        assert_eq!(expand("%x41%x7e%%"), "A~%");
+        assert_eq!(expand("%x4%xzz"), "%x4%xzz");
+    }
+}
@@ -0,0 +1,617 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::PathBuf,
+    process::Command,
+};
+
+use crate::{
+    apply_commit_changes, commit_on_head, commit_parents, commit_subject, create_path,
+    has_uncommitted_changes, head_branch, peel_to_commit, peel_to_tree, reachable_commits,
+    read_ref, reset_to_tree, rev_parse, sequencer::read_commit, strip_space, switch_tree,
+    update_ref_no_deref, update_symbolic_ref, Index, Object, ObjectHeaders, ObjectTypes,
+    ReplayAction, Repository, Signature,
+};
+
+const REBASE_DIR: &str = "rebase-merge";
+const DETACHED_HEAD: &str = "detached HEAD";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebaseCommand {
+    Pick,
+    Reword,
+    Squash,
+    Fixup,
+    Drop,
+    Exec,
+}
+impl RebaseCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pick => "pick",
+            Self::Reword => "reword",
+            Self::Squash => "squash",
+            Self::Fixup => "fixup",
+            Self::Drop => "drop",
+            Self::Exec => "exec",
+        }
+    }
+    fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "pick" | "p" => Ok(Self::Pick),
+            "reword" | "r" => Ok(Self::Reword),
+            "squash" | "s" => Ok(Self::Squash),
+            "fixup" | "f" => Ok(Self::Fixup),
+            "drop" | "d" => Ok(Self::Drop),
+            "exec" | "x" => Ok(Self::Exec),
+            _ => Err(format!("Invalid rebase command, {}", name)),
+        }
+    }
+}
+
+/// A line of the todo list, `argument` is a commit hash, or the shell command for `exec`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RebaseStep {
+    pub command: RebaseCommand,
+    pub argument: String,
+}
+impl RebaseStep {
+    fn to_line(&self, repo: &Repository) -> String {
+        match self.command {
+            RebaseCommand::Exec => format!("exec {}", self.argument),
+            command => {
+                let subject = read_commit(repo, &self.argument)
+                    .map(|header| commit_subject(&header.message).to_owned())
+                    .unwrap_or_default();
+                format!("{} {} {}", command.name(), self.argument, subject)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RebaseOutcome {
+    /// Every step was applied, holds the ref that was rebased (or `detached HEAD`)
+    Finished(String),
+    /// Stopped because applying `commit` conflicted
+    Conflicts {
+        commit: String,
+        conflicts: Vec<String>,
+    },
+    /// Stopped because an `exec` step exited with a non-zero status
+    ExecFailed {
+        command: String,
+        status: Option<i32>,
+    },
+}
+
+fn state_path(repo: &Repository, name: &str) -> PathBuf {
+    create_path(
+        &repo.gitdir,
+        vec![String::from(REBASE_DIR), name.to_owned()],
+    )
+}
+
+fn read_state(repo: &Repository, name: &str) -> Result<String, String> {
+    fs::read_to_string(state_path(repo, name))
+        .map(|content| content.trim_end().to_owned())
+        .map_err(|e| format!("Error reading rebase state {}: {}", name, e))
+}
+
+fn write_state(repo: &Repository, name: &str, content: &str) -> Result<(), String> {
+    fs::write(state_path(repo, name), content)
+        .map_err(|e| format!("Error writing rebase state {}: {}", name, e))
+}
+
+pub fn rebase_in_progress(repo: &Repository) -> bool {
+    create_path(&repo.gitdir, vec![String::from(REBASE_DIR)]).exists()
+}
+
+/// Parses a todo list, blank lines and `#` comments are ignored and commits may be any revision
+pub fn parse_todo(repo: &Repository, todo: &str) -> Result<Vec<RebaseStep>, String> {
+    let mut steps = Vec::new();
+    for line in todo.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let command = RebaseCommand::from_name(command)?;
+        let rest = rest.trim();
+        let argument = match command {
+            RebaseCommand::Exec if rest.is_empty() => {
+                return Err(String::from("Missing command after exec"))
+            }
+            RebaseCommand::Exec => rest.to_owned(),
+            _ => {
+                let commit = rest
+                    .split_whitespace()
+                    .next()
+                    .ok_or(format!("Missing commit in todo line, {}", line))?;
+                peel_to_commit(repo, &rev_parse(repo, commit)?)?
+            }
+        };
+        steps.push(RebaseStep { command, argument });
+    }
+    Ok(steps)
+}
+
+/// Commits of `head` that are not in `upstream`, oldest first, as `pick` steps. Merges are left out.
+pub fn rebase_todo(
+    repo: &Repository,
+    upstream: &str,
+    head: &str,
+) -> Result<Vec<RebaseStep>, String> {
+    let excluded = reachable_commits(repo, &[upstream.to_owned()])?;
+    let mut parents_cache: HashMap<String, Vec<String>> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    // Iterative post-order walk so that parents always come before their children
+    let mut stack = vec![(head.to_owned(), false)];
+    while let Some((commit, expanded)) = stack.pop() {
+        if expanded {
+            order.push(commit);
+            continue;
+        }
+        if excluded.contains(&commit) || !visited.insert(commit.clone()) {
+            continue;
+        }
+        let parents = commit_parents(repo, &commit)?;
+        stack.push((commit.clone(), true));
+        stack.extend(parents.iter().rev().map(|parent| (parent.clone(), false)));
+        parents_cache.insert(commit, parents);
+    }
+    Ok(order
+        .into_iter()
+        .filter(|commit| parents_cache[commit].len() <= 1)
+        .map(|commit| RebaseStep {
+            command: RebaseCommand::Pick,
+            argument: commit,
+        })
+        .collect())
+}
+
+/// Lets the user edit `message` with `GIT_EDITOR` or `EDITOR`, then strips `#` comments.
+///
+/// Without an editor configured the message is only stripped.
+fn edit_message(repo: &Repository, message: &str) -> Result<String, String> {
+    let mut message = message.to_owned();
+    if let Some(editor) = ["GIT_EDITOR", "EDITOR"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|editor| !editor.is_empty()))
+    {
+        let path = create_path(&repo.gitdir, vec![String::from("COMMIT_EDITMSG")]);
+        fs::write(&path, &message).map_err(|e| format!("Error writing COMMIT_EDITMSG: {}", e))?;
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", editor))
+            .arg(&editor)
+            .arg(&path)
+            .current_dir(&repo.worktree)
+            .status()
+            .map_err(|e| format!("Error running editor, {}: {}", editor, e))?;
+        if !status.success() {
+            return Err(format!("There was a problem with the editor, {}", editor));
+        }
+        message = fs::read_to_string(&path)
+            .map_err(|e| format!("Error reading COMMIT_EDITMSG: {}", e))?;
+    }
+    cleanup_message(&message)
+}
+
+/// Strips `#` comments and blank lines around paragraphs, failing when nothing is left
+fn cleanup_message(message: &str) -> Result<String, String> {
+    let message = strip_space(message, true);
+    if message.is_empty() {
+        return Err(String::from("Aborting commit due to empty commit message"));
+    }
+    Ok(message)
+}
+
+/// Replaces HEAD by a commit with the same parents and author, but the given tree and message
+fn amend_head(repo: &Repository, tree: String, message: String) -> Result<String, String> {
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    let header = read_commit(repo, &head)?;
+    let committer = Signature::committer(repo)?;
+    let commit = Object {
+        header: ObjectHeaders::new_commit(
+            tree,
+            header.parents,
+            &header.author,
+            &committer,
+            message,
+        ),
+        _type: ObjectTypes::Commit,
+    }
+    .write_to_repo(repo)?;
+    update_ref_no_deref(repo, "HEAD", &commit)?;
+    Ok(commit)
+}
+
+/// Messages of the commits folded into HEAD combined with the one of `commit`, the way
+/// `squash` and `fixup` present them. The messages of fixups are commented out.
+///
+/// Like git, `current-fixups` lists the commits already folded into HEAD since the last pick,
+/// and `message-squash` holds their combined message.
+fn squash_message(
+    repo: &Repository,
+    command: RebaseCommand,
+    commit: &str,
+) -> Result<String, String> {
+    let folded = read_state(repo, "current-fixups").unwrap_or_default();
+    let count = folded.lines().count() + 2;
+    let previous = match folded.is_empty() {
+        true => {
+            let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+            format!(
+                "# This is the 1st commit message:\n\n{}\n",
+                read_commit(repo, &head)?.message.trim_end()
+            )
+        }
+        // Without its `This is a combination of` line
+        false => read_state(repo, "message-squash")?
+            .split_once('\n')
+            .map(|(_, messages)| format!("{}\n", messages.trim_end()))
+            .unwrap_or_default(),
+    };
+    let message = read_commit(repo, commit)?.message;
+    let message = match command {
+        RebaseCommand::Squash => format!(
+            "# This is the commit message #{}:\n\n{}",
+            count,
+            message.trim_end()
+        ),
+        _ => format!(
+            "# The commit message #{} will be skipped:\n\n{}",
+            count,
+            message
+                .trim_end()
+                .lines()
+                .map(|line| match line.is_empty() {
+                    true => String::from("#"),
+                    false => format!("# {}", line),
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        ),
+    };
+    Ok(format!(
+        "# This is a combination of {} commits.\n{}\n{}\n",
+        count, previous, message
+    ))
+}
+
+/// Commits the tree in the index for the step that was stopped on, following its command
+fn commit_step(repo: &Repository, step: &RebaseStep, tree: String) -> Result<(), String> {
+    let message = read_state(repo, "message")? + "\n";
+    match step.command {
+        RebaseCommand::Squash | RebaseCommand::Fixup => {
+            let combined = match step.command {
+                RebaseCommand::Squash => edit_message(repo, &message)?,
+                _ => cleanup_message(&message)?,
+            };
+            amend_head(repo, tree, combined)?;
+            write_state(repo, "message-squash", &message)?;
+            let mut folded = read_state(repo, "current-fixups").unwrap_or_default();
+            if !folded.is_empty() {
+                folded.push('\n');
+            }
+            folded.push_str(&format!("{} {}\n", step.command.name(), step.argument));
+            write_state(repo, "current-fixups", &folded)?;
+        }
+        _ => {
+            let message = match step.command {
+                RebaseCommand::Reword => edit_message(repo, &message)?,
+                _ => message,
+            };
+            let author = read_commit(repo, &step.argument)?.author;
+            commit_on_head(repo, tree, &author, message)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies a commit step on HEAD, returning the conflicts if it could not be applied cleanly
+fn apply_step(repo: &Repository, step: &RebaseStep) -> Result<Vec<String>, String> {
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    let commit = &step.argument;
+    if !matches!(step.command, RebaseCommand::Squash | RebaseCommand::Fixup) {
+        let _ = fs::remove_file(state_path(repo, "current-fixups"));
+        let _ = fs::remove_file(state_path(repo, "message-squash"));
+    }
+    // Commits already based on HEAD are reused as they are
+    if step.command == RebaseCommand::Pick && commit_parents(repo, commit)? == [head.clone()] {
+        switch_tree(
+            repo,
+            &peel_to_tree(repo, &head)?,
+            &peel_to_tree(repo, commit)?,
+        )?;
+        update_ref_no_deref(repo, "HEAD", commit)?;
+        return Ok(Vec::new());
+    }
+    let result = apply_commit_changes(repo, ReplayAction::Pick, commit, None)?;
+    let message = match step.command {
+        RebaseCommand::Squash | RebaseCommand::Fixup => squash_message(repo, step.command, commit)?,
+        _ => read_commit(repo, commit)?.message,
+    };
+    write_state(repo, "message", &message)?;
+    if !result.is_clean() {
+        write_state(repo, "stopped-sha", &format!("{}\n", commit))?;
+        return Ok(result.conflicts);
+    }
+    let tree = result.write_tree(repo)?;
+    // Picks that end up empty are dropped
+    if tree == peel_to_tree(repo, &head)?
+        && matches!(step.command, RebaseCommand::Pick | RebaseCommand::Reword)
+    {
+        return Ok(Vec::new());
+    }
+    commit_step(repo, step, tree)?;
+    Ok(Vec::new())
+}
+
+fn run_exec(repo: &Repository, command: &str) -> Result<Option<i32>, String> {
+    println!("Executing: {}", command);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(&repo.worktree)
+        .status()
+        .map_err(|e| format!("Error running {}: {}", command, e))?;
+    Ok(if status.success() {
+        None
+    } else {
+        Some(status.code().unwrap_or(-1))
+    })
+}
+
+fn read_steps(repo: &Repository, name: &str) -> Result<Vec<RebaseStep>, String> {
+    parse_todo(
+        repo,
+        &fs::read_to_string(state_path(repo, name)).unwrap_or_default(),
+    )
+}
+
+fn write_steps(repo: &Repository, name: &str, steps: &[RebaseStep]) -> Result<(), String> {
+    let content = steps
+        .iter()
+        .map(|step| step.to_line(repo) + "\n")
+        .collect::<String>();
+    write_state(repo, name, &content)
+}
+
+/// Runs the remaining steps of the todo list, then moves the rebased branch to the result
+fn run_steps(repo: &Repository) -> Result<RebaseOutcome, String> {
+    let mut todo = read_steps(repo, "git-rebase-todo")?;
+    let mut done = read_steps(repo, "done")?;
+    while !todo.is_empty() {
+        let step = todo.remove(0);
+        done.push(step.clone());
+        write_steps(repo, "git-rebase-todo", &todo)?;
+        write_steps(repo, "done", &done)?;
+        match step.command {
+            RebaseCommand::Drop => {}
+            RebaseCommand::Exec => {
+                if let Some(status) = run_exec(repo, &step.argument)? {
+                    return Ok(RebaseOutcome::ExecFailed {
+                        command: step.argument,
+                        status: Some(status),
+                    });
+                }
+            }
+            _ => {
+                let conflicts = apply_step(repo, &step)?;
+                if !conflicts.is_empty() {
+                    return Ok(RebaseOutcome::Conflicts {
+                        commit: step.argument,
+                        conflicts,
+                    });
+                }
+            }
+        }
+    }
+    finish(repo)
+}
+
+fn finish(repo: &Repository) -> Result<RebaseOutcome, String> {
+    let head_name = read_state(repo, "head-name")?;
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    if head_name != DETACHED_HEAD {
+        update_ref_no_deref(repo, &head_name, &head)?;
+        update_symbolic_ref(repo, "HEAD", &head_name)?;
+    }
+    update_ref_no_deref(repo, "ORIG_HEAD", &read_state(repo, "orig-head")?)?;
+    fs::remove_dir_all(create_path(&repo.gitdir, vec![String::from(REBASE_DIR)]))
+        .map_err(|e| format!("Error removing rebase state: {}", e))?;
+    Ok(RebaseOutcome::Finished(head_name))
+}
+
+/// Detaches HEAD at `onto` and runs `steps` on top of it, see [parse_todo] and [rebase_todo].
+///
+/// The index and worktree must match HEAD.
+pub fn rebase_start(
+    repo: &Repository,
+    onto: &str,
+    steps: &[RebaseStep],
+) -> Result<RebaseOutcome, String> {
+    if rebase_in_progress(repo) {
+        return Err(String::from(
+            "A rebase is already in progress, use --continue, --skip or --abort",
+        ));
+    }
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    let head_tree = peel_to_tree(repo, &head)?;
+    if has_uncommitted_changes(repo)? {
+        return Err(String::from(
+            "Cannot rebase: You have unstaged or uncommitted changes",
+        ));
+    }
+    let first_commit_step = steps
+        .iter()
+        .find(|step| !matches!(step.command, RebaseCommand::Exec | RebaseCommand::Drop));
+    if let Some(step) = first_commit_step
+        .filter(|step| matches!(step.command, RebaseCommand::Squash | RebaseCommand::Fixup))
+    {
+        return Err(format!(
+            "Cannot '{}' without a previous commit",
+            step.command.name()
+        ));
+    }
+    fs::create_dir_all(create_path(&repo.gitdir, vec![String::from(REBASE_DIR)]))
+        .map_err(|e| format!("Error creating rebase state: {}", e))?;
+    write_state(
+        repo,
+        "head-name",
+        &format!(
+            "{}\n",
+            head_branch(repo).unwrap_or(String::from(DETACHED_HEAD))
+        ),
+    )?;
+    write_state(repo, "orig-head", &format!("{}\n", head))?;
+    write_state(repo, "onto", &format!("{}\n", onto))?;
+    write_steps(repo, "git-rebase-todo", steps)?;
+    write_steps(repo, "done", &[])?;
+    switch_tree(repo, &head_tree, &peel_to_tree(repo, onto)?)?;
+    update_ref_no_deref(repo, "HEAD", onto)?;
+    run_steps(repo)
+}
+
+/// Commits the resolved conflicts of the stopped step, then runs the remaining ones
+pub fn rebase_continue(repo: &Repository) -> Result<RebaseOutcome, String> {
+    if !rebase_in_progress(repo) {
+        return Err(String::from("No rebase in progress"));
+    }
+    if state_path(repo, "stopped-sha").exists() {
+        let index = Index::read(repo)?;
+        if index.has_conflicts() {
+            return Err(format!(
+                "Committing is not possible because you have unmerged files: {}",
+                index.conflicted_paths().join(", ")
+            ));
+        }
+        let step = read_steps(repo, "done")?
+            .pop()
+            .ok_or("Rebase state has no stopped step")?;
+        let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+        let tree = index.write_tree(repo)?;
+        // Nothing left of the commit once resolved, it is dropped like an empty pick
+        if tree != peel_to_tree(repo, &head)?
+            || matches!(step.command, RebaseCommand::Squash | RebaseCommand::Fixup)
+        {
+            commit_step(repo, &step, tree)?;
+        }
+        fs::remove_file(state_path(repo, "stopped-sha"))
+            .map_err(|e| format!("Error removing rebase state stopped-sha: {}", e))?;
+    }
+    run_steps(repo)
+}
+
+/// Drops the stopped step, resetting the index and worktree to HEAD, then runs the remaining ones
+pub fn rebase_skip(repo: &Repository) -> Result<RebaseOutcome, String> {
+    if !rebase_in_progress(repo) {
+        return Err(String::from("No rebase in progress"));
+    }
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    reset_to_tree(repo, &peel_to_tree(repo, &head)?)?;
+    let _ = fs::remove_file(state_path(repo, "stopped-sha"));
+    run_steps(repo)
+}
+
+/// Stops the rebase, checking out the branch as it was before it started
+pub fn rebase_abort(repo: &Repository) -> Result<(), String> {
+    if !rebase_in_progress(repo) {
+        return Err(String::from("No rebase in progress"));
+    }
+    let head_name = read_state(repo, "head-name")?;
+    let orig_head = read_state(repo, "orig-head")?;
+    reset_to_tree(repo, &peel_to_tree(repo, &orig_head)?)?;
+    if head_name == DETACHED_HEAD {
+        update_ref_no_deref(repo, "HEAD", &orig_head)?;
+    } else {
+        update_symbolic_ref(repo, "HEAD", &head_name)?;
+    }
+    fs::remove_dir_all(create_path(&repo.gitdir, vec![String::from(REBASE_DIR)]))
+        .map_err(|e| format!("Error removing rebase state: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repository::test::TempRepo;
+
+    #[test]
+    fn test_parse_todo() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("a", "a\n")], &[], "A\n");
+        let commit = repo.commit(&[("a", "b\n")], &[&base], "B\n");
+        let todo = format!(
+            "# Rebase\n\np {0} B\nr {0}\n  s {1}\nf {1} A\nd {0}\ndrop {0}\nx make test\nexec  echo a b \n",
+            commit, &base[..7]
+        );
+        let step = |command, argument: &str| RebaseStep {
+            command,
+            argument: argument.to_owned(),
+        };
+        assert_eq!(
+            parse_todo(&repo, &todo).unwrap(),
+            [
+                step(RebaseCommand::Pick, &commit),
+                step(RebaseCommand::Reword, &commit),
+                step(RebaseCommand::Squash, &base),
+                step(RebaseCommand::Fixup, &base),
+                step(RebaseCommand::Drop, &commit),
+                step(RebaseCommand::Drop, &commit),
+                step(RebaseCommand::Exec, "make test"),
+                step(RebaseCommand::Exec, "echo a b"),
+            ]
+        );
+        assert_eq!(
+            parse_todo(&repo, &format!("edit {}", commit)),
+            Err(String::from("Invalid rebase command, edit"))
+        );
+        assert!(parse_todo(&repo, "exec").is_err());
+        assert!(parse_todo(&repo, "pick").is_err());
+        assert!(parse_todo(&repo, "pick unknown").is_err());
+    }
+
+    #[test]
+    fn test_cleanup_message() {
+        assert_eq!(
+            cleanup_message("# header\n\nSubject  \n\n\n# comment\nBody\n\n").unwrap(),
+            "Subject\n\nBody\n"
+        );
+        assert!(cleanup_message("# only comments\n\n").is_err());
+    }
+
+    #[test]
+    fn test_squash_and_fixup() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("a", "a\n")], &[], "A\n");
+        let b = repo.commit(&[("a", "a\n"), ("b", "b\n")], &[&base], "B\n");
+        let c = repo.commit(&[("a", "a\n"), ("b", "b\n"), ("c", "c\n")], &[&b], "C\n");
+        let files = [("a", "a\n"), ("b", "b\n"), ("c", "c\n"), ("d", "d\n")];
+        let d = repo.commit(&files, &[&c], "D\n");
+        repo.checkout(&d);
+        let edited = repo.gitdir.join("edited");
+        env::set_var("GIT_EDITOR", format!("cat >> {}", edited.display()));
+
+        let todo = format!("pick {}\nfixup {}\nsquash {}\n", b, c, d);
+        let steps = parse_todo(&repo, &todo).unwrap();
+        let outcome = rebase_start(&repo, &base, &steps).unwrap();
+        assert_eq!(
+            outcome,
+            RebaseOutcome::Finished(String::from("refs/heads/master"))
+        );
+        assert_eq!(
+            fs::read_to_string(edited).unwrap(),
+            "# This is a combination of 3 commits.\n\
+             # This is the 1st commit message:\n\nB\n\n\
+             # The commit message #2 will be skipped:\n\n# C\n\n\
+             # This is the commit message #3:\n\nD\n"
+        );
+        let head = read_ref(&repo, "HEAD").unwrap();
+        let commit = read_commit(&repo, &head).unwrap();
+        assert_eq!(commit.parents, [base]);
+        assert_eq!(commit.message, "B\n\nD\n");
+        assert_eq!(commit.tree, peel_to_tree(&repo, &d).unwrap());
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use walkdir::WalkDir;
+
+use crate::{create_path, Repository, Signature};
+
+const SYMBOLIC_REF_PREFIX: &str = "ref: ";
+
+fn ref_path(repo: &Repository, name: &str) -> std::path::PathBuf {
+    create_path(&repo.gitdir, vec![name.to_owned()])
+}
+
+/// Entries of the `packed-refs` file as `(name, hash)`, peeled lines (`^<hash>`) are skipped
+fn read_packed_refs(repo: &Repository) -> Vec<(String, String)> {
+    let Ok(packed) = fs::read_to_string(ref_path(repo, "packed-refs")) else {
+        return Vec::new();
+    };
+    packed
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, name)| (name.to_owned(), hash.to_owned()))
+        .collect()
+}
+
+/// Raw content of a ref, either a hash or `ref: <target>`, looking into `packed-refs` when there is no loose ref
+fn read_ref_content(repo: &Repository, name: &str) -> Option<String> {
+    let path = ref_path(repo, name);
+    if path.is_file() {
+        return fs::read_to_string(path)
+            .ok()
+            .map(|content| content.trim().to_owned());
+    }
+    read_packed_refs(repo)
+        .into_iter()
+        .find(|(packed_name, _)| packed_name == name)
+        .map(|(_, hash)| hash)
+}
+
+/// Returns the ref pointed to if `name` is a symbolic ref, like `HEAD` pointing to `refs/heads/main`
+pub fn read_symbolic_ref(repo: &Repository, name: &str) -> Option<String> {
+    read_ref_content(repo, name)?
+        .strip_prefix(SYMBOLIC_REF_PREFIX)
+        .map(|target| target.to_owned())
+}
+
+/// Follows symbolic refs until the final ref name, which may not exist yet (unborn branch)
+pub fn resolve_ref_name(repo: &Repository, name: &str) -> String {
+    let mut name = name.to_owned();
+    // Guards against symbolic ref loops
+    for _ in 0..5 {
+        match read_symbolic_ref(repo, &name) {
+            Some(target) => name = target,
+            None => break,
+        }
+    }
+    name
+}
+
+/// Fully resolves a ref into the hash it points to, `None` if the ref does not exist
+pub fn read_ref(repo: &Repository, name: &str) -> Option<String> {
+    let content = read_ref_content(repo, &resolve_ref_name(repo, name))?;
+    if content.is_empty() || content.starts_with(SYMBOLIC_REF_PREFIX) {
+        None
+    } else {
+        Some(content)
+    }
+}
+
+/// Branch HEAD is on, e.g. `refs/heads/main`, `None` when HEAD is detached
+pub fn head_branch(repo: &Repository) -> Option<String> {
+    read_symbolic_ref(repo, "HEAD")
+}
+
+/// Points a ref at `hash`, following symbolic refs so that updating `HEAD` moves the checked out branch
+pub fn update_ref(repo: &Repository, name: &str, hash: &str) -> Result<(), String> {
+    let name = resolve_ref_name(repo, name);
+    write_ref_content(repo, &name, hash)
+}
+
+/// Points a ref at `hash` without following symbolic refs, e.g. to detach HEAD
+pub fn update_ref_no_deref(repo: &Repository, name: &str, hash: &str) -> Result<(), String> {
+    write_ref_content(repo, name, hash)
+}
+
+/// Makes `name` a symbolic ref to `target`
+pub fn update_symbolic_ref(repo: &Repository, name: &str, target: &str) -> Result<(), String> {
+    write_ref_content(repo, name, &format!("{}{}", SYMBOLIC_REF_PREFIX, target))
+}
+
+fn write_ref_content(repo: &Repository, name: &str, content: &str) -> Result<(), String> {
+    let path = ref_path(repo, name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Error creating ref directory, {:?}: {}", parent, e))?;
+    }
+    let mut ref_file =
+        File::create(&path).map_err(|e| format!("Error creating ref, {}: {}", name, e))?;
+    ref_file
+        .write_all(format!("{}\n", content).as_bytes())
+        .map_err(|e| format!("Failed to write into ref, {}: {}", name, e))
+}
+
+/// Deletes a ref, both its loose file and its `packed-refs` entry
+pub fn delete_ref(repo: &Repository, name: &str) -> Result<(), String> {
+    let path = ref_path(repo, name);
+    let mut found = false;
+    if path.is_file() {
+        fs::remove_file(&path).map_err(|e| format!("Error deleting ref, {}: {}", name, e))?;
+        found = true;
+    }
+    let packed_path = ref_path(repo, "packed-refs");
+    if let Ok(packed) = fs::read_to_string(&packed_path) {
+        let mut lines = Vec::new();
+        let mut skip_peeled = false;
+        for line in packed.lines() {
+            if line.starts_with('^') && skip_peeled {
+                continue;
+            }
+            skip_peeled = line.split_once(' ').map(|(_, ref_name)| ref_name) == Some(name);
+            if skip_peeled {
+                found = true;
+                continue;
+            }
+            lines.push(line);
+        }
+        fs::write(&packed_path, lines.join("\n") + "\n")
+            .map_err(|e| format!("Error writing packed-refs: {}", e))?;
+    }
+    if !found {
+        return Err(format!("Ref does not exist, {}", name));
+    }
+    Ok(())
+}
+
+/// All refs under `prefix` (e.g. `refs/tags/`) as `(name, hash)`, sorted by name
+pub fn list_refs(repo: &Repository, prefix: &str) -> Vec<(String, String)> {
+    let mut refs = read_packed_refs(repo)
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .collect::<Vec<(String, String)>>();
+    let refs_dir = ref_path(repo, "refs");
+    for entry in WalkDir::new(&refs_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+    {
+        let Ok(relative) = entry.path().strip_prefix(&repo.gitdir) else {
+            continue;
+        };
+        let name = relative_ref_name(relative);
+        if !name.starts_with(prefix) {
+            continue;
+        }
+        if let Some(hash) = read_ref(repo, &name) {
+            refs.retain(|(packed_name, _)| packed_name != &name);
+            refs.push((name, hash));
+        }
+    }
+    refs.sort();
+    refs
+}
+
+fn relative_ref_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Whether `name` follows git's `check-ref-format` rules: no component starting with `.` or
+/// ending with `.lock`, no `..`, `@{`, control characters, spaces or any of `~^:?*[\`, no empty
+/// component, no trailing `.` and, unless `allow_onelevel`, at least two components
+pub fn check_ref_format(name: &str, allow_onelevel: bool) -> bool {
+    if name == "@" || name.ends_with('.') || (!allow_onelevel && !name.contains('/')) {
+        return false;
+    }
+    if name.contains("..") || name.contains("@{") {
+        return false;
+    }
+    if name
+        .chars()
+        .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return false;
+    }
+    name.split('/').all(|component| {
+        !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+    })
+}
+
+/// Shortens a full ref name the way git displays it, `refs/heads/main` becomes `main`
+pub fn shorten_ref_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+/// An entry of a ref's log, stored oldest first in `logs/<ref>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub old: String,
+    pub new: String,
+    /// `name <email> <timestamp> <timezone>` of who made the change
+    pub identity: String,
+    pub message: String,
+}
+impl ReflogEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let (fields, message) = line.split_once('\t').unwrap_or((line, ""));
+        let (old, rest) = fields.split_once(' ')?;
+        let (new, identity) = rest.split_once(' ')?;
+        Some(Self {
+            old: old.to_owned(),
+            new: new.to_owned(),
+            identity: identity.to_owned(),
+            message: message.to_owned(),
+        })
+    }
+    fn serialize(&self) -> String {
+        format!(
+            "{} {} {}\t{}\n",
+            self.old, self.new, self.identity, self.message
+        )
+    }
+}
+
+fn reflog_path(repo: &Repository, name: &str) -> std::path::PathBuf {
+    create_path(&repo.gitdir, vec![String::from("logs"), name.to_owned()])
+}
+
+/// Entries of the log of `name`, oldest first, empty if the ref has no log
+pub fn read_reflog(repo: &Repository, name: &str) -> Vec<ReflogEntry> {
+    fs::read_to_string(reflog_path(repo, name))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(ReflogEntry::parse)
+        .collect()
+}
+
+/// Replaces the log of `name`, an empty list removes it
+pub fn write_reflog(repo: &Repository, name: &str, entries: &[ReflogEntry]) -> Result<(), String> {
+    let path = reflog_path(repo, name);
+    if entries.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Error removing reflog, {}: {}", name, e))
+            }
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Error creating reflog directory, {:?}: {}", parent, e))?;
+    }
+    fs::write(
+        &path,
+        entries
+            .iter()
+            .map(ReflogEntry::serialize)
+            .collect::<String>(),
+    )
+    .map_err(|e| format!("Error writing reflog, {}: {}", name, e))
+}
+
+/// Records a change of `name` from `old` (`None` for a new ref) to `new` in its log
+pub fn append_reflog(
+    repo: &Repository,
+    name: &str,
+    old: Option<&str>,
+    new: &str,
+    message: &str,
+) -> Result<(), String> {
+    let mut entries = read_reflog(repo, name);
+    entries.push(ReflogEntry {
+        old: old.unwrap_or(&"0".repeat(40)).to_owned(),
+        new: new.to_owned(),
+        identity: Signature::committer(repo)?.to_string(),
+        message: message.lines().next().unwrap_or("").to_owned(),
+    });
+    write_reflog(repo, name, &entries)
+}
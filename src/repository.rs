@@ -1,4 +1,5 @@
 use std::{
+    env,
     fs::{remove_dir_all, File},
     io::Write,
     path::{Path, PathBuf},
@@ -61,6 +62,19 @@ impl Repository {
             .map_err(|e| format!("Error writing to git description: {}", e))?;
         Ok(())
     }
+    /// Current directory relative to the worktree root, empty or ending with `/`, which git
+    /// prefixes to the paths given on the command line
+    pub fn current_prefix(&self) -> String {
+        let Ok(current) = env::current_dir().and_then(|dir| dir.canonicalize()) else {
+            return String::new();
+        };
+        match current.strip_prefix(&self.worktree) {
+            Ok(relative) if !relative.as_os_str().is_empty() => {
+                format!("{}/", relative.to_string_lossy())
+            }
+            _ => String::new(),
+        }
+    }
     pub fn from_worktree_root(worktree_root: PathBuf, git_dir_path: &str) -> Result<Self, String> {
         let gitdir = create_path(&worktree_root, vec![git_dir_path.to_owned()]);
 
@@ -70,7 +84,7 @@ impl Repository {
         gitconfig_ini
             .load(gitconfig)
             .map_err(|e| format!("Error loading .git directory config: {}", e))?;
-        let repo_config = Config::from_ini(gitconfig_ini);
+        let repo_config = Config::from_repository_ini(gitconfig_ini);
 
         Ok(Self {
             worktree: worktree_root,
@@ -81,7 +95,7 @@ impl Repository {
     pub fn find_worktree_root(current_dir: PathBuf, git_dir_path: &str) -> Option<Self> {
         // TODO: Check if errors on the cannonicalize needs to be dealt
         let mut current_dir = current_dir.canonicalize().unwrap();
-        while current_dir != PathBuf::from("/") {
+        while current_dir != Path::new("/") {
             let potential_worktree_root = create_path(&current_dir, vec![git_dir_path.to_owned()]);
             if potential_worktree_root.exists() {
                 return Some(Self::from_worktree_root(current_dir, git_dir_path).unwrap());
@@ -92,3 +106,97 @@ impl Repository {
         None
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{
+        env, fs,
+        ops::Deref,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        peel_to_tree, reset_to_tree, update_ref, update_symbolic_ref, Object, ObjectHeaders,
+        ObjectTypes, Repository, Signature, TreeNode, TreeObject,
+    };
+
+    /// Repository in a new temporary directory, which is removed when dropped
+    pub(crate) struct TempRepo(Repository);
+    impl TempRepo {
+        pub(crate) fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let worktree = env::temp_dir().join(format!(
+                "rit-test-{}-{}",
+                process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let _ = fs::remove_dir_all(&worktree);
+            fs::create_dir_all(&worktree).unwrap();
+            let repo = Repository::init_worktree(worktree.canonicalize().unwrap(), ".rit");
+            Self(repo.unwrap())
+        }
+        /// Writes a tree holding regular files, given as `(path, content)`
+        pub(crate) fn tree(&self, files: &[(&str, &str)]) -> String {
+            let nodes: Vec<TreeNode> = files
+                .iter()
+                .map(|(path, content)| {
+                    let blob = Object::new(ObjectTypes::Blob, content.as_bytes().to_vec())
+                        .and_then(|blob| blob.write_to_repo(self))
+                        .unwrap();
+                    TreeNode::new(String::from("100644"), path.to_string(), blob).unwrap()
+                })
+                .collect();
+            TreeObject::write_from_nodes(self, &nodes).unwrap()
+        }
+        pub(crate) fn commit(
+            &self,
+            files: &[(&str, &str)],
+            parents: &[&str],
+            message: &str,
+        ) -> String {
+            let identity = Signature {
+                name: String::from("a"),
+                email: String::from("a@b"),
+                time: 0,
+                tz_offset: 0,
+                negative_utc: false,
+            };
+            Object {
+                header: ObjectHeaders::new_commit(
+                    self.tree(files),
+                    parents.iter().map(|parent| parent.to_string()).collect(),
+                    &identity,
+                    &identity,
+                    message.to_owned(),
+                ),
+                _type: ObjectTypes::Commit,
+            }
+            .write_to_repo(self)
+            .unwrap()
+        }
+        /// Points `master`, checked out, at `commit` and resets the index and worktree to it
+        pub(crate) fn checkout(&self, commit: &str) {
+            update_symbolic_ref(self, "HEAD", "refs/heads/master").unwrap();
+            update_ref(self, "HEAD", commit).unwrap();
+            reset_to_tree(self, &peel_to_tree(self, commit).unwrap()).unwrap();
+        }
+        pub(crate) fn read(&self, path: &str) -> Option<String> {
+            fs::read_to_string(self.worktree.join(path)).ok()
+        }
+        pub(crate) fn write(&self, path: &str, content: &str) {
+            fs::write(self.worktree.join(path), content).unwrap();
+        }
+    }
+    impl Deref for TempRepo {
+        type Target = Repository;
+        fn deref(&self) -> &Repository {
+            &self.0
+        }
+    }
+    impl Drop for TempRepo {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.worktree);
+        }
+    }
+}
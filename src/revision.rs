@@ -0,0 +1,231 @@
+use std::fs;
+
+use crate::{
+    create_path, read_ref, read_reflog, Index, Object, ObjectHeaders, ObjectTypes, Repository,
+    TreeObject,
+};
+
+/// Expands a short ref name into the full ref it refers to, using git's lookup order
+pub fn expand_ref_name(repo: &Repository, name: &str) -> Option<String> {
+    let name = if name == "@" { "HEAD" } else { name };
+    let is_special_ref =
+        name.chars().all(|c| c.is_ascii_uppercase() || c == '_') && !name.is_empty();
+    let mut candidates = Vec::new();
+    if is_special_ref || name.starts_with("refs/") {
+        candidates.push(name.to_owned());
+    }
+    for prefix in ["refs/", "refs/tags/", "refs/heads/", "refs/remotes/"] {
+        candidates.push(format!("{}{}", prefix, name));
+    }
+    candidates.push(format!("refs/remotes/{}/HEAD", name));
+    candidates
+        .into_iter()
+        .find(|candidate| read_ref(repo, candidate).is_some())
+}
+
+/// Expands an abbreviated hash into the full hash of the object, failing if it is ambiguous
+fn expand_short_hash(repo: &Repository, short: &str) -> Result<Option<String>, String> {
+    if short.len() < 4 || short.len() > 40 || !short.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    let short = short.to_ascii_lowercase();
+    let directory = create_path(
+        &repo.gitdir,
+        vec![String::from("objects"), short[..2].to_owned()],
+    );
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Ok(None);
+    };
+    let matches = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|filename| filename.starts_with(&short[2..]))
+        .map(|filename| format!("{}{}", &short[..2], filename))
+        .collect::<Vec<String>>();
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.into_iter().next()),
+        _ => Err(format!("Short object ID {} is ambiguous", short)),
+    }
+}
+
+fn resolve_name(repo: &Repository, name: &str) -> Result<String, String> {
+    if name.len() == 40 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(name.to_ascii_lowercase());
+    }
+    if let Some((ref_name, position)) = name
+        .strip_suffix('}')
+        .and_then(|name| name.rsplit_once("@{"))
+        .filter(|(_, position)| position.chars().all(|c| c.is_ascii_digit()))
+    {
+        let ref_name = if ref_name.is_empty() {
+            "HEAD"
+        } else {
+            ref_name
+        };
+        let full_name =
+            expand_ref_name(repo, ref_name).ok_or(format!("Unknown revision, {}", name))?;
+        let position = position
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid reflog position in {}", name))?;
+        let entries = read_reflog(repo, &full_name);
+        return entries
+            .len()
+            .checked_sub(position + 1)
+            .map(|index| entries[index].new.clone())
+            .ok_or(format!(
+                "Log for {} only has {} entries",
+                ref_name,
+                entries.len()
+            ));
+    }
+    if let Some(full_name) = expand_ref_name(repo, name) {
+        if let Some(hash) = read_ref(repo, &full_name) {
+            return Ok(hash);
+        }
+    }
+    expand_short_hash(repo, name)?.ok_or(format!("Unknown revision, {}", name))
+}
+
+/// Dereferences tags (and commits into their tree) until an object of type `target` is reached.
+///
+/// When `target` is `None`, only tags are peeled.
+pub fn peel(repo: &Repository, hash: &str, target: Option<ObjectTypes>) -> Result<String, String> {
+    let mut hash = hash.to_owned();
+    loop {
+        let object = Object::read_from_sha(repo, hash.clone())?;
+        let object_type = object._type.to_string();
+        match (&target, object.header) {
+            (Some(target), _) if target.to_string() == object_type => return Ok(hash),
+            (_, ObjectHeaders::Tag(tag)) => hash = tag.object,
+            (None, _) => return Ok(hash),
+            (Some(ObjectTypes::Tree), ObjectHeaders::Commit(commit)) => hash = commit.tree,
+            (Some(target), _) => {
+                return Err(format!(
+                    "Object {} is a {}, not a {}",
+                    hash,
+                    object_type,
+                    target.to_string()
+                ))
+            }
+        }
+    }
+}
+
+pub fn peel_to_commit(repo: &Repository, hash: &str) -> Result<String, String> {
+    peel(repo, hash, Some(ObjectTypes::Commit))
+}
+
+pub fn peel_to_tree(repo: &Repository, hash: &str) -> Result<String, String> {
+    peel(repo, hash, Some(ObjectTypes::Tree))
+}
+
+/// Parents of a commit, in order
+pub fn commit_parents(repo: &Repository, hash: &str) -> Result<Vec<String>, String> {
+    match Object::read_from_sha(repo, hash.to_owned())?.header {
+        ObjectHeaders::Commit(commit) => Ok(commit.parents),
+        _ => Err(format!("Object {} is not a commit", hash)),
+    }
+}
+
+/// Resolves a revision into an object hash.
+///
+/// Supports what is commonly used from git's revision syntax: full or abbreviated hashes, ref
+/// names, `@`, `<ref>@{<n>}` (n-th prior value from the ref's log), the `^`, `^<n>`, `~<n>` and
+/// `^{<type>}` suffixes, `<rev>:<path>` and `:<path>` (stage 0 of the index).
+pub fn rev_parse(repo: &Repository, spec: &str) -> Result<String, String> {
+    if let Some((rev, path)) = spec.split_once(':') {
+        if rev.is_empty() {
+            let (stage, path) = match path.split_once(':') {
+                Some((stage, path)) if stage.len() == 1 => (
+                    stage
+                        .parse::<u8>()
+                        .map_err(|_| format!("Invalid stage in {}", spec))?,
+                    path,
+                ),
+                _ => (0, path),
+            };
+            return Index::read(repo)?
+                .entries
+                .into_iter()
+                .find(|entry| entry.path == path && entry.stage == stage)
+                .map(|entry| entry.hash)
+                .ok_or(format!("Path {} is not in the index", path));
+        }
+        let tree = TreeObject::read_from_sha(repo, &peel_to_tree(repo, &rev_parse(repo, rev)?)?)?;
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return peel_to_tree(repo, &rev_parse(repo, rev)?);
+        }
+        return lookup_path(repo, tree, path)
+            .ok_or(format!("Path {} does not exist in {}", path, rev));
+    }
+
+    let suffix_start = spec.find(['^', '~']).unwrap_or(spec.len());
+    let mut hash = resolve_name(repo, &spec[..suffix_start])?;
+    let mut suffixes = spec[suffix_start..].chars().peekable();
+    while let Some(operator) = suffixes.next() {
+        if operator == '^' && suffixes.peek() == Some(&'{') {
+            suffixes.next();
+            let object_type = suffixes
+                .by_ref()
+                .take_while(|&c| c != '}')
+                .collect::<String>();
+            hash = match object_type.as_str() {
+                "" => peel(repo, &hash, None)?,
+                "commit" => peel(repo, &hash, Some(ObjectTypes::Commit))?,
+                "tree" => peel(repo, &hash, Some(ObjectTypes::Tree))?,
+                "blob" => peel(repo, &hash, Some(ObjectTypes::Blob))?,
+                "tag" => peel(repo, &hash, Some(ObjectTypes::Tag))?,
+                _ => return Err(format!("Unknown object type in {}", spec)),
+            };
+            continue;
+        }
+        let mut digits = String::new();
+        while let Some(c) = suffixes.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        let count = if digits.is_empty() {
+            1
+        } else {
+            digits
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid revision, {}", spec))?
+        };
+        hash = peel_to_commit(repo, &hash)?;
+        match operator {
+            '^' if count == 0 => {}
+            '^' => {
+                hash = commit_parents(repo, &hash)?
+                    .into_iter()
+                    .nth(count - 1)
+                    .ok_or(format!("Revision {} does not exist", spec))?;
+            }
+            _ => {
+                for _ in 0..count {
+                    hash = commit_parents(repo, &hash)?
+                        .into_iter()
+                        .next()
+                        .ok_or(format!("Revision {} does not exist", spec))?;
+                }
+            }
+        }
+    }
+    Ok(hash)
+}
+
+fn lookup_path(repo: &Repository, tree: TreeObject, path: &str) -> Option<String> {
+    let (name, rest) = match path.split_once('/') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let entry = tree.entries.into_iter().find(|entry| entry.path == name)?;
+    match rest {
+        None => Some(entry.hash),
+        Some(rest) => lookup_path(
+            repo,
+            TreeObject::read_from_sha(repo, &entry.hash).ok()?,
+            rest,
+        ),
+    }
+}
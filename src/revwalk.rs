@@ -0,0 +1,533 @@
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use regex::Regex;
+
+use crate::{
+    diff_tree_paths, is_ancestor, list_refs, merge_bases, peel_to_commit, peel_to_tree,
+    reachable_commits, read_ref, rename_source, rev_parse, CommitInfo, Pathspec, Repository,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Most recent committer date first, as commits are walked
+    #[default]
+    Default,
+    /// No parent before all of its children, otherwise by committer date
+    Date,
+    /// No parent before all of its children, keeping lines of history together
+    Topo,
+}
+
+/// How history is simplified when it is limited to some paths. A commit is TREESAME to a parent
+/// when they do not differ in those paths.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HistorySimplification {
+    /// Merges TREESAME to a parent only have that parent walked, TREESAME commits are hidden
+    #[default]
+    Default,
+    /// Every parent is walked, merges are hidden only when TREESAME to all their parents
+    FullHistory,
+    /// Like [HistorySimplification::FullHistory], then merges that do not bring any change of
+    /// their own are removed once their parents are rewritten to the commits shown. Implies
+    /// [SortOrder::Topo] like git.
+    SimplifyMerges,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RevWalkOptions {
+    pub order: SortOrder,
+    pub reverse: bool,
+    /// Only follow the first parent of merges
+    pub first_parent: bool,
+    /// Only commits with a committer date at or after this timestamp
+    pub since: Option<i64>,
+    /// Only commits with a committer date at or before this timestamp
+    pub until: Option<i64>,
+    /// Regexes matched against the author `name <email>`, any of them has to match
+    pub authors: Vec<String>,
+    /// Regexes matched against the message, any of them has to match
+    pub grep: Vec<String>,
+    /// Only commits with at least this many parents, 2 for `--merges`
+    pub min_parents: Option<usize>,
+    /// Only commits with at most this many parents, 1 for `--no-merges`
+    pub max_parents: Option<usize>,
+    pub max_count: Option<usize>,
+    /// Only commits changing paths matching this
+    pub pathspec: Pathspec,
+    pub simplification: HistorySimplification,
+    /// Keep following the single path of `pathspec` across renames
+    pub follow: bool,
+    /// Walk the whole history first so that [RevWalk::rewritten_parents] can skip every hidden
+    /// commit, as for `--parents` and `--graph`. Like git, merges joining lines of history stay
+    /// shown even when TREESAME.
+    pub rewrite_parents: bool,
+}
+
+/// Splits revision arguments into the commits to include and the ones to exclude.
+///
+/// Understands `<rev>`, `^<rev>`, `<a>..<b>`, `<a>...<b>` (commits reachable from either but
+/// not both) and `--not`, which flips the meaning of the arguments after it.
+pub fn resolve_revision_args(
+    repo: &Repository,
+    args: &[String],
+) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut negated = false;
+    let commit = |revision: &str| {
+        let revision = if revision.is_empty() {
+            "HEAD"
+        } else {
+            revision
+        };
+        peel_to_commit(repo, &rev_parse(repo, revision)?)
+    };
+    for arg in args {
+        if arg == "--not" {
+            negated = !negated;
+            continue;
+        }
+        let (positive, negative) = match negated {
+            false => (&mut include, &mut exclude),
+            true => (&mut exclude, &mut include),
+        };
+        if let Some((a, b)) = arg.split_once("...") {
+            let (a, b) = (commit(a)?, commit(b)?);
+            negative.extend(merge_bases(repo, &a, &b)?);
+            positive.push(a);
+            positive.push(b);
+        } else if let Some((a, b)) = arg.split_once("..") {
+            negative.push(commit(a)?);
+            positive.push(commit(b)?);
+        } else if let Some(revision) = arg.strip_prefix('^') {
+            negative.push(commit(revision)?);
+        } else {
+            positive.push(commit(arg)?);
+        }
+    }
+    Ok((include, exclude))
+}
+
+/// HEAD and the commits every ref points to, for `--all`
+pub fn all_ref_commits(repo: &Repository) -> Vec<String> {
+    read_ref(repo, "HEAD")
+        .into_iter()
+        .chain(list_refs(repo, "refs/").into_iter().map(|(_, hash)| hash))
+        .filter_map(|hash| peel_to_commit(repo, &hash).ok())
+        .collect()
+}
+
+/// Walks the history reachable from a set of commits, see [RevWalkOptions] for ordering and
+/// limiting the commits returned
+pub struct RevWalk<'a> {
+    repo: &'a Repository,
+    options: RevWalkOptions,
+    uninteresting: HashSet<String>,
+    /// The excluded commits given, still relevant to history simplification like in git
+    bottoms: HashSet<String>,
+    authors: Vec<Regex>,
+    grep: Vec<Regex>,
+    queue: BinaryHeap<(i64, String)>,
+    seen: HashSet<String>,
+    /// Every commit to return, when the whole history has to be walked before returning any
+    sorted: Option<VecDeque<CommitInfo>>,
+    returned: usize,
+    /// Walked commits that are not shown, being TREESAME when limited to paths
+    hidden: HashSet<String>,
+    /// Path followed for each commit when following renames, if not the one given
+    renamed: HashMap<String, String>,
+    /// Parents walked from each commit when limited to paths, as rewritten by merge simplification
+    walked_parents: HashMap<String, Vec<String>>,
+}
+impl<'a> RevWalk<'a> {
+    pub fn new(repo: &'a Repository, starts: &[String]) -> Result<Self, String> {
+        Self::with_options(repo, starts, &[], RevWalkOptions::default())
+    }
+    /// Walks the commits reachable from `include` but not from `exclude`
+    pub fn with_options(
+        repo: &'a Repository,
+        include: &[String],
+        exclude: &[String],
+        options: RevWalkOptions,
+    ) -> Result<Self, String> {
+        let mut options = options;
+        if options.simplification == HistorySimplification::SimplifyMerges
+            && options.order == SortOrder::Default
+        {
+            options.order = SortOrder::Topo;
+        }
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern).map_err(|e| format!("Invalid regex, {}: {}", pattern, e))
+                })
+                .collect::<Result<Vec<Regex>, String>>()
+        };
+        let mut walk = Self {
+            repo,
+            uninteresting: reachable_commits(repo, exclude)?,
+            bottoms: exclude.iter().cloned().collect(),
+            authors: compile(&options.authors)?,
+            grep: compile(&options.grep)?,
+            options,
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            sorted: None,
+            returned: 0,
+            hidden: HashSet::new(),
+            renamed: HashMap::new(),
+            walked_parents: HashMap::new(),
+        };
+        for start in include {
+            walk.push(start)?;
+        }
+        if walk.options.order != SortOrder::Default
+            || walk.options.reverse
+            || (walk.options.rewrite_parents && !walk.options.pathspec.is_empty())
+        {
+            walk.sorted = Some(walk.sort()?);
+        }
+        Ok(walk)
+    }
+    fn push(&mut self, hash: &str) -> Result<(), String> {
+        if !self.uninteresting.contains(hash) && self.seen.insert(hash.to_owned()) {
+            let commit = CommitInfo::read(self.repo, hash)?;
+            self.queue.push((commit.committer.time, hash.to_owned()));
+        }
+        Ok(())
+    }
+    fn parents<'c>(&self, commit: &'c CommitInfo) -> &'c [String] {
+        match self.options.first_parent {
+            true => &commit.parents[..commit.parents.len().min(1)],
+            false => &commit.parents,
+        }
+    }
+    /// Next commit of the walk by committer date, before any filtering
+    fn walk_next(&mut self) -> Option<Result<CommitInfo, String>> {
+        let (_, hash) = self.queue.pop()?;
+        let commit = match CommitInfo::read(self.repo, &hash) {
+            Ok(commit) => commit,
+            Err(e) => return Some(Err(e)),
+        };
+        let parents = match self.options.pathspec.is_empty() {
+            true => Ok(self.parents(&commit).to_vec()),
+            false => self.limit_to_paths(&commit).inspect(|parents| {
+                self.walked_parents
+                    .insert(commit.hash.clone(), parents.clone());
+            }),
+        };
+        let pushed =
+            parents.and_then(|parents| parents.iter().try_for_each(|parent| self.push(parent)));
+        Some(pushed.map(|_| commit))
+    }
+    fn pathspec_of(&self, hash: &str) -> Pathspec {
+        match self.renamed.get(hash) {
+            Some(path) => Pathspec::literal(path),
+            None => self.options.pathspec.clone(),
+        }
+    }
+    /// Whether the commit's tree does not differ from `parent_tree` in the paths of `pathspec`
+    fn tree_same(
+        &self,
+        tree: &str,
+        parent_tree: Option<&str>,
+        pathspec: &Pathspec,
+    ) -> Result<bool, String> {
+        Ok(diff_tree_paths(self.repo, parent_tree, Some(tree), pathspec)?.is_empty())
+    }
+    /// Hides the commit if TREESAME and returns the parents to walk, see [HistorySimplification]
+    fn limit_to_paths(&mut self, commit: &CommitInfo) -> Result<Vec<String>, String> {
+        let pathspec = self.pathspec_of(&commit.hash);
+        let parents = self.parents(commit).to_vec();
+        if parents.is_empty() {
+            if self.tree_same(&commit.tree, None, &pathspec)? {
+                self.hidden.insert(commit.hash.clone());
+            }
+            return Ok(parents);
+        }
+        let mut same_parents = Vec::new();
+        for parent in &parents {
+            let parent_tree = peel_to_tree(self.repo, parent)?;
+            if self.tree_same(&commit.tree, Some(&parent_tree), &pathspec)? {
+                same_parents.push(parent.clone());
+            } else if let Some(path) = self.followed_path(&commit.hash) {
+                self.follow_rename(&commit.tree, parent, &parent_tree, &path)?;
+            }
+        }
+        if self.options.follow {
+            // Like git, merges have no diff of their own to show when following a file
+            if parents.len() > 1 {
+                self.hidden.insert(commit.hash.clone());
+            }
+            if let Some(path) = self.renamed.get(&commit.hash).cloned() {
+                for parent in &parents {
+                    if !self.renamed.contains_key(parent) {
+                        self.renamed.insert(parent.clone(), path.clone());
+                    }
+                }
+            }
+        }
+        if self.options.simplification == HistorySimplification::Default {
+            if let Some(parent) = same_parents.iter().find(|parent| self.is_relevant(parent)) {
+                self.hidden.insert(commit.hash.clone());
+                return Ok(vec![parent.clone()]);
+            }
+        }
+        // Irrelevant parents only decide whether the commit is TREESAME when there are no others
+        let mut considered = parents
+            .iter()
+            .filter(|parent| self.is_relevant(parent))
+            .collect::<Vec<&String>>();
+        if considered.is_empty() {
+            considered = parents.iter().collect();
+        }
+        if considered
+            .iter()
+            .all(|parent| same_parents.contains(*parent))
+        {
+            self.hidden.insert(commit.hash.clone());
+        }
+        Ok(parents)
+    }
+    /// Path followed in the commit when following renames
+    fn followed_path(&self, hash: &str) -> Option<String> {
+        if !self.options.follow {
+            return None;
+        }
+        match self.renamed.get(hash) {
+            Some(path) => Some(path.clone()),
+            None => self.options.pathspec.single_path().map(str::to_owned),
+        }
+    }
+    /// Follows `path` in `parent` under its previous name when the commit renamed it there
+    fn follow_rename(
+        &mut self,
+        tree: &str,
+        parent: &str,
+        parent_tree: &str,
+        path: &str,
+    ) -> Result<(), String> {
+        if let Some(source) = rename_source(self.repo, parent_tree, tree, path)? {
+            self.renamed.insert(parent.to_owned(), source);
+        }
+        Ok(())
+    }
+    /// Rewrites parents to the commits shown, oldest first, hiding the commits that end up with
+    /// a single parent they are TREESAME to, see [HistorySimplification::SimplifyMerges]
+    fn simplify_merges(&mut self, oldest_first: &[CommitInfo]) -> Result<(), String> {
+        // Closest commit kept for each walked commit, itself when it is kept
+        let mut simplified: HashMap<String, String> = HashMap::new();
+        let mut roots = HashSet::new();
+        for commit in oldest_first {
+            let pathspec = self.pathspec_of(&commit.hash);
+            let mut parents: Vec<String> = Vec::new();
+            for parent in self.parents(commit) {
+                let parent = simplified.get(parent).unwrap_or(parent);
+                if !parents.contains(parent) {
+                    parents.push(parent.clone());
+                }
+            }
+            let mut same_parents = Vec::new();
+            for parent in &parents {
+                let parent_tree = peel_to_tree(self.repo, parent)?;
+                if self.tree_same(&commit.tree, Some(&parent_tree), &pathspec)? {
+                    same_parents.push(parent.clone());
+                }
+            }
+            let mut kept = Vec::new();
+            for parent in &parents {
+                let mut redundant = roots.contains(parent) && self.hidden.contains(parent);
+                for other in &parents {
+                    if !redundant && other != parent {
+                        redundant = is_ancestor(self.repo, parent, other)?;
+                    }
+                }
+                if !redundant {
+                    kept.push(parent.clone());
+                }
+            }
+            if !same_parents.is_empty() && !kept.iter().any(|p| same_parents.contains(p)) {
+                kept.push(same_parents[0].clone());
+            }
+            if commit.parents.is_empty() {
+                roots.insert(commit.hash.clone());
+            }
+            let replaced_by = match kept.as_slice() {
+                [parent] if same_parents.contains(parent) => parent.clone(),
+                [] | [_] if self.hidden.contains(&commit.hash) => continue,
+                _ => commit.hash.clone(),
+            };
+            if replaced_by == commit.hash {
+                self.hidden.remove(&commit.hash);
+                self.walked_parents.insert(commit.hash.clone(), kept);
+            } else {
+                self.hidden.insert(commit.hash.clone());
+                self.walked_parents
+                    .insert(commit.hash.clone(), vec![replaced_by.clone()]);
+            }
+            simplified.insert(commit.hash.clone(), replaced_by);
+        }
+        Ok(())
+    }
+    /// Parents of a returned commit, skipping the commits hidden by path limiting like git does
+    /// for `--parents` and `--graph`
+    pub fn rewritten_parents(&self, commit: &CommitInfo) -> Vec<String> {
+        let walked = match self.walked_parents.get(&commit.hash) {
+            Some(parents) => parents.as_slice(),
+            None => self.parents(commit),
+        };
+        let mut parents: Vec<String> = Vec::new();
+        for parent in walked {
+            let mut parent = Some(parent);
+            while let Some(hash) = parent.filter(|hash| self.hidden.contains(*hash)) {
+                // Stops at merges with several parents in the walk, they are shown
+                let walked = &self.walked_parents[hash];
+                let mut relevant = walked.iter().filter(|parent| self.is_relevant(parent));
+                parent = match (walked.len(), relevant.next(), relevant.next()) {
+                    (0, _, _) => None,
+                    (1, _, _) => walked.first(),
+                    (_, Some(parent), None) => Some(parent),
+                    _ => break,
+                };
+            }
+            if let Some(parent) = parent.filter(|parent| !parents.contains(parent)) {
+                parents.push(parent.clone());
+            }
+        }
+        parents
+    }
+    /// Whether the commit is one the walk returns, or would return without `max_count`
+    pub fn is_shown(&self, hash: &str) -> Result<bool, String> {
+        Ok(!self.uninteresting.contains(hash) && self.matches(&CommitInfo::read(self.repo, hash)?))
+    }
+    fn is_relevant(&self, hash: &str) -> bool {
+        !self.uninteresting.contains(hash) || self.bottoms.contains(hash)
+    }
+    /// Whether a commit hidden as TREESAME is still shown, being a merge joining lines of history
+    fn joins_history(&self, hash: &str) -> bool {
+        self.options.rewrite_parents
+            && self.walked_parents.get(hash).is_some_and(|parents| {
+                parents
+                    .iter()
+                    .filter(|parent| self.is_relevant(parent))
+                    .count()
+                    >= 2
+            })
+    }
+    fn matches(&self, commit: &CommitInfo) -> bool {
+        let options = &self.options;
+        let timestamp = commit.committer.time;
+        let author = format!("{} <{}>", commit.author.name, commit.author.email);
+        (!self.hidden.contains(&commit.hash) || self.joins_history(&commit.hash))
+            && options.since.is_none_or(|since| timestamp >= since)
+            && options.until.is_none_or(|until| timestamp <= until)
+            && options
+                .min_parents
+                .is_none_or(|min| commit.parents.len() >= min)
+            && options
+                .max_parents
+                .is_none_or(|max| commit.parents.len() <= max)
+            && (self.authors.is_empty() || self.authors.iter().any(|re| re.is_match(&author)))
+            && (self.grep.is_empty() || self.grep.iter().any(|re| re.is_match(&commit.message)))
+    }
+    /// Walks the whole history, then orders and limits it
+    fn sort(&mut self) -> Result<VecDeque<CommitInfo>, String> {
+        let mut commits = Vec::new();
+        while let Some(commit) = self.walk_next() {
+            commits.push(commit?);
+        }
+        if self.options.simplification == HistorySimplification::SimplifyMerges {
+            let mut oldest_first = self.sort_by_children(commits.clone());
+            oldest_first.reverse();
+            self.simplify_merges(&oldest_first)?;
+        }
+        if self.options.order != SortOrder::Default {
+            commits = self.sort_by_children(commits);
+        }
+        let mut commits = commits
+            .into_iter()
+            .filter(|commit| self.matches(commit))
+            .take(self.options.max_count.unwrap_or(usize::MAX))
+            .collect::<VecDeque<CommitInfo>>();
+        if self.options.reverse {
+            commits = commits.into_iter().rev().collect();
+        }
+        Ok(commits)
+    }
+    /// Orders commits (given by date) so that children always come before their parents
+    fn sort_by_children(&self, commits: Vec<CommitInfo>) -> Vec<CommitInfo> {
+        let mut children_left: HashMap<String, usize> = commits
+            .iter()
+            .map(|commit| (commit.hash.clone(), 0))
+            .collect();
+        for commit in &commits {
+            for parent in self.parents(commit) {
+                if let Some(count) = children_left.get_mut(parent) {
+                    *count += 1;
+                }
+            }
+        }
+        let tips = commits
+            .iter()
+            .filter(|commit| children_left[&commit.hash] == 0)
+            .map(|commit| commit.hash.clone())
+            .collect::<Vec<String>>();
+        let mut by_hash: HashMap<String, CommitInfo> = commits
+            .into_iter()
+            .map(|commit| (commit.hash.clone(), commit))
+            .collect();
+        let mut sorted = Vec::new();
+        // Topo order works depth first, the last parent pushed being shown first like git
+        let mut stack = tips.iter().rev().cloned().collect::<Vec<String>>();
+        let mut heap = tips
+            .iter()
+            .map(|hash| (by_hash[hash].committer.time, hash.clone()))
+            .collect::<BinaryHeap<(i64, String)>>();
+        loop {
+            let hash = match self.options.order {
+                SortOrder::Topo => stack.pop(),
+                _ => heap.pop().map(|(_, hash)| hash),
+            };
+            let Some(hash) = hash else {
+                break;
+            };
+            let commit = by_hash.remove(&hash).expect("commit is walked once");
+            for parent in self.parents(&commit) {
+                let Some(count) = children_left.get_mut(parent) else {
+                    continue;
+                };
+                *count -= 1;
+                if *count == 0 {
+                    match self.options.order {
+                        SortOrder::Topo => stack.push(parent.clone()),
+                        _ => heap.push((by_hash[parent].committer.time, parent.clone())),
+                    }
+                }
+            }
+            sorted.push(commit);
+        }
+        sorted
+    }
+}
+impl Iterator for RevWalk<'_> {
+    type Item = Result<CommitInfo, String>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sorted) = &mut self.sorted {
+            return sorted.pop_front().map(Ok);
+        }
+        if self.returned >= self.options.max_count.unwrap_or(usize::MAX) {
+            return None;
+        }
+        loop {
+            match self.walk_next()? {
+                Ok(commit) if !self.matches(&commit) => continue,
+                Ok(commit) => {
+                    self.returned += 1;
+                    return Some(Ok(commit));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
@@ -0,0 +1,595 @@
+use std::{collections::VecDeque, fs, path::PathBuf};
+
+use crate::{
+    create_path, merge_trees, peel_to_tree, read_ref, reset_merge, update_ref, Commit, Index,
+    Object, ObjectHeaders, ObjectTypes, Repository, Signature, TreeMergeOptions, TreeMergeResult,
+    TreeObject,
+};
+
+const SEQUENCER_DIR: &str = "sequencer";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayAction {
+    Pick,
+    Revert,
+}
+impl ReplayAction {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Pick => "pick",
+            Self::Revert => "revert",
+        }
+    }
+    fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "pick" => Ok(Self::Pick),
+            "revert" => Ok(Self::Revert),
+            _ => Err(format!("Unknown sequencer action, {}", name)),
+        }
+    }
+    /// File in the gitdir recording the commit being replayed while stopped on conflicts
+    pub fn head_file(&self) -> &'static str {
+        match self {
+            Self::Pick => "CHERRY_PICK_HEAD",
+            Self::Revert => "REVERT_HEAD",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReplayOptions {
+    /// Appends `(cherry picked from commit <hash>)` to the message of picked commits
+    pub record_origin: bool,
+    /// Parent (starting at 1) to replay merge commits against
+    pub mainline: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SequencerReport {
+    /// Commits created, in order
+    pub created: Vec<String>,
+    /// Commit the sequencer stopped at, with the reasons (conflicts) why
+    pub stopped: Option<(String, Vec<String>)>,
+}
+
+/// First line of a commit message
+pub fn commit_subject(message: &str) -> &str {
+    message.lines().next().unwrap_or("")
+}
+
+fn state_path(repo: &Repository, name: &str) -> PathBuf {
+    create_path(&repo.gitdir, vec![name.to_owned()])
+}
+
+fn sequencer_path(repo: &Repository, name: &str) -> PathBuf {
+    create_path(
+        &repo.gitdir,
+        vec![String::from(SEQUENCER_DIR), name.to_owned()],
+    )
+}
+
+pub(crate) fn read_commit(repo: &Repository, hash: &str) -> Result<Commit, String> {
+    match Object::read_from_sha(repo, hash.to_owned())?.header {
+        ObjectHeaders::Commit(commit) => Ok(commit),
+        _ => Err(format!("Object {} is not a commit", hash)),
+    }
+}
+
+/// Creates a commit on top of HEAD and moves HEAD to it
+pub fn commit_on_head(
+    repo: &Repository,
+    tree: String,
+    author: &Signature,
+    message: String,
+) -> Result<String, String> {
+    let parents = read_ref(repo, "HEAD").into_iter().collect();
+    let committer = Signature::committer(repo)?;
+    let commit = Object {
+        header: ObjectHeaders::new_commit(tree, parents, author, &committer, message),
+        _type: ObjectTypes::Commit,
+    }
+    .write_to_repo(repo)?;
+    update_ref(repo, "HEAD", &commit)?;
+    Ok(commit)
+}
+
+/// Parent `commit` is replayed against, `mainline` choosing it (starting at 1) for merges
+fn replay_parent(
+    header: &Commit,
+    commit: &str,
+    mainline: Option<usize>,
+) -> Result<Option<String>, String> {
+    let parents = &header.parents;
+    match (parents.len(), mainline) {
+        (0, None) => Ok(None),
+        (1, None) => Ok(Some(parents[0].clone())),
+        (0 | 1, Some(_)) => Err(format!(
+            "Mainline was specified but commit {} is not a merge",
+            commit
+        )),
+        (_, None) => Err(format!(
+            "Commit {} is a merge but no -m option was given",
+            commit
+        )),
+        (_, Some(mainline)) => parents
+            .get(mainline.wrapping_sub(1))
+            .cloned()
+            .map(Some)
+            .ok_or(format!(
+                "Commit {} does not have parent {}",
+                commit, mainline
+            )),
+    }
+}
+
+/// Three-way merges the changes introduced by `commit` (or their reverse for reverts) onto HEAD.
+///
+/// The result is written into the index and the worktree, nothing is committed.
+pub fn apply_commit_changes(
+    repo: &Repository,
+    action: ReplayAction,
+    commit: &str,
+    mainline: Option<usize>,
+) -> Result<TreeMergeResult, String> {
+    let header = read_commit(repo, commit)?;
+    let parent = replay_parent(&header, commit, mainline)?;
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    let head_tree = peel_to_tree(repo, &head)?;
+    let commit_tree = peel_to_tree(repo, commit)?;
+    let parent_tree = match &parent {
+        Some(parent) => peel_to_tree(repo, parent)?,
+        // Root commits are replayed against the empty tree
+        None => TreeObject::write_from_nodes(repo, &[])?,
+    };
+    let label = format!("{} ({})", &commit[..7], commit_subject(&header.message));
+    let (base, theirs, theirs_label) = match action {
+        ReplayAction::Pick => (parent_tree, commit_tree, label),
+        ReplayAction::Revert => (commit_tree, parent_tree, format!("parent of {}", label)),
+    };
+    let options = TreeMergeOptions {
+        theirs_label,
+        base_label: String::from("parent of HEAD"),
+        ..Default::default()
+    };
+    let result = merge_trees(repo, Some(&base), &head_tree, &theirs, &options)?;
+    result.checkout(repo, &head_tree)?;
+    Ok(result)
+}
+
+/// Message of the commit created when replaying `commit`
+fn replay_message(
+    repo: &Repository,
+    action: ReplayAction,
+    commit: &str,
+    options: &ReplayOptions,
+) -> Result<String, String> {
+    let header = read_commit(repo, commit)?;
+    let message = header.message;
+    Ok(match action {
+        ReplayAction::Pick if options.record_origin => format!(
+            "{}\n\n(cherry picked from commit {})\n",
+            message.trim_end(),
+            commit
+        ),
+        ReplayAction::Pick => message,
+        ReplayAction::Revert => {
+            let parents = &header.parents;
+            let mut message = format!(
+                "Revert \"{}\"\n\nThis reverts commit {}",
+                commit_subject(&message),
+                commit
+            );
+            if parents.len() > 1 {
+                let mainline = options.mainline.unwrap_or(1);
+                message.push_str(&format!(
+                    ", reversing\nchanges made to {}",
+                    parents[mainline - 1]
+                ));
+            }
+            message + ".\n"
+        }
+    })
+}
+
+/// Author of the commit created when replaying `commit`, picks keep the original author
+fn replay_author(
+    repo: &Repository,
+    action: ReplayAction,
+    commit: &str,
+) -> Result<Signature, String> {
+    match action {
+        ReplayAction::Pick => Ok(read_commit(repo, commit)?.author),
+        ReplayAction::Revert => Signature::author(repo),
+    }
+}
+
+/// Replays a single commit onto HEAD and commits the result.
+///
+/// Returns the new commit, or the reasons the replay stopped (conflicts, empty result). When
+/// stopped, the commit being replayed is recorded in [ReplayAction::head_file] and its message
+/// in `MERGE_MSG`.
+pub fn replay_commit(
+    repo: &Repository,
+    action: ReplayAction,
+    commit: &str,
+    options: &ReplayOptions,
+) -> Result<Result<String, Vec<String>>, String> {
+    let result = apply_commit_changes(repo, action, commit, options.mainline)?;
+    let message = replay_message(repo, action, commit, options)?;
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    let mut stop_reasons = result.conflicts.clone();
+    if result.is_clean() && result.write_tree(repo)? == peel_to_tree(repo, &head)? {
+        stop_reasons.push(format!(
+            "The previous {} is now empty, possibly due to conflict resolution.",
+            match action {
+                ReplayAction::Pick => "cherry-pick",
+                ReplayAction::Revert => "revert",
+            }
+        ));
+    }
+    if !stop_reasons.is_empty() {
+        fs::write(
+            state_path(repo, action.head_file()),
+            format!("{}\n", commit),
+        )
+        .map_err(|e| format!("Error writing {}: {}", action.head_file(), e))?;
+        fs::write(state_path(repo, "MERGE_MSG"), message)
+            .map_err(|e| format!("Error writing MERGE_MSG: {}", e))?;
+        return Ok(Err(stop_reasons));
+    }
+    let author = replay_author(repo, action, commit)?;
+    Ok(Ok(commit_on_head(
+        repo,
+        result.write_tree(repo)?,
+        &author,
+        message,
+    )?))
+}
+
+pub fn sequencer_in_progress(repo: &Repository) -> bool {
+    state_path(repo, SEQUENCER_DIR).exists()
+}
+
+fn write_todo(repo: &Repository, todo: &VecDeque<(ReplayAction, String)>) -> Result<(), String> {
+    let content = todo
+        .iter()
+        .map(|(action, commit)| format!("{} {}\n", action.name(), commit))
+        .collect::<String>();
+    fs::write(sequencer_path(repo, "todo"), content)
+        .map_err(|e| format!("Error writing sequencer todo: {}", e))
+}
+
+fn read_options(repo: &Repository) -> ReplayOptions {
+    let mut options = ReplayOptions::default();
+    for line in fs::read_to_string(sequencer_path(repo, "opts"))
+        .unwrap_or_default()
+        .lines()
+    {
+        match line.split_once(" = ") {
+            Some(("record-origin", value)) => options.record_origin = value == "true",
+            Some(("mainline", value)) => options.mainline = value.parse().ok(),
+            _ => {}
+        }
+    }
+    options
+}
+
+fn run_todo(
+    repo: &Repository,
+    mut todo: VecDeque<(ReplayAction, String)>,
+    options: &ReplayOptions,
+    mut report: SequencerReport,
+) -> Result<SequencerReport, String> {
+    while let Some((action, commit)) = todo.pop_front() {
+        write_todo(repo, &todo)?;
+        match replay_commit(repo, action, &commit, options)? {
+            Ok(created) => report.created.push(created),
+            Err(reasons) => {
+                report.stopped = Some((commit, reasons));
+                return Ok(report);
+            }
+        }
+    }
+    fs::remove_dir_all(state_path(repo, SEQUENCER_DIR))
+        .map_err(|e| format!("Error removing sequencer state: {}", e))?;
+    Ok(report)
+}
+
+/// Replays `commits` onto HEAD one after the other, stopping at the first conflict
+pub fn sequencer_start(
+    repo: &Repository,
+    action: ReplayAction,
+    commits: &[String],
+    options: &ReplayOptions,
+) -> Result<SequencerReport, String> {
+    if sequencer_in_progress(repo) {
+        return Err(String::from(
+            "A cherry-pick or revert is already in progress, use --continue or --abort",
+        ));
+    }
+    let head = read_ref(repo, "HEAD").ok_or("HEAD does not point to a commit yet")?;
+    // What would stop the sequence before anything is replayed is checked before recording it
+    for commit in commits {
+        replay_parent(&read_commit(repo, commit)?, commit, options.mainline)?;
+    }
+    let index = Index::read(repo)?;
+    if index.has_conflicts() || index.write_tree(repo)? != peel_to_tree(repo, &head)? {
+        return Err(format!(
+            "your local changes would be overwritten by {}.",
+            match action {
+                ReplayAction::Pick => "cherry-pick",
+                ReplayAction::Revert => "revert",
+            }
+        ));
+    }
+    fs::create_dir_all(state_path(repo, SEQUENCER_DIR))
+        .map_err(|e| format!("Error creating sequencer state: {}", e))?;
+    fs::write(sequencer_path(repo, "head"), format!("{}\n", head))
+        .map_err(|e| format!("Error writing sequencer head: {}", e))?;
+    let mut opts = format!("[options]\nrecord-origin = {}\n", options.record_origin);
+    if let Some(mainline) = options.mainline {
+        opts.push_str(&format!("mainline = {}\n", mainline));
+    }
+    fs::write(sequencer_path(repo, "opts"), opts)
+        .map_err(|e| format!("Error writing sequencer options: {}", e))?;
+    let todo = commits
+        .iter()
+        .map(|commit| (action, commit.clone()))
+        .collect();
+    let report = run_todo(repo, todo, options, SequencerReport::default());
+    // Failing to apply the first commit leaves everything untouched, and nothing in progress
+    if report.is_err() && read_ref(repo, "HEAD").as_deref() == Some(head.as_str()) {
+        let _ = fs::remove_dir_all(state_path(repo, SEQUENCER_DIR));
+    }
+    report
+}
+
+/// Commits the resolved conflicts of the stopped commit, then replays the remaining ones
+pub fn sequencer_continue(repo: &Repository) -> Result<SequencerReport, String> {
+    if !sequencer_in_progress(repo) {
+        return Err(String::from("No cherry-pick or revert in progress"));
+    }
+    let options = read_options(repo);
+    let mut report = SequencerReport::default();
+    for action in [ReplayAction::Pick, ReplayAction::Revert] {
+        let Ok(commit) = fs::read_to_string(state_path(repo, action.head_file())) else {
+            continue;
+        };
+        let index = Index::read(repo)?;
+        if index.has_conflicts() {
+            return Err(format!(
+                "Committing is not possible because you have unmerged files: {}",
+                index.conflicted_paths().join(", ")
+            ));
+        }
+        let message = fs::read_to_string(state_path(repo, "MERGE_MSG"))
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let author = replay_author(repo, action, commit.trim())?;
+        report.created.push(commit_on_head(
+            repo,
+            index.write_tree(repo)?,
+            &author,
+            message.trim_end().to_owned() + "\n",
+        )?);
+        for name in [action.head_file(), "MERGE_MSG"] {
+            let _ = fs::remove_file(state_path(repo, name));
+        }
+    }
+    let todo = fs::read_to_string(sequencer_path(repo, "todo"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(action, commit)| Ok((ReplayAction::from_name(action)?, commit.to_owned())))
+        .collect::<Result<VecDeque<(ReplayAction, String)>, String>>()?;
+    run_todo(repo, todo, &options, report)
+}
+
+/// Stops the sequence, restoring HEAD and the index to where they were before it started.
+///
+/// Only the worktree files the sequence changed are restored, local changes to the others are
+/// kept.
+pub fn sequencer_abort(repo: &Repository) -> Result<(), String> {
+    let head = fs::read_to_string(sequencer_path(repo, "head"))
+        .map_err(|_| String::from("No cherry-pick or revert in progress"))?;
+    let head = head.trim();
+    reset_merge(repo, &peel_to_tree(repo, head)?)?;
+    update_ref(repo, "HEAD", head)?;
+    for action in [ReplayAction::Pick, ReplayAction::Revert] {
+        let _ = fs::remove_file(state_path(repo, action.head_file()));
+    }
+    let _ = fs::remove_file(state_path(repo, "MERGE_MSG"));
+    fs::remove_dir_all(state_path(repo, SEQUENCER_DIR))
+        .map_err(|e| format!("Error removing sequencer state: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repository::test::TempRepo;
+
+    const LINES: &str = "1\n2\n3\n4\n5\n6\n";
+
+    fn head_commit(repo: &TempRepo) -> Commit {
+        read_commit(repo, &read_ref(repo, "HEAD").unwrap()).unwrap()
+    }
+
+    fn start(repo: &TempRepo, action: ReplayAction, commit: &str, options: &ReplayOptions) {
+        let report = sequencer_start(repo, action, &[commit.to_owned()], options).unwrap();
+        assert_eq!(report.created.len(), 1);
+        assert!(report.stopped.is_none());
+        assert!(!sequencer_in_progress(repo));
+    }
+
+    #[test]
+    fn test_pick() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("f", LINES), ("g", "g\n")], &[], "Base\n");
+        let picked = repo.commit(&[("f", LINES), ("g", "g picked\n")], &[&base], "Pick\n");
+        let head = repo.commit(
+            &[("f", "1h\n2\n3\n4\n5\n6\n"), ("g", "g\n")],
+            &[&base],
+            "Head\n",
+        );
+        repo.checkout(&head);
+
+        start(
+            &repo,
+            ReplayAction::Pick,
+            &picked,
+            &ReplayOptions::default(),
+        );
+        let commit = head_commit(&repo);
+        assert_eq!(commit.parents, std::slice::from_ref(&head));
+        assert_eq!(commit.message, "Pick\n");
+        assert_eq!(repo.read("f").unwrap(), "1h\n2\n3\n4\n5\n6\n");
+        assert_eq!(repo.read("g").unwrap(), "g picked\n");
+
+        repo.checkout(&head);
+        let options = ReplayOptions {
+            record_origin: true,
+            ..Default::default()
+        };
+        start(&repo, ReplayAction::Pick, &picked, &options);
+        assert_eq!(
+            head_commit(&repo).message,
+            format!("Pick\n\n(cherry picked from commit {})\n", picked)
+        );
+    }
+
+    #[test]
+    fn test_revert() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("f", LINES)], &[], "Base\n");
+        let reverted = repo.commit(&[("f", "1r\n2\n3\n4\n5\n6\n")], &[&base], "Change\n");
+        let head = repo.commit(&[("f", "1r\n2\n3\n4\n5\n6h\n")], &[&reverted], "Head\n");
+        repo.checkout(&head);
+
+        start(
+            &repo,
+            ReplayAction::Revert,
+            &reverted,
+            &ReplayOptions::default(),
+        );
+        assert_eq!(
+            head_commit(&repo).message,
+            format!("Revert \"Change\"\n\nThis reverts commit {}.\n", reverted)
+        );
+        assert_eq!(repo.read("f").unwrap(), "1\n2\n3\n4\n5\n6h\n");
+    }
+
+    #[test]
+    fn test_mainline() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("f", "f\n"), ("g", "g\n")], &[], "Base\n");
+        let side = repo.commit(&[("f", "f\n"), ("g", "g side\n")], &[&base], "Side\n");
+        let main = repo.commit(&[("f", "f main\n"), ("g", "g\n")], &[&base], "Main\n");
+        let merge = repo.commit(
+            &[("f", "f main\n"), ("g", "g side\n")],
+            &[&main, &side],
+            "Merge\n",
+        );
+        repo.checkout(&base);
+
+        // Against its first parent, the merge brings the changes of the side branch
+        let options = ReplayOptions {
+            mainline: Some(1),
+            ..Default::default()
+        };
+        start(&repo, ReplayAction::Pick, &merge, &options);
+        assert_eq!(repo.read("f").unwrap(), "f\n");
+        assert_eq!(repo.read("g").unwrap(), "g side\n");
+
+        repo.checkout(&base);
+        let options = ReplayOptions {
+            mainline: Some(2),
+            ..Default::default()
+        };
+        start(&repo, ReplayAction::Pick, &merge, &options);
+        assert_eq!(repo.read("f").unwrap(), "f main\n");
+        assert_eq!(repo.read("g").unwrap(), "g\n");
+
+        repo.checkout(&merge);
+        let options = ReplayOptions {
+            mainline: Some(1),
+            ..Default::default()
+        };
+        start(&repo, ReplayAction::Revert, &merge, &options);
+        assert_eq!(
+            head_commit(&repo).message,
+            format!(
+                "Revert \"Merge\"\n\nThis reverts commit {}, reversing\nchanges made to {}.\n",
+                merge, main
+            )
+        );
+        assert_eq!(repo.read("g").unwrap(), "g\n");
+    }
+
+    #[test]
+    fn test_conflict() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("f", LINES)], &[], "Base\n");
+        let picked = repo.commit(&[("f", "1p\n2\n3\n4\n5\n6\n")], &[&base], "Pick\n");
+        let head = repo.commit(&[("f", "1h\n2\n3\n4\n5\n6\n")], &[&base], "Head\n");
+        repo.checkout(&head);
+
+        let options = ReplayOptions::default();
+        let report = sequencer_start(
+            &repo,
+            ReplayAction::Pick,
+            std::slice::from_ref(&picked),
+            &options,
+        );
+        let report = report.unwrap();
+        assert!(report.created.is_empty());
+        assert_eq!(
+            report.stopped,
+            Some((
+                picked.clone(),
+                vec![String::from("CONFLICT (content): Merge conflict in f")]
+            ))
+        );
+        assert!(sequencer_in_progress(&repo));
+        let state = |name: &str| fs::read_to_string(repo.gitdir.join(name)).unwrap();
+        assert_eq!(state("CHERRY_PICK_HEAD"), format!("{}\n", picked));
+        assert_eq!(state("MERGE_MSG"), "Pick\n");
+        assert!(repo
+            .read("f")
+            .unwrap()
+            .starts_with("<<<<<<< HEAD\n1h\n=======\n1p\n"));
+
+        sequencer_abort(&repo).unwrap();
+        assert!(!sequencer_in_progress(&repo));
+        assert!(!repo.gitdir.join("CHERRY_PICK_HEAD").exists());
+        assert_eq!(read_ref(&repo, "HEAD"), Some(head));
+        assert_eq!(repo.read("f").unwrap(), "1h\n2\n3\n4\n5\n6\n");
+    }
+
+    #[test]
+    fn test_failed_pick_leaves_no_state() {
+        let repo = TempRepo::new();
+        let base = repo.commit(&[("f", "base\n")], &[], "m\n");
+        let picked = repo.commit(&[("f", "picked\n")], &[&base], "m\n");
+        repo.checkout(&base);
+        repo.write("f", "local edit\n");
+
+        let options = ReplayOptions::default();
+        let error = sequencer_start(
+            &repo,
+            ReplayAction::Pick,
+            std::slice::from_ref(&picked),
+            &options,
+        );
+        assert!(error.unwrap_err().contains("would be overwritten"));
+        assert!(!sequencer_in_progress(&repo));
+        assert_eq!(repo.read("f").unwrap(), "local edit\n");
+        assert_eq!(read_ref(&repo, "HEAD"), Some(base));
+
+        let merge = repo.commit(&[("f", "merge\n")], &[&picked, &picked], "m\n");
+        assert!(sequencer_start(&repo, ReplayAction::Pick, &[merge], &options).is_err());
+        assert!(!sequencer_in_progress(&repo));
+    }
+}
@@ -0,0 +1,123 @@
+use std::{env, fmt};
+
+use crate::{
+    pretty::{format_timezone, now, parse_date_and_offset, timezone_offset},
+    Repository,
+};
+
+/// `name <email> <time> <timezone>` as found in the `author`, `committer` and `tagger` headers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the unix epoch
+    pub time: i64,
+    /// Offset of the timezone from UTC in minutes
+    pub tz_offset: i32,
+    /// The timezone is written `-0000`, an unknown timezone rather than UTC
+    pub negative_utc: bool,
+}
+impl Signature {
+    /// Parses a header value, the name being everything before ` <`
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid identity, {}", line);
+        let (name, rest) = line.split_once('<').ok_or_else(invalid)?;
+        let (email, date) = rest.split_once('>').ok_or_else(invalid)?;
+        let (time, timezone) = date
+            .strip_prefix(' ')
+            .and_then(|date| date.split_once(' '))
+            .ok_or_else(invalid)?;
+        // Timestamps written any other way than `i64` formats them would not round trip
+        let time = time
+            .parse::<i64>()
+            .ok()
+            .filter(|parsed| parsed.to_string() == time);
+        let valid_timezone = timezone.len() == 5
+            && timezone.starts_with(['+', '-'])
+            && timezone[1..].bytes().all(|digit| digit.is_ascii_digit());
+        match (time, valid_timezone) {
+            (Some(time), true) => Ok(Self {
+                name: name.strip_suffix(' ').unwrap_or(name).to_owned(),
+                email: email.to_owned(),
+                time,
+                tz_offset: (timezone_offset(timezone) / 60) as i32,
+                negative_utc: timezone == "-0000",
+            }),
+            _ => Err(invalid()),
+        }
+    }
+    /// `+hhmm`/`-hhmm` timezone, as written in headers
+    pub fn timezone(&self) -> String {
+        match self.negative_utc {
+            true => String::from("-0000"),
+            false => format_timezone(self.tz_offset),
+        }
+    }
+    /// Author of new commits: `GIT_AUTHOR_NAME`, `GIT_AUTHOR_EMAIL` and `GIT_AUTHOR_DATE`,
+    /// falling back to `user.name`, `user.email` and the current time
+    pub fn author(repo: &Repository) -> Result<Self, String> {
+        Self::from_env(repo, "AUTHOR")
+    }
+    /// Committer of new commits and tagger of new tags: `GIT_COMMITTER_NAME`,
+    /// `GIT_COMMITTER_EMAIL` and `GIT_COMMITTER_DATE`, falling back like [Signature::author]
+    pub fn committer(repo: &Repository) -> Result<Self, String> {
+        Self::from_env(repo, "COMMITTER")
+    }
+    fn from_env(repo: &Repository, role: &str) -> Result<Self, String> {
+        let var = |field: &str| env::var(format!("GIT_{}_{}", role, field)).ok();
+        let (time, tz_offset) = match var("DATE") {
+            Some(date) => parse_date_and_offset(&date)?,
+            None => (now(), 0),
+        };
+        Ok(Self {
+            name: var("NAME").unwrap_or_else(|| repo.config.user.name.clone()),
+            email: var("EMAIL").unwrap_or_else(|| repo.config.user.email.clone()),
+            time,
+            tz_offset,
+            negative_utc: false,
+        })
+    }
+}
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.time,
+            self.timezone()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let line = "Jane Doe <jane@x.x> 1700000000 -0530";
+        let signature = Signature::parse(line).unwrap();
+        assert_eq!(signature.time, 1700000000);
+        assert_eq!(signature.tz_offset, -330);
+        assert_eq!(signature.to_string(), line);
+        for line in [
+            "Jane  Doe <jane@x.x> 1700000000 +0100",
+            "Jane Doe  <jane@x.x> 1700000000 +0100",
+            "Jane Doe <jane@x.x> -1700000000 -0000",
+        ] {
+            assert_eq!(Signature::parse(line).unwrap().to_string(), line);
+        }
+        for line in [
+            "Jane Doe <jane@x.x>",
+            "Jane Doe <jane@x.x> +0000",
+            "Jane Doe <jane@x.x> soon +0000",
+            "Jane Doe <jane@x.x> 99999999999999999999 +0000",
+            "Jane Doe <jane@x.x> 1700000000 +01",
+            "Jane Doe <jane@x.x> 01700000000 +0100",
+        ] {
+            assert!(Signature::parse(line).is_err());
+        }
+    }
+}
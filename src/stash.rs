@@ -0,0 +1,400 @@
+use std::collections::HashSet;
+
+use crate::{
+    append_reflog, clean_file, commit_parents, commit_subject, delete_ref, file_mode, head_branch,
+    is_modified, merge_trees, peel_to_tree, read_ref, read_reflog, refresh_index, remove_file,
+    reset_to_tree, sequencer::read_commit, shorten_ref_name, untracked_files, update_ref_no_deref,
+    worktree_path, write_file, write_reflog, Excludes, Index, IndexEntry, Object, ObjectHeaders,
+    ObjectTypes, Repository, Signature, TreeMergeOptions, TreeMergeResult, TreeNode, TreeObject,
+};
+
+pub const STASH_REF: &str = "refs/stash";
+
+#[derive(Clone, Debug, Default)]
+pub struct StashOptions {
+    /// Used instead of the `WIP on <branch>` description
+    pub message: Option<String>,
+    /// Also stash (then remove) untracked files
+    pub include_untracked: bool,
+}
+
+/// Position of a stash entry from `stash@{<n>}`, `refs/stash@{<n>}` or `<n>`
+pub fn stash_position(spec: &str) -> Result<usize, String> {
+    let position = spec
+        .strip_prefix("stash@{")
+        .or_else(|| spec.strip_prefix("refs/stash@{"))
+        .and_then(|spec| spec.strip_suffix('}'))
+        .unwrap_or(spec);
+    position
+        .parse()
+        .map_err(|_| format!("{} is not a valid stash reference", spec))
+}
+
+/// Stash entries, most recent first, as `(commit, description)`
+pub fn stash_list(repo: &Repository) -> Vec<(String, String)> {
+    read_reflog(repo, STASH_REF)
+        .into_iter()
+        .rev()
+        .map(|entry| (entry.new, entry.message))
+        .collect()
+}
+
+/// Commit of the stash entry at `position`
+pub fn stash_commit(repo: &Repository, position: usize) -> Result<String, String> {
+    stash_list(repo)
+        .into_iter()
+        .nth(position)
+        .map(|(commit, _)| commit)
+        .ok_or(format!("stash@{{{}}} is not a valid reference", position))
+}
+
+fn write_commit(
+    repo: &Repository,
+    tree: String,
+    parents: Vec<String>,
+    message: String,
+) -> Result<String, String> {
+    let identity = Signature::committer(repo)?;
+    Object {
+        header: ObjectHeaders::new_commit(tree, parents, &identity, &identity, message),
+        _type: ObjectTypes::Commit,
+    }
+    .write_to_repo(repo)
+}
+
+fn write_blob(repo: &Repository, path: &str) -> Result<String, String> {
+    Object::new(ObjectTypes::Blob, clean_file(repo, path, None)?)?.write_to_repo(repo)
+}
+
+/// Records the local changes in a stash entry, then resets the index and worktree to HEAD.
+///
+/// Like git, the entry is a commit of the worktree whose parents are HEAD, a commit of the index
+/// and, when untracked files are included, a commit of those files. Returns `None` when there is
+/// nothing to stash.
+pub fn stash_save(repo: &Repository, options: &StashOptions) -> Result<Option<String>, String> {
+    let head = read_ref(repo, "HEAD").ok_or("You do not have the initial commit yet")?;
+    let head_tree = peel_to_tree(repo, &head)?;
+    let index = Index::read(repo)?;
+    if index.has_conflicts() {
+        return Err(format!(
+            "Cannot save the current state, needs merge: {}",
+            index.conflicted_paths().join(", ")
+        ));
+    }
+    let index_tree = index.write_tree(repo)?;
+    let mut worktree_nodes = Vec::new();
+    for entry in &index.entries {
+        if !is_modified(repo, entry) {
+            worktree_nodes.push(entry.to_tree_node()?);
+            continue;
+        }
+        let Ok(metadata) = std::fs::symlink_metadata(worktree_path(repo, &entry.path)) else {
+            continue;
+        };
+        worktree_nodes.push(TreeNode::new(
+            format!("{:o}", file_mode(&metadata)),
+            entry.path.clone(),
+            write_blob(repo, &entry.path)?,
+        )?);
+    }
+    let worktree_tree = TreeObject::write_from_nodes(repo, &worktree_nodes)?;
+    let untracked = match options.include_untracked {
+        // Nested repositories are left where they are
+        true => untracked_files(repo, &index, &mut Excludes::standard(repo))?
+            .into_iter()
+            .filter(|path| !path.ends_with('/'))
+            .collect(),
+        false => Vec::new(),
+    };
+    if index_tree == head_tree && worktree_tree == head_tree && untracked.is_empty() {
+        return Ok(None);
+    }
+
+    let branch = head_branch(repo)
+        .map(|branch| shorten_ref_name(&branch).to_owned())
+        .unwrap_or(String::from("(no branch)"));
+    let head_subject = commit_subject(&read_commit(repo, &head)?.message).to_owned();
+    let description = format!("{}: {} {}", branch, &head[..7], head_subject);
+    let index_commit = write_commit(
+        repo,
+        index_tree,
+        vec![head.clone()],
+        format!("index on {}\n", description),
+    )?;
+    let mut parents = vec![head, index_commit];
+    if !untracked.is_empty() {
+        let mut untracked_nodes = Vec::new();
+        for path in &untracked {
+            let metadata = std::fs::symlink_metadata(worktree_path(repo, path))
+                .map_err(|e| format!("Unable to stat {}: {}", path, e))?;
+            untracked_nodes.push(TreeNode::new(
+                format!("{:o}", file_mode(&metadata)),
+                path.clone(),
+                write_blob(repo, path)?,
+            )?);
+        }
+        parents.push(write_commit(
+            repo,
+            TreeObject::write_from_nodes(repo, &untracked_nodes)?,
+            Vec::new(),
+            format!("untracked files on {}\n", description),
+        )?);
+    }
+    let message = match &options.message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}", description),
+    };
+    let stash = write_commit(repo, worktree_tree, parents, format!("{}\n", message))?;
+    let previous = read_ref(repo, STASH_REF);
+    update_ref_no_deref(repo, STASH_REF, &stash)?;
+    append_reflog(repo, STASH_REF, previous.as_deref(), &stash, &message)?;
+
+    reset_to_tree(repo, &head_tree)?;
+    for path in &untracked {
+        remove_file(repo, path)?;
+    }
+    Ok(Some(stash))
+}
+
+fn write_index(repo: &Repository, entries: Vec<IndexEntry>) -> Result<(), String> {
+    let mut index = Index {
+        version: 2,
+        entries,
+    };
+    index
+        .entries
+        .sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+    refresh_index(repo, &mut index);
+    index.write(repo)
+}
+
+/// Applies the changes recorded in a stash entry onto the current HEAD.
+///
+/// The changes land in the worktree, only new files are staged unless `restore_index` asks for
+/// the staged changes to be restored as well. Conflicts are left in the index and worktree.
+pub fn stash_apply(
+    repo: &Repository,
+    stash: &str,
+    restore_index: bool,
+) -> Result<TreeMergeResult, String> {
+    let parents = commit_parents(repo, stash)?;
+    if parents.len() < 2 {
+        return Err(format!("{} does not look like a stash commit", stash));
+    }
+    let head = read_ref(repo, "HEAD").ok_or("You do not have the initial commit yet")?;
+    let head_tree = peel_to_tree(repo, &head)?;
+    let base_tree = peel_to_tree(repo, &parents[0])?;
+    let index_tree = peel_to_tree(repo, &parents[1])?;
+    let untracked = match parents.get(2) {
+        Some(commit) => {
+            TreeObject::read_from_sha(repo, &peel_to_tree(repo, commit)?)?.flatten(repo)?
+        }
+        None => Vec::new(),
+    };
+    if let Some(node) = untracked
+        .iter()
+        .find(|node| std::fs::symlink_metadata(worktree_path(repo, &node.path)).is_ok())
+    {
+        return Err(format!("{} already exists, no checkout", node.path));
+    }
+    let labels = TreeMergeOptions {
+        ours_label: String::from("Updated upstream"),
+        theirs_label: String::from("Stashed changes"),
+        base_label: String::from("Stash base"),
+        ..Default::default()
+    };
+    let staged = match restore_index && index_tree != base_tree {
+        true => {
+            let result = merge_trees(repo, Some(&base_tree), &head_tree, &index_tree, &labels)?;
+            if !result.is_clean() {
+                return Err(String::from("Conflicts in index. Try without --index."));
+            }
+            Some(result.entries)
+        }
+        false => None,
+    };
+    let result = merge_trees(
+        repo,
+        Some(&base_tree),
+        &head_tree,
+        &peel_to_tree(repo, stash)?,
+        &labels,
+    )?;
+    result.checkout(repo, &head_tree)?;
+    if result.is_clean() {
+        let entries = match staged {
+            Some(entries) => entries,
+            None => {
+                let mut entries = Index::from_tree(repo, &head_tree)?.entries;
+                let head_paths = entries
+                    .iter()
+                    .map(|entry| entry.path.clone())
+                    .collect::<HashSet<String>>();
+                entries.extend(
+                    result
+                        .entries
+                        .iter()
+                        .filter(|entry| !head_paths.contains(&entry.path))
+                        .cloned(),
+                );
+                entries
+            }
+        };
+        write_index(repo, entries)?;
+    }
+    for node in &untracked {
+        let mode = u32::from_str_radix(&node.mode, 8)
+            .map_err(|e| format!("Invalid mode, {}: {}", node.mode, e))?;
+        write_file(repo, &node.path, mode, &node.hash)?;
+    }
+    Ok(result)
+}
+
+/// Removes the stash entry at `position`, returning its commit
+pub fn stash_drop(repo: &Repository, position: usize) -> Result<String, String> {
+    let mut entries = read_reflog(repo, STASH_REF);
+    let index = entries
+        .len()
+        .checked_sub(position + 1)
+        .ok_or(format!("stash@{{{}}} is not a valid reference", position))?;
+    let dropped = entries.remove(index);
+    match entries.last() {
+        Some(top) => update_ref_no_deref(repo, STASH_REF, &top.new)?,
+        None => delete_ref(repo, STASH_REF)?,
+    }
+    write_reflog(repo, STASH_REF, &entries)?;
+    Ok(dropped.new)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{hash_file, repository::test::TempRepo};
+
+    #[test]
+    fn test_stash_position() {
+        assert_eq!(stash_position("stash@{2}"), Ok(2));
+        assert_eq!(stash_position("refs/stash@{0}"), Ok(0));
+        assert_eq!(stash_position("3"), Ok(3));
+        for spec in ["stash@{x}", "stash@{1", "stash", "-1", ""] {
+            assert!(stash_position(spec).is_err());
+        }
+    }
+
+    fn stage(repo: &TempRepo, path: &str, content: &str) {
+        repo.write(path, content);
+        let mut index = Index::read(repo).unwrap();
+        let entry = index.entries.iter_mut().find(|e| e.path == path).unwrap();
+        entry.hash = Object::write_data(repo, &ObjectTypes::Blob, content.as_bytes()).unwrap();
+        refresh_index(repo, &mut index);
+        index.write(repo).unwrap();
+    }
+
+    fn tree_files(repo: &TempRepo, commit: &str) -> Vec<(String, String)> {
+        let tree = TreeObject::read_from_sha(repo, &peel_to_tree(repo, commit).unwrap());
+        tree.unwrap()
+            .flatten(repo)
+            .unwrap()
+            .into_iter()
+            .map(|node| {
+                let data = Object::read_data(repo, &node.hash).unwrap().1;
+                (node.path, String::from_utf8(data).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stash_round_trip() {
+        let repo = TempRepo::new();
+        let head = repo.commit(&[("a", "a\n"), ("b", "b\n")], &[], "Base\n");
+        repo.checkout(&head);
+        let files = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(path, content)| (path.to_string(), content.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(stash_save(&repo, &StashOptions::default()), Ok(None));
+        repo.write("a", "a edited\n");
+        stage(&repo, "b", "b staged\n");
+        repo.write("u", "untracked\n");
+        let options = StashOptions {
+            include_untracked: true,
+            ..Default::default()
+        };
+        let first = stash_save(&repo, &options).unwrap().unwrap();
+        assert_eq!(repo.read("a").unwrap(), "a\n");
+        assert_eq!(repo.read("b").unwrap(), "b\n");
+        assert_eq!(repo.read("u"), None);
+        assert_eq!(
+            Index::read(&repo).unwrap().write_tree(&repo),
+            peel_to_tree(&repo, &head)
+        );
+        let parents = commit_parents(&repo, &first).unwrap();
+        assert_eq!(parents[0], head);
+        assert_eq!(
+            tree_files(&repo, &parents[1]),
+            files(&[("a", "a\n"), ("b", "b staged\n")])
+        );
+        assert_eq!(
+            tree_files(&repo, &parents[2]),
+            files(&[("u", "untracked\n")])
+        );
+        assert_eq!(
+            tree_files(&repo, &first),
+            files(&[("a", "a edited\n"), ("b", "b staged\n")])
+        );
+
+        repo.write("a", "a again\n");
+        let message = Some(String::from("second"));
+        let second = stash_save(
+            &repo,
+            &StashOptions {
+                message,
+                ..Default::default()
+            },
+        );
+        let second = second.unwrap().unwrap();
+        let descriptions = stash_list(&repo)
+            .into_iter()
+            .map(|(_, d)| d)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            [
+                "On master: second".to_owned(),
+                format!("WIP on master: {} Base", &head[..7])
+            ]
+        );
+
+        // apply --index keeps the staged change staged and restores untracked files
+        let result = stash_apply(&repo, &stash_commit(&repo, 1).unwrap(), true).unwrap();
+        assert!(result.is_clean());
+        assert_eq!(repo.read("a").unwrap(), "a edited\n");
+        assert_eq!(repo.read("u").unwrap(), "untracked\n");
+        let index = Index::read(&repo).unwrap();
+        assert_eq!(
+            index.get("b").unwrap().hash,
+            hash_file(&repo, "b", None).unwrap()
+        );
+        let head_index = Index::from_tree(&repo, &peel_to_tree(&repo, &head).unwrap()).unwrap();
+        assert_eq!(
+            index.get("a").unwrap().hash,
+            head_index.get("a").unwrap().hash
+        );
+
+        reset_to_tree(&repo, &peel_to_tree(&repo, &head).unwrap()).unwrap();
+        remove_file(&repo, "u").unwrap();
+        assert_eq!(stash_drop(&repo, 1), Ok(first));
+        assert_eq!(stash_commit(&repo, 0), Ok(second.clone()));
+        assert!(stash_drop(&repo, 1).is_err());
+
+        // pop, an apply followed by a drop
+        assert!(stash_apply(&repo, &second, false).unwrap().is_clean());
+        assert_eq!(stash_drop(&repo, 0), Ok(second));
+        assert_eq!(repo.read("a").unwrap(), "a again\n");
+        assert!(stash_list(&repo).is_empty());
+        assert_eq!(read_ref(&repo, STASH_REF), None);
+    }
+}
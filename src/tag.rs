@@ -0,0 +1,270 @@
+use std::{
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    check_ref_format, list_refs, shorten_ref_name, version_cmp, wildmatch, Object, ObjectHeaders,
+    ObjectTypes, Repository,
+};
+
+/// What `tag -l --sort=<key>` orders tags by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagSortKey {
+    Refname,
+    /// `version:refname`, version numbers compared numerically
+    Version,
+    /// Date of the tagger for annotated tags, of the committer for lightweight ones
+    CreatorDate,
+    /// Date of the tagger, lightweight tags having none
+    TaggerDate,
+    ObjectName,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagSort {
+    pub key: TagSortKey,
+    pub reverse: bool,
+}
+impl TagSort {
+    /// Parses a `--sort` key, reversed when prefixed by `-`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (reverse, key) = match text.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, text),
+        };
+        let key = match key {
+            "refname" => TagSortKey::Refname,
+            "version:refname" | "v:refname" => TagSortKey::Version,
+            "creatordate" => TagSortKey::CreatorDate,
+            "taggerdate" => TagSortKey::TaggerDate,
+            "objectname" => TagSortKey::ObjectName,
+            _ => return Err(format!("unsupported sort specification '{}'", text)),
+        };
+        Ok(Self { key, reverse })
+    }
+}
+
+/// Tagger date of an annotated tag, or committer date of a lightweight tag when `creator`
+fn tag_date(repo: &Repository, hash: &str, creator: bool) -> Result<i64, String> {
+    Ok(match Object::read_from_sha(repo, hash.to_owned())?.header {
+        ObjectHeaders::Tag(tag) => tag.tagger.map(|tagger| tagger.time).unwrap_or(0),
+        ObjectHeaders::Commit(commit) if creator => commit.committer.time,
+        _ => 0,
+    })
+}
+
+/// Tags as `(name, hash)`, keeping those matching any of `patterns` (all when empty).
+///
+/// The last of `sorts` is the primary key, ties are ordered by name.
+pub fn list_tags(
+    repo: &Repository,
+    patterns: &[String],
+    sorts: &[TagSort],
+) -> Result<Vec<(String, String)>, String> {
+    let mut tags = Vec::new();
+    for (ref_name, hash) in list_refs(repo, "refs/tags/") {
+        let name = shorten_ref_name(&ref_name).to_owned();
+        if !patterns.is_empty() && !patterns.iter().any(|pattern| wildmatch(pattern, &name)) {
+            continue;
+        }
+        let mut dates = (0, 0);
+        if sorts.iter().any(|sort| sort.key == TagSortKey::CreatorDate) {
+            dates.0 = tag_date(repo, &hash, true)?;
+        }
+        if sorts.iter().any(|sort| sort.key == TagSortKey::TaggerDate) {
+            dates.1 = tag_date(repo, &hash, false)?;
+        }
+        tags.push((name, hash, dates));
+    }
+    tags.sort_by(|a, b| {
+        sorts
+            .iter()
+            .rev()
+            .map(|sort| {
+                let ordering = match sort.key {
+                    TagSortKey::Refname => a.0.cmp(&b.0),
+                    TagSortKey::Version => version_cmp(&a.0, &b.0),
+                    TagSortKey::CreatorDate => a.2 .0.cmp(&b.2 .0),
+                    TagSortKey::TaggerDate => a.2 .1.cmp(&b.2 .1),
+                    TagSortKey::ObjectName => a.1.cmp(&b.1),
+                };
+                if sort.reverse {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.0.cmp(&b.0))
+    });
+    Ok(tags
+        .into_iter()
+        .map(|(name, hash, _)| (name, hash))
+        .collect())
+}
+
+/// Message shown by `tag -n`: the annotation of annotated tags without their signature, the
+/// message of tagged commits, nothing for other objects
+pub fn tag_message(repo: &Repository, hash: &str) -> Result<String, String> {
+    Ok(match Object::read_from_sha(repo, hash.to_owned())?.header {
+        ObjectHeaders::Tag(tag) => tag.message,
+        ObjectHeaders::Commit(commit) => commit.message,
+        _ => String::new(),
+    })
+}
+
+/// Checks tag data as strictly as `git mktag` does, returning the tagged object and the type it
+/// is claimed to have, or the `msgId: message` of the first problem found
+pub fn fsck_tag(data: &[u8]) -> Result<(String, ObjectTypes), String> {
+    if let Some(offset) = data.iter().position(|&b| b == 0) {
+        let header_end = data.windows(2).position(|pair| pair == b"\n\n");
+        if header_end.is_none_or(|end| offset < end) {
+            return Err(format!(
+                "nulInHeader: unterminated header: NUL at offset {}",
+                offset
+            ));
+        }
+    }
+    if !data.windows(2).any(|pair| pair == b"\n\n") && data.last() != Some(&b'\n') {
+        return Err(String::from("unterminatedHeader: unterminated header"));
+    }
+    let text = String::from_utf8_lossy(data);
+    let mut rest: &str = &text;
+    let mut header = |key: &str, missing: &str| -> Result<&str, String> {
+        let value = rest
+            .strip_prefix(key)
+            .and_then(|value| value.strip_prefix(' '))
+            .ok_or_else(|| format!("{}: invalid format - expected '{}' line", missing, key))?;
+        let (value, next) = value.split_once('\n').ok_or_else(|| {
+            format!(
+                "{}: invalid format - unexpected end after '{}' line",
+                missing, key
+            )
+        })?;
+        rest = next;
+        Ok(value)
+    };
+    let object = header("object", "missingObject")?;
+    if object.len() != 40 || !object.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(String::from(
+            "badObjectSha1: invalid 'object' line format - bad sha1",
+        ));
+    }
+    let object = object.to_ascii_lowercase();
+    let kind = match header("type", "missingTypeEntry")? {
+        kind @ ("commit" | "tree" | "blob" | "tag") => ObjectTypes::from_string(kind),
+        _ => return Err(String::from("badType: invalid 'type' value")),
+    };
+    let name = header("tag", "missingTagEntry")?;
+    if !check_ref_format(&format!("refs/tags/{}", name), false) {
+        return Err(format!("badTagName: invalid 'tag' name: {}", name));
+    }
+    let tagger = header("tagger", "missingTaggerEntry")?;
+    fsck_ident(tagger)?;
+    if !rest.is_empty() && !rest.starts_with('\n') {
+        return Err(String::from(
+            "extraHeaderEntry: invalid format - extra header(s) after 'tagger'",
+        ));
+    }
+    Ok((object, kind))
+}
+
+/// Checks a `name <email> <timestamp> <+hhmm>` identity like git's fsck does
+fn fsck_ident(ident: &str) -> Result<(), String> {
+    let invalid = |id: &str, problem: &str| {
+        Err(format!(
+            "{}: invalid author/committer line - {}",
+            id, problem
+        ))
+    };
+    if ident.starts_with('<') {
+        return invalid("missingNameBeforeEmail", "missing space before email");
+    }
+    let Some(open) = ident
+        .find(['<', '>'])
+        .filter(|&i| ident.as_bytes()[i] == b'<')
+    else {
+        return match ident.contains('>') {
+            true => invalid("badName", "bad name"),
+            false => invalid("missingEmail", "missing email"),
+        };
+    };
+    if !ident[..open].ends_with(' ') {
+        return invalid("missingSpaceBeforeEmail", "missing space before email");
+    }
+    let Some(close) = ident[open + 1..]
+        .find(['<', '>'])
+        .map(|i| open + 1 + i)
+        .filter(|&i| ident.as_bytes()[i] == b'>')
+    else {
+        return invalid("badEmail", "bad email");
+    };
+    let Some(date) = ident[close + 1..].strip_prefix(' ') else {
+        return invalid("missingSpaceBeforeDate", "missing space before date");
+    };
+    if !date.starts_with(|c: char| c.is_ascii_digit()) {
+        return invalid("badDate", "bad date");
+    }
+    if date.starts_with('0') && !date[1..].starts_with(' ') {
+        return invalid("zeroPaddedDate", "zero-padded date");
+    }
+    let digits = date
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(date.len());
+    if date[..digits].parse::<i64>().is_err() {
+        return invalid("badDateOverflow", "date causes integer overflow");
+    }
+    let Some(timezone) = date[digits..].strip_prefix(' ') else {
+        return invalid("badDate", "bad date");
+    };
+    let timezone = timezone.as_bytes();
+    if timezone.len() != 5
+        || !matches!(timezone[0], b'+' | b'-')
+        || !timezone[1..].iter().all(|c| c.is_ascii_digit())
+    {
+        return invalid("badTimezone", "bad time zone");
+    }
+    Ok(())
+}
+
+/// Checks the signature of a signed tag's `payload` with `gpg`, returning its report in both
+/// cases
+pub fn verify_signature(payload: &[u8], signature: &str) -> Result<String, String> {
+    if !signature.starts_with("-----BEGIN PGP") {
+        return Err(String::from("only PGP signatures can be verified"));
+    }
+    let signature_path = env::temp_dir().join(format!("rit-tag-signature-{}", std::process::id()));
+    fs::write(&signature_path, signature)
+        .map_err(|e| format!("Error writing {:?}: {}", signature_path, e))?;
+    let child = Command::new("gpg")
+        .args(["--status-fd=1", "--keyid-format=long", "--verify"])
+        .arg(&signature_path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let output = child.and_then(|mut child| {
+        child
+            .stdin
+            .take()
+            .expect("Piped stdin")
+            .write_all(payload)?;
+        child.wait_with_output()
+    });
+    let _ = fs::remove_file(&signature_path);
+    let output = output.map_err(|e| format!("could not run gpg: {}", e))?;
+    let report = String::from_utf8_lossy(&output.stderr).into_owned();
+    let status = String::from_utf8_lossy(&output.stdout);
+    if output.status.success()
+        && status
+            .lines()
+            .any(|line| line.starts_with("[GNUPG:] GOODSIG "))
+    {
+        Ok(report)
+    } else {
+        Err(report)
+    }
+}
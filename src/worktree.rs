@@ -0,0 +1,416 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    os::unix::fs::{symlink, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    create_path, peel_to_tree, read_ref, Attributes, Conversion, Excludes, Index, IndexEntry,
+    Object, ObjectHeaders, ObjectTypes, Repository, TreeNode, TreeObject,
+};
+
+/// Absolute path of a worktree file from its path relative to the worktree root
+pub fn worktree_path(repo: &Repository, path: &str) -> PathBuf {
+    create_path(&repo.worktree, vec![path.to_owned()])
+}
+
+/// Mode git would record for a worktree file
+pub fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        0o120000
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        0o100755
+    } else {
+        0o100644
+    }
+}
+
+/// Content git would store as a blob for a worktree file, symbolic links store their target
+pub fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    let metadata =
+        fs::symlink_metadata(path).map_err(|e| format!("Unable to stat {:?}: {}", path, e))?;
+    if metadata.file_type().is_symlink() {
+        let target =
+            fs::read_link(path).map_err(|e| format!("Unable to read link {:?}: {}", path, e))?;
+        Ok(target.to_string_lossy().as_bytes().to_owned())
+    } else {
+        fs::read(path).map_err(|e| format!("Unable to read file {:?}: {}", path, e))
+    }
+}
+
+/// Content git stores as a blob for the worktree file at `path`, relative to the worktree root:
+/// regular files go through the conversion their attributes ask for.
+///
+/// `indexed` is the blob the index has for the file, if any.
+pub fn clean_file(repo: &Repository, path: &str, indexed: Option<&str>) -> Result<Vec<u8>, String> {
+    let file_path = worktree_path(repo, path);
+    let data = read_file(&file_path)?;
+    if fs::symlink_metadata(&file_path).is_ok_and(|metadata| metadata.is_symlink()) {
+        return Ok(data);
+    }
+    let mut conversion = Conversion::new(repo, &mut Attributes::new(repo), path);
+    if let Some(hash) = indexed {
+        conversion = conversion.with_indexed(repo, hash)?;
+    }
+    conversion.to_git(data)
+}
+
+/// Blob hash of the worktree file at `path`, relative to the worktree root, `indexed` being the
+/// blob the index has for it
+pub fn hash_file(repo: &Repository, path: &str, indexed: Option<&str>) -> Result<String, String> {
+    Object::new(ObjectTypes::Blob, clean_file(repo, path, indexed)?)?.calculate_hash()
+}
+
+/// Whether the worktree file differs from what the index entry records (missing files count as modified)
+pub fn is_modified(repo: &Repository, entry: &IndexEntry) -> bool {
+    let path = worktree_path(repo, &entry.path);
+    let Ok(metadata) = fs::symlink_metadata(&path) else {
+        return true;
+    };
+    if entry.stat_matches(&metadata) {
+        return false;
+    }
+    hash_file(repo, &entry.path, Some(&entry.hash)).map_or(true, |hash| hash != entry.hash)
+}
+
+/// Writes the blob `hash` to the worktree at `path`, creating the parent directories as needed
+pub fn write_file(repo: &Repository, path: &str, mode: u32, hash: &str) -> Result<(), String> {
+    let data = match Object::read_from_sha(repo, hash.to_owned())?.header {
+        ObjectHeaders::Blob { data } => data,
+        _ => return Err(format!("Expected blob for {}, {}", path, hash)),
+    };
+    let data = match mode {
+        0o120000 | 0o160000 => data,
+        _ => Conversion::new(repo, &mut Attributes::new(repo), path).to_worktree(data)?,
+    };
+    let file_path = worktree_path(repo, path);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Error creating dir, {:?}: {}", parent, e))?;
+    }
+    if fs::symlink_metadata(&file_path).is_ok_and(|metadata| metadata.is_dir()) {
+        fs::remove_dir_all(&file_path)
+            .map_err(|e| format!("Error removing dir, {:?}: {}", file_path, e))?;
+    } else if fs::symlink_metadata(&file_path).is_ok() {
+        fs::remove_file(&file_path)
+            .map_err(|e| format!("Error removing file, {:?}: {}", file_path, e))?;
+    }
+    match mode {
+        0o120000 => symlink(String::from_utf8_lossy(&data).to_string(), &file_path)
+            .map_err(|e| format!("Error creating symlink, {:?}: {}", file_path, e)),
+        // Submodules are not checked out
+        0o160000 => Ok(()),
+        _ => {
+            fs::write(&file_path, &data)
+                .map_err(|e| format!("Error writing file, {:?}: {}", file_path, e))?;
+            let permissions = if mode == 0o100755 { 0o755 } else { 0o644 };
+            fs::set_permissions(&file_path, fs::Permissions::from_mode(permissions))
+                .map_err(|e| format!("Error setting permissions, {:?}: {}", file_path, e))
+        }
+    }
+}
+
+/// Removes a worktree file, along with the parent directories left empty
+pub fn remove_file(repo: &Repository, path: &str) -> Result<(), String> {
+    let file_path = worktree_path(repo, path);
+    match fs::remove_file(&file_path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Error removing file, {:?}: {}", file_path, e)),
+    }
+    let mut parent = file_path.parent();
+    while let Some(dir) = parent {
+        if dir == repo.worktree || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+fn nodes_by_path(nodes: &[TreeNode]) -> BTreeMap<&str, &TreeNode> {
+    nodes
+        .iter()
+        .map(|node| (node.path.as_str(), node))
+        .collect()
+}
+
+/// Paths whose worktree content would be lost when moving the worktree from `old` to `new`.
+///
+/// A path is at risk when it changes between `old` and `new` while the worktree file does not
+/// match `old`, including untracked files that `new` would overwrite.
+pub fn overwritten_paths(repo: &Repository, old: &[TreeNode], new: &[TreeNode]) -> Vec<String> {
+    let old_nodes = nodes_by_path(old);
+    let new_nodes = nodes_by_path(new);
+    let mut paths = Vec::new();
+    for (path, new_node) in &new_nodes {
+        let old_node = old_nodes.get(path);
+        if old_node.is_some_and(|old_node| old_node.hash == new_node.hash) {
+            continue;
+        }
+        let file_path = worktree_path(repo, path);
+        let at_risk = match old_node {
+            Some(old_node) => {
+                fs::symlink_metadata(&file_path).is_ok()
+                    && hash_file(repo, path, Some(&old_node.hash))
+                        .map_or(true, |hash| hash != old_node.hash)
+            }
+            None => fs::symlink_metadata(&file_path).is_ok_and(|metadata| !metadata.is_dir()),
+        };
+        if at_risk {
+            paths.push(path.to_string());
+        }
+    }
+    for (path, old_node) in &old_nodes {
+        if new_nodes.contains_key(path) {
+            continue;
+        }
+        let file_path = worktree_path(repo, path);
+        if fs::symlink_metadata(&file_path).is_ok()
+            && hash_file(repo, path, Some(&old_node.hash))
+                .map_or(true, |hash| hash != old_node.hash)
+        {
+            paths.push(path.to_string());
+        }
+    }
+    paths.sort();
+    paths
+}
+
+/// Moves the worktree from the files of `old` to the files of `new`, only touching the paths that changed
+pub fn update_worktree(
+    repo: &Repository,
+    old: &[TreeNode],
+    new: &[TreeNode],
+) -> Result<(), String> {
+    let old_nodes = nodes_by_path(old);
+    let new_nodes = nodes_by_path(new);
+    for path in old_nodes.keys() {
+        if !new_nodes.contains_key(path) {
+            remove_file(repo, path)?;
+        }
+    }
+    for (path, node) in &new_nodes {
+        let unchanged = old_nodes
+            .get(path)
+            .is_some_and(|old_node| old_node.hash == node.hash && old_node.mode == node.mode);
+        if unchanged && fs::symlink_metadata(worktree_path(repo, path)).is_ok() {
+            continue;
+        }
+        let mode = u32::from_str_radix(&node.mode, 8)
+            .map_err(|e| format!("Invalid mode, {}: {}", node.mode, e))?;
+        write_file(repo, path, mode, &node.hash)?;
+    }
+    Ok(())
+}
+
+/// Refreshes the stat information of the stage 0 entries whose worktree file matches them
+pub fn refresh_index(repo: &Repository, index: &mut Index) {
+    for entry in index.entries.iter_mut().filter(|entry| entry.stage == 0) {
+        let path = worktree_path(repo, &entry.path);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if entry.stat_matches(&metadata) {
+            continue;
+        }
+        if hash_file(repo, &entry.path, Some(&entry.hash)).is_ok_and(|hash| hash == entry.hash) {
+            entry.update_stat(&metadata);
+        }
+    }
+}
+
+/// Worktree files that are not in the index nor excluded, sorted, the gitdir left aside.
+///
+/// Excluded directories are not looked into, and nested repositories are listed as `dir/`.
+pub fn untracked_files(
+    repo: &Repository,
+    index: &Index,
+    excludes: &mut Excludes,
+) -> Result<Vec<String>, String> {
+    let tracked: HashSet<&str> = index
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect();
+    let mut untracked = Vec::new();
+    let mut directories = vec![String::new()];
+    while let Some(directory) = directories.pop() {
+        let path = worktree_path(repo, &directory);
+        let entries =
+            fs::read_dir(&path).map_err(|e| format!("Unable to read dir {:?}: {}", path, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Unable to read dir {:?}: {}", path, e))?;
+            if entry.path() == repo.gitdir {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = match directory.as_str() {
+                "" => name,
+                directory => format!("{}/{}", directory, name),
+            };
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if tracked.contains(relative.as_str()) || excludes.is_excluded(&relative, is_dir) {
+                continue;
+            }
+            if !is_dir {
+                untracked.push(relative);
+            } else if entry.path().join(".git").exists() {
+                untracked.push(format!("{}/", relative));
+            } else {
+                directories.push(relative);
+            }
+        }
+    }
+    untracked.sort();
+    Ok(untracked)
+}
+
+/// Moves the index and the worktree from tree `from` to tree `to`.
+///
+/// Local changes to paths that are the same in both trees are kept. Fails without touching
+/// anything if local changes would be overwritten.
+pub fn switch_tree(repo: &Repository, from: &str, to: &str) -> Result<(), String> {
+    let from_nodes = TreeObject::read_from_sha(repo, from)?.flatten(repo)?;
+    let to_nodes = TreeObject::read_from_sha(repo, to)?.flatten(repo)?;
+    let from_by_path = nodes_by_path(&from_nodes);
+    let to_by_path = nodes_by_path(&to_nodes);
+    let changed = from_by_path
+        .keys()
+        .chain(to_by_path.keys())
+        .filter(
+            |path| match (from_by_path.get(*path), to_by_path.get(*path)) {
+                (Some(a), Some(b)) => a.hash != b.hash || a.mode != b.mode,
+                _ => true,
+            },
+        )
+        .map(|path| path.to_string())
+        .collect::<BTreeSet<String>>();
+
+    let mut index = Index::read(repo)?;
+    let mut overwritten = overwritten_paths(repo, &from_nodes, &to_nodes);
+    for path in &changed {
+        let staged = index.get(path).map(|entry| entry.hash.as_str());
+        let expected = from_by_path
+            .get(path.as_str())
+            .map(|node| node.hash.as_str());
+        if staged != expected || index.conflicted_paths().contains(path) {
+            overwritten.push(path.clone());
+        }
+    }
+    if !overwritten.is_empty() {
+        overwritten.sort();
+        overwritten.dedup();
+        return Err(format!(
+            "Your local changes to the following files would be overwritten:\n\t{}",
+            overwritten.join("\n\t")
+        ));
+    }
+
+    update_worktree(repo, &from_nodes, &to_nodes)?;
+    index.entries.retain(|entry| !changed.contains(&entry.path));
+    for path in &changed {
+        if let Some(node) = to_by_path.get(path.as_str()) {
+            index.entries.push(IndexEntry::from_tree_node(node, 0)?);
+        }
+    }
+    index
+        .entries
+        .sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+    refresh_index(repo, &mut index);
+    index.write(repo)
+}
+
+/// Resets the index and the worktree to a tree, discarding every change to tracked files
+/// (including unresolved conflicts). Untracked files are left alone.
+pub fn reset_to_tree(repo: &Repository, tree: &str) -> Result<(), String> {
+    let nodes = TreeObject::read_from_sha(repo, tree)?.flatten(repo)?;
+    let by_path = nodes_by_path(&nodes);
+    let index = Index::read(repo)?;
+    for entry in &index.entries {
+        if !by_path.contains_key(entry.path.as_str()) {
+            remove_file(repo, &entry.path)?;
+        }
+    }
+    for node in &nodes {
+        let file_path = worktree_path(repo, &node.path);
+        let up_to_date = fs::symlink_metadata(&file_path).is_ok_and(|metadata| {
+            format!("{:o}", file_mode(&metadata)) == node.mode
+                && hash_file(repo, &node.path, Some(&node.hash)).is_ok_and(|hash| hash == node.hash)
+        });
+        if !up_to_date {
+            let mode = u32::from_str_radix(&node.mode, 8)
+                .map_err(|e| format!("Invalid mode, {}: {}", node.mode, e))?;
+            write_file(repo, &node.path, mode, &node.hash)?;
+        }
+    }
+    let mut index = Index::from_tree(repo, tree)?;
+    // Files now match their entry, having been checked or written above, except submodules
+    for entry in index
+        .entries
+        .iter_mut()
+        .filter(|entry| entry.mode != 0o160000)
+    {
+        if let Ok(metadata) = fs::symlink_metadata(worktree_path(repo, &entry.path)) {
+            entry.update_stat(&metadata);
+        }
+    }
+    index.write(repo)
+}
+
+/// Resets the index to `tree` and the worktree files whose entry differs from it, like
+/// `git reset --merge` does to abort a merge: local changes to the other files are kept
+pub fn reset_merge(repo: &Repository, tree: &str) -> Result<(), String> {
+    let nodes = TreeObject::read_from_sha(repo, tree)?.flatten(repo)?;
+    let by_path = nodes_by_path(&nodes);
+    let index = Index::read(repo)?;
+    let unchanged = index
+        .entries
+        .iter()
+        .filter(|entry| {
+            entry.stage == 0
+                && by_path.get(entry.path.as_str()).is_some_and(|node| {
+                    format!("{:o}", entry.mode) == node.mode && entry.hash == node.hash
+                })
+        })
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect::<BTreeMap<&str, &IndexEntry>>();
+    for entry in &index.entries {
+        if !by_path.contains_key(entry.path.as_str()) {
+            remove_file(repo, &entry.path)?;
+        }
+    }
+    for node in nodes
+        .iter()
+        .filter(|node| !unchanged.contains_key(node.path.as_str()))
+    {
+        let mode = u32::from_str_radix(&node.mode, 8)
+            .map_err(|e| format!("Invalid mode, {}: {}", node.mode, e))?;
+        write_file(repo, &node.path, mode, &node.hash)?;
+    }
+    let mut new_index = Index::from_tree(repo, tree)?;
+    for entry in new_index.entries.iter_mut() {
+        match unchanged.get(entry.path.as_str()) {
+            Some(&kept) => *entry = kept.clone(),
+            None if entry.mode != 0o160000 => {
+                if let Ok(metadata) = fs::symlink_metadata(worktree_path(repo, &entry.path)) {
+                    entry.update_stat(&metadata);
+                }
+            }
+            None => {}
+        }
+    }
+    new_index.write(repo)
+}
+
+/// Whether the index or the tracked files of the worktree differ from HEAD
+pub fn has_uncommitted_changes(repo: &Repository) -> Result<bool, String> {
+    let head_tree = match read_ref(repo, "HEAD") {
+        Some(head) => Some(peel_to_tree(repo, &head)?),
+        None => None,
+    };
+    let index = Index::read(repo)?;
+    Ok(Some(index.write_tree(repo)?) != head_tree
+        || index.entries.iter().any(|entry| is_modified(repo, entry)))
+}